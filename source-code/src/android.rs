@@ -0,0 +1,119 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use owo_colors::OwoColorize;
+use crate::cross::Cross;
+use crate::pkgdeps::PkgConfigMode;
+use crate::{compile_c_cpp, find_config_file, install_deps, parse_config, rules, sandbox, target_path, verbosity, Android, BuildOptions};
+
+const DEFAULT_ABIS: &[&str] = &["arm64-v8a", "armeabi-v7a", "x86_64"];
+const DEFAULT_API: u32 = 24;
+
+/// The NDK from `ndk` in the config, or `ANDROID_NDK_HOME`/`ANDROID_NDK_ROOT`.
+fn ndk_root(android: &Android, path: &Path) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    let ndk = android.ndk.as_ref().map(|n| path.join(n))
+    .or_else(|| env::var_os("ANDROID_NDK_HOME").map(PathBuf::from))
+    .or_else(|| env::var_os("ANDROID_NDK_ROOT").map(PathBuf::from))
+    .ok_or("No Android NDK: set `ndk` in [android] or ANDROID_NDK_HOME")?;
+    if !ndk.join("toolchains/llvm/prebuilt").exists() {
+        return Err(format!("{} does not look like an Android NDK (r19 or newer)", ndk.display()).into());
+    }
+    Ok(ndk)
+}
+
+fn host_tag() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "darwin-x86_64"
+    } else if cfg!(windows) {
+        "windows-x86_64"
+    } else {
+        "linux-x86_64"
+    }
+}
+
+/// Clang target prefix for an ABI; the API level is appended to form the compiler wrapper name.
+fn triple(abi: &str) -> Result<&'static str, Box<dyn std::error::Error + Send + Sync>> {
+    match abi {
+        "arm64-v8a" => Ok("aarch64-linux-android"),
+        "armeabi-v7a" => Ok("armv7a-linux-androideabi"),
+        "x86" => Ok("i686-linux-android"),
+        "x86_64" => Ok("x86_64-linux-android"),
+        _ => Err(format!("Unknown Android ABI '{}'", abi).into()),
+    }
+}
+
+/// The NDK clang toolchain for one ABI. pkg-config only sees `pkg_config_libdir` (with `$abi` expanded),
+/// since host `.pc` files describe libraries the device doesn't have.
+fn toolchain(android: &Android, ndk: &Path, abi: &str, cplusplus: bool, path: &Path) -> Result<Cross, Box<dyn std::error::Error + Send + Sync>> {
+    let bin = ndk.join("toolchains/llvm/prebuilt").join(host_tag()).join("bin");
+    let api = android.api.unwrap_or(DEFAULT_API);
+    let compiler = bin.join(format!("{}{}-clang{}", triple(abi)?, api, if cplusplus { "++" } else { "" }));
+    if !compiler.exists() {
+        return Err(format!("{} not found; is API level {} supported by this NDK?", compiler.display(), api).into());
+    }
+    let pkg_config = match &android.pkg_config_libdir {
+        Some(dir) => PkgConfigMode::Sysroot { libdirs: vec![path.join(dir.replace("$abi", abi))], sysroot: None },
+        None => PkgConfigMode::Disabled,
+    };
+    Ok(Cross {
+        triple: triple(abi)?.to_string(),
+        compiler: compiler.display().to_string(),
+        ar: bin.join("llvm-ar").display().to_string(),
+        cflags: "-fPIC".to_string(),
+        // Catch missing symbols at link time rather than when the app loads the library
+        ldflags: "-Wl,--no-undefined".to_string(),
+        pkg_config,
+        runner: None,
+    })
+}
+
+/// Builds the C/C++ target for every configured ABI into `build/android/<abi>`. Shared libraries are
+/// also collected as `build/android/jniLibs/<abi>/lib<name>.so`, the layout an APK's jniLibs expects.
+pub fn run(path: &Path, children: &Arc<Mutex<Vec<u32>>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let build = config.build.as_ref().ok_or("No build section")?;
+    let android = config.android.as_ref().ok_or("No [android] section in config")?;
+    let ndk = ndk_root(android, path)?;
+    let abis: Vec<String> = android.abis.clone().unwrap_or_else(|| DEFAULT_ABIS.iter().map(|a| a.to_string()).collect());
+    let cplusplus = config.specs.languages.iter().any(|l| l == "c++");
+    verbosity::progress(format!("Building {} for Android ({})", config.metadata.name, abis.join(", ")).blue().bold());
+    install_deps(&config, path)?;
+    if let Some(r) = &config.rules {
+        rules::run(r, path, &path.join("build"), sandbox::enabled(&config))?;
+    }
+
+    let android_dir = path.join("build/android");
+    for abi in &abis {
+        verbosity::progress(format!("Building for {}", abi).cyan());
+        let opts = BuildOptions {
+            build_dir: Some(android_dir.join(abi)),
+            cross: Some(toolchain(android, &ndk, abi, cplusplus, path)?),
+            ..Default::default()
+        };
+        compile_c_cpp(&config, path, children, &opts)?;
+        let target = target_path(build, path, &opts);
+        if build.build_type == "shared" {
+            let name = target.file_name().unwrap().to_string_lossy().to_string();
+            let name = if name.starts_with("lib") { name } else { format!("lib{}", name) };
+            let dest = android_dir.join("jniLibs").join(abi);
+            fs::create_dir_all(&dest)?;
+            fs::copy(&target, dest.join(name))?;
+        } else if build.build_type == "executable" {
+            verbosity::progress(format!("  adb push {} /data/local/tmp/", target.display()));
+        }
+    }
+    if build.build_type == "shared" {
+        verbosity::progress(format!("Android libraries in {}", android_dir.join("jniLibs").display()).green().bold());
+    } else {
+        verbosity::status("Android build complete!");
+    }
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+/// Splits a flags string from the config into arguments the way a POSIX shell would: on unquoted
+/// whitespace, with `'...'` and `"..."` quoting and backslash escapes, so `-DNAME="my app"` stays one
+/// argument. An unterminated quote runs to the end of the string.
+pub fn split(flags: &str) -> Vec<OsString> {
+    let mut args = vec![];
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut chars = flags.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_arg {
+                    args.push(OsString::from(std::mem::take(&mut current)));
+                    in_arg = false;
+                }
+            }
+            '\'' => {
+                in_arg = true;
+                current.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                in_arg = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(next @ ('"' | '\\' | '$' | '`')) => current.push(next),
+                            Some(next) => {
+                                current.push('\\');
+                                current.push(next);
+                            }
+                            None => current.push('\\'),
+                        },
+                        c => current.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_arg = true;
+                current.extend(chars.next());
+            }
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_arg {
+        args.push(OsString::from(current));
+    }
+    args
+}
+
+/// `prefix` and `path` as one argument, e.g. `-I` and an include directory.
+pub fn with_path(prefix: &str, path: &Path) -> OsString {
+    let mut arg = OsString::from(prefix);
+    arg.push(path.as_os_str());
+    arg
+}
+
+/// `arg` as it would be typed in a shell, single-quoted when needed.
+pub fn quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || "\"'$\\`*?;&|<>()#".contains(c)) {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    } else {
+        arg.into_owned()
+    }
+}
+
+/// `args` as one shell-quoted line, for display and fingerprints.
+pub fn display(args: &[OsString]) -> String {
+    args.iter().map(|a| quote(a)).collect::<Vec<_>>().join(" ")
+}
+
+/// True for a `-D` or `-U` flag, which can change the `#include`s a source reaches.
+pub fn is_define(flag: &OsStr) -> bool {
+    let flag = flag.to_string_lossy();
+    flag.starts_with("-D") || flag.starts_with("-U")
+}
+
+/// Prerequisites of the make rule `-MM` prints, with its line continuations and `\ `-escaped spaces.
+pub fn make_prerequisites(rule: &str) -> Vec<PathBuf> {
+    let rule = rule.replace("\\\r\n", " ").replace("\\\n", " ");
+    // The target ends at the first colon followed by whitespace, so Windows drive letters survive
+    let Some(start) = rule.match_indices(':').map(|(i, _)| i + 1).find(|&i| rule[i..].chars().next().is_none_or(char::is_whitespace)) else {
+        return vec![];
+    };
+    let mut deps = vec![];
+    let mut current = String::new();
+    let mut chars = rule[start..].chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek().is_some_and(|n| *n == ' ' || *n == '#') => current.extend(chars.next()),
+            '$' if chars.peek() == Some(&'$') => current.extend(chars.next()),
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    deps.push(PathBuf::from(std::mem::take(&mut current)));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        deps.push(PathBuf::from(current));
+    }
+    deps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: Vec<OsString>) -> Vec<String> {
+        args.into_iter().map(|a| a.into_string().unwrap()).collect()
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(strings(split("  -O2 -Wall\t-g\n")), ["-O2", "-Wall", "-g"]);
+        assert!(split("").is_empty());
+        assert!(split("   ").is_empty());
+    }
+
+    #[test]
+    fn keeps_quoted_defines_together() {
+        assert_eq!(strings(split(r#"-DVERSION="my app" -Wall"#)), ["-DVERSION=my app", "-Wall"]);
+        assert_eq!(strings(split(r#"-DNAME='"my app"'"#)), [r#"-DNAME="my app""#]);
+        assert_eq!(strings(split(r#"-DNAME="\"my app\"""#)), [r#"-DNAME="my app""#]);
+    }
+
+    #[test]
+    fn keeps_spacey_paths_together() {
+        assert_eq!(strings(split(r#"-I"/home/me/My Project/include" -L/opt/My\ Libs"#)), ["-I/home/me/My Project/include", "-L/opt/My Libs"]);
+        assert_eq!(strings(split(r#"'' """#)), ["", ""]);
+        assert_eq!(strings(split(r#""unterminated quote"#)), ["unterminated quote"]);
+    }
+
+    #[test]
+    fn joins_prefix_and_path() {
+        assert_eq!(with_path("-I", Path::new("/home/me/My Project/include")), OsString::from("-I/home/me/My Project/include"));
+    }
+
+    #[test]
+    fn parses_make_prerequisites() {
+        let rule = "main.o: src/main.cpp /home/me/My\\ Project/include/app.h \\\n  include/cost\\#1.h gen/$$x.h\n";
+        assert_eq!(make_prerequisites(rule), [
+            PathBuf::from("src/main.cpp"),
+            PathBuf::from("/home/me/My Project/include/app.h"),
+            PathBuf::from("include/cost#1.h"),
+            PathBuf::from("gen/$x.h"),
+        ]);
+        assert_eq!(make_prerequisites("C:/src/main.o: C:/src/main.c"), [PathBuf::from("C:/src/main.c")]);
+        assert!(make_prerequisites("").is_empty());
+    }
+
+    #[test]
+    fn display_round_trips_through_split() {
+        let args: Vec<OsString> = ["g++", "-DVERSION=my app", "-I/home/me/My Project/src", "it's", "", "-c", "main.cpp"].iter().map(OsString::from).collect();
+        assert_eq!(display(&args[..2]), "g++ '-DVERSION=my app'");
+        assert_eq!(split(&display(&args)), args);
+    }
+}
@@ -0,0 +1,265 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use dirs::home_dir;
+use owo_colors::OwoColorize;
+use crate::{args, hash_bytes, hash_file, state, vendor, verbosity, HBuildConfig};
+
+/// Default size `~/.hbuild/cache/objects` is trimmed to after a build.
+const DEFAULT_MAX_SIZE_MB: u64 = 5120;
+
+/// The cache of compiled objects and linked artifacts shared by every build on the machine, so a
+/// clean checkout or a branch switched back restores outputs instead of rebuilding them.
+pub struct Cache {
+    dir: PathBuf,
+    max_bytes: u64,
+    compiler: String, // hash of the compilers' version output; a different compiler never shares entries
+    remote: Option<Remote>,
+    pending: Mutex<Vec<String>>, // keys stored this build, uploaded once it succeeds
+}
+
+/// A shared cache other machines fill: entries are `GET` and `PUT` as `<url>/<key>`, which an
+/// HTTP file server or an S3-compatible bucket both serve.
+struct Remote {
+    url: String,
+    read_only: bool,
+    secrets: String, // curl config lines with the credentials, kept off the command line
+    reachable: AtomicBool, // cleared on the first network error, so an unreachable cache costs one timeout
+}
+
+impl Remote {
+    fn new(settings: &crate::Cache, url: &str) -> Result<Remote, Box<dyn std::error::Error + Send + Sync>> {
+        let read_only = match std::env::var("HBUILD_CACHE_READ_ONLY").as_deref() {
+            Ok("1") => true,
+            Ok("0") => false,
+            _ => settings.read_only.unwrap_or(false),
+        };
+        let mut secrets = String::new();
+        if let Some(var) = &settings.token_env {
+            let token = std::env::var(var).map_err(|_| format!("{} is not set; it holds the token for the remote cache {}", var, url))?;
+            secrets.push_str(&format!("header = \"Authorization: Bearer {}\"\n", token));
+        }
+        if let Some(region) = &settings.s3_region {
+            let key = std::env::var("AWS_ACCESS_KEY_ID").map_err(|_| "AWS_ACCESS_KEY_ID is not set; the S3 remote cache needs it")?;
+            let secret = std::env::var("AWS_SECRET_ACCESS_KEY").map_err(|_| "AWS_SECRET_ACCESS_KEY is not set; the S3 remote cache needs it")?;
+            secrets.push_str(&format!("aws-sigv4 = \"aws:amz:{}:s3\"\nuser = \"{}:{}\"\n", region, key, secret));
+        }
+        Ok(Remote { url: url.trim_end_matches('/').to_string(), read_only, secrets, reachable: AtomicBool::new(true) })
+    }
+
+    /// Runs curl with `args` and the credentials; its exit code, or None when it couldn't run.
+    fn curl(&self, args: &[&std::ffi::OsStr]) -> Option<i32> {
+        let mut child = Command::new("curl")
+        .args(["--config", "-", "-fsS", "--connect-timeout", "5"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn().ok()?;
+        child.stdin.take()?.write_all(self.secrets.as_bytes()).ok()?;
+        let output = child.wait_with_output().ok()?;
+        // 22 is an HTTP error status: for a GET, the entry doesn't exist
+        if !output.status.success() && output.status.code() != Some(22) && self.reachable.swap(false, Ordering::Relaxed) {
+            eprintln!("{}", format!("Remote cache {} is unreachable; building without it: {}", self.url, String::from_utf8_lossy(&output.stderr).trim()).yellow());
+        }
+        output.status.code()
+    }
+
+    /// Downloads the entry for `key` to `dest`.
+    fn get(&self, key: &str, dest: &Path) -> bool {
+        if !self.reachable.load(Ordering::Relaxed) {
+            return false;
+        }
+        let url = format!("{}/{}", self.url, key);
+        self.curl(&["-o".as_ref(), dest.as_os_str(), url.as_ref()]) == Some(0)
+    }
+
+    /// Uploads each `(key, file)` in one parallel transfer.
+    fn put(&self, entries: &[(String, PathBuf)]) {
+        if entries.is_empty() || self.read_only || !self.reachable.load(Ordering::Relaxed) {
+            return;
+        }
+        let urls: Vec<String> = entries.iter().map(|(key, _)| format!("{}/{}", self.url, key)).collect();
+        let mut args: Vec<&std::ffi::OsStr> = vec!["--parallel".as_ref()];
+        for ((_, file), url) in entries.iter().zip(&urls) {
+            args.extend(["-T".as_ref(), file.as_os_str(), url.as_ref()]);
+        }
+        if self.curl(&args) == Some(22) {
+            eprintln!("{}", format!("Remote cache {} refused an upload; check its credentials or set read_only", self.url).yellow());
+        }
+    }
+}
+
+/// Flags taking a directory, joined to it or followed by it.
+const PATH_FLAGS: &[&str] = &["-I", "-isystem", "-iquote", "-idirafter", "-L", "-J"];
+
+/// `args` as `args::display` shows them, with the directories of include, library and module path
+/// flags named by `state::portable_path`, so checkouts at different paths share cache entries.
+pub fn portable(args: &[OsString], root: &Path) -> String {
+    let mut out: Vec<OsString> = vec![];
+    let mut takes_path = false;
+    for arg in args {
+        let text = arg.to_string_lossy();
+        let rewritten = if takes_path {
+            Some(OsString::from(state::portable_path(Path::new(arg), root)))
+        } else {
+            PATH_FLAGS.iter().find(|f| text.len() > f.len() && text.starts_with(*f)).map(|f| OsString::from(format!("{}{}", f, state::portable_path(Path::new(&text[f.len()..]), root))))
+        };
+        takes_path = PATH_FLAGS.contains(&text.as_ref());
+        out.push(rewritten.unwrap_or_else(|| arg.clone()));
+    }
+    args::display(&out)
+}
+
+impl Cache {
+    /// The cache for builds with `compilers`, every driver the build runs, e.g. gfortran or nvcc too,
+    /// unless `[cache] enabled = false` or `HBUILD_CACHE=0`. `[cache] remote` is left out under `--offline`.
+    pub fn open(config: &HBuildConfig, compilers: &[&str]) -> Result<Option<Cache>, Box<dyn std::error::Error + Send + Sync>> {
+        let settings = config.cache.as_ref();
+        if !settings.and_then(|c| c.enabled).unwrap_or(true) || std::env::var("HBUILD_CACHE").is_ok_and(|v| v == "0") {
+            return Ok(None);
+        }
+        let dir = match settings.and_then(|c| c.dir.as_ref()) {
+            Some(dir) => PathBuf::from(dir),
+            None => home_dir().ok_or("Cannot find home directory")?.join(".hbuild/cache/objects"),
+        };
+        // A compiler that can't report its version can't be told apart from another build of it
+        let mut versions = vec![];
+        for compiler in compilers {
+            let Some(version) = Command::new(compiler).arg("--version").output().ok().filter(|o| o.status.success()) else {
+                return Ok(None);
+            };
+            versions.extend(version.stdout);
+        }
+        fs::create_dir_all(&dir)?;
+        // Without its credentials the build still works, just without sharing
+        let remote = match settings.and_then(|c| Some((c, c.remote.as_ref()?))).filter(|_| !vendor::offline()) {
+            Some((settings, url)) => Remote::new(settings, url).map_err(|e| eprintln!("{}", format!("{}; building without it", e).yellow())).ok(),
+            None => None,
+        };
+        Ok(Some(Cache {
+            dir,
+            max_bytes: settings.and_then(|c| c.max_size_mb).unwrap_or(DEFAULT_MAX_SIZE_MB) * 1024 * 1024,
+            compiler: hash_bytes(&versions),
+            remote,
+            pending: Mutex::new(vec![]),
+        }))
+    }
+
+    /// The key of an object with the given `state::portable_fingerprint`.
+    pub fn object_key(&self, fingerprint: &str) -> String {
+        format!("{}-{}", self.compiler, fingerprint)
+    }
+
+    /// The key of a linked artifact: `name` built by `command` (its flags, libraries and scripts,
+    /// made portable) from `inputs`, which are hashed by contents.
+    pub fn link_key(&self, name: &str, command: &str, inputs: &[PathBuf]) -> String {
+        let mut combined = format!("{}\n{}\n", name, command);
+        for input in inputs {
+            combined.push_str(&hash_file(input).unwrap_or_else(|| "missing".to_string()));
+            combined.push('\n');
+        }
+        format!("{}-link-{}", self.compiler, hash_bytes(combined.as_bytes()))
+    }
+
+    /// Copies the entry for `key` to `dest`, fetching it from the remote cache into the local one
+    /// first when only that has it; false when neither does. Like the build's own outputs, `dest`
+    /// is replaced whole or not at all.
+    pub fn restore(&self, key: &str, dest: &Path) -> bool {
+        let entry = self.dir.join(key);
+        if !entry.exists() {
+            let Some(remote) = &self.remote else {
+                return false;
+            };
+            let download = self.dir.join(format!("{}.{}.partial", key, std::process::id()));
+            if !remote.get(key, &download) || fs::rename(&download, &entry).is_err() {
+                let _ = fs::remove_file(&download);
+                return false;
+            }
+        }
+        let mut partial = dest.as_os_str().to_owned();
+        partial.push(".partial");
+        if fs::copy(&entry, &partial).and_then(|_| fs::rename(&partial, dest)).is_err() {
+            let _ = fs::remove_file(&partial);
+            return false;
+        }
+        // Trimming evicts the least recently used entries first
+        let _ = fs::File::options().append(true).open(&entry).and_then(|f| f.set_modified(SystemTime::now()));
+        true
+    }
+
+    /// Stores `file` as the entry for `key`. Written beside it and renamed, so concurrent builds
+    /// never see a partial entry; a failure only costs the next build a cache miss.
+    pub fn store(&self, key: &str, file: &Path) {
+        let entry = self.dir.join(key);
+        let partial = self.dir.join(format!("{}.{}.partial", key, std::process::id()));
+        if fs::copy(file, &partial).and_then(|_| fs::rename(&partial, &entry)).is_err() {
+            let _ = fs::remove_file(&partial);
+            eprintln!("{}", format!("Could not cache {}", file.display()).yellow());
+            return;
+        }
+        self.pending.lock().unwrap().push(key.to_string());
+    }
+
+    /// Uploads what this build stored to the remote cache, unless it is read-only. Called once the
+    /// build succeeded, so a failing build shares nothing.
+    pub fn upload(&self) {
+        let Some(remote) = &self.remote else {
+            return;
+        };
+        let entries: Vec<(String, PathBuf)> = self.pending.lock().unwrap().drain(..).map(|key| (key.clone(), self.dir.join(key))).collect();
+        if !entries.is_empty() && !remote.read_only && remote.reachable.load(Ordering::Relaxed) {
+            verbosity::progress(format!("Uploading {} cache entr{} to {}", entries.len(), if entries.len() == 1 { "y" } else { "ies" }, remote.url).cyan());
+        }
+        remote.put(&entries);
+    }
+
+    /// Removes the least recently used entries until the local cache fits `[cache] max_size_mb`.
+    pub fn trim(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut entries = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            entries.push((meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), meta.len(), entry.path()));
+        }
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= len;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn portable_str(args: &[&str], root: &str) -> String {
+        portable(&args.iter().map(OsString::from).collect::<Vec<_>>(), Path::new(root))
+    }
+
+    #[test]
+    fn names_paths_relative_to_the_project() {
+        assert_eq!(portable_str(&["-O2", "-I/nonexistent/a/include", "-c", "src/main.c"], "/nonexistent/a"), "-O2 -Iinclude -c src/main.c");
+        assert_eq!(portable_str(&["-isystem", "/nonexistent/a/vendor", "-L/nonexistent/a/lib"], "/nonexistent/a"), "-isystem vendor -Llib");
+        assert_eq!(portable_str(&["-I/nonexistent/a/include"], "/nonexistent/a"), portable_str(&["-I/nonexistent/b/include"], "/nonexistent/b"));
+    }
+
+    #[test]
+    fn keeps_other_flags() {
+        assert_eq!(portable_str(&["-I/usr/include/glib-2.0", "-DX=1", "-Wall"], "/nonexistent/a"), "-I/usr/include/glib-2.0 -DX=1 -Wall");
+        // A bare flag whose path follows isn't rewritten itself
+        assert_eq!(portable_str(&["-I"], "/nonexistent/a"), "-I");
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::args;
+
+/// Assembly sources: `.s` goes straight to the assembler, `.S` and `.sx` through the C preprocessor first.
+pub fn is_asm(src: &Path) -> bool {
+    src.extension().is_some_and(|e| e == "s" || e == "S" || e == "sx")
+}
+
+fn preprocessed(src: &Path) -> bool {
+    src.extension().is_some_and(|e| e == "S" || e == "sx")
+}
+
+/// Directories the assembler searches for `.include` and `.incbin` files: the source's own, then the
+/// include directories.
+fn search_dirs(src: &Path, include_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    src.parent().into_iter().map(Path::to_path_buf).chain(include_dirs.iter().cloned()).collect()
+}
+
+/// The compiler driver's arguments assembling `src` to `obj`. The C flags apply, since they carry
+/// the target's `-m` options and the defines `.S` files test, but not the language standard.
+/// Without the preprocessor, the include directories are passed to the assembler itself.
+pub fn compile_args(opt_flag: &str, cflags: &[OsString], include_flags: &[OsString], include_dirs: &[PathBuf], src: &Path, obj: &Path) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![opt_flag.into()];
+    args.extend(cflags.iter().cloned());
+    if preprocessed(src) {
+        args.extend(include_flags.iter().cloned());
+    } else {
+        args.extend(search_dirs(src, include_dirs).iter().map(|d| args::with_path("-Wa,-I", d)));
+    }
+    args.extend(["-c".into(), src.into(), "-o".into(), obj.into()]);
+    args
+}
+
+/// The files a `.s` source pulls in with `.include` or `.incbin`, which the compiler's `-MM` scan
+/// doesn't see. None for `.S` sources, which the preprocessor scans like C.
+pub fn dependencies(src: &Path, include_dirs: &[PathBuf]) -> Option<HashSet<PathBuf>> {
+    if preprocessed(src) {
+        return None;
+    }
+    let text = fs::read_to_string(src).unwrap_or_default();
+    let dirs = search_dirs(src, include_dirs);
+    let mut deps = HashSet::new();
+    for line in text.lines() {
+        let line = line.trim_start();
+        let Some(rest) = line.strip_prefix(".include").or_else(|| line.strip_prefix(".incbin")) else {
+            continue;
+        };
+        if let Some(file) = rest.trim_start().strip_prefix('"').and_then(|r| r.split('"').next()) {
+            deps.extend(dirs.iter().map(|d| d.join(file)).find(|p| p.exists()));
+        }
+    }
+    Some(deps)
+}
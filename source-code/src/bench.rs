@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+use crate::test::{self, Program};
+use crate::{cross, expand_globs, find_config_file, parse_config, profile, verbosity, BuildOptions};
+
+/// A change in median time within this many percent is taken for noise.
+const NOISE_PERCENT: f64 = 5.0;
+
+/// One benchmark's timings over its runs, in nanoseconds.
+#[derive(Debug, Serialize, Deserialize)]
+struct Measurement {
+    name: String,
+    median_ns: f64,
+    min_ns: f64,
+    runs: usize,
+}
+
+/// What a `hbuild bench` run stores in `build/bench/latest.json`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Results {
+    timestamp: u64,
+    profile: String,
+    benchmarks: Vec<Measurement>,
+}
+
+/// Libraries and link flags a framework's benchmarks need; its `main` comes from the framework.
+fn framework_link(framework: &str) -> Result<(Vec<&'static str>, &'static str), Box<dyn std::error::Error + Send + Sync>> {
+    match framework {
+        "none" => Ok((vec![], "")),
+        "benchmark" => Ok((vec!["benchmark_main", "benchmark"], "-pthread")),
+        _ => Err(format!("Unknown benchmark framework '{}' (expected none or benchmark)", framework).into()),
+    }
+}
+
+fn measurement(name: &str, mut times: Vec<f64>) -> Measurement {
+    times.sort_by(f64::total_cmp);
+    Measurement { name: name.to_string(), median_ns: times[times.len() / 2], min_ns: times[0], runs: times.len() }
+}
+
+fn run_command(command: &str, path: &Path) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut sh = Command::new("sh");
+    sh.arg("-c").arg(command).current_dir(path);
+    verbosity::command(&sh);
+    let output = sh.output()?;
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stdout));
+        eprint!("{}", String::from_utf8_lossy(&output.stderr).red());
+        return Err(format!("Benchmark failed: {}", command).into());
+    }
+    Ok(output.stdout)
+}
+
+/// Wall time of `iterations` runs of a plain benchmark program.
+fn time_runs(name: &str, command: &str, path: &Path, iterations: u32) -> Result<Measurement, Box<dyn std::error::Error + Send + Sync>> {
+    let mut times = vec![];
+    for _ in 0..iterations {
+        let start = Instant::now();
+        run_command(command, path)?;
+        times.push(start.elapsed().as_nanos() as f64);
+    }
+    Ok(measurement(name, times))
+}
+
+/// The real time of each benchmark in Google Benchmark's JSON report, over its repetitions.
+fn google_benchmark(command: &str, path: &Path) -> Result<Vec<Measurement>, Box<dyn std::error::Error + Send + Sync>> {
+    let report: serde_json::Value = serde_json::from_slice(&run_command(command, path)?).map_err(|e| format!("Cannot read the benchmark's JSON report: {}", e))?;
+    let mut runs: Vec<(String, Vec<f64>)> = vec![];
+    // Means, medians and deviations over the repetitions are recomputed from the runs themselves
+    for entry in report["benchmarks"].as_array().into_iter().flatten().filter(|e| e["run_type"] != "aggregate") {
+        let Some(name) = entry["run_name"].as_str().or(entry["name"].as_str()) else {
+            continue;
+        };
+        let scale = match entry["time_unit"].as_str() {
+            Some("us") => 1e3,
+            Some("ms") => 1e6,
+            Some("s") => 1e9,
+            _ => 1.0,
+        };
+        let Some(time) = entry["real_time"].as_f64() else {
+            continue;
+        };
+        match runs.iter_mut().find(|(n, _)| n == name) {
+            Some((_, times)) => times.push(time * scale),
+            None => runs.push((name.to_string(), vec![time * scale])),
+        }
+    }
+    if runs.is_empty() {
+        return Err(format!("No benchmarks reported by {}", command).into());
+    }
+    Ok(runs.into_iter().map(|(name, times)| measurement(&name, times)).collect())
+}
+
+fn human(ns: f64) -> String {
+    match ns {
+        ns if ns >= 1e9 => format!("{:.3} s", ns / 1e9),
+        ns if ns >= 1e6 => format!("{:.3} ms", ns / 1e6),
+        ns if ns >= 1e3 => format!("{:.3} us", ns / 1e3),
+        ns => format!("{:.1} ns", ns),
+    }
+}
+
+/// Prints each benchmark's median next to that of the previous run, when it was of the same profile.
+fn print_table(results: &Results, previous: Option<&Results>) {
+    let previous = previous.filter(|p| p.profile == results.profile);
+    let w = results.benchmarks.iter().map(|m| m.name.len()).chain(["Benchmark".len()]).max().unwrap_or(0);
+    println!("{}", format!("{:w$}  {:>12}  {:>12}  {:>8}", "Benchmark", "Median", "Previous", "Change").bold());
+    for m in &results.benchmarks {
+        let before = previous.and_then(|p| p.benchmarks.iter().find(|b| b.name == m.name));
+        let (old, change) = match before {
+            Some(b) => {
+                let change = (m.median_ns - b.median_ns) / b.median_ns * 100.0;
+                let text = format!("{:>+7.1}%", change);
+                let text = if change <= -NOISE_PERCENT {
+                    text.green().to_string()
+                } else if change >= NOISE_PERCENT {
+                    text.red().to_string()
+                } else {
+                    text
+                };
+                (human(b.median_ns), text)
+            }
+            None => ("-".to_string(), format!("{:>8}", "-")),
+        };
+        println!("{:w$}  {:>12}  {:>12}  {}", m.name, human(m.median_ns), old, change);
+    }
+}
+
+/// Builds the `[bench]` sources with the project's code, like `hbuild test` does the tests but with
+/// the release profile unless another one is chosen, into `build/bench/<name>`, and runs them. With
+/// `framework = "none"` every source is its own program, timed over `iterations` runs; with
+/// `"benchmark"` they form one Google Benchmark binary repeating each benchmark `iterations` times.
+/// The medians are stored in `build/bench/latest.json`, the run before moved to `previous.json`, and
+/// compared with it.
+pub fn run(path: &Path, children: &Arc<Mutex<Vec<u32>>>, opts: &BuildOptions) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = find_config_file(path).ok_or("No config file found")?;
+    let config = parse_config(&config_path, &format)?;
+    let build = config.build.as_ref().ok_or("No build section")?;
+    let bench = config.bench.as_ref().ok_or("No [bench] section in config")?;
+    let framework = bench.framework.as_deref().unwrap_or("none");
+    let (framework_libs, framework_flags) = framework_link(framework)?;
+    let iterations = bench.iterations.unwrap_or(5).max(1);
+    let sources = expand_globs(path, &bench.sources)?;
+    if sources.is_empty() {
+        return Err("No benchmark sources match [bench] sources".into());
+    }
+    let code = test::code_under_test(path, &build.sources, bench.exclude.as_ref())?;
+    let opts = match opts.profile {
+        Some(_) => opts.clone(),
+        None => profile::apply(&config, "release", path, opts.clone())?,
+    };
+    let dir = cross::build_root(path, opts.cross.as_ref()).join("bench");
+    verbosity::progress(format!("Benchmarking {}", config.metadata.name).blue().bold());
+    test::prepare(&config, path)?;
+
+    let programs: Vec<(String, Vec<&PathBuf>)> = if framework == "none" {
+        sources.iter().map(|s| (s.file_stem().unwrap().to_string_lossy().to_string(), vec![s])).collect()
+    } else {
+        vec![(format!("{}-bench", build.target), sources.iter().collect())]
+    };
+    let mut benchmarks = vec![];
+    for (name, bench_sources) in &programs {
+        let program = Program {
+            name: name.clone(),
+            sources: code.iter().chain(bench_sources.iter().copied()).cloned().collect(),
+            libs: bench.libs.iter().flatten().cloned().chain(framework_libs.iter().map(|l| l.to_string())).collect(),
+            ldflags: framework_flags.to_string(),
+            pkg_dependencies: vec![],
+            pkg_fallbacks: BTreeMap::new(),
+        };
+        let bench_opts = BuildOptions {
+            build_dir: Some(dir.join(name)),
+            ..opts.clone()
+        };
+        verbosity::progress(format!("Building benchmark {}", name).cyan());
+        let binary = test::build_program(path, children, &config_path, &format, &program, &bench_opts)?;
+        verbosity::progress(format!("Running {}", name).cyan());
+        let invocation = cross::invocation(&binary, opts.cross.as_ref());
+        let args = bench.args.as_deref().unwrap_or("");
+        if framework == "none" {
+            benchmarks.push(time_runs(name, format!("{} {}", invocation, args).trim_end(), path, iterations)?);
+        } else {
+            let command = format!("{} --benchmark_format=json --benchmark_repetitions={} {}", invocation, iterations, args);
+            benchmarks.extend(google_benchmark(command.trim_end(), path)?);
+        }
+    }
+
+    let latest = dir.join("latest.json");
+    let previous: Option<Results> = fs::read_to_string(&latest).ok().and_then(|json| serde_json::from_str(&json).ok());
+    if latest.exists() {
+        fs::rename(&latest, dir.join("previous.json"))?;
+    }
+    let results = Results {
+        timestamp: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs(),
+        profile: opts.profile.clone().unwrap_or_default(),
+        benchmarks,
+    };
+    fs::create_dir_all(&dir)?;
+    fs::write(&latest, serde_json::to_string_pretty(&results)?)?;
+    print_table(&results, previous.as_ref());
+    verbosity::status(&format!("Results written to {}", latest.display()));
+    Ok(())
+}
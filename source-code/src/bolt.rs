@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use owo_colors::OwoColorize;
+use crate::{find_config_file, is_stale, make, parse_config, target_path, verbosity, Bolt, BuildOptions};
+
+const DEFAULT_FLAGS: &str = "-reorder-blocks=ext-tsp -reorder-functions=hfsort -split-functions -split-all-cold -dyno-stats";
+
+/// BOLT rewrites functions in place and needs the static relocations kept in the executable.
+pub fn link_flags() -> &'static str {
+    "-Wl,--emit-relocs"
+}
+
+/// The profile converted for llvm-bolt by `hbuild bolt`.
+pub fn profile_path(build_dir: &Path) -> PathBuf {
+    build_dir.join("bolt/perf.fdata")
+}
+
+/// The BOLT-optimized executable written next to `target`.
+pub fn output_path(target: &Path) -> PathBuf {
+    let mut name = target.file_name().unwrap().to_os_string();
+    name.push(".bolt");
+    target.with_file_name(name)
+}
+
+fn run(cmd: &mut Command, what: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let output = cmd.output()?;
+    if !output.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
+        return Err(format!("{} failed", what).into());
+    }
+    Ok(())
+}
+
+/// Runs llvm-bolt over the linked executable when a profile has been recorded and the
+/// optimized binary is older than either.
+pub fn optimize(bolt: &Bolt, target: &Path, build_dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let profile = profile_path(build_dir);
+    if !profile.exists() {
+        verbosity::progress("No BOLT profile yet; run `hbuild bolt` to record one".yellow());
+        return Ok(());
+    }
+    let out = output_path(target);
+    if !is_stale(target, &out) && !is_stale(&profile, &out) {
+        return Ok(());
+    }
+    verbosity::progress(format!("Optimizing {} with BOLT", target.display()).cyan());
+    run(Command::new("llvm-bolt")
+    .arg(target)
+    .arg("-o").arg(&out)
+    .arg(format!("-data={}", profile.display()))
+    .args(bolt.flags.as_deref().unwrap_or(DEFAULT_FLAGS).split_whitespace()), "llvm-bolt")
+}
+
+/// Builds the project, runs the `[bolt]` record command under `perf record` and converts the samples
+/// with perf2bolt into the profile the post-link step uses.
+pub fn record(path: &Path, children: &Arc<Mutex<Vec<u32>>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let build = config.build.as_ref().ok_or("No build section")?;
+    let bolt = config.bolt.as_ref().ok_or("No [bolt] section in config")?;
+    if build.build_type != "executable" {
+        return Err("BOLT only applies to executable targets".into());
+    }
+    if !cfg!(target_os = "linux") {
+        return Err("BOLT is only available for Linux ELF executables".into());
+    }
+    make(path, children)?;
+
+    let opts = BuildOptions::default();
+    let build_dir = opts.build_dir(path);
+    let target = target_path(build, path, &opts);
+    let bolt_dir = build_dir.join("bolt");
+    fs::create_dir_all(&bolt_dir)?;
+    let perf_data = bolt_dir.join("perf.data");
+    let lbr = bolt.lbr.unwrap_or(true);
+    let command = bolt.record.replace("$target", &target.display().to_string());
+
+    verbosity::progress(format!("Recording: {}", command).cyan());
+    let mut perf = Command::new("perf");
+    perf.arg("record").args(["-e", "cycles:u"]);
+    if lbr {
+        perf.args(["-j", "any,u"]);
+    }
+    let status = perf.arg("-o").arg(&perf_data)
+    .args(["--", "sh", "-c", &command])
+    .current_dir(path)
+    .status()?;
+    if !status.success() {
+        return Err(format!("perf record failed: {}", command).into());
+    }
+
+    verbosity::progress("Converting profile with perf2bolt".cyan());
+    let mut perf2bolt = Command::new("perf2bolt");
+    if !lbr {
+        perf2bolt.arg("-nl");
+    }
+    run(perf2bolt.arg("-p").arg(&perf_data).arg("-o").arg(profile_path(&build_dir)).arg(&target), "perf2bolt")?;
+
+    optimize(bolt, &target, &build_dir)?;
+    verbosity::progress(format!("BOLT binary: {}", output_path(&target).display()).green().bold());
+    Ok(())
+}
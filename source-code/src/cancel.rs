@@ -0,0 +1,34 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use owo_colors::OwoColorize;
+use crate::platform;
+
+/// How long build tools get to exit after SIGTERM before they are killed.
+const TERM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Files the running build tools are writing, by PID: objects and link targets.
+static WRITING: Mutex<Vec<(u32, PathBuf)>> = Mutex::new(Vec::new());
+
+/// Records that the tool `pid` writes `output`, which is removed if the build is cancelled.
+pub fn writing(pid: u32, output: &Path) {
+    WRITING.lock().unwrap().push((pid, output.to_path_buf()));
+}
+
+/// Records that `pid` exited, so its output is complete or the tool cleaned up after itself.
+pub fn finished(pid: u32) {
+    WRITING.lock().unwrap().retain(|(p, _)| *p != pid);
+}
+
+/// Ctrl-C: stops `children` and their process groups, then deletes the outputs they were writing
+/// so a truncated object or target never looks up to date. The caller holds the children lock, so
+/// build threads can't report the stopped tools as failed compiles in the meantime.
+pub fn cancel(children: &[u32]) {
+    platform::terminate(children, TERM_TIMEOUT);
+    for (_, output) in WRITING.lock().unwrap().drain(..) {
+        if fs::remove_file(&output).is_ok() {
+            eprintln!("{}", format!("Removed partial output {}", output.display()).yellow());
+        }
+    }
+}
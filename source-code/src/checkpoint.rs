@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Progress of a build phase in flight, appended to `<build dir>/<phase>.checkpoint` as steps start and
+/// finish and removed once the phase completes. A checkpoint left behind by Ctrl-C or a crash tells the
+/// next build which steps finished and which were cut off half-way, leaving truncated objects or targets
+/// whose mtimes would otherwise make them look up to date. A phase ended by an error rather than an
+/// interrupt leaves nothing to resume of its own, since compilers and linkers remove what they failed to
+/// write: dropping its checkpoint keeps only the steps an earlier interrupt cut off and it didn't redo.
+pub struct Checkpoint {
+    path: PathBuf,
+    started: HashSet<String>,
+    done: HashSet<String>,
+    finished: Mutex<HashSet<String>>,
+    log: Mutex<Option<File>>,
+    written: AtomicBool,
+}
+
+impl Checkpoint {
+    pub fn open(build_dir: &Path, phase: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        fs::create_dir_all(build_dir)?;
+        let checkpoint = Self::read(build_dir, phase);
+        let log = OpenOptions::new().create(true).append(true).open(&checkpoint.path)?;
+        *checkpoint.log.lock().unwrap() = Some(log);
+        Ok(checkpoint)
+    }
+
+    /// The checkpoint of `phase` to consult only, which creates and records nothing.
+    pub fn read(build_dir: &Path, phase: &str) -> Self {
+        let path = build_dir.join(format!("{}.checkpoint", phase));
+        let (mut started, mut done) = (HashSet::new(), HashSet::new());
+        for line in fs::read_to_string(&path).unwrap_or_default().lines() {
+            match line.split_once(' ') {
+                Some(("start", step)) => started.insert(step.to_string()),
+                Some(("done", step)) => done.insert(step.to_string()),
+                _ => false,
+            };
+        }
+        Checkpoint { path, started, done, finished: Mutex::new(HashSet::new()), log: Mutex::new(None), written: AtomicBool::new(false) }
+    }
+
+    /// True when a previous build stopped before completing this phase.
+    pub fn resuming(&self) -> bool {
+        !self.started.is_empty()
+    }
+
+    /// True when `step` finished in the interrupted build.
+    pub fn is_done(&self, step: &str) -> bool {
+        self.done.contains(step)
+    }
+
+    /// True when `step` was started but not finished in the interrupted build, so its output can't be trusted.
+    pub fn was_cut_off(&self, step: &str) -> bool {
+        self.started.contains(step) && !self.done.contains(step)
+    }
+
+    fn append(&self, line: String) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut log = self.log.lock().unwrap();
+        let log = log.as_mut().ok_or("Checkpoint is not open for writing")?;
+        log.write_all(line.as_bytes())?;
+        log.sync_data()?;
+        self.written.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn start(&self, step: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.append(format!("start {}\n", step))
+    }
+
+    pub fn finish(&self, step: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.finished.lock().unwrap().insert(step.to_string());
+        self.append(format!("done {}\n", step))
+    }
+
+    /// The phase completed; nothing is left to resume.
+    pub fn complete(self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.log.lock().unwrap().take();
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+impl Drop for Checkpoint {
+    /// Reached without `complete` when the phase failed or returned early, e.g. for `hbuild why`, which
+    /// ran no steps and leaves the checkpoint as it found it. Ctrl-C exits without dropping it.
+    fn drop(&mut self) {
+        if self.log.get_mut().unwrap().take().is_none() || !self.written.load(Ordering::Relaxed) {
+            return;
+        }
+        let finished = self.finished.get_mut().unwrap();
+        let mut cut_off: Vec<&String> = self.started.iter().filter(|s| !self.done.contains(*s) && !finished.contains(*s)).collect();
+        cut_off.sort();
+        let _ = if cut_off.is_empty() {
+            fs::remove_file(&self.path)
+        } else {
+            fs::write(&self.path, cut_off.iter().map(|s| format!("start {}\n", s)).collect::<String>())
+        };
+    }
+}
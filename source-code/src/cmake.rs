@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use crate::install::{Layout, Manifest};
+use crate::{args, job_count, pkgdeps, soname, staged, Build, HBuildConfig};
+
+/// `items` as a CMake list, quoted for a property value.
+fn list(items: &[String]) -> String {
+    format!("\"{}\"", items.join(";"))
+}
+
+/// `<name>Config.cmake`, defining the imported target `<name>::<target>` for the library installed
+/// as `target`'s file name. Paths are relative to the file itself, so a staged or moved prefix works.
+/// Like the `.pc` file, a static library passes its own dependencies on, found through pkg-config.
+pub fn config_file(config: &HBuildConfig, build: &Build, target: &Path) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let name = &config.metadata.name;
+    let imported = format!("{}::{}", name, build.target);
+    let shared = build.build_type == "shared";
+    let mut text = format!("# {} {}, generated by hbuild\n", name, config.metadata.version);
+    text.push_str("get_filename_component(_IMPORT_PREFIX \"${CMAKE_CURRENT_LIST_DIR}/../../..\" ABSOLUTE)\n");
+
+    let mut link_libraries: Vec<String> = build.public_libs.iter().flatten().cloned().collect();
+    let modules: Vec<String> = build.pkg_dependencies.iter().flatten().map(|d| pkgdeps::parse(d).map(|r| r.name)).collect::<Result<_, _>>()?;
+    if !shared {
+        link_libraries.extend(build.libs.iter().flatten().cloned());
+        if !modules.is_empty() {
+            let deps = format!("_{}_deps", name);
+            text.push_str("include(CMakeFindDependencyMacro)\nfind_dependency(PkgConfig)\n");
+            text.push_str(&format!("if(NOT TARGET PkgConfig::{})\n  pkg_check_modules({} REQUIRED IMPORTED_TARGET {})\nendif()\n", deps, deps, modules.join(" ")));
+            link_libraries.push(format!("PkgConfig::{}", deps));
+        }
+    }
+
+    text.push_str(&format!("if(NOT TARGET {})\n", imported));
+    text.push_str(&format!("  add_library({} {} IMPORTED)\n", imported, if shared { "SHARED" } else { "STATIC" }));
+    text.push_str(&format!("  set_target_properties({} PROPERTIES\n", imported));
+    text.push_str(&format!("    IMPORTED_LOCATION \"${{_IMPORT_PREFIX}}/lib/{}\"\n", target.file_name().unwrap().to_string_lossy()));
+    if let Some(versioned) = soname::versioned(target, &config.metadata.version).filter(|_| shared) {
+        text.push_str(&format!("    IMPORTED_SONAME \"{}\"\n", versioned.soname));
+    }
+    text.push_str("    INTERFACE_INCLUDE_DIRECTORIES \"${_IMPORT_PREFIX}/include\"\n");
+    if let Some(defines) = build.public_defines.as_ref().filter(|d| !d.is_empty()) {
+        text.push_str(&format!("    INTERFACE_COMPILE_DEFINITIONS {}\n", list(defines)));
+    }
+    if !link_libraries.is_empty() {
+        text.push_str(&format!("    INTERFACE_LINK_LIBRARIES {}\n", list(&link_libraries)));
+    }
+    text.push_str("  )\nendif()\nunset(_IMPORT_PREFIX)\n");
+    Ok(text)
+}
+
+/// `<name>ConfigVersion.cmake`, accepting requests for the same major version no newer than this
+/// one, or the same minor version before 1.0, where minor versions break compatibility.
+pub fn version_file(version: &str) -> String {
+    let mut parts = version.split('.');
+    let major = parts.next().unwrap_or("0");
+    let (variable, value) = match (major, parts.next()) {
+        ("0", Some(minor)) => ("PACKAGE_FIND_VERSION_MINOR", minor),
+        _ => ("PACKAGE_FIND_VERSION_MAJOR", major),
+    };
+    let mut text = format!("set(PACKAGE_VERSION \"{}\")\n", version);
+    text.push_str("if(PACKAGE_FIND_VERSION VERSION_GREATER PACKAGE_VERSION)\n  set(PACKAGE_VERSION_COMPATIBLE FALSE)\n");
+    if major == "0" {
+        text.push_str(&format!("elseif(PACKAGE_FIND_VERSION_MAJOR STREQUAL \"0\" AND {} STREQUAL \"{}\")\n", variable, value));
+    } else {
+        text.push_str(&format!("elseif({} STREQUAL \"{}\")\n", variable, value));
+    }
+    text.push_str("  set(PACKAGE_VERSION_COMPATIBLE TRUE)\nelse()\n  set(PACKAGE_VERSION_COMPATIBLE FALSE)\nendif()\n");
+    text.push_str("if(PACKAGE_FIND_VERSION STREQUAL PACKAGE_VERSION)\n  set(PACKAGE_VERSION_EXACT TRUE)\nendif()\n");
+    text
+}
+
+/// Writes the package config files to `build_dir` and installs them to `<libdir>/cmake/<name>`,
+/// where `find_package(<name>)` looks once the prefix is on `CMAKE_PREFIX_PATH`.
+pub fn install(config: &HBuildConfig, build: &Build, target: &Path, build_dir: &Path, layout: &Layout, manifest: &mut Manifest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let name = &config.metadata.name;
+    let generated = build_dir.join("cmake");
+    fs::create_dir_all(&generated)?;
+    let cmake_dir = layout.libdir().join("cmake").join(name);
+    manifest.create_dir_all(&cmake_dir)?;
+    let files = [
+        (format!("{}Config.cmake", name), config_file(config, build, target)?),
+        (format!("{}ConfigVersion.cmake", name), version_file(&config.metadata.version)),
+    ];
+    for (file, text) in files {
+        fs::write(generated.join(&file), text)?;
+        manifest.copy(&generated.join(&file), &cmake_dir.join(&file))?;
+    }
+    Ok(())
+}
+
+/// Builds git dependency `name`, a CMake project checked out in `src` at `commit`, and installs it
+/// into its staging prefix: a release build without its tests, position independent so shared
+/// libraries can link its static ones.
+pub fn build_dependency(name: &str, src: &Path, commit: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let dir = staged::dir(name)?;
+    let build_dir = dir.join("build");
+    let mut configure = Command::new("cmake");
+    configure.arg("-S").arg(src).arg("-B").arg(&build_dir);
+    configure.arg("-DCMAKE_BUILD_TYPE=Release").arg(args::with_path("-DCMAKE_INSTALL_PREFIX=", &dir.join("prefix")));
+    configure.args(["-DCMAKE_INSTALL_LIBDIR=lib", "-DCMAKE_POSITION_INDEPENDENT_CODE=ON", "-DBUILD_TESTING=OFF"]);
+    let mut build = Command::new("cmake");
+    build.arg("--build").arg(&build_dir).env("CMAKE_BUILD_PARALLEL_LEVEL", job_count()?.to_string());
+    let mut install = Command::new("cmake");
+    install.arg("--install").arg(&build_dir);
+    staged::run(name, commit, vec![configure, build, install])
+}
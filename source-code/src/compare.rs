@@ -0,0 +1,125 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use owo_colors::OwoColorize;
+use crate::cross::{self, Cross};
+use crate::{compile_c_cpp, find_config_file, install_deps, parse_config, rules, sandbox, shaders, target_path, verbosity, BuildOptions};
+
+struct Variant {
+    flags: String,
+    target: PathBuf,
+    size: u64,
+    text: Option<u64>,
+    bench: Option<Duration>,
+}
+
+/// Directory name for a flag set: `1-O3_-march=native`.
+fn dir_name(index: usize, flags: &str) -> String {
+    let clean: String = flags.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '=' { c } else { '_' }).collect();
+    format!("{}-{}", index, clean.trim_matches(|c| c == '-' || c == '_'))
+}
+
+/// Size of the `.text` segment as reported by `size` (Berkeley format), if available.
+fn text_size(target: &Path) -> Option<u64> {
+    let out = Command::new("size").arg(target).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    stdout.lines().nth(1)?.split_whitespace().next()?.parse().ok()
+}
+
+/// Median wall time of `runs` executions of `bench`, with `$target` replaced by the built binary.
+fn bench_time(bench: &str, target: &Path, cross: Option<&Cross>, path: &Path, runs: usize) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+    let command = bench.replace("$target", &cross::invocation(target, cross));
+    let mut times = vec![];
+    for _ in 0..runs.max(1) {
+        let start = Instant::now();
+        let output = Command::new("sh").arg("-c").arg(&command).current_dir(path).output()?;
+        if !output.status.success() {
+            eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
+            return Err(format!("Benchmark failed: {}", command).into());
+        }
+        times.push(start.elapsed());
+    }
+    times.sort();
+    Ok(times[times.len() / 2])
+}
+
+fn print_table(variants: &[Variant]) {
+    let base = variants[0].size as f64;
+    let w = variants.iter().map(|v| v.flags.len()).chain(["Flags".len()]).max().unwrap_or(0);
+    let with_bench = variants.iter().any(|v| v.bench.is_some());
+    let mut header = format!("{:w$}  {:>10}  {:>8}  {:>10}", "Flags", "Size", "Delta", "Text");
+    if with_bench {
+        header.push_str(&format!("  {:>10}", "Time"));
+    }
+    println!("{}", header.bold());
+    for v in variants {
+        let delta = (v.size as f64 - base) / base * 100.0;
+        let text = v.text.map(|t| t.to_string()).unwrap_or_else(|| "-".to_string());
+        let mut line = format!("{:w$}  {:>10}  {:>7.1}%  {:>10}", v.flags, v.size, delta, text);
+        if let Some(t) = v.bench {
+            line.push_str(&format!("  {:>9.3}s", t.as_secs_f64()));
+        }
+        println!("{}", line);
+    }
+}
+
+/// Builds the target once per flag set into `build/compare/<n>-<flags>` and reports the binary sizes,
+/// and with `bench` the median run time, side by side. The first flag set is the baseline.
+pub fn run(path: &Path, children: &Arc<Mutex<Vec<u32>>>, flag_sets: &[String], bench: Option<&str>, runs: usize, cross: Option<&Cross>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if flag_sets.is_empty() {
+        return Err("compare needs at least one --flags set".into());
+    }
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let build = config.build.as_ref().ok_or("No build section")?;
+    verbosity::progress(format!("Comparing {} flag sets for {}", flag_sets.len(), config.metadata.name).blue().bold());
+    install_deps(&config, path)?;
+    if let Some(r) = &config.rules {
+        rules::run(r, path, &path.join("build"), sandbox::enabled(&config))?;
+    }
+
+    let mut variants = vec![];
+    for (i, flags) in flag_sets.iter().enumerate() {
+        verbosity::progress(format!("Building with {}", flags).cyan());
+        let opts = BuildOptions {
+            build_dir: Some(cross::build_root(path, cross).join("compare").join(dir_name(i, flags))),
+            extra_flags: Some(flags.clone()),
+            cross: cross.cloned(),
+            ..Default::default()
+        };
+        if let Some(sh) = &config.shaders {
+            shaders::compile(sh, path, &opts.build_dir(path))?;
+        }
+        compile_c_cpp(&config, path, children, &opts)?;
+        let target = target_path(build, path, &opts);
+        let bench = match bench {
+            Some(b) => Some(bench_time(b, &target, cross, path, runs)?),
+            None => None,
+        };
+        variants.push(Variant {
+            flags: flags.clone(),
+            size: fs::metadata(&target)?.len(),
+            text: text_size(&target),
+            target,
+            bench,
+        });
+    }
+
+    print_table(&variants);
+    for v in &variants {
+        println!("  {}", v.target.display());
+    }
+    verbosity::status("Compare complete!");
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+/// One translation unit in `compile_commands.json`, in the format clangd and clang-tidy read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    directory: String,
+    file: String,
+    arguments: Vec<String>,
+    output: String,
+}
+
+impl Entry {
+    /// The command compiling `src` to `obj` with `compiler` and `args`, run in `dir`.
+    pub fn new(dir: &Path, compiler: &str, args: &[OsString], src: &Path, obj: &Path) -> Self {
+        let mut arguments = vec![compiler.to_string()];
+        arguments.extend(args.iter().map(|a| a.to_string_lossy().into_owned()));
+        Entry {
+            directory: dir.display().to_string(),
+            file: dir.join(src).display().to_string(),
+            arguments,
+            output: dir.join(obj).display().to_string(),
+        }
+    }
+}
+
+/// Merges `entries` into `compile_commands.json` in the project root. Entries for other files are kept
+/// unless their source no longer exists; the file is only rewritten
+/// when something changed so tools watching it aren't woken up by every build.
+pub fn update(path: &Path, entries: Vec<Entry>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let file = path.join("compile_commands.json");
+    let existing: Vec<Entry> = fs::read_to_string(&file).ok()
+    .and_then(|json| serde_json::from_str(&json).ok())
+    .unwrap_or_default();
+    let mut merged: Vec<Entry> = existing.iter()
+    .filter(|old| Path::new(&old.file).exists() && !entries.iter().any(|e| e.file == old.file))
+    .cloned()
+    .collect();
+    merged.extend(entries);
+    merged.sort_by(|a, b| a.file.cmp(&b.file));
+    if merged != existing {
+        fs::write(&file, serde_json::to_string_pretty(&merged)?)?;
+    }
+    Ok(())
+}
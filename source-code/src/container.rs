@@ -0,0 +1,50 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use dirs::home_dir;
+use owo_colors::OwoColorize;
+use crate::verbosity;
+
+/// podman when installed, docker otherwise.
+fn engine() -> &'static str {
+    if Command::new("podman").arg("--version").output().is_ok_and(|o| o.status.success()) {
+        "podman"
+    } else {
+        "docker"
+    }
+}
+
+fn id(flag: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let out = Command::new("id").arg(flag).output()?;
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// Runs `hbuild make` with `make_args` inside `image`. The project is mounted at `/work` and the
+/// `~/.hbuild` cache at the container's home, and this hbuild binary is mounted in so the image only
+/// needs the toolchain. Files are created as the calling user.
+pub fn make(path: &Path, image: &str, make_args: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let engine = engine();
+    let project = path.canonicalize()?;
+    let cache = home_dir().ok_or("Cannot find home directory")?.join(".hbuild");
+    fs::create_dir_all(&cache)?;
+    let exe = env::current_exe()?;
+    verbosity::progress(format!("Building in {} container {}", engine, image).blue().bold());
+
+    let mut cmd = Command::new(engine);
+    cmd.args(["run", "--rm", "-w", "/work", "-e", "HOME=/hbuild-home"])
+    .arg("-v").arg(format!("{}:/work", project.display()))
+    .arg("-v").arg(format!("{}:/hbuild-home/.hbuild", cache.display()))
+    .arg("-v").arg(format!("{}:/usr/local/bin/hbuild:ro", exe.display()));
+    if engine == "podman" {
+        cmd.arg("--userns=keep-id");
+    } else {
+        cmd.arg("--user").arg(format!("{}:{}", id("-u")?, id("-g")?));
+    }
+    let status = cmd.arg(image).args(["hbuild", "make", "/work"]).args(make_args).status()?;
+    if !status.success() {
+        return Err(format!("Container build in {} failed", image).into());
+    }
+    verbosity::status("Container build complete!");
+    Ok(())
+}
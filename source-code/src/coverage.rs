@@ -0,0 +1,189 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use owo_colors::OwoColorize;
+use crate::{expand_globs, find_config_file, msvc, parse_config, platform, test, verbosity, BuildOptions};
+
+/// Line hit counts by source file.
+type Lines = BTreeMap<PathBuf, BTreeMap<u32, u64>>;
+
+/// Files under `dir` with extension `ext`, at any depth.
+fn files(dir: &Path, ext: &str) -> Vec<PathBuf> {
+    let mut found = vec![];
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let file = entry.path();
+        if file.is_dir() {
+            found.extend(files(&file, ext));
+        } else if file.extension().is_some_and(|e| e == ext) {
+            found.push(file);
+        }
+    }
+    found
+}
+
+/// The gcov matching `compiler`: `llvm-cov gcov` for clang, else the gcov named like the compiler,
+/// e.g. `gcov-13` for `gcc-13` or `aarch64-linux-gnu-gcov` for a cross gcc.
+fn gcov(compiler: &str) -> Vec<String> {
+    let name = Path::new(compiler).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    if name.contains("clang") {
+        let llvm_cov = name.replacen("clang++", "llvm-cov", 1).replacen("clang", "llvm-cov", 1);
+        let llvm_cov = if platform::which(&llvm_cov).is_some() { llvm_cov } else { "llvm-cov".to_string() };
+        return vec![llvm_cov, "gcov".to_string()];
+    }
+    let named = name.replacen("g++", "gcov", 1).replacen("gcc", "gcov", 1);
+    vec![if named != name && platform::which(&named).is_some() { named } else { "gcov".to_string() }]
+}
+
+/// Adds the counts in gcov's report to `lines`, each for the source the `Source:` line before it
+/// names relative to `path`.
+fn read_gcov(text: &str, path: &Path, lines: &mut Lines) {
+    let mut counts: Option<&mut BTreeMap<u32, u64>> = None;
+    for line in text.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (Some(count), Some(number), Some(rest)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        let Ok(number) = number.trim().parse::<u32>() else {
+            continue;
+        };
+        if number == 0 {
+            if let Some(source) = rest.strip_prefix("Source:") {
+                let source = path.join(source);
+                counts = Some(lines.entry(source.canonicalize().unwrap_or(source)).or_default());
+            }
+            continue;
+        }
+        // `-` marks lines without code, `#####` and `=====` code never run; `*` blocks run in part
+        let hits = match count.trim().trim_end_matches('*') {
+            "-" => continue,
+            "#####" | "=====" => 0,
+            hits => match hits.parse::<u64>() {
+                Ok(hits) => hits,
+                Err(_) => continue,
+            },
+        };
+        if let Some(counts) = counts.as_mut() {
+            *counts.entry(number).or_default() += hits;
+        }
+    }
+}
+
+/// Runs gcov on the coverage data under `dir` and sums the line counts of each source over the
+/// objects built from or including it, e.g. by several test binaries.
+fn collect(dir: &Path, path: &Path, compiler: &str) -> Result<Lines, Box<dyn std::error::Error + Send + Sync>> {
+    let data: Vec<PathBuf> = files(dir, "gcda").iter().map(|d| d.canonicalize()).collect::<Result<_, _>>()?;
+    let mut lines = Lines::new();
+    if data.is_empty() {
+        return Ok(lines);
+    }
+    let tool = gcov(compiler);
+    let mut command = Command::new(&tool[0]);
+    // In the project, where the relative source paths the objects record lead
+    command.args(&tool[1..]).arg("--stdout").args(&data).current_dir(path);
+    verbosity::command(&command);
+    let output = command.output().map_err(|e| format!("Cannot run {}: {}", tool.join(" "), e))?;
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr).red());
+        return Err(format!("{} failed", tool.join(" ")).into());
+    }
+    read_gcov(&String::from_utf8_lossy(&output.stdout), path, &mut lines);
+    Ok(lines)
+}
+
+/// `lines` as an lcov tracefile, which genhtml and most CI coverage services read.
+fn lcov(lines: &Lines) -> String {
+    let mut text = String::from("TN:\n");
+    for (file, counts) in lines {
+        text.push_str(&format!("SF:{}\n", file.display()));
+        for (number, hits) in counts {
+            text.push_str(&format!("DA:{},{}\n", number, hits));
+        }
+        text.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", counts.len(), counts.values().filter(|&&h| h > 0).count()));
+    }
+    text
+}
+
+fn percent(hit: usize, total: usize) -> f64 {
+    if total == 0 { 100.0 } else { hit as f64 * 100.0 / total as f64 }
+}
+
+/// Prints lines covered per file and in total.
+fn print_summary(lines: &Lines, root: &Path) {
+    let rows: Vec<(String, usize, usize)> = lines.iter().map(|(file, counts)| {
+        let name = file.strip_prefix(root).unwrap_or(file).display().to_string();
+        (name, counts.len(), counts.values().filter(|&&h| h > 0).count())
+    }).collect();
+    let width = rows.iter().map(|(name, _, _)| name.len()).max().unwrap_or(0).max(5);
+    println!("{}", format!("{:<width$} {:>7} {:>7} {:>7}", "File", "Lines", "Covered", "Cover", width = width).bold());
+    for (name, total, hit) in &rows {
+        let cover = format!("{:>6.1}%", percent(*hit, *total));
+        let cover = match percent(*hit, *total) {
+            p if p >= 80.0 => cover.green().to_string(),
+            p if p >= 50.0 => cover.yellow().to_string(),
+            _ => cover.red().to_string(),
+        };
+        println!("{:<width$} {:>7} {:>7} {}", name, total, hit, cover, width = width);
+    }
+    let (total, hit) = rows.iter().fold((0, 0), |(t, h), (_, total, hit)| (t + total, h + hit));
+    println!("{}", format!("{:<width$} {:>7} {:>7} {:>6.1}%", "Total", total, hit, percent(hit, total), width = width).bold());
+}
+
+/// Builds the `[test]` binaries instrumented with `--coverage`, unoptimized so lines map to code,
+/// in `build/coverage`, runs them and reports the lines of the project's own sources and headers
+/// they ran, test sources and generated files left out: as a table, as `build/coverage/lcov.info`
+/// and, when lcov's genhtml is installed, as HTML in `build/coverage/html`. The report is written
+/// even when tests fail, whose failure is then returned.
+pub fn run(path: &Path, children: &Arc<Mutex<Vec<u32>>>, opts: &BuildOptions) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = find_config_file(path).ok_or("No config file found")?;
+    let config = parse_config(&config_path, &format)?;
+    let build = config.build.as_ref().ok_or("No build section")?;
+    let test = config.test.as_ref().ok_or("No [test] section in config")?;
+    let compiler = opts.compiler.as_deref().or(opts.cross.as_ref().map(|c| c.compiler.as_str())).unwrap_or(&build.compiler);
+    if msvc::is_msvc(compiler) {
+        return Err("Coverage needs gcc or clang; MSVC has no --coverage".into());
+    }
+    let dir = opts.build_dir(path).join("coverage");
+    // Counts add up across runs, so each report starts from none
+    for data in files(&dir, "gcda") {
+        fs::remove_file(data)?;
+    }
+    let flags: Vec<String> = opts.extra_flags.iter().cloned().chain(["--coverage -g".to_string()]).collect();
+    let opts = BuildOptions {
+        build_dir: Some(dir.clone()),
+        optimize: Some("O0".to_string()),
+        extra_flags: Some(flags.join(" ")),
+        // Objects restored from the cache come without the .gcno notes gcov reads
+        no_cache: true,
+        ..opts.clone()
+    };
+    let tested = test::run(path, children, &opts);
+
+    let root = path.canonicalize()?;
+    let build_root = opts.build_dir(path).canonicalize()?;
+    let tests: HashSet<PathBuf> = expand_globs(path, &test.sources)?.iter().filter_map(|t| t.canonicalize().ok()).collect();
+    let mut lines = match collect(&dir, path, compiler) {
+        Ok(lines) => lines,
+        Err(e) => return tested.and(Err(e)),
+    };
+    lines.retain(|file, _| file.starts_with(&root) && !file.starts_with(&build_root) && !tests.contains(file));
+    if lines.is_empty() {
+        return tested.and(Err("No coverage data; did the tests run?".into()));
+    }
+    print_summary(&lines, &root);
+    let info = dir.join("lcov.info");
+    fs::write(&info, lcov(&lines))?;
+    verbosity::progress(format!("Wrote {}", info.display()).cyan());
+    if platform::which("genhtml").is_some() {
+        let mut genhtml = Command::new("genhtml");
+        genhtml.arg("--quiet").arg(&info).arg("--output-directory").arg(dir.join("html"));
+        verbosity::command(&genhtml);
+        if genhtml.status()?.success() {
+            verbosity::progress(format!("Wrote {}", dir.join("html").join("index.html").display()).cyan());
+        } else {
+            eprintln!("{}", "Warning: genhtml failed; only the lcov report was written".yellow());
+        }
+    }
+    tested
+}
@@ -0,0 +1,161 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use dirs::{config_dir, home_dir};
+use git2::build::RepoBuilder;
+use git2::{Cred, CredentialType, ErrorClass, ErrorCode, FetchOptions, RemoteCallbacks, Repository};
+use owo_colors::OwoColorize;
+use serde::Deserialize;
+
+/// `~/.config/hbuild/credentials`: per-host credentials for private git dependencies, e.g.
+///
+/// ```toml
+/// [hosts."git.example.com"]
+/// username = "oauth2"
+/// token_env = "EXAMPLE_TOKEN"
+///
+/// [hosts."github.com"]
+/// ssh_key = "~/.ssh/id_work"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+struct Credentials {
+    #[serde(default)]
+    hosts: BTreeMap<String, Host>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Host {
+    username: Option<String>, // default: the URL's user, else "git"
+    token: Option<String>, // HTTPS password or access token
+    token_env: Option<String>, // environment variable holding the token instead
+    ssh_key: Option<String>, // private key tried before ssh-agent and the default ~/.ssh keys
+}
+
+pub fn path() -> Option<PathBuf> {
+    Some(config_dir()?.join("hbuild/credentials"))
+}
+
+/// The host of an `https://`, `ssh://`, `git://` or scp-like `git@host:path` URL.
+fn host(url: &str) -> Option<&str> {
+    let rest = match url.split_once("://") {
+        Some((_, rest)) => rest,
+        None => url.split_once(':')?.0,
+    };
+    let authority = rest.split('/').next()?;
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    Some(host.split(':').next().unwrap_or(host))
+}
+
+/// The credentials configured for `url`'s host; none when the file is missing.
+fn configured(url: &str) -> Result<Host, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(file) = path().filter(|p| p.exists()) else {
+        return Ok(Host::default());
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if fs::metadata(&file)?.permissions().mode() & 0o077 != 0 {
+            eprintln!("{}", format!("{} is readable by other users; `chmod 600` it", file.display()).yellow());
+        }
+    }
+    let credentials: Credentials = toml::from_str(&fs::read_to_string(&file)?).map_err(|e| format!("{}: {}", file.display(), e))?;
+    Ok(host(url).and_then(|h| credentials.hosts.get(h)).cloned().unwrap_or_default())
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Callbacks answering git2's credential requests for `url`. Each kind of credential is offered
+/// once, since git2 asks again after every rejection: SSH tries the configured key, ssh-agent and
+/// then the default `~/.ssh` keys; HTTPS the configured token and then git's credential helpers.
+pub fn callbacks(url: &str) -> Result<RemoteCallbacks<'static>, Box<dyn std::error::Error + Send + Sync>> {
+    let host = configured(url)?;
+    let token = match (&host.token, &host.token_env) {
+        (Some(token), _) => Some(token.clone()),
+        (None, Some(var)) => Some(std::env::var(var).map_err(|_| format!("{} is not set; it holds the git token for {}", var, url))?),
+        (None, None) => None,
+    };
+    let mut ssh_keys: Vec<PathBuf> = host.ssh_key.iter().map(|k| expand_home(k)).collect();
+    let agent_index = ssh_keys.len();
+    ssh_keys.push(PathBuf::new());
+    if let Some(ssh) = home_dir().map(|h| h.join(".ssh")) {
+        ssh_keys.extend(["id_ed25519", "id_ecdsa", "id_rsa"].iter().map(|k| ssh.join(k)).filter(|k| k.exists()));
+    }
+    let ssh_attempt = Cell::new(0);
+    let token_tried = Cell::new(false);
+    let helper_tried = Cell::new(false);
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username_from_url, allowed| {
+        let username = host.username.as_deref().or(username_from_url).unwrap_or("git");
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username);
+        }
+        if allowed.contains(CredentialType::SSH_KEY) {
+            while ssh_attempt.get() < ssh_keys.len() {
+                let attempt = ssh_attempt.get();
+                ssh_attempt.set(attempt + 1);
+                if attempt == agent_index {
+                    if std::env::var_os("SSH_AUTH_SOCK").is_some() {
+                        return Cred::ssh_key_from_agent(username);
+                    }
+                    continue;
+                }
+                let key = &ssh_keys[attempt];
+                let public = key.with_extension("pub");
+                return Cred::ssh_key(username, public.exists().then_some(public.as_path()), key, None);
+            }
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            if let Some(token) = token.as_deref().filter(|_| !token_tried.replace(true)) {
+                return Cred::userpass_plaintext(username, token);
+            }
+            if !helper_tried.replace(true) {
+                if let Ok(cred) = git2::Config::open_default().and_then(|config| Cred::credential_helper(&config, url, username_from_url)) {
+                    return Ok(cred);
+                }
+            }
+        }
+        Err(git2::Error::new(ErrorCode::Auth, ErrorClass::Callback, "no credentials left to try"))
+    });
+    Ok(callbacks)
+}
+
+/// Turns git2's authentication failures into advice on where credentials come from.
+pub fn explain(url: &str, e: git2::Error) -> Box<dyn std::error::Error + Send + Sync> {
+    if e.code() != ErrorCode::Auth {
+        return e.into();
+    }
+    let file = path().map(|p| p.display().to_string()).unwrap_or_else(|| "~/.config/hbuild/credentials".to_string());
+    format!(
+        "Authentication failed for {}: {}\nLoad an SSH key into ssh-agent (`ssh-add`), or add a token or ssh_key under [hosts.\"{}\"] in {}",
+        url, e.message().split("; class=").next().unwrap_or_default(), host(url).unwrap_or(url), file
+    ).into()
+}
+
+fn fetch_options(url: &str) -> Result<FetchOptions<'static>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks(url)?);
+    Ok(options)
+}
+
+/// `Repository::clone` with the credentials of `url`'s host.
+pub fn clone(url: &str, into: &Path) -> Result<Repository, Box<dyn std::error::Error + Send + Sync>> {
+    RepoBuilder::new().fetch_options(fetch_options(url)?).clone(url, into).map_err(|e| explain(url, e))
+}
+
+/// Fetches every branch and tag from `repo`'s origin with the credentials of its host.
+pub fn fetch(repo: &Repository) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut remote = repo.find_remote("origin")?;
+    let url = remote.url().unwrap_or_default().to_string();
+    let mut options = fetch_options(&url)?;
+    options.download_tags(git2::AutotagOption::All);
+    // No refspecs: fetch every branch as configured by the clone
+    remote.fetch(&[] as &[&str], Some(&mut options), None).map_err(|e| explain(&url, e))
+}
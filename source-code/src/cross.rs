@@ -0,0 +1,130 @@
+use std::path::{Path, PathBuf};
+use crate::args;
+use crate::pkgdeps::PkgConfigMode;
+use crate::{Qemu, Toolchain};
+
+/// A toolchain building on this host for another system, e.g. one Android ABI.
+#[derive(Debug, Clone, Default)]
+pub struct Cross {
+    pub triple: String,
+    pub compiler: String,
+    pub ar: String,
+    pub cflags: String,
+    pub ldflags: String,
+    pub pkg_config: PkgConfigMode,
+    /// Command prefix that runs target binaries on this host, e.g. `qemu-riscv64 -L <sysroot>`.
+    pub runner: Option<String>,
+}
+
+/// A built-in `--target-triple`.
+struct Preset {
+    triples: &'static [&'static str],
+    /// `std::env::consts::ARCH` of hosts that run the target's binaries natively.
+    arch: &'static str,
+    prefix: &'static str,
+    flags: &'static str,
+    /// Multiarch sysroot: pkg-config and the runner look here. None for freestanding targets.
+    sysroot: Option<&'static str>,
+    qemu: Option<&'static str>,
+}
+
+const PRESETS: &[Preset] = &[
+    Preset {
+        triples: &["riscv64gc-linux-gnu", "riscv64-linux-gnu"],
+        arch: "riscv64",
+        prefix: "riscv64-linux-gnu-",
+        flags: "-march=rv64gc -mabi=lp64d",
+        sysroot: Some("/usr/riscv64-linux-gnu"),
+        qemu: Some("qemu-riscv64"),
+    },
+    // The riscv64-unknown-elf toolchain ships rv32 multilibs, so it covers 32-bit embedded targets too
+    Preset {
+        triples: &["riscv32imac-unknown-none-elf", "riscv32-unknown-elf"],
+        arch: "riscv32",
+        prefix: "riscv64-unknown-elf-",
+        flags: "-march=rv32imac -mabi=ilp32",
+        sysroot: None,
+        qemu: None,
+    },
+];
+
+/// The toolchain for a built-in target triple.
+pub fn preset(triple: &str, cplusplus: bool) -> Result<Cross, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(preset) = PRESETS.iter().find(|p| p.triples.contains(&triple)) else {
+        let known: Vec<&str> = PRESETS.iter().flat_map(|p| p.triples.iter().copied()).collect();
+        return Err(format!("Unknown target triple '{}' (known: {}); define it in [toolchain.{}]", triple, known.join(", "), triple).into());
+    };
+    let pkg_config = match preset.sysroot {
+        // Debian multiarch keeps the target's .pc files under /usr/lib/<triple>
+        Some(sysroot) => PkgConfigMode::Sysroot {
+            libdirs: vec![PathBuf::from(format!("/usr/lib/{}/pkgconfig", preset.prefix.trim_end_matches('-'))), PathBuf::from(sysroot).join("lib/pkgconfig")],
+            sysroot: None,
+        },
+        None => PkgConfigMode::Disabled,
+    };
+    let mut ldflags = preset.flags.to_string();
+    if preset.sysroot.is_none() {
+        ldflags.push_str(" -Wl,--gc-sections");
+    }
+    Ok(Cross {
+        triple: triple.to_string(),
+        compiler: format!("{}{}", preset.prefix, if cplusplus { "g++" } else { "gcc" }),
+        ar: format!("{}ar", preset.prefix),
+        cflags: preset.flags.to_string(),
+        ldflags,
+        pkg_config,
+        runner: preset.qemu.filter(|_| std::env::consts::ARCH != preset.arch).map(|qemu| match preset.sysroot {
+            Some(sysroot) => format!("{} -L {}", qemu, sysroot),
+            None => qemu.to_string(),
+        }),
+    })
+}
+
+/// The toolchain from a `[toolchain.<name>]` section. Tools default to the `<triple>-` prefix, and a
+/// sysroot is passed to the compiler and used for pkg-config lookups.
+pub fn configured(name: &str, toolchain: &Toolchain, cplusplus: bool) -> Cross {
+    let triple = toolchain.triple.clone().unwrap_or_else(|| name.to_string());
+    let prefix = toolchain.prefix.clone().unwrap_or_else(|| format!("{}-", triple));
+    // Quoted so the flags split back into one argument when the path has spaces
+    let sysroot_flag = toolchain.sysroot.as_ref().map(|s| format!("--sysroot={}", args::quote(s.as_ref()))).unwrap_or_default();
+    let libdirs: Vec<PathBuf> = match (&toolchain.pkg_config_path, &toolchain.sysroot) {
+        (Some(dirs), _) => dirs.iter().map(PathBuf::from).collect(),
+        (None, Some(sysroot)) => ["usr/lib/pkgconfig", "usr/share/pkgconfig", &format!("usr/lib/{}/pkgconfig", triple)]
+        .iter().map(|dir| Path::new(sysroot).join(dir)).collect(),
+        // Debian multiarch
+        (None, None) => vec![PathBuf::from(format!("/usr/lib/{}/pkgconfig", triple))],
+    };
+    Cross {
+        compiler: toolchain.compiler.clone().unwrap_or_else(|| format!("{}{}", prefix, if cplusplus { "g++" } else { "gcc" })),
+        ar: toolchain.ar.clone().unwrap_or_else(|| format!("{}ar", prefix)),
+        cflags: format!("{} {}", sysroot_flag, toolchain.cflags.as_deref().unwrap_or("")).trim().to_string(),
+        ldflags: format!("{} {}", sysroot_flag, toolchain.ldflags.as_deref().unwrap_or("")).trim().to_string(),
+        pkg_config: PkgConfigMode::Sysroot { libdirs, sysroot: toolchain.sysroot.as_ref().map(PathBuf::from) },
+        runner: toolchain.runner.clone(),
+        triple,
+    }
+}
+
+/// qemu-user runner from a `[qemu]` section; it replaces the preset's.
+pub fn qemu_runner(qemu: &Qemu) -> String {
+    match &qemu.sysroot {
+        Some(sysroot) => format!("{} -L {}", qemu.binary, sysroot),
+        None => qemu.binary.clone(),
+    }
+}
+
+/// How to invoke a built binary: through the runner when cross-compiling for a foreign architecture.
+pub fn invocation(target: &Path, cross: Option<&Cross>) -> String {
+    match cross.and_then(|c| c.runner.as_ref()) {
+        Some(runner) => format!("{} {}", runner, target.display()),
+        None => target.display().to_string(),
+    }
+}
+
+/// `build`, or `build/<triple>` when cross-compiling, so host and target objects never mix.
+pub fn build_root(path: &Path, cross: Option<&Cross>) -> PathBuf {
+    match cross {
+        Some(c) => path.join("build").join(&c.triple),
+        None => path.join("build"),
+    }
+}
@@ -0,0 +1,113 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use crate::{args, platform, Cuda};
+
+pub fn is_cuda(src: &Path) -> bool {
+    src.extension().is_some_and(|e| e == "cu")
+}
+
+/// nvcc and the CUDA runtime `.cu` objects link against.
+pub struct Toolkit {
+    pub nvcc: String,
+    host: String, // the C++ compiler, which compiles the host code
+    lib_dir: Option<PathBuf>, // None when the runtime is in the linker's default path, e.g. distro packages
+    gencode: Vec<String>,
+    flags: Vec<OsString>,
+}
+
+/// `-gencode` options for the `[cuda] arch` entries: `sm_XX` builds machine code for that GPU,
+/// `compute_XX` PTX the driver compiles at load time, and `native` or `all` are nvcc's own.
+/// The newest `sm_XX` also gets its PTX, so GPUs newer than any listed still run the code.
+fn gencode(arch: &[String]) -> Vec<String> {
+    let mut flags = vec![];
+    for entry in arch {
+        if let Some(version) = entry.strip_prefix("sm_") {
+            flags.push(format!("-gencode=arch=compute_{},code=sm_{}", version, version));
+        } else if let Some(version) = entry.strip_prefix("compute_") {
+            flags.push(format!("-gencode=arch=compute_{},code=compute_{}", version, version));
+        } else {
+            flags.push(format!("-arch={}", entry));
+        }
+    }
+    let newest = arch.iter().filter_map(|a| a.strip_prefix("sm_")).max_by_key(|v| v.trim_end_matches(char::is_alphabetic).parse::<u32>().unwrap_or(0));
+    if let Some(version) = newest {
+        flags.push(format!("-gencode=arch=compute_{},code=compute_{}", version, version));
+    }
+    flags
+}
+
+/// The toolkit's runtime library directory, from the `bin/nvcc` it belongs to.
+fn lib_dir(nvcc: &Path) -> Option<PathBuf> {
+    let root = nvcc.canonicalize().ok()?.parent()?.parent()?.to_path_buf();
+    if root == Path::new("/usr") {
+        return None;
+    }
+    ["lib64", "lib", "targets/x86_64-linux/lib"].iter().map(|d| root.join(d)).find(|d| d.join("libcudart.so").exists() || d.join("libcudart_static.a").exists())
+}
+
+impl Toolkit {
+    /// `[cuda] nvcc`, else nvcc on PATH, in `$CUDA_PATH/bin` or in `/usr/local/cuda/bin`, compiling
+    /// host code with `host`.
+    pub fn find(config: Option<&Cuda>, host: &str) -> Result<Toolkit, Box<dyn std::error::Error + Send + Sync>> {
+        let nvcc = match config.and_then(|c| c.nvcc.as_ref()) {
+            Some(nvcc) => PathBuf::from(nvcc),
+            None => platform::which("nvcc")
+            .or_else(|| std::env::var_os("CUDA_PATH").map(|p| PathBuf::from(p).join("bin/nvcc")).filter(|p| p.exists()))
+            .or_else(|| Some(PathBuf::from("/usr/local/cuda/bin/nvcc")).filter(|p| p.exists()))
+            .ok_or("nvcc not found for the .cu sources; install the CUDA toolkit or set [cuda] nvcc")?,
+        };
+        let lib_dir = platform::which(&nvcc.to_string_lossy()).and_then(|p| lib_dir(&p));
+        Ok(Toolkit {
+            nvcc: nvcc.to_string_lossy().to_string(),
+            host: host.to_string(),
+            lib_dir,
+            gencode: gencode(config.and_then(|c| c.arch.as_deref()).unwrap_or_default()),
+            flags: args::split(config.and_then(|c| c.flags.as_deref()).unwrap_or_default()),
+        })
+    }
+
+    /// What the objects depend on besides their sources and the C flags, for their fingerprints.
+    pub fn key(&self) -> String {
+        format!("{} {} {}", self.nvcc, self.gencode.join(" "), args::display(&self.flags))
+    }
+
+    /// nvcc's arguments compiling `src` to `obj`. Includes and defines apply to device code too;
+    /// the other C flags only to the host compiler.
+    pub fn compile_args(&self, std_flag: &str, opt_flag: &str, cflags: &[OsString], include_flags: &[OsString], src: &Path, obj: &Path) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec!["-ccbin".into(), self.host.clone().into()];
+        // The project's C++ standard; a C-only project leaves nvcc's default
+        if !std_flag.is_empty() {
+            args.push(std_flag.into());
+        }
+        args.push(opt_flag.into());
+        for flag in cflags {
+            let text = flag.to_string_lossy();
+            if text.starts_with("-D") || text.starts_with("-U") || text.starts_with("-I") {
+                args.push(flag.clone());
+            } else {
+                args.push(host_flag(&text));
+            }
+        }
+        args.extend(include_flags.iter().cloned());
+        args.extend(self.gencode.iter().map(OsString::from));
+        args.extend(self.flags.iter().cloned());
+        args.extend(["-c".into(), src.into(), "-o".into(), obj.into()]);
+        args
+    }
+
+    /// The CUDA runtime, for linking the objects with the C++ driver.
+    pub fn link_flags(&self) -> Vec<OsString> {
+        let mut flags = vec![];
+        if let Some(dir) = &self.lib_dir {
+            flags.push(args::with_path("-L", dir));
+            flags.push(args::with_path("-Wl,-rpath,", dir));
+        }
+        flags.push("-lcudart".into());
+        flags
+    }
+}
+
+/// `flag` passed through nvcc to the host compiler.
+pub fn host_flag(flag: &str) -> OsString {
+    format!("-Xcompiler={}", flag).into()
+}
@@ -0,0 +1,259 @@
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, OnceLock};
+use dirs::config_dir;
+use owo_colors::OwoColorize;
+use serde::Deserialize;
+use crate::verbosity;
+
+/// With `--distribute`, C/C++ compile jobs also run on the workers in `workers.toml`.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// `~/.config/hbuild/workers.toml`: machines reachable with `ssh <host>` that have the same
+/// compiler as this one, e.g.
+///
+/// ```toml
+/// [[worker]]
+/// host = "build@10.0.0.5"
+/// jobs = 16
+/// ```
+#[derive(Debug, Default, Deserialize)]
+struct Workers {
+    #[serde(default)]
+    worker: Vec<WorkerConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WorkerConfig {
+    host: String,
+    jobs: Option<usize>, // compile jobs run there at once; default: 4
+}
+
+struct Worker {
+    host: String,
+    jobs: usize,
+    compatible: OnceLock<bool>, // its compiler reports the same version as ours; probed on first use
+    down: AtomicBool,
+}
+
+/// Compile slots: `local` jobs here, plus each worker's. Jobs take a local slot first, since
+/// those skip the transfers, then a worker's.
+pub struct Distributor {
+    compiler: String,
+    local: usize,
+    workers: Vec<Worker>,
+    busy: Mutex<(usize, Vec<usize>)>, // local jobs, jobs per worker
+    freed: Condvar,
+}
+
+/// Where a compile job runs; the slot is released when it is dropped.
+pub enum Slot<'a> {
+    Local(&'a Distributor),
+    Remote(&'a Distributor, usize),
+}
+
+pub fn path() -> Option<PathBuf> {
+    Some(config_dir()?.join("hbuild/workers.toml"))
+}
+
+/// First line of `compiler --version` run by `command`, e.g. through ssh.
+fn version(mut command: Command) -> Option<String> {
+    let output = command.stdin(Stdio::null()).stderr(Stdio::null()).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or_default().to_string())
+}
+
+fn ssh(host: &str) -> Command {
+    let mut command = Command::new("ssh");
+    command.args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=5", host]);
+    command
+}
+
+fn quote(arg: &OsStr) -> String {
+    format!("'{}'", arg.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Flags that only matter to the preprocessor, which runs here; the value of the separate forms follows.
+const PREPROCESSOR_FLAGS: &[&str] = &["-I", "-D", "-U", "-include", "-imacros", "-isystem", "-iquote", "-idirafter"];
+
+/// Flags whose outputs or inputs are files next to the object, which a worker can't reach, and those
+/// targeting the CPU compiling, which on a worker would be its own rather than the one preprocessed for.
+const LOCAL_ONLY_FLAGS: &[&str] = &["-fprofile", "--coverage", "-ftest-coverage", "-save-temps", "-fdump-", "-fcallgraph-info", "-march=native", "-mtune=native", "-mcpu=native"];
+
+impl Distributor {
+    /// The slots for compiling with `compiler` at `local` jobs here, when `--distribute` is on and
+    /// `workers.toml` lists any workers.
+    pub fn new(compiler: &str, local: usize) -> Result<Option<Distributor>, Box<dyn std::error::Error + Send + Sync>> {
+        if !ENABLED.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let file = path().ok_or("Cannot find the config directory")?;
+        let workers: Workers = match fs::read_to_string(&file) {
+            Ok(text) => toml::from_str(&text).map_err(|e| format!("{}: {}", file.display(), e))?,
+            Err(_) => return Err(format!("--distribute needs workers in {}", file.display()).into()),
+        };
+        if workers.worker.is_empty() {
+            return Err(format!("--distribute needs workers in {}", file.display()).into());
+        }
+        let workers: Vec<Worker> = workers.worker.into_iter().map(|w| Worker {
+            host: w.host,
+            jobs: w.jobs.unwrap_or(4).max(1),
+            compatible: OnceLock::new(),
+            down: AtomicBool::new(false),
+        }).collect();
+        let count = workers.len();
+        Ok(Some(Distributor {
+            compiler: compiler.to_string(),
+            local,
+            workers,
+            busy: Mutex::new((0, vec![0; count])),
+            freed: Condvar::new(),
+        }))
+    }
+
+    /// Compile jobs that can run at once, here and on the workers.
+    pub fn jobs(&self) -> usize {
+        self.local + self.workers.iter().map(|w| w.jobs).sum::<usize>()
+    }
+
+    fn usable(&self, index: usize) -> bool {
+        let worker = &self.workers[index];
+        !worker.down.load(Ordering::Relaxed) && *worker.compatible.get_or_init(|| {
+            // The compiler is looked up by name there, so a toolchain outside PATH isn't distributed
+            let name = Path::new(&self.compiler).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let mut local = Command::new(&self.compiler);
+            local.arg("--version");
+            let mut remote = ssh(&worker.host);
+            remote.arg(format!("{} --version", quote(name.as_ref())));
+            let (ours, theirs) = (version(local), version(remote));
+            let compatible = ours.is_some() && ours == theirs;
+            if !compatible {
+                eprintln!("{}", format!("Not distributing to {}: its {} is {}, not {}", worker.host, name, theirs.as_deref().unwrap_or("missing or unreachable"), ours.as_deref().unwrap_or("unknown")).yellow());
+            }
+            compatible
+        })
+    }
+
+    /// Waits for a free slot: a local one if there is, else, when `remote_ok`, one on a usable worker.
+    pub fn acquire(&self, remote_ok: bool) -> Slot<'_> {
+        let mut busy = self.busy.lock().unwrap();
+        loop {
+            if busy.0 < self.local {
+                busy.0 += 1;
+                return Slot::Local(self);
+            }
+            let free = (0..self.workers.len()).filter(|_| remote_ok).find(|&i| busy.1[i] < self.workers[i].jobs && !self.workers[i].down.load(Ordering::Relaxed));
+            if let Some(index) = free {
+                busy.1[index] += 1;
+                // Probing takes a round trip, so it happens outside the lock
+                drop(busy);
+                if self.usable(index) {
+                    return Slot::Remote(self, index);
+                }
+                self.workers[index].down.store(true, Ordering::Relaxed);
+                busy = self.busy.lock().unwrap();
+                busy.1[index] -= 1;
+                continue;
+            }
+            // Every worker being down leaves the local slots, which free up eventually
+            busy = self.freed.wait(busy).unwrap();
+        }
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let distributor = match self {
+            Slot::Local(d) | Slot::Remote(d, _) => *d,
+        };
+        let mut busy = distributor.busy.lock().unwrap();
+        match self {
+            Slot::Local(_) => busy.0 -= 1,
+            Slot::Remote(_, index) => busy.1[*index] -= 1,
+        }
+        distributor.freed.notify_all();
+    }
+}
+
+/// Whether a compile with `args` can run on a worker at all.
+pub fn distributable(args: &[OsString]) -> bool {
+    !args.iter().any(|a| LOCAL_ONLY_FLAGS.iter().any(|f| a.to_string_lossy().starts_with(f)))
+}
+
+/// Compiles `src` to `obj` on the worker of `slot`: preprocesses here with `args`, the full local
+/// compile command, then sends the result to the worker and receives the object. None when the
+/// worker couldn't be reached, which takes it out of the rotation; the caller compiles locally.
+pub fn compile(slot: &Slot, compiler: &str, args: &[OsString], src: &Path, obj: &Path, cwd: &Path) -> Result<Option<Output>, Box<dyn std::error::Error + Send + Sync>> {
+    let Slot::Remote(distributor, index) = slot else {
+        return Ok(None);
+    };
+    let worker = &distributor.workers[*index];
+    // gcc and clang take the language of preprocessed input from its extension
+    let preprocessed = obj.with_extension(if src.extension().is_some_and(|e| e == "c") { "i" } else { "ii" });
+    let mut preprocess: Vec<OsString> = vec![];
+    let mut remote: Vec<OsString> = vec![];
+    let mut iter = args.iter().peekable();
+    while let Some(arg) = iter.next() {
+        let text = arg.to_string_lossy();
+        if arg == "-c" {
+            preprocess.push("-E".into());
+            remote.push(arg.clone());
+        } else if arg == "-o" {
+            iter.next();
+            preprocess.extend(["-o".into(), preprocessed.clone().into()]);
+        } else if arg.as_os_str() == src.as_os_str() {
+            preprocess.push(arg.clone());
+        } else if let Some(flag) = PREPROCESSOR_FLAGS.iter().find(|f| text.starts_with(**f)) {
+            preprocess.push(arg.clone());
+            if text == *flag {
+                preprocess.extend(iter.next().cloned());
+            }
+        } else {
+            preprocess.push(arg.clone());
+            remote.push(arg.clone());
+        }
+    }
+    let mut command = Command::new(compiler);
+    command.args(&preprocess).current_dir(cwd);
+    verbosity::command(&command);
+    let output = command.output()?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&preprocessed);
+        return Ok(Some(output));
+    }
+
+    let name = Path::new(compiler).file_name().unwrap_or(compiler.as_ref());
+    let input = format!("in.{}", preprocessed.extension().unwrap().to_string_lossy());
+    let flags: Vec<String> = remote.iter().map(|a| quote(a)).collect();
+    // The object comes back on stdout, so the compiler's own output goes to stderr
+    let script = format!(
+        "t=$(mktemp -d) && trap 'rm -rf \"$t\"' EXIT && cat > \"$t/{input}\" && cd \"$t\" && {} {} {input} -o out.o >&2 && cat out.o",
+        quote(name), flags.join(" "), input = input
+    );
+    let mut command = ssh(&worker.host);
+    command.arg(&script).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    verbosity::command(&command);
+    let mut child = command.spawn()?;
+    let sent = fs::read(&preprocessed).and_then(|source| child.stdin.take().unwrap().write_all(&source));
+    let output = child.wait_with_output()?;
+    let _ = fs::remove_file(&preprocessed);
+    // ssh exits with 255 for its own failures; anything else is the compiler's
+    if sent.is_err() || output.status.code() == Some(255) {
+        if !worker.down.swap(true, Ordering::Relaxed) {
+            eprintln!("{}", format!("Worker {} failed, compiling locally instead: {}", worker.host, String::from_utf8_lossy(&output.stderr).trim()).yellow());
+        }
+        return Ok(None);
+    }
+    if output.status.success() {
+        fs::write(obj, &output.stdout)?;
+    }
+    Ok(Some(Output { status: output.status, stdout: vec![], stderr: output.stderr }))
+}
@@ -0,0 +1,102 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use crate::pkgdeps::PkgConfigMode;
+use crate::{args, expand_globs, pkgdeps, platform, target_path, verbosity, BuildOptions, HBuildConfig};
+
+/// The D compiler: `$DC` like dub, else ldc2, which also cross-compiles, else dmd.
+pub fn compiler() -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(dc) = std::env::var("DC").ok().filter(|dc| !dc.is_empty()) {
+        return Ok(dc);
+    }
+    ["ldc2", "dmd"].iter().find(|dc| platform::which(dc).is_some()).map(|dc| dc.to_string())
+    .ok_or_else(|| "No D compiler found; install ldc2 or dmd, or set DC".into())
+}
+
+fn is_ldc(compiler: &str) -> bool {
+    Path::new(compiler).file_name().is_some_and(|n| n.to_string_lossy().starts_with("ldc"))
+}
+
+/// Optimization flags for an hbuild optimize level: ldc2 takes the level itself, dmd only on or off.
+fn optimize_flags(compiler: &str, optimize: &str) -> Vec<String> {
+    match optimize {
+        "O0" => vec!["-g".to_string()],
+        "Ofast" if is_ldc(compiler) => vec!["-O3".to_string()],
+        level if is_ldc(compiler) => vec![format!("-{}", level)],
+        _ => vec!["-O".to_string(), "-inline".to_string()],
+    }
+}
+
+fn run(mut command: Command, path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    command.current_dir(path);
+    verbosity::command(&command);
+    if !command.status()?.success() {
+        return Err("D build failed".into());
+    }
+    Ok(())
+}
+
+/// Builds the D part of the project: with `dub build` when it has a `dub.json` or `dub.sdl`, else the
+/// `[build]` target from its `.d` sources, with the include directories as import paths.
+pub fn build(config: &HBuildConfig, path: &Path, opts: &BuildOptions) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let triple = opts.cross.as_ref().map(|c| c.triple.as_str());
+    let optimize = opts.optimize.as_deref().or(config.build.as_ref().map(|b| b.optimize.as_str())).unwrap_or("O0");
+    let compiler = compiler()?;
+    if triple.is_some() && !is_ldc(&compiler) {
+        return Err(format!("{} can't cross-compile; install ldc2 or set DC to it", compiler).into());
+    }
+    if path.join("dub.json").exists() || path.join("dub.sdl").exists() {
+        let mut command = Command::new("dub");
+        command.arg("build").arg(format!("--compiler={}", compiler));
+        command.arg(format!("--build={}", if optimize == "O0" { "debug" } else { "release" }));
+        if let Some(triple) = triple {
+            command.arg(format!("--arch={}", triple));
+        }
+        return run(command, path);
+    }
+
+    let build = config.build.as_ref().ok_or("D needs a dub.json or a [build] section")?;
+    let sources: Vec<PathBuf> = expand_globs(path, &build.sources)?.into_iter().filter(|s| s.extension().is_some_and(|e| e == "d")).collect();
+    if sources.is_empty() {
+        return Err("No .d source in [build] sources".into());
+    }
+    let build_dir = opts.build_dir(path);
+    fs::create_dir_all(&build_dir)?;
+
+    let mut command = Command::new(&compiler);
+    command.args(optimize_flags(&compiler, optimize));
+    match build.build_type.as_str() {
+        "static" => {
+            command.arg("-lib");
+        }
+        "shared" => {
+            command.arg("-shared").arg(if is_ldc(&compiler) { "--relocation-model=pic" } else { "-fPIC" });
+        }
+        _ => {}
+    }
+    if let Some(triple) = triple {
+        command.arg(format!("-mtriple={}", triple));
+    }
+    command.args(args::split(build.cflags.as_deref().unwrap_or_default()));
+    command.args(build.include_dirs.iter().map(|d| args::with_path("-I", &path.join(d))));
+    // Link flags reach the linker through -L
+    let mut link: Vec<OsString> = build.lib_dirs.iter().flatten().map(|d| args::with_path("-L-L", &path.join(d))).collect();
+    link.extend(build.libs.iter().flatten().map(|l| OsString::from(format!("-L-l{}", l))));
+    let mode = opts.cross.as_ref().map_or(PkgConfigMode::Host, |c| c.pkg_config.clone());
+    for pkg in build.pkg_dependencies.iter().flatten() {
+        let lib = pkgdeps::resolve(pkg, config.pkg_fallbacks.as_ref(), &mode)?;
+        link.extend(lib.link_paths.iter().map(|d| args::with_path("-L-L", d)));
+        link.extend(lib.libs.iter().map(|l| OsString::from(format!("-L-l{}", l))));
+    }
+    link.extend(args::split(build.ldflags.as_deref().unwrap_or_default()).iter().map(|f| {
+        let mut flag = OsString::from("-L");
+        flag.push(f);
+        flag
+    }));
+    command.args(&link);
+    command.arg(args::with_path("-od=", &build_dir.join("d")));
+    command.arg(args::with_path("-of=", &target_path(build, path, opts)));
+    command.args(&sources);
+    run(command, path)
+}
@@ -0,0 +1,183 @@
+use std::path::Path;
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::pkgdeps::{self, PkgConfigMode};
+use crate::syspkg::{self, Manager};
+use crate::{cuda, dlang, expand_globs, find_config_file, fortran, gettext, language, parse_config, verbosity, BuildOptions, HBuildConfig};
+
+/// A program the build runs, and why.
+struct Tool {
+    program: String,
+    version_args: &'static [&'static str],
+    needed_for: String,
+}
+
+impl Tool {
+    fn new(program: &str, needed_for: &str) -> Self {
+        let version_args: &[&str] = match program {
+            "go" | "odin" | "zig" => &["version"],
+            _ => &["--version"],
+        };
+        Tool { program: program.to_string(), version_args, needed_for: needed_for.to_string() }
+    }
+}
+
+/// How to install `program` on HackerOS, by its file name so configured paths and cross
+/// prefixes (`aarch64-linux-gnu-g++`) get the same advice.
+fn fix(program: &str) -> String {
+    let name = Path::new(program).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let package = match name.rsplit('-').next().unwrap_or(&name) {
+        "g++" | "c++" => "g++",
+        "gcc" | "cc" => "gcc",
+        "clang" | "clang++" => "clang",
+        "gfortran" => "gfortran",
+        "nvcc" => return "install the CUDA toolkit from https://developer.nvidia.com/cuda-downloads or `sudo apt install nvidia-cuda-toolkit`".to_string(),
+        "ar" => "binutils",
+        "config" if name.ends_with("pkg-config") => "pkg-config",
+        "cargo" => return "install Rust with rustup (https://rustup.rs) or `sudo apt install cargo`".to_string(),
+        "go" => "golang",
+        "valac" => "valac",
+        "pip" => "python3-pip",
+        "odin" => return "install Odin from https://odin-lang.org/docs/install/".to_string(),
+        "crystal" => return "install Crystal from https://crystal-lang.org/install/".to_string(),
+        "zig" => return "install Zig from https://ziglang.org/download/".to_string(),
+        "ldc2" | "dmd" => "ldc",
+        "dub" => "dub",
+        "nim" => "nim",
+        "protoc" => "protobuf-compiler",
+        "swig" => "swig",
+        "msgfmt" | "xgettext" | "msgmerge" => "gettext",
+        _ => return format!("install {} or point the config at it", program),
+    };
+    let cross = name.contains('-') && !name.ends_with("pkg-config");
+    if cross {
+        format!("install the cross toolchain providing {} (e.g. `sudo apt install {}-{}`)", name, package, name.rsplit_once('-').unwrap().0)
+    } else {
+        format!("`sudo apt install {}`", package)
+    }
+}
+
+/// Programs the configured languages and sections run, with the C/C++ compiler and archiver of
+/// the selected target.
+fn tools(config: &HBuildConfig, path: &Path, opts: &BuildOptions) -> Vec<Tool> {
+    let mut tools = vec![];
+    for lang in &config.specs.languages {
+        match lang.as_str() {
+            "c" | "c++" => {
+                let Some(build) = &config.build else {
+                    continue;
+                };
+                let compiler = opts.compiler.as_ref().or(opts.cross.as_ref().map(|c| &c.compiler)).unwrap_or(&build.compiler);
+                let drivers = language::drivers(build, compiler, opts.compiler.is_none() && opts.cross.is_none());
+                let compiler = if lang == "c" { &drivers.c } else { &drivers.cxx };
+                if !tools.iter().any(|t: &Tool| &t.program == compiler) {
+                    tools.push(Tool::new(compiler, lang));
+                }
+                if build.build_type == "static" || build.static_variant.unwrap_or(false) {
+                    tools.push(Tool::new(opts.cross.as_ref().map_or("ar", |c| c.ar.as_str()), &format!("{} static libraries", lang)));
+                }
+                let sources = expand_globs(path, &build.sources).unwrap_or_default();
+                let fortran = fortran::compiler(build, compiler);
+                if !tools.iter().any(|t| t.program == fortran) && sources.iter().any(|s| fortran::is_fortran(s)) {
+                    tools.push(Tool::new(&fortran, "Fortran sources"));
+                }
+                if !tools.iter().any(|t| t.needed_for == "CUDA sources") && sources.iter().any(|s| cuda::is_cuda(s)) {
+                    let nvcc = cuda::Toolkit::find(config.cuda.as_ref(), compiler).map_or("nvcc".to_string(), |t| t.nvcc);
+                    tools.push(Tool::new(&nvcc, "CUDA sources"));
+                }
+            }
+            "rust" => tools.push(Tool::new("cargo", lang)),
+            "go" => tools.push(Tool::new("go", lang)),
+            "vala" => tools.push(Tool::new("valac", lang)),
+            "odin" => tools.push(Tool::new("odin", lang)),
+            "crystal" => tools.push(Tool::new("crystal", lang)),
+            "zig" => tools.push(Tool::new("zig", lang)),
+            "nim" => tools.push(Tool::new("nim", lang)),
+            "d" if path.join("dub.json").exists() || path.join("dub.sdl").exists() => tools.push(Tool::new("dub", lang)),
+            "d" => tools.push(Tool::new(&dlang::compiler().unwrap_or_else(|_| "ldc2".to_string()), lang)),
+            "python" if path.join("requirements.txt").exists() => tools.push(Tool::new("pip", "python requirements.txt")),
+            _ => {}
+        }
+    }
+    if config.build.as_ref().is_some_and(|b| b.pkg_dependencies.as_ref().is_some_and(|d| !d.is_empty())) {
+        tools.push(Tool::new("pkg-config", "pkg_dependencies"));
+    }
+    if let Some(pb) = &config.protobuf {
+        tools.push(Tool::new(pb.protoc.as_deref().unwrap_or("protoc"), "[protobuf]"));
+    }
+    if let Some(swig) = &config.swig {
+        tools.push(Tool::new(swig.swig.as_deref().unwrap_or("swig"), "[swig]"));
+    }
+    if gettext::is_enabled(config, path) {
+        tools.push(Tool::new("msgfmt", "[gettext]"));
+    }
+    tools
+}
+
+/// First line `program` prints for its version, or `None` when it can't be run.
+fn version(tool: &Tool) -> Option<String> {
+    let output = Command::new(&tool.program).args(tool.version_args).output().ok()?;
+    let text = if output.stdout.is_empty() { output.stderr } else { output.stdout };
+    Some(String::from_utf8_lossy(&text).lines().next().unwrap_or_default().trim().to_string())
+}
+
+/// Checks that the programs the project's build runs are installed and its `pkg_dependencies`
+/// resolve, printing versions and how to fix what is missing. Fails when anything is.
+pub fn run(path: &Path, opts: &BuildOptions) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    println!("{}", format!("Checking the environment for {}", config.metadata.name).blue().bold());
+    let mut problems = 0;
+
+    println!("{}", "Tools:".cyan());
+    let mut have_pkg_config = true;
+    for tool in tools(&config, path, opts) {
+        match version(&tool) {
+            Some(version) => println!("   {} {} ({}): {}", "ok".green(), tool.program, tool.needed_for, version),
+            None => {
+                problems += 1;
+                have_pkg_config &= tool.program != "pkg-config";
+                println!("   {} {} ({}) not found", "missing".red().bold(), tool.program, tool.needed_for);
+                println!("      fix: {}", fix(&tool.program));
+            }
+        }
+    }
+
+    let pkg_deps = config.build.as_ref().and_then(|b| b.pkg_dependencies.as_ref()).filter(|d| !d.is_empty());
+    if let Some(pkg_deps) = pkg_deps.filter(|_| have_pkg_config) {
+        println!("{}", "pkg_dependencies:".cyan());
+        let mode = opts.cross.as_ref().map_or(PkgConfigMode::Host, |c| c.pkg_config.clone());
+        for entry in pkg_deps {
+            let name = pkgdeps::parse(entry)?.name;
+            match pkgdeps::installed(entry, &mode) {
+                Ok(version) => println!("   {} {} {}", "ok".green(), name, version),
+                // Fallbacks are built for the host
+                Err(e) if matches!(mode, PkgConfigMode::Host) && config.pkg_fallbacks.as_ref().is_some_and(|f| f.contains_key(&name)) => {
+                    println!("   {} {}: {}", "fallback".yellow(), name, e);
+                    println!("      hbuild builds it from [pkg_fallbacks.{}] on the next make", name);
+                }
+                Err(e) => {
+                    problems += 1;
+                    println!("   {} {}: {}", "missing".red().bold(), name, e);
+                    let install = match Manager::detect() {
+                        Some(manager) => format!("`{}`", syspkg::display(&manager.install_command(&[manager.package(&name)]))),
+                        None => format!("install the -dev package providing {}.pc", name),
+                    };
+                    println!("      fix: {} or add [pkg_fallbacks.{}]", install, name);
+                }
+            }
+        }
+    }
+
+    if problems > 0 {
+        return Err(format!("{} problem{} found", problems, if problems == 1 { "" } else { "s" }).into());
+    }
+    verbosity::status("No problems found!");
+    Ok(())
+}
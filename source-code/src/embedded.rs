@@ -0,0 +1,54 @@
+use std::path::Path;
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::cross::Cross;
+use crate::args;
+use crate::pkgdeps::PkgConfigMode;
+use crate::{verbosity, Embedded, HBuildConfig};
+
+/// The freestanding toolchain named by `prefix`, e.g. `arm-none-eabi-gcc` and `arm-none-eabi-ar`.
+/// pkg-config is disabled: host `.pc` files never describe libraries for the firmware target.
+pub fn toolchain(embedded: &Embedded, config: &HBuildConfig, path: &Path) -> Cross {
+    let driver = if config.specs.languages.iter().any(|l| l == "c++") { "g++" } else { "gcc" };
+    // The CPU flags also select the multilib at link time, so both sides get them
+    let mut common = embedded.cpu.as_ref().map(|cpu| format!("-mcpu={}", cpu)).unwrap_or_default();
+    for spec in embedded.specs.iter().flatten() {
+        common.push_str(&format!(" --specs={}", spec));
+    }
+    let mut ldflags = common.clone();
+    if let Some(script) = &embedded.linker_script {
+        ldflags.push_str(&format!(" -T{}", args::quote(path.join(script).as_os_str())));
+    }
+    ldflags.push_str(" -Wl,--gc-sections");
+    Cross {
+        triple: embedded.prefix.trim_end_matches('-').to_string(),
+        compiler: format!("{}{}", embedded.prefix, driver),
+        ar: format!("{}ar", embedded.prefix),
+        cflags: format!("{} -ffunction-sections -fdata-sections {}", common, embedded.flags.as_deref().unwrap_or("")),
+        ldflags,
+        pkg_config: PkgConfigMode::Disabled,
+        runner: None,
+    }
+}
+
+/// Writes the flashable images listed in `outputs` (`bin`, `hex`) next to the linked ELF.
+pub fn objcopy(embedded: &Embedded, target: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    for output in embedded.outputs.iter().flatten() {
+        let format = match output.as_str() {
+            "bin" => "binary",
+            "hex" => "ihex",
+            _ => return Err(format!("Unknown embedded output '{}' (expected bin or hex)", output).into()),
+        };
+        let image = target.with_extension(output);
+        verbosity::progress(format!("Writing {}", image.display()).cyan());
+        let status = Command::new(format!("{}objcopy", embedded.prefix))
+        .args(["-O", format])
+        .arg(target)
+        .arg(&image)
+        .status()?;
+        if !status.success() {
+            return Err(format!("objcopy to {} failed", image.display()).into());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::sync::OnceLock;
+use hk_parser::{HkConfig, HkValue};
+use serde_json::Value;
+use crate::HBuildConfig;
+
+/// hbuild's environment before any `[env]` was exported, which config values are expanded from.
+static INHERITED: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// `value` with its `${...}` interpolations replaced:
+/// - `${env:VAR}` by the variable, or nothing when it is unset;
+/// - `${VAR}` by the variable, or left as written when it is unset, so commands run through a shell
+///   (hooks, rules) keep their own variables like `${HBUILD_TARGET}`;
+/// - `${section.key}` by `reference`, another value of the config, itself expanded.
+///
+/// Variables come from the environment hbuild started with, so a `PATH = "${HOME}/bin:${PATH}"`
+/// entry of `[env]` means the same however often the config is parsed and exported.
+pub fn expand(value: &str, reference: &dyn Fn(&str) -> Option<String>) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    expand_nested(value, reference, 0)
+}
+
+fn expand_nested(value: &str, reference: &dyn Fn(&str) -> Option<String>, depth: usize) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if depth > 16 {
+        return Err(format!("References nest too deeply in '{}'; do they refer to each other?", value).into());
+    }
+    let inherited = INHERITED.get_or_init(|| std::env::vars().collect());
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        let name = &rest[start + 2..start + len];
+        match name.strip_prefix("env:") {
+            Some(var) => expanded.push_str(inherited.get(var).map_or("", String::as_str)),
+            None if name.contains('.') => {
+                let referenced = reference(name).ok_or_else(|| format!("Unknown reference ${{{}}}", name))?;
+                expanded.push_str(&expand_nested(&referenced, reference, depth + 1)?);
+            }
+            None => expanded.push_str(inherited.get(name).map_or(&rest[start..start + len + 1], String::as_str)),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Expands every string of an hk config.
+pub fn interpolate_hk(hk: &mut HkConfig) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    fn walk(value: &mut HkValue, reference: &dyn Fn(&str) -> Option<String>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match value {
+            HkValue::String(s) => *s = expand(s, reference)?,
+            HkValue::Array(a) => a.iter_mut().try_for_each(|v| walk(v, reference))?,
+            HkValue::Map(m) => m.values_mut().try_for_each(|v| walk(v, reference))?,
+            _ => {}
+        }
+        Ok(())
+    }
+    let original = hk.clone();
+    let reference = |path: &str| {
+        let mut parts = path.split('.');
+        let mut current = original.get(parts.next()?)?;
+        for part in parts {
+            current = current.as_map().ok()?.get(part)?;
+        }
+        current.as_string().ok()
+    };
+    hk.values_mut().try_for_each(|v| walk(v, &reference))
+}
+
+/// Expands every string of a toml, yaml, json or hcl config.
+pub fn interpolate(config: &mut Value) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    fn walk(value: &mut Value, reference: &dyn Fn(&str) -> Option<String>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match value {
+            Value::String(s) => *s = expand(s, reference)?,
+            Value::Array(a) => a.iter_mut().try_for_each(|v| walk(v, reference))?,
+            Value::Object(m) => m.values_mut().try_for_each(|v| walk(v, reference))?,
+            _ => {}
+        }
+        Ok(())
+    }
+    let original = config.clone();
+    let reference = |path: &str| match original.pointer(&format!("/{}", path.replace('.', "/")))? {
+        Value::String(s) => Some(s.clone()),
+        v @ (Value::Number(_) | Value::Bool(_)) => Some(v.to_string()),
+        _ => None,
+    };
+    walk(config, &reference)
+}
+
+/// The `[env]` variables exported while it lives; dropping it restores what they were before, so a
+/// dependency's `[env]` doesn't leak into the project that built it.
+pub struct Exported {
+    previous: Vec<(String, Option<OsString>)>,
+}
+
+/// Exports the `[env]` entries, already expanded by `parse_config`, into hbuild's own environment,
+/// where every compiler, codegen step and hook it spawns inherits them.
+pub fn export(config: &HBuildConfig) -> Exported {
+    let mut previous = vec![];
+    for (name, value) in config.env.iter().flatten() {
+        previous.push((name.clone(), std::env::var_os(name)));
+        std::env::set_var(name, value);
+    }
+    Exported { previous }
+}
+
+impl Drop for Exported {
+    fn drop(&mut self) {
+        for (name, value) in self.previous.drain(..).rev() {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::{embedded, find_config_file, language, parse_config, pathdep, pkgdeps, staged, target_path, BuildOptions, HBuildConfig};
+
+/// `dirs` in front of the inherited value of `var`.
+fn prepend(var: &str, dirs: &[PathBuf]) -> Result<OsString, Box<dyn std::error::Error + Send + Sync>> {
+    let inherited = env::var_os(var).map(|v| env::split_paths(&v).collect::<Vec<_>>()).unwrap_or_default();
+    Ok(env::join_paths(dirs.iter().cloned().chain(inherited))?)
+}
+
+/// The variables a build of the project sees: the toolchain as `CC`/`CXX`/`AR`, the configured and
+/// pkg-config flags as `CFLAGS`/`CXXFLAGS`/`LDFLAGS`/`LIBS`, what path and staged git dependencies
+/// offer, and search paths reaching fallback builds and the libraries of those dependencies.
+fn environment(config: &HBuildConfig, path: &Path, opts: &BuildOptions) -> Result<Vec<(String, OsString)>, Box<dyn std::error::Error + Send + Sync>> {
+    let project = path.canonicalize()?;
+    let mut vars: Vec<(String, OsString)> = vec![
+        ("HBUILD_PROJECT".to_string(), project.clone().into()),
+        ("HBUILD_BUILD_DIR".to_string(), opts.build_dir(&project).into()),
+    ];
+    let (mut bin_dirs, mut lib_dirs, mut pc_dirs) = (vec![], vec![], vec![]);
+    for name in config.pkg_fallbacks.iter().flat_map(|f| f.keys()) {
+        if let Some(prefix) = pkgdeps::fallback_prefix(name) {
+            pc_dirs.extend(pkgdeps::fallback_pc_dirs(&prefix));
+            lib_dirs.extend([prefix.join("lib"), prefix.join("lib64")]);
+            bin_dirs.push(prefix.join("bin"));
+        }
+    }
+
+    if let Some(build) = &config.build {
+        let embedded = config.embedded.as_ref().filter(|_| opts.cross.is_none()).map(|e| embedded::toolchain(e, config, path));
+        let cross = opts.cross.as_ref().or(embedded.as_ref());
+        let compiler = opts.compiler.as_ref().or(cross.map(|c| &c.compiler)).unwrap_or(&build.compiler);
+        if let Some(dir) = Path::new(compiler).parent().filter(|d| !d.as_os_str().is_empty()) {
+            bin_dirs.push(dir.to_path_buf());
+        }
+        let mut cflags = format!("-{}", opts.optimize.as_ref().unwrap_or(&build.optimize));
+        let mut ldflags = String::new();
+        let mut libs = String::new();
+        for flags in [build.cflags.as_ref(), cross.map(|c| &c.cflags), opts.extra_flags.as_ref()].into_iter().flatten() {
+            cflags.push_str(&format!(" {}", flags));
+        }
+        for flags in [build.ldflags.as_ref(), cross.map(|c| &c.ldflags)].into_iter().flatten() {
+            ldflags.push_str(&format!(" {}", flags));
+        }
+        for dir in &build.include_dirs {
+            cflags.push_str(&format!(" -I{}", project.join(dir).display()));
+        }
+        for dir in build.lib_dirs.iter().flatten() {
+            ldflags.push_str(&format!(" -L{}", project.join(dir).display()));
+            lib_dirs.push(project.join(dir));
+        }
+        for lib in build.libs.iter().flatten() {
+            libs.push_str(&format!(" -l{}", lib));
+        }
+        let pkg_mode = cross.map(|c| c.pkg_config.clone()).unwrap_or_default();
+        for pkg in &pkgdeps::modules(config) {
+            let lib = pkgdeps::resolve(pkg, config.pkg_fallbacks.as_ref(), &pkg_mode)?;
+            for dir in &lib.include_paths {
+                cflags.push_str(&format!(" -I{}", dir.display()));
+            }
+            for (key, val) in &lib.defines {
+                match val {
+                    Some(val) => cflags.push_str(&format!(" -D{}={}", key, val)),
+                    None => cflags.push_str(&format!(" -D{}", key)),
+                }
+            }
+            for flag in &lib.other_cflags {
+                cflags.push_str(&format!(" {}", flag));
+            }
+            for dir in &lib.link_paths {
+                ldflags.push_str(&format!(" -L{}", dir.display()));
+            }
+            for l in &lib.libs {
+                libs.push_str(&format!(" -l{}", l));
+            }
+            for flag in &lib.other_libs {
+                ldflags.push_str(&format!(" {}", flag));
+            }
+        }
+        // As compile_c_cpp links them: the built libraries of path dependencies and the prefixes of staged ones
+        let mut dep_usage = pathdep::usage(config, path)?;
+        dep_usage.extend(staged::usage(config)?);
+        for dir in &dep_usage.include_dirs {
+            cflags.push_str(&format!(" -I{}", dir.display()));
+        }
+        for define in &dep_usage.defines {
+            cflags.push_str(&format!(" -D{}", define));
+        }
+        for dir in &dep_usage.link_dirs {
+            ldflags.push_str(&format!(" -L{}", dir.display()));
+        }
+        for library in &dep_usage.libraries {
+            libs.push_str(&format!(" {}", library.display()));
+        }
+        for lib in &dep_usage.libs {
+            libs.push_str(&format!(" -l{}", lib));
+        }
+        lib_dirs.extend(dep_usage.link_dirs.iter().cloned());
+        lib_dirs.extend(dep_usage.libraries.iter().filter_map(|l| l.parent()).map(Path::to_path_buf));
+        if let Some(pkgdeps::PkgConfigMode::Sysroot { libdirs, sysroot }) = cross.map(|c| &c.pkg_config) {
+            vars.push(("PKG_CONFIG_LIBDIR".to_string(), env::join_paths(libdirs)?));
+            if let Some(sysroot) = sysroot {
+                vars.push(("PKG_CONFIG_SYSROOT_DIR".to_string(), sysroot.into()));
+            }
+        }
+        let drivers = language::drivers(build, compiler, opts.compiler.is_none() && cross.is_none());
+        vars.push(("CC".to_string(), drivers.c.into()));
+        if config.specs.languages.iter().any(|l| l == "c++") {
+            vars.push(("CXX".to_string(), drivers.cxx.into()));
+        }
+        vars.push(("AR".to_string(), cross.map_or("ar", |c| c.ar.as_str()).into()));
+        vars.push(("CFLAGS".to_string(), cflags.clone().into()));
+        vars.push(("CXXFLAGS".to_string(), cflags.into()));
+        vars.push(("LDFLAGS".to_string(), ldflags.trim().into()));
+        vars.push(("LIBS".to_string(), libs.trim().into()));
+        vars.push(("HBUILD_TARGET".to_string(), target_path(build, &project, opts).into()));
+    }
+    vars.push(("PATH".to_string(), prepend("PATH", &bin_dirs)?));
+    vars.push(("LD_LIBRARY_PATH".to_string(), prepend("LD_LIBRARY_PATH", &lib_dirs)?));
+    vars.push(("PKG_CONFIG_PATH".to_string(), prepend("PKG_CONFIG_PATH", &pc_dirs)?));
+    Ok(vars)
+}
+
+/// Runs `command` with the project's build environment and exits with its status.
+pub fn run(path: &Path, opts: &BuildOptions, command: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let (program, args) = command.split_first().ok_or("exec needs a command: hbuild exec <folder> -- <command> [args]")?;
+    let status = Command::new(program).args(args).envs(environment(&config, path, opts)?).status()
+    .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    std::process::exit(status.code().unwrap_or(1));
+}
@@ -0,0 +1,105 @@
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::path::Path;
+use glob::{MatchOptions, Pattern};
+use crate::{args, Build, FileFlags};
+
+struct Entry {
+    patterns: Vec<Pattern>,
+    flags: Vec<OsString>,
+    include_flags: Vec<OsString>,
+    replace: bool,
+    set: bool, // a source set's, which each source belongs to one of at most
+}
+
+fn patterns(files: &[String], context: &str) -> Result<Vec<Pattern>, Box<dyn std::error::Error + Send + Sync>> {
+    files.iter().map(|f| Pattern::new(f.trim_start_matches("./")).map_err(|e| format!("{} {}: {}", context, f, e).into())).collect()
+}
+
+/// The flags particular to some sources: those of the `[[build.source_set]]` they belong to, then
+/// of the `[build.file_flags]` entries matching them, by their path in the project.
+pub struct Overrides {
+    entries: Vec<Entry>,
+    configured: usize, // how many of the C flags come from `[build] cflags`, which `replace` drops
+}
+
+impl Overrides {
+    /// The build's entries, for C flags whose first `configured` come from `[build] cflags`.
+    pub fn new(build: &Build, path: &Path, configured: usize) -> Result<Overrides, Box<dyn std::error::Error + Send + Sync>> {
+        let mut entries = vec![];
+        for set in build.source_set.iter().flatten() {
+            let mut flags = args::split(set.cflags.as_deref().unwrap_or_default());
+            flags.extend(set.defines.iter().flatten().map(|d| OsString::from(format!("-D{}", d))));
+            entries.push(Entry {
+                patterns: patterns(&set.sources, &format!("[[build.source_set]] {}:", set.name))?,
+                flags,
+                include_flags: set.include_dirs.iter().flatten().map(|d| args::with_path("-I", &path.join(d))).collect(),
+                replace: true,
+                set: true,
+            });
+        }
+        for (key, entry) in build.file_flags.iter().flatten() {
+            let (files, flags, replace) = match entry {
+                FileFlags::Append(flags) => (None, flags, false),
+                FileFlags::Table { flags, replace, files } => (files.as_ref(), flags, replace.unwrap_or(false)),
+            };
+            let files = files.map_or_else(|| vec![key.clone()], Clone::clone);
+            entries.push(Entry { patterns: patterns(&files, "[build.file_flags]")?, flags: args::split(flags), include_flags: vec![], replace, set: false });
+        }
+        Ok(Overrides { entries, configured })
+    }
+
+    fn matching<'a>(&'a self, path: &Path, src: &'a Path) -> impl Iterator<Item = &'a Entry> {
+        let relative = src.strip_prefix(path).unwrap_or(src);
+        let options = MatchOptions { require_literal_separator: true, ..MatchOptions::new() };
+        // A source belongs to the first source set matching it only
+        let mut in_set = false;
+        self.entries.iter().filter(move |e| {
+            if !e.patterns.iter().any(|p| p.matches_path_with(relative, options)) || (e.set && in_set) {
+                return false;
+            }
+            in_set |= e.set;
+            true
+        })
+    }
+
+    /// `cflags` for compiling `src`, with the flags of every entry matching it appended in order,
+    /// after dropping the configured ones when any of them replaces those.
+    pub fn cflags<'a>(&self, path: &Path, src: &Path, cflags: &'a [OsString]) -> Cow<'a, [OsString]> {
+        let matching: Vec<&Entry> = self.matching(path, src).collect();
+        if matching.is_empty() {
+            return Cow::Borrowed(cflags);
+        }
+        let replace = matching.iter().any(|e| e.replace);
+        let mut flags = cflags[if replace { self.configured } else { 0 }..].to_vec();
+        flags.extend(matching.iter().flat_map(|e| e.flags.iter().cloned()));
+        Cow::Owned(flags)
+    }
+
+    /// `include_flags` for `src`, with the include directories of its source set searched first.
+    pub fn include_flags<'a>(&self, path: &Path, src: &Path, include_flags: &'a [OsString]) -> Cow<'a, [OsString]> {
+        let mut flags: Vec<OsString> = self.matching(path, src).flat_map(|e| e.include_flags.iter().cloned()).collect();
+        if flags.is_empty() {
+            return Cow::Borrowed(include_flags);
+        }
+        flags.extend(include_flags.iter().cloned());
+        Cow::Owned(flags)
+    }
+
+    /// What scanning `src` for headers takes: its include flags, and the defines its entries add, which
+    /// decide the `#include`s it reaches.
+    pub fn scan_flags<'a>(&self, path: &Path, src: &Path, include_flags: &'a [OsString]) -> Cow<'a, [OsString]> {
+        let defines: Vec<OsString> = self.matching(path, src).flat_map(|e| e.flags.iter()).filter(|f| args::is_define(f)).cloned().collect();
+        let include_flags = self.include_flags(path, src, include_flags);
+        if defines.is_empty() {
+            return include_flags;
+        }
+        Cow::Owned(include_flags.iter().cloned().chain(defines).collect())
+    }
+
+    /// What the entries matching `src` add to its fingerprint, with flag lists rendered by `display`;
+    /// empty for the other sources.
+    pub fn key(&self, path: &Path, src: &Path, display: &dyn Fn(&[OsString]) -> String) -> String {
+        self.matching(path, src).map(|e| format!(" {}{} {}", if e.replace { "replace " } else { "" }, display(&e.flags), display(&e.include_flags))).collect()
+    }
+}
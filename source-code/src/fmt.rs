@@ -0,0 +1,253 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::Lines;
+use hk_parser::parse_hk;
+use owo_colors::OwoColorize;
+use toml_edit::{DocumentMut, Item, Table, Value};
+use crate::{find_config_file, parse_value, CONFIG_FILES};
+
+/// The config files in `path`: the default ones and the named `hbuild.<name>.config` kind, or just
+/// the one `--config` selected.
+fn config_files(path: &Path, selected: bool) -> Result<Vec<(PathBuf, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    if selected {
+        return Ok(find_config_file(path).into_iter().collect());
+    }
+    let mut files = vec![];
+    for entry in fs::read_dir(path)?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let format = CONFIG_FILES.iter().find(|(default, _)| {
+            let stem = default.trim_end_matches(".config");
+            name == *default || (name.starts_with(&format!("{}.", stem)) && name.ends_with(".config"))
+        });
+        if let Some((_, format)) = format {
+            files.push((entry.path(), format.to_string()));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// An hk value with array items separated by `, `, or None past what the parser accepts.
+fn hk_value(value: &str) -> Option<String> {
+    fn array(text: &str) -> Option<(String, &str)> {
+        let mut rest = text.strip_prefix('[')?.trim_start();
+        let mut items: Vec<String> = vec![];
+        while !rest.starts_with(']') {
+            let (item, after) = if rest.starts_with('[') {
+                array(rest)?
+            } else if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted.find('"')? + 2;
+                (rest[..end].to_string(), &rest[end..])
+            } else {
+                let end = rest.find(|c: char| c.is_whitespace() || c == ',' || c == ']').unwrap_or(rest.len());
+                (rest[..end].to_string(), &rest[end..])
+            };
+            items.push(item);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after.trim_start();
+            } else if !rest.starts_with(']') {
+                return None;
+            }
+        }
+        Some((format!("[{}]", items.join(", ")), &rest[1..]))
+    }
+    if !value.starts_with('[') {
+        return Some(value.to_string());
+    }
+    array(value).filter(|(_, rest)| rest.trim().is_empty()).map(|(array, _)| array)
+}
+
+/// The part of an hk entry after its arrow: `key => value`, or just `key` for a map, reading the
+/// following lines of an array spanning several.
+fn hk_entry(entry: &str, lines: &mut Lines) -> Option<String> {
+    let Some((key, value)) = entry.split_once("=>") else {
+        return Some(entry.trim().to_string());
+    };
+    let mut value = value.trim().to_string();
+    if value.starts_with('[') {
+        let depth = |v: &str| v.split('"').step_by(2).map(|s| s.matches('[').count() as i64 - s.matches(']').count() as i64).sum::<i64>();
+        while depth(&value) > 0 {
+            value.push(' ');
+            value.push_str(lines.next()?.trim());
+        }
+    }
+    Some(format!("{} => {}", key.trim(), hk_value(&value)?))
+}
+
+/// An hk config in canonical form: sections apart by one blank line, `->` entries unindented and
+/// `-->` ones under them indented by four spaces, array items separated by `, `. Comments stay
+/// where they are, at the start of their line, where the parser requires them; so do the blank
+/// lines grouping a section's entries, except where no comment may follow one.
+fn format_hk(content: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    parse_hk(content)?;
+    let mut out: Vec<String> = vec![];
+    let mut blank = false;
+    let mut lines = content.lines();
+    let mut number = 0;
+    while let Some(line) = lines.next() {
+        number += 1;
+        let line = line.trim();
+        if line.is_empty() {
+            blank = true;
+            continue;
+        }
+        let grouped = blank && out.last().is_some_and(|l| !l.starts_with('['));
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if !out.is_empty() {
+                out.push(String::new());
+            }
+            out.push(format!("[{}]", name.trim()));
+        } else if line.starts_with('!') {
+            if grouped {
+                out.push(String::new());
+            }
+            out.push(line.to_string());
+        } else if let Some(entry) = line.strip_prefix("-->") {
+            out.push(format!("    --> {}", hk_entry(entry, &mut lines).ok_or(format!("Cannot format line {}", number))?));
+        } else if let Some(entry) = line.strip_prefix("->") {
+            if grouped {
+                out.push(String::new());
+            }
+            out.push(format!("-> {}", hk_entry(entry, &mut lines).ok_or(format!("Cannot format line {}", number))?));
+        } else {
+            return Err(format!("Cannot format line {}", number).into());
+        }
+        blank = false;
+    }
+    Ok(format!("{}\n", out.join("\n")))
+}
+
+/// A TOML decor prefix reduced to its comment lines, unindented, after a blank line when `blank`.
+fn toml_prefix(raw: Option<&str>, blank: bool) -> String {
+    let comments: String = raw.unwrap_or_default().lines().map(str::trim).filter(|l| l.starts_with('#')).map(|l| format!("{}\n", l)).collect();
+    format!("{}{}", if blank { "\n" } else { "" }, comments)
+}
+
+/// True when a TOML decor prefix starts on or holds an empty line.
+fn toml_blank(raw: Option<&str>) -> bool {
+    let raw = raw.unwrap_or_default();
+    raw.split('\n').rev().skip(1).any(|l| l.trim().is_empty())
+}
+
+/// A TOML decor suffix reduced to its trailing comment, one space after what it follows.
+fn toml_suffix(raw: Option<&str>) -> String {
+    let raw = raw.unwrap_or_default().trim();
+    if raw.starts_with('#') { format!(" {}", raw) } else { String::new() }
+}
+
+/// Single spaces inside single-line arrays and inline tables, nested ones included.
+fn format_toml_value(value: &mut Value) {
+    match value {
+        Value::Array(array) if !array.to_string().contains('\n') => {
+            array.iter_mut().for_each(format_toml_value);
+            array.fmt();
+        }
+        Value::InlineTable(table) => {
+            table.iter_mut().for_each(|(_, v)| format_toml_value(v));
+            table.fmt();
+        }
+        _ => {}
+    }
+}
+
+/// Canonical spacing for a TOML table and those in it: a blank line before each header, none
+/// after it, `key = value` with single spaces and single-line arrays as `[a, b]`. Comments and the
+/// blank lines grouping keys are kept; multi-line arrays stay as written.
+fn format_toml_table(table: &mut Table, root: bool) {
+    if !root && !table.is_dotted() {
+        let decor = table.decor_mut();
+        let prefix = toml_prefix(decor.prefix().and_then(|p| p.as_str()), true);
+        let suffix = toml_suffix(decor.suffix().and_then(|s| s.as_str()));
+        decor.set_prefix(prefix);
+        decor.set_suffix(suffix);
+    }
+    for (index, (mut key, item)) in table.iter_mut().enumerate() {
+        let decor = key.leaf_decor_mut();
+        let raw = decor.prefix().and_then(|p| p.as_str());
+        let prefix = toml_prefix(raw, index > 0 && toml_blank(raw));
+        decor.set_prefix(prefix);
+        match item {
+            Item::Value(value) => {
+                key.leaf_decor_mut().set_suffix(" ");
+                format_toml_value(value);
+                let decor = value.decor_mut();
+                let suffix = toml_suffix(decor.suffix().and_then(|s| s.as_str()));
+                decor.set_prefix(" ");
+                decor.set_suffix(suffix);
+            }
+            Item::Table(table) => format_toml_table(table, false),
+            Item::ArrayOfTables(tables) => tables.iter_mut().for_each(|t| format_toml_table(t, false)),
+            Item::None => {}
+        }
+    }
+}
+
+fn format_toml(content: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let mut doc: DocumentMut = content.parse()?;
+    format_toml_table(doc.as_table_mut(), true);
+    let raw = doc.trailing().as_str();
+    let trailing = toml_prefix(raw, toml_blank(raw));
+    doc.set_trailing(trailing);
+    Ok(format!("{}\n", doc.to_string().trim()))
+}
+
+/// How many comments `content` seems to hold, to notice a rewrite losing some.
+fn comment_marks(content: &str, format: &str) -> usize {
+    let marks: &[&str] = if format == "hcl" { &["#", "//", "/*"] } else { &["#"] };
+    marks.iter().map(|m| content.matches(m).count()).sum()
+}
+
+/// `content` in canonical form for its format. YAML, JSON and HCL are written back from their
+/// parsed value, which keeps key order but not comments, so a YAML or HCL file with comments is
+/// left for hand formatting.
+pub fn format_config(content: &str, format: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let formatted = match format {
+        "hk" => format_hk(content)?,
+        "toml" => format_toml(content)?,
+        "yaml" => serde_yaml::to_string(&serde_yaml::from_str::<serde_yaml::Value>(content)?)?,
+        "json" => format!("{}\n", serde_json::to_string_pretty(&serde_json::from_str::<serde_json::Value>(content)?)?),
+        "hcl" => hcl::format::to_string(&hcl::parse(content)?)?,
+        _ => return Err("Unknown format".into()),
+    };
+    if comment_marks(&formatted, format) < comment_marks(content, format) {
+        return Err(format!("Formatting would drop comments, which the {} parser does not keep", format).into());
+    }
+    // Formatting only ever changes layout; anything else is a formatter bug to stop at
+    let same = if format == "hk" { parse_hk(&formatted)? == parse_hk(content)? } else { parse_value(&formatted, format)? == parse_value(content, format)? };
+    if !same {
+        return Err("Formatting would change the config's meaning; leaving it as is".into());
+    }
+    Ok(formatted)
+}
+
+/// Formats the project's config files in place, or with `check` lists those not in canonical form
+/// and fails if there are any, for CI.
+pub fn run(path: &Path, selected: bool, check: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let files = config_files(path, selected)?;
+    if files.is_empty() {
+        return Err(format!("No hbuild config in {}", path.display()).into());
+    }
+    let mut unformatted = 0;
+    for (file, format) in &files {
+        let content = fs::read_to_string(file)?;
+        let formatted = format_config(&content, format).map_err(|e| format!("{}: {}", file.display(), e))?;
+        if formatted == content {
+            continue;
+        }
+        if check {
+            println!("{}", format!("{} is not formatted", file.display()).yellow());
+            unformatted += 1;
+        } else {
+            fs::write(file, formatted)?;
+            println!("{}", format!("Formatted {}", file.display()).green());
+        }
+    }
+    if unformatted > 0 {
+        return Err(format!("{} of {} config files not formatted; run hbuild fmt", unformatted, files.len()).into());
+    }
+    if check {
+        println!("{}", "Config files are formatted".green().bold());
+    }
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::{args, Build};
+
+/// Fortran sources are recognized by extension; the upper-case ones go through the preprocessor.
+const EXTENSIONS: &[&str] = &["f", "for", "ftn", "f77", "f90", "f95", "f03", "f08", "F", "FOR", "F90", "F95", "F03", "F08"];
+
+pub fn is_fortran(src: &Path) -> bool {
+    src.extension().is_some_and(|e| EXTENSIONS.iter().any(|x| e == *x))
+}
+
+/// `[build] fortran_compiler`, else the gfortran matching the C compiler, so `aarch64-linux-gnu-gcc`
+/// pairs with `aarch64-linux-gnu-gfortran` and `gcc-13` with `gfortran-13`.
+pub fn compiler(build: &Build, c_compiler: &str) -> String {
+    if let Some(compiler) = &build.fortran_compiler {
+        return compiler.clone();
+    }
+    let (dir, name) = match c_compiler.rsplit_once('/') {
+        Some((dir, name)) => (format!("{}/", dir), name),
+        None => (String::new(), c_compiler),
+    };
+    match ["gcc", "g++"].iter().find(|gcc| name.contains(**gcc)) {
+        Some(gcc) => format!("{}{}", dir, name.replacen(gcc, "gfortran", 1)),
+        None => "gfortran".to_string(),
+    }
+}
+
+/// The modules a source defines and uses, and the files it includes.
+#[derive(Debug, Default)]
+struct Unit {
+    provides: Vec<String>,
+    uses: Vec<String>,
+    includes: Vec<PathBuf>,
+}
+
+/// Which source provides each module, so a source is compiled after those of the modules it uses:
+/// gfortran reads a module's interface from the `.mod` file compiling its source writes.
+#[derive(Debug, Default)]
+pub struct Modules {
+    units: HashMap<PathBuf, Unit>,
+    providers: HashMap<String, PathBuf>,
+}
+
+/// Strips a free-form `!` comment, leaving ones inside string literals.
+fn code(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '!') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// The name a statement starts with, lower-cased like Fortran's case-insensitive names.
+fn name(rest: &str) -> Option<String> {
+    let name: String = rest.trim_start().chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '_').collect();
+    (!name.is_empty()).then(|| name.to_ascii_lowercase())
+}
+
+fn scan(src: &Path, include_dirs: &[PathBuf]) -> Result<Unit, Box<dyn std::error::Error + Send + Sync>> {
+    let text = fs::read_to_string(src).map_err(|e| format!("{}: {}", src.display(), e))?;
+    let fixed_form = src.extension().is_some_and(|e| ["f", "for", "ftn", "f77", "F", "FOR"].iter().any(|x| e == *x));
+    let mut unit = Unit::default();
+    for line in text.lines() {
+        // Fixed form marks whole-line comments in the first column
+        if fixed_form && line.starts_with(['c', 'C', '*']) {
+            continue;
+        }
+        let original = code(line).trim();
+        // Lower-casing ASCII keeps byte offsets, so slices of `line` line up with `original`
+        let line = original.to_ascii_lowercase();
+        if let Some(rest) = line.strip_prefix("module ") {
+            // `module procedure` and the separate module procedures of submodules define no module
+            if let Some(module) = name(rest).filter(|m| !matches!(m.as_str(), "procedure" | "function" | "subroutine")) {
+                unit.provides.push(module);
+            }
+        } else if let Some(rest) = line.strip_prefix("submodule") {
+            // `submodule (ancestor:parent) name` needs the ancestor's interface
+            if let Some(ancestor) = rest.trim_start().strip_prefix('(').and_then(name) {
+                unit.uses.push(ancestor);
+            }
+        } else if let Some(rest) = line.strip_prefix("use").filter(|r| r.starts_with([' ', ',', ':'])) {
+            let rest = rest.trim_start();
+            // Intrinsic modules ship with the compiler
+            if rest.starts_with(',') && !rest.contains("non_intrinsic") {
+                continue;
+            }
+            unit.uses.extend(name(rest.split_once("::").map_or(rest, |(_, r)| r)));
+        } else if line.starts_with("include") {
+            let file = original["include".len()..].trim();
+            if let Some(file) = file.strip_prefix(['\'', '"']).and_then(|f| f.strip_suffix(['\'', '"'])) {
+                let dirs = src.parent().into_iter().map(Path::to_path_buf).chain(include_dirs.iter().cloned());
+                unit.includes.extend(dirs.map(|d| d.join(file)).find(|p| p.exists()));
+            }
+        }
+    }
+    Ok(unit)
+}
+
+impl Modules {
+    /// Scans the Fortran ones among `sources` for their modules and includes.
+    pub fn scan(sources: &[PathBuf], include_dirs: &[PathBuf]) -> Result<Modules, Box<dyn std::error::Error + Send + Sync>> {
+        let mut modules = Modules::default();
+        for src in sources.iter().filter(|s| is_fortran(s)) {
+            let unit = scan(src, include_dirs)?;
+            for module in &unit.provides {
+                if let Some(other) = modules.providers.insert(module.clone(), src.clone()) {
+                    return Err(format!("Fortran module {} is defined in both {} and {}", module, other.display(), src.display()).into());
+                }
+            }
+            modules.units.insert(src.clone(), unit);
+        }
+        Ok(modules)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.units.is_empty()
+    }
+
+    /// What `src` is built from besides itself: its includes, and the sources of the project's
+    /// modules it uses. Modules the project doesn't define come from libraries and aren't tracked.
+    pub fn dependencies(&self, src: &Path) -> HashSet<PathBuf> {
+        let Some(unit) = self.units.get(src) else {
+            return HashSet::new();
+        };
+        let mut deps: HashSet<PathBuf> = unit.includes.iter().cloned().collect();
+        deps.extend(unit.uses.iter().filter_map(|m| self.providers.get(m)).filter(|p| p.as_path() != src).cloned());
+        deps
+    }
+
+    /// Whether `src` defines modules. Restoring only its object from the cache would leave their
+    /// `.mod` files missing, so those sources are always compiled.
+    pub fn provides_modules(&self, src: &Path) -> bool {
+        self.units.get(src).is_some_and(|u| !u.provides.is_empty())
+    }
+
+    /// Whether a `.mod` file `src` writes is missing from `build_dir`, e.g. after it was cleaned by hand.
+    pub fn missing_modules(&self, src: &Path, build_dir: &Path) -> bool {
+        self.units.get(src).is_some_and(|u| u.provides.iter().any(|m| !build_dir.join(format!("{}.mod", m)).exists()))
+    }
+
+    /// `to_compile` split into batches to compile in order, each only using modules of earlier
+    /// batches or of sources that are already up to date. C and C++ sources all go in the first.
+    pub fn batches(&self, to_compile: &[PathBuf]) -> Result<Vec<Vec<PathBuf>>, Box<dyn std::error::Error + Send + Sync>> {
+        let pending: HashSet<&PathBuf> = to_compile.iter().collect();
+        let mut done: HashSet<PathBuf> = HashSet::new();
+        let mut batches = vec![];
+        while done.len() < to_compile.len() {
+            let batch: Vec<PathBuf> = to_compile.iter()
+            .filter(|s| !done.contains(*s))
+            .filter(|s| self.dependencies(s).iter().all(|d| !pending.contains(d) || done.contains(d)))
+            .cloned().collect();
+            if batch.is_empty() {
+                let cycle: Vec<String> = to_compile.iter().filter(|s| !done.contains(*s)).map(|s| s.display().to_string()).collect();
+                return Err(format!("Fortran modules use each other in a cycle: {}", cycle.join(", ")).into());
+            }
+            done.extend(batch.iter().cloned());
+            batches.push(batch);
+        }
+        Ok(batches)
+    }
+}
+
+/// gfortran's arguments compiling `src` to `obj`: the C flags and include directories apply as
+/// well, and module files are written to and read from `build_dir`.
+pub fn compile_args(opt_flag: &str, cflags: &[OsString], include_flags: &[OsString], build_dir: &Path, src: &Path, obj: &Path) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![opt_flag.into()];
+    args.extend(cflags.iter().cloned());
+    args.extend(include_flags.iter().cloned());
+    args.push(args::with_path("-J", build_dir));
+    args.push(args::with_path("-I", build_dir));
+    args.extend(["-c".into(), src.into(), "-o".into(), obj.into()]);
+    args
+}
@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use glob::glob;
+use owo_colors::OwoColorize;
+use crate::{expand_globs, install, is_stale, verbosity, HBuildConfig};
+
+struct Settings {
+    domain: String,
+    po_dir: PathBuf,
+    keywords: Vec<String>,
+    sources: Vec<String>,
+}
+
+fn settings(config: &HBuildConfig, path: &Path) -> Settings {
+    let gettext = config.gettext.as_ref();
+    Settings {
+        domain: gettext.and_then(|g| g.domain.clone()).unwrap_or_else(|| config.metadata.name.clone()),
+        po_dir: path.join(gettext.and_then(|g| g.po_dir.clone()).unwrap_or_else(|| "po".to_string())),
+        keywords: gettext.and_then(|g| g.keywords.clone()).unwrap_or_else(|| vec!["_".to_string(), "N_".to_string()]),
+        sources: gettext.and_then(|g| g.sources.clone())
+        .or_else(|| config.build.as_ref().map(|b| b.sources.clone()))
+        .unwrap_or_default(),
+    }
+}
+
+/// Languages with a translation catalog, as (language, .po path) pairs.
+fn catalogs(po_dir: &Path) -> Result<Vec<(String, PathBuf)>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut result = vec![];
+    if !po_dir.is_dir() {
+        return Ok(result);
+    }
+    for entry in glob(po_dir.join("*.po").to_str().ok_or("Invalid path")?)? {
+        let po = entry?;
+        if let Some(lang) = po.file_stem().map(|s| s.to_string_lossy().to_string()) {
+            result.push((lang, po));
+        }
+    }
+    Ok(result)
+}
+
+/// True when the project has a translation directory and the gettext steps should run.
+pub fn is_enabled(config: &HBuildConfig, path: &Path) -> bool {
+    settings(config, path).po_dir.is_dir()
+}
+
+/// Compiles every `po/<lang>.po` into `build/locale/<lang>/LC_MESSAGES/<domain>.mo`, skipping up-to-date catalogs.
+pub fn compile_catalogs(config: &HBuildConfig, path: &Path, build_dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let settings = settings(config, path);
+    for (lang, po) in catalogs(&settings.po_dir)? {
+        let mo_dir = build_dir.join("locale").join(&lang).join("LC_MESSAGES");
+        let mo = mo_dir.join(format!("{}.mo", settings.domain));
+        if !is_stale(&po, &mo) {
+            continue;
+        }
+        fs::create_dir_all(&mo_dir)?;
+        verbosity::progress(format!("Compiling translation {}", lang).cyan());
+        let output = Command::new("msgfmt").arg("--check").arg("-o").arg(&mo).arg(&po).output()?;
+        if !output.status.success() {
+            eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
+            return Err(format!("msgfmt failed for {}", po.display()).into());
+        }
+    }
+    Ok(())
+}
+
+/// Extracts translatable strings into `po/<domain>.pot` with xgettext and merges them into existing catalogs.
+pub fn extract(config: &HBuildConfig, path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let settings = settings(config, path);
+    let inputs: Vec<PathBuf> = expand_globs(path, &settings.sources)?
+    .into_iter()
+    .map(|entry| entry.strip_prefix(path).map(Path::to_path_buf).unwrap_or(entry))
+    .collect();
+    if inputs.is_empty() {
+        return Err("No sources to extract strings from (set gettext.sources or build.sources)".into());
+    }
+    fs::create_dir_all(&settings.po_dir)?;
+    let pot = settings.po_dir.join(format!("{}.pot", settings.domain));
+    verbosity::progress(format!("Extracting strings into {}", pot.display()).blue().bold());
+    let status = Command::new("xgettext")
+    .args(["--from-code=UTF-8", "--add-comments=TRANSLATORS:"])
+    .args(settings.keywords.iter().map(|k| format!("--keyword={}", k)))
+    .arg(format!("--package-name={}", config.metadata.name))
+    .arg(format!("--package-version={}", config.metadata.version))
+    .arg("-o")
+    .arg(&pot)
+    .args(&inputs)
+    .current_dir(path)
+    .status()?;
+    if !status.success() {
+        return Err("xgettext failed".into());
+    }
+    for (lang, po) in catalogs(&settings.po_dir)? {
+        verbosity::progress(format!("Updating translation {}", lang).cyan());
+        let status = Command::new("msgmerge").args(["--update", "--backup=none"]).arg(&po).arg(&pot).status()?;
+        if !status.success() {
+            return Err(format!("msgmerge failed for {}", po.display()).into());
+        }
+    }
+    verbosity::status("Extraction complete!");
+    Ok(())
+}
+
+/// Copies compiled catalogs into `<prefix>/share/locale/<lang>/LC_MESSAGES`.
+pub fn install_catalogs(config: &HBuildConfig, path: &Path, build_dir: &Path, prefix: &Path, manifest: &mut install::Manifest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let settings = settings(config, path);
+    for (lang, _) in catalogs(&settings.po_dir)? {
+        let file = format!("{}.mo", settings.domain);
+        let mo = build_dir.join("locale").join(&lang).join("LC_MESSAGES").join(&file);
+        if !mo.exists() {
+            eprintln!("{}", format!("Translation {} not built", lang).yellow());
+            continue;
+        }
+        let dest_dir = prefix.join("share/locale").join(&lang).join("LC_MESSAGES");
+        manifest.create_dir_all(&dest_dir)?;
+        manifest.copy(&mo, &dest_dir.join(&file))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,227 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use dirs::home_dir;
+use git2::build::CheckoutBuilder;
+use git2::{Oid, Repository};
+use owo_colors::OwoColorize;
+use crate::{cmake, credentials, find_config_file, lock, make, makedep, meson, parse_config, vendor, verbosity, Dependency};
+
+/// What a git dependency checks out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GitRef {
+    DefaultBranch,
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
+impl GitRef {
+    /// How the ref is recorded in `hbuild.lock`; the default branch isn't, so older locks stay valid.
+    pub fn lock_key(&self) -> Option<String> {
+        (*self != GitRef::DefaultBranch).then(|| self.to_string())
+    }
+}
+
+impl fmt::Display for GitRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GitRef::DefaultBranch => write!(f, "default branch"),
+            GitRef::Branch(branch) => write!(f, "branch {}", branch),
+            GitRef::Tag(tag) => write!(f, "tag {}", tag),
+            GitRef::Rev(rev) => write!(f, "rev {}", rev),
+        }
+    }
+}
+
+/// Where a git dependency comes from.
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub url: String,
+    pub reference: GitRef,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.reference {
+            GitRef::DefaultBranch => write!(f, "{}", self.url),
+            reference => write!(f, "{} ({})", self.url, reference),
+        }
+    }
+}
+
+/// Where git dependencies are cloned, shared by all projects.
+pub fn cache_dir() -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(home_dir().ok_or("Cannot find home directory")?.join(".hbuild/cache"))
+}
+
+/// Where dependency `name` is built from: its copy in the project's `vendor/`, else the cache.
+pub fn dep_dir(name: &str) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    match vendor::dir(name) {
+        Some(dir) => Ok(dir),
+        None => Ok(cache_dir()?.join(name)),
+    }
+}
+
+/// Builds dependency `name`, checked out in `dep_dir` at `commit`: with its `build_command` when it
+/// has one, with hbuild when it is an hbuild project, else with its own build system into its staging
+/// prefix. Anything else is left as is.
+pub fn build(name: &str, dep: &Dependency, dep_dir: &Path, commit: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (command, outputs) = match dep {
+        Dependency::Git { build_command, outputs, .. } => (build_command.as_deref(), outputs.as_deref().unwrap_or_default()),
+        _ => (None, &[][..]),
+    };
+    if command.is_some() {
+        makedep::build_dependency(name, dep_dir, commit, command, outputs)
+    } else if find_config_file(dep_dir).is_some() {
+        make(dep_dir, &Arc::new(Mutex::new(Vec::new())))
+    } else if dep_dir.join("CMakeLists.txt").exists() {
+        cmake::build_dependency(name, dep_dir, commit)
+    } else if dep_dir.join("meson.build").exists() {
+        meson::build_dependency(name, dep_dir, commit)
+    } else if makedep::has_makefile(dep_dir) {
+        makedep::build_dependency(name, dep_dir, commit, None, outputs)
+    } else {
+        Ok(())
+    }
+}
+
+/// `credentials::fetch`, refused under `--offline`.
+fn fetch(repo: &Repository, name: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if vendor::offline() {
+        return Err(vendor::offline_error(&format!("Fetching {}", name)));
+    }
+    credentials::fetch(repo)
+}
+
+/// The tip of the remote's default branch, falling back to master and main for clones without origin/HEAD.
+fn remote_head(repo: &Repository) -> Result<Oid, Box<dyn std::error::Error + Send + Sync>> {
+    for name in ["refs/remotes/origin/HEAD", "refs/remotes/origin/master", "refs/remotes/origin/main"] {
+        if let Ok(commit) = repo.find_reference(name).and_then(|r| r.resolve()).and_then(|r| r.peel_to_commit()) {
+            return Ok(commit.id());
+        }
+    }
+    Err("Cannot find the remote's default branch".into())
+}
+
+/// The commit `reference` names among the fetched branches and tags, or in the history.
+fn resolve(repo: &Repository, reference: &GitRef) -> Option<Oid> {
+    let commit = match reference {
+        GitRef::DefaultBranch => return remote_head(repo).ok(),
+        GitRef::Branch(branch) => repo.find_reference(&format!("refs/remotes/origin/{}", branch)).and_then(|r| r.peel_to_commit()),
+        GitRef::Tag(tag) => repo.find_reference(&format!("refs/tags/{}", tag)).and_then(|r| r.peel_to_commit()),
+        GitRef::Rev(rev) => repo.revparse_single(rev).and_then(|o| o.peel_to_commit()),
+    };
+    commit.ok().map(|c| c.id())
+}
+
+fn checkout(repo: &Repository, oid: Oid) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let commit = repo.find_commit(oid)?;
+    repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().force()))?;
+    repo.set_head_detached(oid)?;
+    Ok(())
+}
+
+/// Clones dependency `name` from its source into the cache if needed and checks out its locked commit.
+/// Without one, or with `update`, the commit of the source's ref is fetched and locked instead. Returns
+/// the commit.
+pub fn sync(name: &str, source: &Source, lockfile: &mut lock::Lockfile, update: bool) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let url = source.url.as_str();
+    let cache = cache_dir()?;
+    fs::create_dir_all(&cache)?;
+    let dep_dir = cache.join(name);
+    let fresh = !dep_dir.exists();
+    if fresh {
+        // Clone next to the cache entry so an interrupted clone is never mistaken for a complete one
+        let partial = cache.join(format!("{}.partial", name));
+        if partial.exists() {
+            fs::remove_dir_all(&partial)?;
+        }
+        if vendor::offline() {
+            return Err(format!("{} is neither vendored nor cached; run `hbuild vendor` while online", name).into());
+        }
+        credentials::clone(url, &partial)?;
+        fs::rename(&partial, &dep_dir)?;
+    }
+    let repo = Repository::open(&dep_dir)?;
+    // A lock for a different URL or ref is stale: the dependency was pointed elsewhere in the config
+    let reference = source.reference.lock_key();
+    let locked = lockfile.git.get(name).filter(|l| l.url == url && l.reference == reference && !update).map(|l| l.commit.clone());
+    let oid = match locked {
+        Some(commit) => {
+            let oid = Oid::from_str(&commit)?;
+            if repo.find_commit(oid).is_err() {
+                fetch(&repo, name)?;
+            }
+            if repo.find_commit(oid).is_err() {
+                return Err(format!("Locked commit {} of {} is not in {}; run `hbuild update` to relock", commit, name, url).into());
+            }
+            oid
+        }
+        None => {
+            let missing = || format!("The {} of {} does not exist in {}", source.reference, name, url);
+            // A commit can't move, so one already fetched isn't fetched again
+            let pinned = matches!(source.reference, GitRef::Rev(_)) && resolve(&repo, &source.reference).is_some();
+            if !fresh && !pinned {
+                fetch(&repo, name)?;
+            }
+            match resolve(&repo, &source.reference) {
+                Some(oid) => oid,
+                // A fresh clone only has the tags on its branches
+                None if fresh => {
+                    fetch(&repo, name)?;
+                    resolve(&repo, &source.reference).ok_or_else(missing)?
+                }
+                None => return Err(missing().into()),
+            }
+        }
+    };
+    if repo.head().ok().and_then(|h| h.target()) != Some(oid) {
+        checkout(&repo, oid)?;
+    }
+    let commit = oid.to_string();
+    lockfile.git.insert(name.to_string(), lock::GitLock { url: url.to_string(), reference, commit: commit.clone() });
+    Ok(commit)
+}
+
+/// Moves the locked git dependencies, or just `names`, to the latest commit of their ref, rebuilds
+/// the ones that changed, vendored ones from a fresh copy in `vendor/`, and rewrites `hbuild.lock`.
+pub fn update(path: &Path, names: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let git_deps: Vec<(&String, &Dependency, Source)> = config.specs.dependencies.iter().filter_map(|(name, dep)| Some((name, dep, dep.git()?))).collect();
+    for name in names {
+        if !git_deps.iter().any(|(dep, _, _)| *dep == name) {
+            return Err(format!("'{}' is not a git dependency of this project", name).into());
+        }
+    }
+    let mut lockfile = lock::read(path)?;
+    verbosity::progress("Updating git dependencies".blue().bold());
+    for (name, dep, source) in git_deps {
+        if !names.is_empty() && !names.contains(name) {
+            continue;
+        }
+        let old = lockfile.git.get(name.as_str()).map(|l| l.commit.clone());
+        let new = sync(name, &source, &mut lockfile, true)?;
+        match &old {
+            Some(old) if *old == new => {
+                verbosity::progress(format!("   {} {}", name, "up to date".green()));
+                continue;
+            }
+            Some(old) => verbosity::progress(format!("   {} {} -> {}", name.cyan(), lock::short(old), lock::short(&new))),
+            None => verbosity::progress(format!("   {} locked at {}", name.cyan(), lock::short(&new))),
+        }
+        vendor::refresh(name, &lockfile.git[name.as_str()])?;
+        build(name, dep, &dep_dir(name)?, &new)?;
+    }
+    lock::write(path, &lockfile)?;
+    verbosity::progress(format!("Wrote {}", lock::path(path).display()).green().bold());
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use git2::{Repository, StatusOptions};
+use serde::{Deserialize, Serialize};
+use crate::{ignore, mtime};
+
+fn state_path(build_dir: &Path) -> PathBuf {
+    build_dir.join("git-state")
+}
+
+/// The working tree as git sees it.
+struct Tree {
+    repo: Repository,
+    workdir: PathBuf,
+    project: PathBuf,
+    cwd: PathBuf,
+    ignore: ignore::Ignore,
+    /// HEAD plus every path git reports as modified or untracked, with its mtime. Two builds with the
+    /// same snapshot saw the same working tree.
+    snapshot: String,
+    /// Untracked directories, which git lists as a whole
+    untracked: Vec<PathBuf>,
+}
+
+impl Tree {
+    /// None outside a git repository.
+    fn read(path: &Path) -> Option<Tree> {
+        let repo = Repository::discover(path).ok()?;
+        let workdir = repo.workdir()?.to_path_buf();
+        let head = repo.head().ok().and_then(|h| h.target()).map(|id| id.to_string()).unwrap_or_default();
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(true).include_ignored(false);
+        let ignore = ignore::Ignore::load(path);
+        let mut snapshot = format!("{}\n", head);
+        let mut untracked = vec![];
+        for entry in repo.statuses(Some(&mut opts)).ok()?.iter() {
+            let file = entry.path()?;
+            // Editor temp files and other ignored junk don't invalidate the build
+            if ignore.is_ignored(path, &workdir.join(file)) {
+                continue;
+            }
+            // Untracked directories are listed as a whole; their contents are not tracked either
+            if file.ends_with('/') {
+                snapshot.push_str(&format!("{:?}\t{}\n", entry.status(), file));
+                untracked.push(workdir.join(file));
+                continue;
+            }
+            let modified = nanos(&workdir.join(file));
+            snapshot.push_str(&format!("{:?}\t{}\t{}\n", entry.status(), modified, file));
+        }
+        Some(Tree { repo, workdir, project: path.to_path_buf(), cwd: std::env::current_dir().ok()?, ignore, snapshot, untracked })
+    }
+
+    /// True when the snapshot reflects any change to `file`: it is in the repository, not ignored by
+    /// git or `.hbuildignore`, and not in an untracked directory. Other files are checked by mtime.
+    fn covers(&self, file: &Path) -> bool {
+        let file = self.cwd.join(file);
+        let Ok(relative) = file.strip_prefix(&self.workdir) else {
+            return false;
+        };
+        !self.untracked.iter().any(|dir| file.starts_with(dir))
+        && !self.ignore.is_ignored(&self.project, &file)
+        && !self.repo.is_path_ignored(relative).unwrap_or(true)
+    }
+}
+
+fn nanos(file: &Path) -> u128 {
+    mtime(file).duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0)
+}
+
+/// The include graph and content hashes the last successful build used, with what they were read under.
+#[derive(Serialize, Deserialize)]
+struct Scan {
+    snapshot: String,
+    key: String,
+    /// Inputs the snapshot doesn't cover, by mtime
+    mtimes: BTreeMap<PathBuf, u128>,
+    deps: HashMap<PathBuf, HashSet<PathBuf>>,
+    contents: HashMap<PathBuf, String>,
+}
+
+/// What the last successful build read of the sources and headers, reusable as is.
+pub struct Saved {
+    pub deps: HashMap<PathBuf, HashSet<PathBuf>>,
+    /// Contents hashes by file, as `state::fingerprint` caches them
+    pub contents: HashMap<PathBuf, String>,
+}
+
+/// The include graph and content hashes recorded by the last successful build, when git reports
+/// exactly the same changes as then, it was scanned with the same sources, compilers and flags
+/// (`key`), and no input git doesn't track, e.g. a dependency's header, was modified since. Inputs
+/// git tracks are neither read nor stat'd: an unchanged snapshot means they are unchanged. Objects
+/// are still fingerprinted, from these hashes, and the target relinked as usual.
+pub fn load(path: &Path, build_dir: &Path, key: &str) -> Option<Saved> {
+    let scan: Scan = serde_json::from_str(&fs::read_to_string(state_path(build_dir)).ok()?).ok()?;
+    if scan.key != key || Tree::read(path)?.snapshot != scan.snapshot {
+        return None;
+    }
+    scan.mtimes.iter().all(|(file, modified)| nanos(file) == *modified).then_some(Saved { deps: scan.deps, contents: scan.contents })
+}
+
+/// Records the working tree state, the include graph scanned under `key` and the inputs' `contents`
+/// hashes after a successful build.
+pub fn record(path: &Path, build_dir: &Path, key: &str, deps: &HashMap<PathBuf, HashSet<PathBuf>>, contents: &HashMap<PathBuf, String>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(tree) = Tree::read(path) else {
+        let _ = fs::remove_file(state_path(build_dir));
+        return Ok(());
+    };
+    let mtimes = deps.iter().flat_map(|(file, included)| std::iter::once(file).chain(included))
+    .filter(|file| !tree.covers(file))
+    .map(|file| (file.clone(), nanos(file)))
+    .collect();
+    let scan = Scan { snapshot: tree.snapshot, key: key.to_string(), mtimes, deps: deps.clone(), contents: contents.clone() };
+    fs::write(state_path(build_dir), serde_json::to_string(&scan)?)?;
+    Ok(())
+}
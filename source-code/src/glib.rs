@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::{expand_globs, install, is_stale, mtime, verbosity, Generated, Glib};
+
+/// `data/app.gresource.xml` -> `app`
+fn base_name(file: &Path, suffix: &str) -> String {
+    let name = file.file_name().unwrap().to_string_lossy().to_string();
+    name.strip_suffix(suffix).map(str::to_string).unwrap_or(name)
+}
+
+fn run(cmd: &mut Command, what: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let out = cmd.output()?;
+    if !out.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&out.stderr).red());
+        return Err(format!("{} failed for {}", program, what.display()).into());
+    }
+    Ok(())
+}
+
+/// Files referenced by a gresource XML, relative to its source directory.
+fn resource_inputs(xml: &Path, source_dir: &Path) -> Vec<PathBuf> {
+    let out = Command::new("glib-compile-resources")
+    .arg("--generate-dependencies")
+    .arg(format!("--sourcedir={}", source_dir.display()))
+    .arg(xml)
+    .output();
+    match out {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout).lines().map(PathBuf::from).collect(),
+        _ => vec![],
+    }
+}
+
+/// Generates C sources for gresource bundles and D-Bus interfaces and compiles GSettings schemas into `build/glib`.
+pub fn generate(glib: &Glib, path: &Path, build_dir: &Path) -> Result<Generated, Box<dyn std::error::Error + Send + Sync>> {
+    let out_dir = build_dir.join("glib");
+    fs::create_dir_all(&out_dir)?;
+    let mut generated = Generated::default();
+    generated.include_dirs.push(out_dir.clone());
+
+    for xml in expand_globs(path, glib.resources.as_deref().unwrap_or_default())? {
+        let name = base_name(&xml, ".gresource.xml");
+        let source_dir = xml.parent().unwrap_or(path).to_path_buf();
+        let c_out = out_dir.join(format!("{}-resources.c", name));
+        let h_out = out_dir.join(format!("{}-resources.h", name));
+        let out_mtime = mtime(&c_out);
+        let inputs_changed = resource_inputs(&xml, &source_dir).iter().any(|f| mtime(f) > out_mtime);
+        if is_stale(&xml, &c_out) || inputs_changed {
+            verbosity::progress(format!("glib-compile-resources {}", xml.display()).cyan());
+            for (mode, out) in [("--generate-source", &c_out), ("--generate-header", &h_out)] {
+                run(Command::new("glib-compile-resources")
+                .arg(mode)
+                .arg(format!("--sourcedir={}", source_dir.display()))
+                .arg(format!("--target={}", out.display()))
+                .arg(&xml), &xml)?;
+            }
+        }
+        generated.sources.push(c_out);
+    }
+
+    for xml in expand_globs(path, glib.dbus.as_deref().unwrap_or_default())? {
+        let name = base_name(&xml, ".xml");
+        let c_out = out_dir.join(format!("{}.c", name));
+        if is_stale(&xml, &c_out) {
+            verbosity::progress(format!("gdbus-codegen {}", xml.display()).cyan());
+            let mut cmd = Command::new("gdbus-codegen");
+            if let Some(prefix) = &glib.dbus_interface_prefix {
+                cmd.args(["--interface-prefix", prefix]);
+            }
+            if let Some(ns) = &glib.dbus_namespace {
+                cmd.args(["--c-namespace", ns]);
+            }
+            run(cmd.arg("--generate-c-code").arg(out_dir.join(&name)).arg(&xml), &xml)?;
+        }
+        generated.sources.push(c_out);
+    }
+
+    let schemas = expand_globs(path, glib.schemas.as_deref().unwrap_or_default())?;
+    if !schemas.is_empty() {
+        // Compiled into the build dir so the app can run uninstalled with GSETTINGS_SCHEMA_DIR
+        let schema_dir = out_dir.join("schemas");
+        fs::create_dir_all(&schema_dir)?;
+        let compiled = schema_dir.join("gschemas.compiled");
+        let mut changed = !compiled.exists();
+        for schema in &schemas {
+            let dest = schema_dir.join(schema.file_name().unwrap());
+            if is_stale(schema, &dest) {
+                fs::copy(schema, &dest)?;
+                changed = true;
+            }
+        }
+        if changed {
+            verbosity::progress("glib-compile-schemas".cyan());
+            run(Command::new("glib-compile-schemas").arg("--strict").arg(&schema_dir), &schema_dir)?;
+        }
+    }
+    Ok(generated)
+}
+
+/// Installs GSettings schemas into `<prefix>/share/glib-2.0/schemas` and refreshes the compiled cache there.
+pub fn install_schemas(glib: &Glib, path: &Path, prefix: &Path, manifest: &mut install::Manifest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let schemas = expand_globs(path, glib.schemas.as_deref().unwrap_or_default())?;
+    if schemas.is_empty() {
+        return Ok(());
+    }
+    let schema_dir = prefix.join("share/glib-2.0/schemas");
+    manifest.create_dir_all(&schema_dir)?;
+    for schema in &schemas {
+        manifest.copy(schema, &schema_dir.join(schema.file_name().unwrap()))?;
+    }
+    compile_schemas(&schema_dir)
+}
+
+/// Rebuilds the compiled schema cache of `schema_dir`, which all installed schemas there share.
+pub fn compile_schemas(schema_dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    run(Command::new("glib-compile-schemas").arg(schema_dir), schema_dir)
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::{is_stale, verbosity, Generated};
+
+/// True for bison (`.y`, `.yy`) and flex (`.l`, `.ll`) inputs.
+pub fn is_grammar(file: &Path) -> bool {
+    matches!(file.extension().and_then(|e| e.to_str()), Some("y" | "yy" | "l" | "ll"))
+}
+
+fn run(cmd: &mut Command, input: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let out = cmd.output()?;
+    if !out.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&out.stderr).red());
+        return Err(format!("{} failed for {}", program, input.display()).into());
+    }
+    Ok(())
+}
+
+/// Runs bison and flex over `grammars`, writing into `build/parser`. Parsers are generated first so
+/// scanners can include the `<name>.tab.h` token header, which is emitted next to the parser source.
+pub fn generate(grammars: &[PathBuf], build_dir: &Path) -> Result<Generated, Box<dyn std::error::Error + Send + Sync>> {
+    let out_dir = build_dir.join("parser");
+    fs::create_dir_all(&out_dir)?;
+    let mut generated = Generated::default();
+    generated.include_dirs.push(out_dir.clone());
+
+    let mut ordered: Vec<&PathBuf> = grammars.iter().collect();
+    ordered.sort_by_key(|g| !matches!(g.extension().and_then(|e| e.to_str()), Some("y" | "yy")));
+    for grammar in ordered {
+        let stem = grammar.file_stem().unwrap().to_string_lossy().to_string();
+        let ext = grammar.extension().and_then(|e| e.to_str()).unwrap_or_default();
+        let out = match ext {
+            "y" => out_dir.join(format!("{}.tab.c", stem)),
+            "yy" => out_dir.join(format!("{}.tab.cc", stem)),
+            "l" => out_dir.join(format!("{}.yy.c", stem)),
+            _ => out_dir.join(format!("{}.yy.cc", stem)),
+        };
+        if is_stale(grammar, &out) {
+            verbosity::progress(format!("{} {}", if ext.starts_with('y') { "bison" } else { "flex" }, grammar.display()).cyan());
+            if ext.starts_with('y') {
+                // -d writes the token header alongside: <stem>.tab.h (or .tab.hh for C++ parsers)
+                run(Command::new("bison").arg("-d").arg("-o").arg(&out).arg(grammar), grammar)?;
+            } else {
+                run(Command::new("flex").arg("-o").arg(&out).arg(grammar), grammar)?;
+            }
+        }
+        generated.sources.push(out);
+    }
+    Ok(generated)
+}
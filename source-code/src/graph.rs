@@ -0,0 +1,84 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use owo_colors::OwoColorize;
+use serde_json::json;
+use crate::{compile_c_cpp, find_config_file, parse_config, BuildOptions};
+
+/// Runs the dependency scan of `hbuild make` without compiling and prints the include graph as
+/// Graphviz DOT or JSON.
+pub fn run(path: &Path, children: &Arc<Mutex<Vec<u32>>>, format: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !matches!(format, "dot" | "json") {
+        return Err(format!("Unknown graph format '{}' (expected dot or json)", format).into());
+    }
+    let (config_path, config_format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &config_format)?;
+    let opts = BuildOptions {
+        graph: Some(format.to_string()),
+        ..Default::default()
+    };
+    compile_c_cpp(&config, path, children, &opts)
+}
+
+/// Include edges: `-MM` lists everything a file includes transitively, so a dependency is kept only
+/// when no other dependency of the same file already includes it. Headers that include each other
+/// keep their edges.
+fn direct_edges(deps: &BTreeMap<String, BTreeSet<String>>) -> BTreeSet<(String, String)> {
+    let includes = |from: &String, to: &String| deps.get(from).is_some_and(|d| d.contains(to));
+    let mut edges = BTreeSet::new();
+    for (file, file_deps) in deps {
+        for dep in file_deps.iter().filter(|dep| *dep != file) {
+            if !file_deps.iter().any(|other| other != file && other != dep && includes(other, dep) && !includes(dep, other)) {
+                edges.insert((file.clone(), dep.clone()));
+            }
+        }
+    }
+    edges
+}
+
+/// Prints the graph of `target` built from `sources`, paths relative to the project.
+pub fn print(format: &str, target: &str, path: &Path, sources: &[PathBuf], deps: &HashMap<PathBuf, HashSet<PathBuf>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let root = path.canonicalize()?;
+    let name = |file: &Path| {
+        let file = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+        file.strip_prefix(&root).map(Path::to_path_buf).unwrap_or(file).display().to_string()
+    };
+    let named: BTreeMap<String, BTreeSet<String>> = deps.iter().map(|(file, d)| (name(file), d.iter().map(|dep| name(dep)).collect())).collect();
+    let edges = direct_edges(&named);
+    let source_names: Vec<String> = sources.iter().map(|s| name(s)).collect();
+    let headers: BTreeSet<&String> = edges.iter().map(|(_, to)| to).filter(|h| !source_names.contains(h)).collect();
+
+    if format == "json" {
+        let graph = json!({
+            "targets": [{"name": target, "sources": source_names}],
+            "nodes": source_names.iter().map(|s| json!({"id": s, "kind": "source"}))
+            .chain(headers.iter().map(|h| json!({"id": h, "kind": "header"})))
+            .collect::<Vec<_>>(),
+            "edges": edges.iter().map(|(from, to)| json!({"from": from, "to": to})).collect::<Vec<_>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&graph)?);
+        return Ok(());
+    }
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    println!("digraph {} {{", quote(target));
+    println!("    rankdir=LR;");
+    println!("    {} [shape=box3d];", quote(&format!("target:{}", target)));
+    for source in &source_names {
+        println!("    {} [shape=box];", quote(source));
+        println!("    {} -> {};", quote(&format!("target:{}", target)), quote(source));
+    }
+    for header in &headers {
+        println!("    {} [shape=note];", quote(header));
+    }
+    for (from, to) in &edges {
+        println!("    {} -> {};", quote(from), quote(to));
+    }
+    println!("}}");
+    Ok(())
+}
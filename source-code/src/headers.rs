@@ -0,0 +1,39 @@
+use std::path::{Component, Path, PathBuf};
+use owo_colors::OwoColorize;
+use crate::{expand_globs, install, PublicHeaders};
+
+/// The part of `pattern` before its first wildcard component: `include` for `include/**/*.h`, and
+/// the directory of a plain file. Headers keep their path below it.
+fn base(pattern: &str) -> PathBuf {
+    let literal: PathBuf = Path::new(pattern).components()
+    .take_while(|c| !matches!(c, Component::Normal(s) if s.to_string_lossy().contains(['*', '?', '['])))
+    .collect();
+    if literal == Path::new(pattern) {
+        literal.parent().map(Path::to_path_buf).unwrap_or_default()
+    } else {
+        literal
+    }
+}
+
+/// Installs the public headers to `<include_dir>/<subdir>` (the project name by default), each at
+/// its path below the literal part of the pattern that matched it.
+pub fn install(headers: &PublicHeaders, name: &str, path: &Path, include_dir: &Path, manifest: &mut install::Manifest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let dest = include_dir.join(headers.subdir.as_deref().unwrap_or(name));
+    for pattern in &headers.files {
+        let files = expand_globs(path, std::slice::from_ref(pattern))?;
+        if files.is_empty() {
+            eprintln!("{}", format!("No public headers match {}", pattern).yellow());
+        }
+        // Glob drops the leading `./` of the project path
+        let without_curdir = |p: &Path| p.components().filter(|c| *c != Component::CurDir).collect::<PathBuf>();
+        let base = without_curdir(&path.join(base(pattern)));
+        for file in files.iter().filter(|f| f.is_file()) {
+            let relative = without_curdir(file);
+            let relative = relative.strip_prefix(&base).map_err(|_| format!("{} is outside {}", file.display(), base.display()))?;
+            let to = dest.join(relative);
+            manifest.create_dir_all(to.parent().unwrap())?;
+            manifest.copy(file, &to)?;
+        }
+    }
+    Ok(())
+}
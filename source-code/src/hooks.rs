@@ -0,0 +1,48 @@
+use std::path::Path;
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::{target_path, verbosity, BuildOptions, HBuildConfig};
+
+/// Variables describing the build, exported to every hook: the project name, version and directory,
+/// the build directory, the linked target (with a `[build]` section) and the profile, when one is used.
+/// Paths are absolute, since hooks run in the project directory rather than hbuild's.
+pub fn env(config: &HBuildConfig, path: &Path, opts: &BuildOptions) -> Vec<(&'static str, String)> {
+    let path = &path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut vars = vec![
+        ("HBUILD_NAME", config.metadata.name.clone()),
+        ("HBUILD_VERSION", config.metadata.version.clone()),
+        ("HBUILD_PROJECT_DIR", path.display().to_string()),
+        ("HBUILD_BUILD_DIR", opts.build_dir(path).display().to_string()),
+    ];
+    if let Some(build) = &config.build {
+        vars.push(("HBUILD_TARGET", target_path(build, path, opts).display().to_string()));
+    }
+    if let Some(profile) = &opts.profile {
+        vars.push(("HBUILD_PROFILE", profile.clone()));
+    }
+    vars
+}
+
+/// Runs the `[hooks]` commands for `stage` (`pre_build`, `post_build`, `pre_install` or `post_install`)
+/// in order, each with `sh -c` in the project directory and `env` exported. A failing command stops
+/// the build. Hooks have no declared outputs, so unlike rules they are not sandboxed.
+pub fn run(config: &HBuildConfig, stage: &str, path: &Path, env: &[(&str, String)]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(hooks) = &config.hooks else {
+        return Ok(());
+    };
+    let commands = match stage {
+        "pre_build" => &hooks.pre_build,
+        "post_build" => &hooks.post_build,
+        "pre_install" => &hooks.pre_install,
+        "post_install" => &hooks.post_install,
+        _ => return Err(format!("Unknown hook stage '{}'", stage).into()),
+    };
+    for command in commands.iter().flatten() {
+        verbosity::progress(format!("[{}] {}", stage, command).cyan());
+        let status = Command::new("sh").arg("-c").arg(command).current_dir(path).envs(env.iter().map(|(k, v)| (k, v))).status()?;
+        if !status.success() {
+            return Err(format!("{} hook failed: {}", stage, command).into());
+        }
+    }
+    Ok(())
+}
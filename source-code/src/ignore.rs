@@ -0,0 +1,87 @@
+use std::fs;
+use std::path::{Component, Path};
+use glob::{MatchOptions, Pattern};
+
+struct Rule {
+    pattern: Pattern,
+    negate: bool,
+    dir_only: bool,
+    /// Matched against the whole relative path instead of any single name.
+    anchored: bool,
+}
+
+/// Patterns from `.hbuildignore` in gitignore syntax: `#` comments, `!` negation, a trailing `/` for
+/// directories only, and patterns containing a `/` anchored at the project root. Later lines win.
+#[derive(Default)]
+pub struct Ignore {
+    rules: Vec<Rule>,
+}
+
+const OPTIONS: MatchOptions = MatchOptions { case_sensitive: true, require_literal_separator: true, require_literal_leading_dot: false };
+
+impl Ignore {
+    pub fn load(path: &Path) -> Ignore {
+        let text = fs::read_to_string(path.join(".hbuildignore")).unwrap_or_default();
+        let mut rules = vec![];
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negate, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let line = line.trim_start_matches('/');
+            // "**/name" is the same as an unanchored "name"
+            let (anchored, line) = match line.strip_prefix("**/") {
+                Some(rest) if !rest.contains('/') => (false, rest),
+                _ => (anchored, line),
+            };
+            if let Ok(pattern) = Pattern::new(line) {
+                rules.push(Rule { pattern, negate, dir_only, anchored });
+            }
+        }
+        Ignore { rules }
+    }
+
+    /// Whether `rel` (a directory when `is_dir`) is excluded by the last matching rule.
+    fn matches(&self, rel: &str, is_dir: bool) -> Option<bool> {
+        let name = rel.rsplit('/').next().unwrap_or(rel);
+        self.rules.iter().rev().find(|r| {
+            (!r.dir_only || is_dir) && if r.anchored { r.pattern.matches_with(rel, OPTIONS) } else { r.pattern.matches_with(name, OPTIONS) }
+        }).map(|r| !r.negate)
+    }
+
+    /// True when `file`, or a directory containing it, is ignored. Relative paths are taken relative to
+    /// `root`; absolute paths outside it are never ignored.
+    pub fn is_ignored(&self, root: &Path, file: &Path) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        let rel = match file.strip_prefix(root) {
+            Ok(rel) => rel.to_path_buf(),
+            Err(_) if file.is_absolute() => match root.canonicalize().ok().and_then(|r| file.strip_prefix(r).ok().map(Path::to_path_buf)) {
+                Some(rel) => rel,
+                None => return false,
+            },
+            Err(_) => file.to_path_buf(),
+        };
+        let names: Vec<String> = rel.components().filter_map(|c| match c {
+            Component::Normal(n) => Some(n.to_string_lossy().to_string()),
+            _ => None,
+        }).collect();
+        // As in git, nothing inside an excluded directory can be re-included
+        for i in 1..names.len() {
+            if self.matches(&names[..i].join("/"), true) == Some(true) {
+                return true;
+            }
+        }
+        self.matches(&names.join("/"), false) == Some(true)
+    }
+}
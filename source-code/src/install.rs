@@ -0,0 +1,334 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use owo_colors::OwoColorize;
+use crate::{bolt, cmake, find_config_file, gettext, glib, headers, hooks, messages, parse_config, pcfile, profile, service, shaders, soname, swig, target_path, verbosity, BuildOptions};
+
+/// Where `hbuild install` puts files: the directories under `prefix`, all staged below `destdir` when
+/// one is given so packaging can collect them without touching the system.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub prefix: PathBuf,
+    pub destdir: Option<PathBuf>,
+}
+
+impl Layout {
+    /// The layout for `--prefix` and `--destdir`, else the `PREFIX` and `DESTDIR` environment variables.
+    pub fn new(prefix: Option<String>, destdir: Option<String>) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let from_env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let prefix = PathBuf::from(prefix.or_else(|| from_env("PREFIX")).unwrap_or_else(|| "/usr/local".to_string()));
+        if !prefix.is_absolute() {
+            return Err(format!("The install prefix must be an absolute path, got {}", prefix.display()).into());
+        }
+        let destdir = destdir.or_else(|| from_env("DESTDIR")).map(PathBuf::from);
+        Ok(Layout { prefix, destdir })
+    }
+
+    /// `dir`, an absolute path on the target system, as written during installation.
+    pub fn staged(&self, dir: &Path) -> PathBuf {
+        match &self.destdir {
+            Some(destdir) => destdir.join(dir.strip_prefix("/").unwrap_or(dir)),
+            None => dir.to_path_buf(),
+        }
+    }
+
+    /// The prefix as written during installation; data helpers install below it.
+    pub fn root(&self) -> PathBuf {
+        self.staged(&self.prefix)
+    }
+
+    pub fn bindir(&self) -> PathBuf {
+        self.root().join("bin")
+    }
+
+    pub fn libdir(&self) -> PathBuf {
+        self.root().join("lib")
+    }
+
+    pub fn includedir(&self) -> PathBuf {
+        self.root().join("include")
+    }
+
+    /// `<prefix>/lib/systemd/system`, which systemd searches for `/usr` and `/usr/local`.
+    pub fn unitdir(&self) -> PathBuf {
+        self.libdir().join("systemd/system")
+    }
+
+    /// Where debuggers find the split debug info of the installed `file`: its path on the target
+    /// system below `/usr/lib/debug` for the system prefixes, `<prefix>/lib/debug` for any other.
+    pub fn debug_file(&self, file: &Path) -> PathBuf {
+        let dir = if self.is_system() {
+            self.staged(Path::new("/usr/lib/debug"))
+        } else {
+            self.root().join("lib/debug")
+        };
+        let file = self.unstaged(file);
+        profile::debug_file(&dir.join(file.strip_prefix("/").unwrap_or(&file)))
+    }
+
+    fn is_system(&self) -> bool {
+        self.prefix == Path::new("/usr") || self.prefix == Path::new("/usr/local")
+    }
+
+    /// `/etc` for the system prefixes, `<prefix>/etc` for any other.
+    pub fn sysconfdir(&self) -> PathBuf {
+        if self.is_system() {
+            self.staged(Path::new("/etc"))
+        } else {
+            self.root().join("etc")
+        }
+    }
+
+    /// `/var/lib/hbuild` for the system prefixes, `<prefix>/var/lib/hbuild` for any other.
+    fn manifest_dir(&self) -> PathBuf {
+        if self.is_system() {
+            self.staged(Path::new("/var/lib/hbuild"))
+        } else {
+            self.root().join("var/lib/hbuild")
+        }
+    }
+
+    /// `staged` as a path on the target system, without DESTDIR.
+    fn unstaged(&self, staged: &Path) -> PathBuf {
+        match self.destdir.as_ref().and_then(|d| staged.strip_prefix(d).ok()) {
+            Some(rest) => Path::new("/").join(rest),
+            None => staged.to_path_buf(),
+        }
+    }
+}
+
+/// Every file an installation wrote and every directory it created, so `hbuild uninstall` can remove
+/// exactly those. Stored as `<name>.manifest` with one `file <path>` or `dir <path>` line per entry,
+/// paths as on the target system. Reinstalling adds to the previous manifest.
+pub struct Manifest {
+    layout: Layout,
+    path: PathBuf,
+    files: BTreeSet<PathBuf>,
+    dirs: BTreeSet<PathBuf>,
+}
+
+impl Manifest {
+    pub fn open(layout: &Layout, name: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut manifest = Manifest {
+            layout: layout.clone(),
+            path: layout.manifest_dir().join(format!("{}.manifest", name)),
+            files: BTreeSet::new(),
+            dirs: BTreeSet::new(),
+        };
+        let text = match fs::read_to_string(&manifest.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(manifest),
+            Err(e) => return Err(e.into()),
+        };
+        for line in text.lines() {
+            match line.split_once(' ') {
+                Some(("file", path)) => manifest.files.insert(PathBuf::from(path)),
+                Some(("dir", path)) => manifest.dirs.insert(PathBuf::from(path)),
+                _ => return Err(format!("Invalid line in {}: {}", manifest.path.display(), line).into()),
+            };
+        }
+        Ok(manifest)
+    }
+
+    /// `fs::create_dir_all`, recording the directories that didn't exist yet below DESTDIR.
+    pub fn create_dir_all(&mut self, dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let missing: Vec<&Path> = dir.ancestors()
+        .take_while(|d| !d.exists() && self.layout.destdir.as_deref().is_none_or(|destdir| d.starts_with(destdir) && *d != destdir))
+        .collect();
+        fs::create_dir_all(dir)?;
+        for d in missing {
+            self.dirs.insert(self.layout.unstaged(d));
+        }
+        Ok(())
+    }
+
+    /// `fs::copy`, recording `to`.
+    pub fn copy(&mut self, from: &Path, to: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        fs::copy(from, to)?;
+        messages::emit(serde_json::json!({"event": "install", "source": from, "file": self.layout.unstaged(to), "staged": to}));
+        self.files.insert(self.layout.unstaged(to));
+        Ok(())
+    }
+
+    /// Makes `link` a symlink to `original`, recording `link`.
+    pub fn symlink(&mut self, original: &Path, link: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        soname::symlink(original, link)?;
+        messages::emit(serde_json::json!({"event": "install", "source": original, "file": self.layout.unstaged(link), "staged": link}));
+        self.files.insert(self.layout.unstaged(link));
+        Ok(())
+    }
+
+    /// Records `file`, written by a tool rather than copied.
+    pub fn record(&mut self, file: &Path) {
+        self.files.insert(self.layout.unstaged(file));
+    }
+
+    pub fn save(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let dir = self.path.parent().unwrap().to_path_buf();
+        self.create_dir_all(&dir)?;
+        let mut text = String::new();
+        for file in &self.files {
+            text.push_str(&format!("file {}\n", file.display()));
+        }
+        for dir in &self.dirs {
+            text.push_str(&format!("dir {}\n", dir.display()));
+        }
+        fs::write(&self.path, text)?;
+        Ok(())
+    }
+}
+
+/// Installs the executable or shared library `from` as `to`. Its debug info goes to
+/// [`Layout::debug_file`]: the `.debug` file a stripping profile split off at build time, else
+/// split off the installed copy with `strip`, the strip tool of `--strip`.
+fn install_binary(manifest: &mut Manifest, layout: &Layout, from: &Path, to: &Path, strip: Option<&str>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    manifest.copy(from, to)?;
+    let debug = layout.debug_file(to);
+    let built_debug = profile::debug_file(from);
+    if built_debug.exists() {
+        manifest.create_dir_all(debug.parent().unwrap())?;
+        manifest.copy(&built_debug, &debug)?;
+    } else if let Some(tool) = strip {
+        manifest.create_dir_all(debug.parent().unwrap())?;
+        profile::split_debug(to, &debug, tool)?;
+        manifest.record(&debug);
+    }
+    Ok(())
+}
+
+/// Installs what `hbuild make` built with `opts`; `strip` splits debug info off the installed
+/// binaries and `service` adds a systemd unit for the executable.
+pub fn run(path: &Path, layout: &Layout, opts: &BuildOptions, strip: bool, service: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if let Some((config_path, format)) = find_config_file(path) {
+        let config = parse_config(&config_path, &format)?;
+        let build = config.build.as_ref().ok_or("No build section")?;
+        let mut target_path = target_path(build, path, opts);
+        let build_dir = opts.build_dir(path);
+        let strip_tool = strip.then(|| profile::strip_tool(build, opts.cross.as_ref().map_or("ar", |c| c.ar.as_str())));
+        if !target_path.exists() {
+            eprintln!("{}", "Target not built".red().bold());
+            return Ok(());
+        }
+        verbosity::progress(format!("Installing to {}", layout.root().display()).blue().bold());
+        let mut hook_env = hooks::env(&config, path, opts);
+        hook_env.push(("HBUILD_PREFIX", layout.prefix.display().to_string()));
+        hook_env.push(("DESTDIR", layout.destdir.as_ref().map(|d| d.display().to_string()).unwrap_or_default()));
+        hooks::run(&config, "pre_install", path, &hook_env)?;
+        if service && build.build_type != "executable" {
+            return Err("--service needs build_type = \"executable\"".into());
+        }
+        let mut manifest = Manifest::open(layout, &config.metadata.name)?;
+        match build.build_type.as_str() {
+            "executable" => {
+                let bin_dir = layout.bindir();
+                manifest.create_dir_all(&bin_dir)?;
+                if config.bolt.is_some() && bolt::output_path(&target_path).exists() {
+                    target_path = bolt::output_path(&target_path);
+                }
+                install_binary(&mut manifest, layout, &target_path, &bin_dir.join(&config.metadata.name), strip_tool.as_deref())?;
+                if service {
+                    let exec = layout.prefix.join("bin").join(&config.metadata.name);
+                    service::install(&config, &exec, &build_dir, layout, &mut manifest)?;
+                }
+            }
+            "shared" => {
+                let lib_dir = layout.libdir();
+                manifest.create_dir_all(&lib_dir)?;
+                match soname::versioned(&target_path, &config.metadata.version) {
+                    Some(v) => {
+                        let file = lib_dir.join(v.file.file_name().unwrap());
+                        install_binary(&mut manifest, layout, &v.file, &file, strip_tool.as_deref())?;
+                        let installed = soname::Versioned { file, soname: v.soname };
+                        for (original, link) in installed.links() {
+                            manifest.symlink(&original, &link)?;
+                        }
+                    }
+                    None => install_binary(&mut manifest, layout, &target_path, &lib_dir.join(target_path.file_name().unwrap()), strip_tool.as_deref())?,
+                }
+                if build.static_variant.unwrap_or(false) {
+                    let archive = target_path.with_extension("a");
+                    manifest.copy(&archive, &lib_dir.join(archive.file_name().unwrap()))?;
+                }
+            }
+            "static" => {
+                let lib_dir = layout.libdir();
+                manifest.create_dir_all(&lib_dir)?;
+                manifest.copy(&target_path, &lib_dir.join(target_path.file_name().unwrap()))?;
+            }
+            _ => {}
+        }
+        if matches!(build.build_type.as_str(), "shared" | "static") {
+            pcfile::install(&config, build, &target_path.file_name().unwrap().to_string_lossy(), &build_dir, layout, &mut manifest)?;
+            cmake::install(&config, build, &target_path, &build_dir, layout, &mut manifest)?;
+        }
+        if let Some(h) = &config.public_headers {
+            headers::install(h, &config.metadata.name, path, &layout.includedir(), &mut manifest)?;
+        }
+        let install_prefix = layout.root();
+        if gettext::is_enabled(&config, path) {
+            gettext::install_catalogs(&config, path, &build_dir, &install_prefix, &mut manifest)?;
+        }
+        if let Some(g) = &config.glib {
+            glib::install_schemas(g, path, &install_prefix, &mut manifest)?;
+        }
+        if let Some(sw) = &config.swig {
+            swig::install(sw, path, &build_dir, &install_prefix, &mut manifest)?;
+        }
+        if let Some(sh) = config.shaders.as_ref().filter(|sh| !sh.embed.unwrap_or(false)) {
+            shaders::install(sh, &config.metadata.name, path, &build_dir, &install_prefix, &mut manifest)?;
+        }
+        // Config files to <sysconfdir>/<project>
+        if let Some((config_file, _)) = find_config_file(path) {
+            let etc_dir = layout.sysconfdir().join(&config.metadata.name);
+            manifest.create_dir_all(&etc_dir)?;
+            manifest.copy(&config_file, &etc_dir.join("config"))?;
+        }
+        manifest.save()?;
+        hooks::run(&config, "post_install", path, &hook_env)?;
+        verbosity::status("Installation complete!");
+    } else {
+        eprintln!("{}", "No config file found".red().bold());
+    }
+    Ok(())
+}
+
+/// Removes the files recorded in the project's install manifest, then the directories the installation
+/// created once they are empty, and finally the manifest itself.
+pub fn uninstall(path: &Path, layout: &Layout) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some((config_path, format)) = find_config_file(path) else {
+        eprintln!("{}", "No config file found".red().bold());
+        return Ok(());
+    };
+    let config = parse_config(&config_path, &format)?;
+    let manifest = Manifest::open(layout, &config.metadata.name)?;
+    if !manifest.path.exists() {
+        return Err(format!("{} is not installed under {} (no {})", config.metadata.name, layout.root().display(), manifest.path.display()).into());
+    }
+    verbosity::progress(format!("Uninstalling {} from {}", config.metadata.name, layout.root().display()).blue().bold());
+    let mut schema_dirs = BTreeSet::new();
+    for file in &manifest.files {
+        let staged = layout.staged(file);
+        match fs::remove_file(&staged) {
+            Ok(()) => verbosity::progress(format!("Removed {}", staged.display()).cyan()),
+            Err(e) if e.kind() == ErrorKind::NotFound => eprintln!("{}", format!("{} was already removed", staged.display()).yellow()),
+            Err(e) => return Err(format!("Cannot remove {}: {}", staged.display(), e).into()),
+        }
+        if file.to_string_lossy().ends_with(".gschema.xml") {
+            schema_dirs.extend(staged.parent().map(Path::to_path_buf));
+        }
+    }
+    // The compiled schema cache is shared with other packages, so it is rebuilt rather than removed
+    for dir in schema_dirs.iter().filter(|d| d.exists()) {
+        if let Err(e) = glib::compile_schemas(dir) {
+            eprintln!("{}", format!("Could not refresh {}: {}", dir.display(), e).yellow());
+        }
+    }
+    fs::remove_file(&manifest.path)?;
+    // Deepest first, so parents are empty by the time they are reached
+    for dir in manifest.dirs.iter().rev() {
+        let _ = fs::remove_dir(layout.staged(dir));
+    }
+    verbosity::status("Uninstall complete!");
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use std::path::Path;
+use crate::{msvc, Build};
+
+/// The language a C-family source is compiled as, by its extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    C,
+    Cxx,
+}
+
+const CXX_EXTENSIONS: &[&str] = &["cpp", "cc", "cxx", "c++", "cp", "C", "CPP"];
+
+pub fn of(src: &Path) -> Option<Language> {
+    let ext = src.extension()?.to_str()?;
+    if ext == "c" {
+        Some(Language::C)
+    } else if CXX_EXTENSIONS.contains(&ext) {
+        Some(Language::Cxx)
+    } else {
+        None
+    }
+}
+
+/// The C and C++ driver names of the toolchains hbuild knows.
+const PAIRS: &[(&str, &str)] = &[("clang", "clang++"), ("gcc", "g++"), ("icx", "icpx"), ("cc", "c++")];
+
+/// Where `token` appears in `name` as a whole word, between the start or a target prefix's `-`
+/// and the end or a version suffix.
+fn find(name: &str, token: &str) -> Option<usize> {
+    name.match_indices(token).map(|(i, _)| i).find(|&i| {
+        let after = &name[i + token.len()..];
+        (i == 0 || name[..i].ends_with('-')) && (after.is_empty() || after.starts_with(['-', '.']))
+    })
+}
+
+/// `compiler`'s counterpart for `language`, keeping its directory, target prefix and version:
+/// `aarch64-linux-gnu-g++` pairs with `aarch64-linux-gnu-gcc`, `clang-18` with `clang++-18`.
+/// MSVC and compilers hbuild doesn't know compile both languages themselves.
+pub fn driver(compiler: &str, language: Language) -> String {
+    if msvc::is_msvc(compiler) {
+        return compiler.to_string();
+    }
+    let (dir, name) = compiler.rsplit_once('/').map_or(("", compiler), |(dir, name)| (&compiler[..=dir.len()], name));
+    for (c, cxx) in PAIRS {
+        // The C++ name first: `clang` is also in `clang++`
+        let Some((i, token)) = find(name, cxx).map(|i| (i, cxx)).or_else(|| find(name, c).map(|i| (i, c))) else {
+            continue;
+        };
+        let wanted = if language == Language::C { c } else { cxx };
+        return format!("{}{}{}{}", dir, &name[..i], wanted, &name[i + token.len()..]);
+    }
+    compiler.to_string()
+}
+
+/// The language `compiler` compiles sources it has no counterpart for in, e.g. Objective-C.
+pub fn of_driver(compiler: &str) -> Language {
+    if driver(compiler, Language::C) == compiler { Language::C } else { Language::Cxx }
+}
+
+/// The compilers of a build's C and C++ sources.
+pub struct Drivers {
+    pub c: String,
+    pub cxx: String,
+}
+
+/// `[build] cc` and `cxx`, else `compiler` for its own language and its counterpart for the other.
+/// A compiler picked for this build, by a matrix cell or a cross toolchain, isn't `configured`
+/// and always comes with its counterpart.
+pub fn drivers(build: &Build, compiler: &str, configured: bool) -> Drivers {
+    let configured = |driver: &Option<String>| driver.as_ref().filter(|_| configured).cloned();
+    Drivers {
+        c: configured(&build.cc).unwrap_or_else(|| driver(compiler, Language::C)),
+        cxx: configured(&build.cxx).unwrap_or_else(|| driver(compiler, Language::Cxx)),
+    }
+}
+
+/// The standard `language` is compiled with: an override for this build naming that language,
+/// else `[build] c_standard` or `cxx_standard`, else `standard` when it names that language. None
+/// leaves the compiler's default.
+pub fn standard(build: &Build, override_standard: Option<&String>, language: Language) -> Option<String> {
+    let names = |standard: &&String| standard.contains("++") == (language == Language::Cxx);
+    let configured = match language {
+        Language::C => build.c_standard.as_ref(),
+        Language::Cxx => build.cxx_standard.as_ref(),
+    };
+    override_standard.filter(names).or(configured).or(Some(&build.standard).filter(names)).cloned()
+}
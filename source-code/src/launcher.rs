@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+use std::process::Command;
+use crate::{platform, Build};
+
+/// Launchers picked up from PATH when `compiler_launcher` isn't set.
+const AUTO_DETECTED: &[&str] = &["ccache", "sccache"];
+
+/// The program every compile command is prefixed with: `compiler_launcher` from `[build]`, `none` for
+/// none, or by default the first of ccache and sccache found on PATH. Auto-detection is skipped for
+/// MSVC and in the sandbox, where the launcher would need its cache and, for sccache, its server.
+pub fn resolve(build: &Build, msvc: bool, sandboxed: bool) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    match build.compiler_launcher.as_deref() {
+        Some("none") | Some("") => Ok(None),
+        Some(launcher) => {
+            if platform::which(launcher).is_none() {
+                return Err(format!("compiler_launcher '{}' not found on PATH", launcher).into());
+            }
+            if sandboxed && is_sccache(launcher) {
+                return Err("sccache cannot reach its server inside the sandbox; use ccache or sandbox = false".into());
+            }
+            Ok(Some(launcher.to_string()))
+        }
+        None if msvc || sandboxed => Ok(None),
+        None => Ok(AUTO_DETECTED.iter().find(|l| platform::which(l).is_some()).map(|l| l.to_string())),
+    }
+}
+
+fn is_sccache(launcher: &str) -> bool {
+    launcher.rsplit('/').next() == Some("sccache")
+}
+
+/// Directories the launcher writes its cache to, made writable in the sandbox.
+pub fn cache_dirs(launcher: &str) -> Vec<PathBuf> {
+    if launcher.rsplit('/').next() != Some("ccache") {
+        return vec![];
+    }
+    Command::new(launcher).args(["--get-config", "cache_dir"]).output().ok()
+    .filter(|o| o.status.success())
+    .map(|o| PathBuf::from(String::from_utf8_lossy(&o.stdout).trim()))
+    .filter(|dir| dir.is_absolute() && std::fs::create_dir_all(dir).is_ok())
+    .into_iter()
+    .collect()
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use owo_colors::OwoColorize;
+use crate::{args, platform};
+
+/// Number of symbols listed per object in the post-link report.
+const TOP_SYMBOLS: usize = 5;
+
+#[derive(Debug)]
+struct InputSection {
+    name: String,
+    size: u64,
+    object: String,
+}
+
+#[derive(Debug, Default)]
+struct LinkMap {
+    discarded: Vec<InputSection>,
+    kept: Vec<InputSection>,
+}
+
+/// Compile flags that put every function/object in its own section so `--gc-sections` can drop them.
+pub fn compile_flags() -> &'static [&'static str] {
+    &["-ffunction-sections", "-fdata-sections"]
+}
+
+/// Link flags emitting a map file at `map_path` and enabling section garbage collection.
+pub fn link_flags(map_path: &Path) -> Vec<OsString> {
+    if platform::is_macos() {
+        vec!["-Wl,-dead_strip".into(), args::with_path("-Wl,-map,", map_path)]
+    } else {
+        vec!["-Wl,--gc-sections".into(), args::with_path("-Wl,-Map=", map_path)]
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
+
+fn parse_map(content: &str) -> LinkMap {
+    let mut map = LinkMap::default();
+    let mut in_discarded = false;
+    let mut in_memory_map = false;
+    let mut pending_name: Option<String> = None;
+    for line in content.lines() {
+        if line.starts_with("Discarded input sections") {
+            in_discarded = true;
+            in_memory_map = false;
+            continue;
+        }
+        if line.starts_with("Memory Configuration") {
+            in_discarded = false;
+            continue;
+        }
+        if line.starts_with("Linker script and memory map") {
+            in_memory_map = true;
+            continue;
+        }
+        if !in_discarded && !in_memory_map {
+            continue;
+        }
+        // Input sections are indented by exactly one space; output sections and symbols are not.
+        if !line.starts_with(' ') || (line.starts_with("  ") && pending_name.is_none()) {
+            continue;
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (name, rest) = match (pending_name.take(), tokens.first()) {
+            (Some(name), _) => (name, &tokens[..]),
+            (None, Some(first)) if first.starts_with('.') => {
+                if tokens.len() == 1 {
+                    // ld wraps long section names onto the following line
+                    pending_name = Some(first.to_string());
+                    continue;
+                }
+                (first.to_string(), &tokens[1..])
+            }
+            _ => continue,
+        };
+        if rest.len() < 3 {
+            continue;
+        }
+        let size = match parse_hex(rest[1]) {
+            Some(size) => size,
+            None => continue,
+        };
+        let section = InputSection {
+            name,
+            size,
+            object: rest[2..].join(" "),
+        };
+        if in_discarded {
+            map.discarded.push(section);
+        } else if size > 0 {
+            map.kept.push(section);
+        }
+    }
+    map
+}
+
+/// Sections that carry metadata rather than code or data and are left out of the symbol listing.
+fn is_metadata_section(section: &str) -> bool {
+    section == ".comment" || section.starts_with(".eh_frame") || section.starts_with(".note") || section.starts_with(".debug")
+}
+
+/// Strips the section prefix added by `-ffunction-sections`/`-fdata-sections`, leaving the symbol name.
+fn symbol_name(section: &str) -> &str {
+    for prefix in [".text.", ".data.rel.ro.", ".data.", ".rodata.", ".bss.", ".tbss.", ".tdata."] {
+        if let Some(sym) = section.strip_prefix(prefix) {
+            return sym;
+        }
+    }
+    section
+}
+
+/// Prints the discarded sections and the largest retained symbols of each project object.
+pub fn report(map_path: &Path, build_dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("{}", format!("Link map written to {}", map_path.display()).blue().bold());
+    // ld64 maps use a different layout; only GNU-style maps are summarized
+    if platform::is_macos() {
+        return Ok(());
+    }
+    let content = fs::read_to_string(map_path)?;
+    let map = parse_map(&content);
+    let build_prefix = build_dir.display().to_string();
+    let is_project_object = |obj: &str| obj.starts_with(&build_prefix);
+
+    let discarded: Vec<&InputSection> = map.discarded.iter().filter(|s| is_project_object(&s.object) && s.size > 0).collect();
+    let discarded_total: u64 = discarded.iter().map(|s| s.size).sum();
+    println!("{}", format!("Discarded sections: {} ({} bytes)", discarded.len(), discarded_total).cyan());
+    for s in &discarded {
+        println!(" {:>8} {} ({})", s.size, symbol_name(&s.name), s.object);
+    }
+
+    let mut per_object: HashMap<&str, Vec<&InputSection>> = HashMap::new();
+    for s in map.kept.iter().filter(|s| is_project_object(&s.object) && !is_metadata_section(&s.name)) {
+        per_object.entry(&s.object).or_default().push(s);
+    }
+    let mut objects: Vec<(&str, Vec<&InputSection>)> = per_object.into_iter().collect();
+    objects.sort_by_key(|(_, sections)| std::cmp::Reverse(sections.iter().map(|s| s.size).sum::<u64>()));
+    println!("{}", "Largest symbols per object:".cyan());
+    for (object, mut sections) in objects {
+        let total: u64 = sections.iter().map(|s| s.size).sum();
+        println!(" {} ({} bytes)", object.bold(), total);
+        sections.sort_by_key(|s| std::cmp::Reverse(s.size));
+        for s in sections.iter().take(TOP_SYMBOLS) {
+            println!("   {:>8} {}", s.size, symbol_name(&s.name));
+        }
+    }
+    Ok(())
+}
+
+/// Bytes each project object contributes to the linked output, largest first.
+pub fn object_sizes(map_path: &Path, build_dir: &Path) -> Result<Vec<(String, u64)>, Box<dyn std::error::Error + Send + Sync>> {
+    let map = parse_map(&fs::read_to_string(map_path)?);
+    let build_prefix = build_dir.display().to_string();
+    let mut totals: HashMap<String, u64> = HashMap::new();
+    for s in map.kept.iter().filter(|s| s.object.starts_with(&build_prefix) && !is_metadata_section(&s.name)) {
+        *totals.entry(s.object.clone()).or_default() += s.size;
+    }
+    let mut sizes: Vec<(String, u64)> = totals.into_iter().collect();
+    sizes.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    Ok(sizes)
+}
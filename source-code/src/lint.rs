@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use owo_colors::OwoColorize;
+use rayon::prelude::*;
+use crate::{compile_c_cpp, find_config_file, messages, parse_config, platform, verbosity, BuildOptions};
+
+/// Standards cppcheck takes with `--std`; for others it keeps its default.
+const CPPCHECK_STANDARDS: &[&str] = &["c89", "c99", "c11", "c++03", "c++11", "c++14", "c++17", "c++20"];
+
+/// cppcheck severities as GCC diagnostic levels, so both linters' output is read alike.
+const CPPCHECK_LEVELS: &[(&str, &str)] = &[(": style: ", ": warning: "), (": performance: ", ": warning: "), (": portability: ", ": warning: "), (": information: ", ": note: ")];
+
+/// Runs `linter`, or clang-tidy when installed and cppcheck otherwise, over the project's C and C++
+/// sources with the flags `hbuild make` would compile each of them with.
+pub fn run(path: &Path, children: &Arc<Mutex<Vec<u32>>>, opts: &BuildOptions, linter: Option<&str>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let linter = match linter {
+        Some(linter @ ("clang-tidy" | "cppcheck")) => linter,
+        Some(other) => return Err(format!("Unknown linter '{}' (expected clang-tidy or cppcheck)", other).into()),
+        None if platform::which("clang-tidy").is_some() => "clang-tidy",
+        None => "cppcheck",
+    };
+    if platform::which(linter).is_none() {
+        return Err(format!("{} not found; install it or choose the other linter with --linter", linter).into());
+    }
+    let (config_path, format) = find_config_file(path).ok_or("No config file found")?;
+    let config = parse_config(&config_path, &format)?;
+    let opts = BuildOptions {
+        lint: Some(linter.to_string()),
+        ..opts.clone()
+    };
+    compile_c_cpp(&config, path, children, &opts)
+}
+
+/// The compile arguments of `src` without those naming its input and output.
+pub fn flags(args: &[OsString], src: &Path, obj: &Path) -> Vec<OsString> {
+    let mut flags = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "-o" {
+            args.next();
+        } else if arg != "-c" && arg != src.as_os_str() && arg != obj.as_os_str() {
+            flags.push(arg.clone());
+        }
+    }
+    flags
+}
+
+/// cppcheck's arguments from a source's compile flags: include directories, defines and the
+/// standard, which are all it understands.
+fn cppcheck_flags(flags: &[OsString]) -> Vec<OsString> {
+    let mut out = vec![];
+    let mut flags = flags.iter().map(|f| f.to_string_lossy());
+    while let Some(flag) = flags.next() {
+        if matches!(flag.as_ref(), "-I" | "-D" | "-U") {
+            if let Some(value) = flags.next() {
+                out.push(OsString::from(format!("{}{}", flag, value)));
+            }
+        } else if flag.starts_with("-I") || flag.starts_with("-D") || flag.starts_with("-U") {
+            out.push(OsString::from(flag.as_ref()));
+        } else if let Some(standard) = flag.strip_prefix("-std=") {
+            let standard = standard.replacen("gnu", "c", 1);
+            if CPPCHECK_STANDARDS.contains(&standard.as_str()) {
+                out.push(OsString::from(format!("--std={}", standard)));
+            }
+        }
+    }
+    out
+}
+
+fn command(linter: &str, path: &Path, src: &Path, flags: &[OsString]) -> Command {
+    let mut command = Command::new(linter);
+    if linter == "clang-tidy" {
+        command.arg("--quiet");
+        // Explicitly, as clang-tidy only looks for it in the parents of each source
+        let config = path.join(".clang-tidy");
+        if config.is_file() {
+            command.arg(format!("--config-file={}", config.display()));
+        }
+        command.arg(src).arg("--").args(flags);
+    } else {
+        command.args(["--quiet", "--inline-suppr", "--enable=warning,style,performance,portability", "--suppress=missingIncludeSystem"]);
+        command.arg("--template={file}:{line}:{column}: {severity}: {message} [{id}]");
+        command.args(cppcheck_flags(flags)).arg(src);
+    }
+    command
+}
+
+/// A diagnostic with the lines after it: its notes, source excerpt and caret.
+struct Diagnostic {
+    header: String,
+    level: String,
+    text: String,
+}
+
+/// `text` with cppcheck's severities as GCC diagnostic levels.
+fn gcc_levels(text: &str) -> String {
+    CPPCHECK_LEVELS.iter().fold(text.to_string(), |text, (from, to)| text.replace(from, to))
+}
+
+/// The diagnostics in a linter's output; lines before the first belong to none.
+fn diagnostics(output: &str) -> Vec<Diagnostic> {
+    let mut found: Vec<Diagnostic> = vec![];
+    for line in output.lines() {
+        let level = messages::parse_diagnostic(&gcc_levels(line)).and_then(|d| d["level"].as_str().map(String::from));
+        match (level, found.last_mut()) {
+            (Some(level), _) if level != "note" => found.push(Diagnostic { header: line.to_string(), level, text: format!("{}\n", line) }),
+            (_, Some(last)) => {
+                last.text.push_str(line);
+                last.text.push('\n');
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Lints `units`, each a source with its compile flags, `jobs` at a time. Diagnostics are printed
+/// once each, however many sources include the header they point at, and tallied; any error fails
+/// the run. A `.clang-tidy` in the project, e.g. with `WarningsAsErrors`, configures clang-tidy.
+pub fn check(linter: &str, path: &Path, units: &[(PathBuf, Vec<OsString>)], jobs: usize) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let seen: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+    let errors = AtomicUsize::new(0);
+    let warnings = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let console = Mutex::new(());
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+    pool.install(|| units.par_iter().try_for_each(|(src, flags)| -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut command = command(linter, path, src, flags);
+        verbosity::command(&command);
+        let output = command.output()?;
+        // clang-tidy reports on stdout, cppcheck on stderr
+        let report = String::from_utf8_lossy(if linter == "clang-tidy" { &output.stdout } else { &output.stderr }).to_string();
+        messages::diagnostics(&gcc_levels(&report), src);
+        let mut found = diagnostics(&report);
+        if found.is_empty() && !output.status.success() {
+            let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+            found.push(Diagnostic { header: format!("{} failed on {}", linter, src.display()), level: "error".to_string(), text });
+        }
+        let done = finished.fetch_add(1, Ordering::SeqCst) + 1;
+        let _console = console.lock().unwrap();
+        verbosity::progress(format!("[{}/{}] {}", done, units.len(), src.display()).cyan());
+        for diagnostic in found.into_iter().filter(|d| seen.lock().unwrap().insert(d.header.clone())) {
+            if diagnostic.level == "warning" {
+                warnings.fetch_add(1, Ordering::SeqCst);
+                eprint!("{}", diagnostic.text.yellow());
+            } else {
+                errors.fetch_add(1, Ordering::SeqCst);
+                eprint!("{}", diagnostic.text.red());
+            }
+        }
+        Ok(())
+    }))?;
+    let (errors, warnings) = (errors.into_inner(), warnings.into_inner());
+    let summary = format!("{}: {} error{}, {} warning{} in {} file{}", linter, errors, if errors == 1 { "" } else { "s" }, warnings, if warnings == 1 { "" } else { "s" }, units.len(), if units.len() == 1 { "" } else { "s" });
+    if errors > 0 {
+        return Err(summary.into());
+    }
+    verbosity::status(&summary);
+    Ok(())
+}
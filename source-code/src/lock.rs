@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+use crate::{hash_file, pkgdeps, verbosity, write_if_changed, HBuildConfig};
+
+/// `hbuild.lock`, kept next to the config and meant to be committed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// git dependency -> the commit builds check out
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub git: BTreeMap<String, GitLock>,
+    pub toolchain: Option<Toolchain>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GitLock {
+    pub url: String,
+    /// The branch, tag or rev the config asks for; none for the default branch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    pub commit: String,
+}
+
+/// The build environment a project was last pinned to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Toolchain {
+    pub compiler: String,
+    pub compiler_version: String,
+    pub linker_version: String,
+    pub pkg_config_version: String,
+    /// pkg dependency -> "<version> <hash of its .pc file and libraries>"
+    pub libraries: BTreeMap<String, String>,
+}
+
+pub fn path(project: &Path) -> PathBuf {
+    project.join("hbuild.lock")
+}
+
+/// `commit` abbreviated for display; a shorter one, e.g. edited in by hand, as it is.
+pub fn short(commit: &str) -> &str {
+    commit.get(..12).unwrap_or(commit)
+}
+
+pub fn read(project: &Path) -> Result<Lockfile, Box<dyn std::error::Error + Send + Sync>> {
+    match fs::read_to_string(path(project)) {
+        Ok(text) => Ok(toml::from_str(&text)?),
+        Err(_) => Ok(Lockfile::default()),
+    }
+}
+
+pub fn write(project: &Path, lock: &Lockfile) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    write_if_changed(&path(project), &toml::to_string(lock)?)
+}
+
+/// First line of `<program> <args>`, or "missing".
+fn version_line(program: &str, args: &[&str]) -> String {
+    Command::new(program).args(args).output().ok()
+    .filter(|o| o.status.success())
+    .and_then(|o| String::from_utf8_lossy(&o.stdout).lines().next().map(|l| l.trim().to_string()))
+    .unwrap_or_else(|| "missing".to_string())
+}
+
+fn pkg_config(args: &[&str]) -> Option<String> {
+    let out = Command::new("pkg-config").args(args).output().ok()?;
+    out.status.success().then(|| String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// Version plus a hash over the module's `.pc` file and the libraries the compiler resolves for it.
+fn library_fingerprint(compiler: &str, name: &str) -> String {
+    let version = pkg_config(&["--modversion", name]).unwrap_or_else(|| "missing".to_string());
+    let mut files = vec![];
+    if let Some(dir) = pkg_config(&["--variable=pcfiledir", name]) {
+        files.push(PathBuf::from(dir).join(format!("{}.pc", name)));
+    }
+    let search: Vec<String> = pkg_config(&["--libs-only-L", name]).unwrap_or_default().split_whitespace().map(String::from).collect();
+    for lib in pkg_config(&["--libs-only-l", name]).unwrap_or_default().split_whitespace().filter_map(|l| l.strip_prefix("-l")) {
+        for file in [format!("lib{}.so", lib), format!("lib{}.a", lib)] {
+            let found = version_line(compiler, &search.iter().map(String::as_str).chain([format!("-print-file-name={}", file).as_str()]).collect::<Vec<_>>());
+            // Unresolved names come back unchanged
+            if found != file {
+                files.push(PathBuf::from(found));
+                break;
+            }
+        }
+    }
+    let hashes: Vec<String> = files.iter().filter_map(|f| hash_file(&f.canonicalize().unwrap_or(f.clone()))).collect();
+    format!("{} {}", version, hashes.join("+")).trim_end().to_string()
+}
+
+fn current(compiler: &str, pkg_deps: &[String]) -> Result<Toolchain, Box<dyn std::error::Error + Send + Sync>> {
+    let ld = version_line(compiler, &["-print-prog-name=ld"]);
+    let mut libraries = BTreeMap::new();
+    for entry in pkg_deps {
+        let name = pkgdeps::parse(entry)?.name;
+        libraries.insert(name.clone(), library_fingerprint(compiler, &name));
+    }
+    Ok(Toolchain {
+        compiler: compiler.to_string(),
+        compiler_version: version_line(compiler, &["--version"]),
+        linker_version: version_line(&ld, &["--version"]),
+        pkg_config_version: version_line("pkg-config", &["--version"]),
+        libraries,
+    })
+}
+
+/// Human-readable differences between the pinned and the current toolchain. Libraries only present on
+/// one side were added or removed in the config and are not drift.
+fn drift(pinned: &Toolchain, now: &Toolchain) -> Vec<String> {
+    let mut changes = vec![];
+    for (what, old, new) in [
+        ("compiler", &pinned.compiler_version, &now.compiler_version),
+        ("linker", &pinned.linker_version, &now.linker_version),
+        ("pkg-config", &pinned.pkg_config_version, &now.pkg_config_version),
+    ] {
+        if old != new {
+            changes.push(format!("{}: pinned '{}', found '{}'", what, old, new));
+        }
+    }
+    for (name, old) in &pinned.libraries {
+        if let Some(new) = now.libraries.get(name).filter(|new| *new != old) {
+            changes.push(format!("{}: pinned {}, found {}", name, old, new));
+        }
+    }
+    changes
+}
+
+/// A versioned binary such as `gcc-12` on PATH reporting exactly the pinned version.
+fn select(pinned: &Toolchain) -> Option<String> {
+    let major = pinned.compiler_version.split_whitespace()
+    .filter_map(|w| w.split('.').next().filter(|m| !m.is_empty() && m.chars().all(|c| c.is_ascii_digit())))
+    .next_back()?;
+    let candidate = format!("{}-{}", pinned.compiler, major);
+    (version_line(&candidate, &["--version"]) == pinned.compiler_version).then_some(candidate)
+}
+
+/// The compiler `check_toolchain` would switch to, without pinning, writing or reporting anything.
+pub fn pinned(config: &HBuildConfig, path: &Path, compiler: &str) -> Option<String> {
+    let build = config.build.as_ref()?;
+    if build.pin_toolchain.as_deref() != Some("select") {
+        return None;
+    }
+    let pinned = read(path).ok()?.toolchain.filter(|t| t.compiler == compiler)?;
+    (version_line(compiler, &["--version"]) != pinned.compiler_version).then(|| select(&pinned)).flatten()
+}
+
+/// Compares the environment with the toolchain pinned in `hbuild.lock` according to `pin_toolchain`:
+/// `warn` prints the drift, `fail` stops the build, and `select` switches to a matching versioned
+/// compiler when one is installed. The first build, or a different configured compiler, pins the
+/// current environment. Returns the compiler to use instead of `compiler`, if any.
+pub fn check_toolchain(config: &HBuildConfig, path: &Path, compiler: &str) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(build) = &config.build else { return Ok(None) };
+    let Some(mode) = build.pin_toolchain.as_deref() else { return Ok(None) };
+    if !matches!(mode, "warn" | "fail" | "select") {
+        return Err(format!("Unknown pin_toolchain '{}' (expected warn, fail or select)", mode).into());
+    }
+    let pkg_deps = pkgdeps::modules(config);
+    let mut lock = read(path)?;
+    let now = current(compiler, &pkg_deps)?;
+    let pinned = match &mut lock.toolchain {
+        Some(pinned) if pinned.compiler == compiler => pinned,
+        _ => {
+            verbosity::progress(format!("Pinning toolchain in {}", self::path(path).display()).cyan());
+            lock.toolchain = Some(now);
+            write(path, &lock)?;
+            return Ok(None);
+        }
+    };
+    // Newly added dependencies are pinned as they are found
+    let mut added = false;
+    for (name, fingerprint) in &now.libraries {
+        if !pinned.libraries.contains_key(name) {
+            pinned.libraries.insert(name.clone(), fingerprint.clone());
+            added = true;
+        }
+    }
+    pinned.libraries.retain(|name, _| now.libraries.contains_key(name));
+    let changes = drift(pinned, &now);
+    let selected = if mode == "select" && changes.iter().any(|c| c.starts_with("compiler:")) { select(pinned) } else { None };
+    if added {
+        write(path, &lock)?;
+    }
+    if let Some(selected) = selected {
+        verbosity::progress(format!("Using {} to match the pinned toolchain", selected).cyan());
+        return Ok(Some(selected));
+    }
+    if changes.is_empty() {
+        return Ok(None);
+    }
+    if mode == "fail" {
+        for change in &changes {
+            eprintln!("{}", change.red());
+        }
+        return Err("Toolchain differs from hbuild.lock; delete its [toolchain] section to re-pin".into());
+    }
+    for change in &changes {
+        eprintln!("{}", format!("Toolchain drift: {}", change).yellow());
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abbreviates_commits() {
+        assert_eq!(short("0123456789abcdef0123456789abcdef01234567"), "0123456789ab");
+        assert_eq!(short("0123456"), "0123456");
+        assert_eq!(short(""), "");
+    }
+
+    #[test]
+    fn reads_git_entries() {
+        let lock: Lockfile = toml::from_str(r#"
+[git.fmt]
+url = "https://github.com/fmtlib/fmt"
+reference = "10.2.1"
+commit = "e69e5f977d458f2650bb346dadf2ad30c5320281"
+
+[git.json]
+url = "https://github.com/nlohmann/json"
+commit = "abc"
+"#).unwrap();
+        assert_eq!(lock.git["fmt"].reference.as_deref(), Some("10.2.1"));
+        assert_eq!(lock.git["json"], GitLock { url: "https://github.com/nlohmann/json".to_string(), reference: None, commit: "abc".to_string() });
+        assert!(lock.toolchain.is_none());
+        let again: Lockfile = toml::from_str(&toml::to_string(&lock).unwrap()).unwrap();
+        assert_eq!(again.git, lock.git);
+        assert!(toml::from_str::<Lockfile>("").unwrap().git.is_empty());
+    }
+}
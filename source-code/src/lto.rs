@@ -0,0 +1,46 @@
+use owo_colors::OwoColorize;
+use crate::platform;
+
+fn is_clang(compiler: &str) -> bool {
+    compiler.contains("clang")
+}
+
+/// Flags added to both compiling and linking for `lto` = `off`, `full` or `thin`. ThinLTO is clang's;
+/// GCC falls back to full LTO, partitioned over all CPUs with `-flto=auto`.
+pub fn flags(mode: &str, compiler: &str, msvc: bool) -> Result<Option<&'static str>, Box<dyn std::error::Error + Send + Sync>> {
+    if !matches!(mode, "off" | "full" | "thin") {
+        return Err(format!("Unknown lto '{}' (expected off, full or thin)", mode).into());
+    }
+    if mode == "off" {
+        return Ok(None);
+    }
+    if msvc {
+        eprintln!("{}", "Warning: lto is not supported with MSVC; building without it".yellow());
+        return Ok(None);
+    }
+    Ok(Some(match (mode, is_clang(compiler)) {
+        ("thin", true) => "-flto=thin",
+        (_, true) => "-flto",
+        ("thin", false) => {
+            eprintln!("{}", format!("Warning: {} has no ThinLTO; using full LTO", compiler).yellow());
+            "-flto=auto"
+        }
+        _ => "-flto=auto",
+    }))
+}
+
+/// The archiver for LTO objects, which hold compiler IR rather than machine code and need the
+/// compiler's plugin to be indexed: `llvm-ar` for clang, `gcc-ar` (with the toolchain's prefix) for GCC.
+pub fn archiver(ar: &str, compiler: &str) -> String {
+    let wrapper = if is_clang(compiler) {
+        "llvm-ar".to_string()
+    } else {
+        format!("{}gcc-ar", ar.strip_suffix("ar").unwrap_or(""))
+    };
+    if platform::which(&wrapper).is_some() {
+        wrapper
+    } else {
+        eprintln!("{}", format!("Warning: {} not found; archiving LTO objects with {}", wrapper, ar).yellow());
+        ar.to_string()
+    }
+}
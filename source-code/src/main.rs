@@ -1,23 +1,102 @@
-use std::collections::{HashMap, HashSet};
-use std::fs::{self, File};
-use std::io::Write;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::SystemTime;
 use lexopt::prelude::*;
 use owo_colors::OwoColorize;
 use serde::{Deserialize, Serialize};
-use hk_parser::{HkConfig, HkValue, parse_hk, resolve_interpolations};
+use hk_parser::{HkConfig, HkValue, parse_hk};
 use rayon::prelude::*;
-use git2::{Repository, FetchOptions};
 use glob::glob;
-use dirs::home_dir;
-use num_cpus;
-use ctrlc;
-use pkg_config;
 use indexmap::IndexMap;
-use std::os::unix::process::ExitStatusExt;
+use language::Language;
+
+mod android;
+mod args;
+mod artifacts;
+mod asm;
+mod bench;
+mod bolt;
+mod cancel;
+mod checkpoint;
+mod cmake;
+mod compare;
+mod compdb;
+mod container;
+mod coverage;
+mod credentials;
+mod cross;
+mod cuda;
+mod distribute;
+mod dlang;
+mod doctor;
+mod embedded;
+mod env;
+mod exec;
+mod fileflags;
+mod fmt;
+mod fortran;
+mod gettext;
+mod gitdep;
+mod gitstate;
+mod glib;
+mod headers;
+mod grammar;
+mod graph;
+mod hooks;
+mod ignore;
+mod install;
+mod language;
+mod launcher;
+mod linkmap;
+mod lint;
+mod lock;
+mod lto;
+mod makedep;
+mod matrix;
+mod memory;
+mod meson;
+mod messages;
+mod msvc;
+mod nim;
+mod order;
+mod package;
+mod pathdep;
+mod pcfile;
+mod pgo;
+mod platform;
+mod profile;
+mod pkgdeps;
+mod protobuf;
+mod qt;
+mod remote;
+mod resources;
+mod rules;
+mod run;
+mod sandbox;
+mod sanitize;
+mod service;
+mod setup;
+mod shaders;
+mod state;
+mod size;
+mod soname;
+mod staged;
+mod swig;
+mod syspkg;
+mod test;
+mod tree;
+mod validate;
+mod vendor;
+mod verbosity;
+mod visibility;
+mod watch;
+mod why;
+mod zig;
 
 #[derive(Debug, Deserialize, Serialize)]
 struct Metadata {
@@ -36,7 +115,62 @@ struct Description {
 #[derive(Debug, Deserialize, Serialize)]
 struct Specs {
     languages: Vec<String>,
-    dependencies: HashMap<String, String>,
+    dependencies: HashMap<String, Dependency>,
+}
+
+/// A `[specs] dependencies` entry: a registry version or a git URL tracking the default branch, a
+/// git URL with the branch, tag or commit to check out, or the path of a local hbuild project.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Dependency {
+    Short(String),
+    Git {
+        git: String,
+        branch: Option<String>,
+        tag: Option<String>,
+        rev: Option<String>,
+        build_command: Option<String>, // for what hbuild can't build itself, e.g. `make lib`; `$prefix` is the staging prefix
+        outputs: Option<Vec<String>>, // what `build_command` or `make` produces: libraries and header directories
+    },
+    Path {
+        path: String, // relative to the project depending on it
+    },
+}
+
+impl Dependency {
+    /// The repository and ref of a git dependency; `None` for a registry version.
+    fn git(&self) -> Option<gitdep::Source> {
+        match self {
+            Dependency::Short(spec) if is_git_url(spec) => Some(gitdep::Source { url: spec.clone(), reference: gitdep::GitRef::DefaultBranch }),
+            Dependency::Short(_) => None,
+            Dependency::Git { git, branch, tag, rev, .. } => {
+                let reference = match (branch, tag, rev) {
+                    (_, _, Some(rev)) => gitdep::GitRef::Rev(rev.clone()),
+                    (_, Some(tag), None) => gitdep::GitRef::Tag(tag.clone()),
+                    (Some(branch), None, None) => gitdep::GitRef::Branch(branch.clone()),
+                    (None, None, None) => gitdep::GitRef::DefaultBranch,
+                };
+                Some(gitdep::Source { url: git.clone(), reference })
+            }
+            Dependency::Path { .. } => None,
+        }
+    }
+
+    /// The directory of a path dependency of the project at `project`.
+    fn path(&self, project: &Path) -> Option<PathBuf> {
+        match self {
+            Dependency::Path { path } => Some(project.join(path)),
+            _ => None,
+        }
+    }
+
+    /// The registry version of a dependency that isn't from git.
+    fn version(&self) -> Option<&str> {
+        match self {
+            Dependency::Short(spec) if !is_git_url(spec) => Some(spec),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -44,6 +178,10 @@ struct Runtime {
     priority: Option<String>,
     #[serde(rename = "auto-restart")]
     auto_restart: Option<bool>,
+    args: Option<Vec<String>>, // passed to the executable by the service unit
+    user: Option<String>,
+    restart: Option<String>, // systemd Restart= policy; default: on-failure with auto-restart, else no
+    environment: Option<BTreeMap<String, String>>, // set for the service
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -61,9 +199,267 @@ struct Build {
     pkg_dependencies: Option<Vec<String>>,
     build_type: String, // "executable", "shared", "static"
     native: Option<bool>,
+    link_map: Option<bool>,
+    prune_system_headers: Option<bool>,
+    system_header_prefixes: Option<Vec<String>>,
+    visibility: Option<String>, // "default", "hidden", "protected", "internal"
+    exported_symbols: Option<Vec<String>>,
+    version_script: Option<String>,
+    static_variant: Option<bool>, // with build_type = "shared", also archive the PIC objects into a .a
+    sandbox: Option<bool>, // run compile and rule commands under bubblewrap
+    pin_toolchain: Option<String>, // "warn", "fail" or "select" on drift from hbuild.lock
+    compiler_launcher: Option<String>, // e.g. "ccache" or "sccache", "none" to disable; default: ccache/sccache when on PATH
+    sanitizers: Option<Vec<String>>, // "address", "undefined", "thread", "memory", "leak"; --sanitize overrides
+    lto: Option<String>, // "off", "full" or "thin"
+    strip_tool: Option<String>, // e.g. "llvm-strip"; objcopy is taken from beside it; default: the toolchain's strip
+    fortran_compiler: Option<String>, // compiles .f/.f90 sources; default: the gfortran matching the C compiler
+    cc: Option<String>, // compiles .c sources; default: `compiler` or its C counterpart, e.g. gcc for g++
+    cxx: Option<String>, // compiles .cpp/.cc/.cxx sources; default: `compiler` or its C++ counterpart
+    c_standard: Option<String>, // default: `standard` when it is a C standard
+    cxx_standard: Option<String>, // default: `standard` when it is a C++ standard
+    file_flags: Option<BTreeMap<String, FileFlags>>, // keyed by a source path or glob, relative to the project
+    source_set: Option<Vec<SourceSet>>,
+    public_include_dirs: Option<Vec<String>>, // also used by projects depending on this one; default: include_dirs
+    public_defines: Option<Vec<String>>, // "NAME" or "NAME=VALUE", here and in dependent projects
+    public_libs: Option<Vec<String>>, // linked here and into dependent projects
+}
+
+/// A `[[build.source_set]]`: sources compiled with their own C flags, defines and include directories
+/// instead of `[build] cflags`, and linked into the target, e.g. a bundled library built without warnings.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct SourceSet {
+    name: String,
+    sources: Vec<String>,
+    cflags: Option<String>,
+    defines: Option<Vec<String>>, // "NAME" or "NAME=VALUE"
+    include_dirs: Option<Vec<String>>, // searched before the target's
+}
+
+/// A `[build.file_flags]` entry: flags appended to the C flags of the sources matching its key, or a
+/// table whose flags `replace` the `[build] cflags` instead, optionally for other `files` than the key.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum FileFlags {
+    Append(String),
+    Table {
+        flags: String,
+        replace: Option<bool>,
+        files: Option<Vec<String>>,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Resources {
+    files: Vec<String>,
+    mode: Option<String>, // "c" or "objcopy"
+    prefix: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Gettext {
+    domain: Option<String>,
+    po_dir: Option<String>,
+    keywords: Option<Vec<String>>,
+    sources: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Qt {
+    version: Option<String>, // "5" or "6"
+    headers: Option<Vec<String>>,
+    ui: Option<Vec<String>>,
+    qrc: Option<Vec<String>>,
+    moc: Option<String>,
+    uic: Option<String>,
+    rcc: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Glib {
+    resources: Option<Vec<String>>,
+    schemas: Option<Vec<String>>,
+    dbus: Option<Vec<String>>,
+    dbus_interface_prefix: Option<String>,
+    dbus_namespace: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Protobuf {
+    files: Vec<String>,
+    import_dirs: Option<Vec<String>>,
+    languages: Option<Vec<String>>, // "cpp", "rust", "go"
+    grpc: Option<bool>,
+    protoc: Option<String>,
+    go_out: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Swig {
+    interfaces: Vec<String>,
+    languages: Vec<String>, // "python", "lua"
+    cplusplus: Option<bool>,
+    swig: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct PublicHeaders {
+    files: Vec<String>,
+    subdir: Option<String>, // under <prefix>/include; default: the project name
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Shaders {
+    files: Vec<String>,
+    compiler: Option<String>, // "glslc" or "glslangValidator"
+    flags: Option<String>,
+    include_dirs: Option<Vec<String>>,
+    embed: Option<bool>,
+    install_dir: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Rule {
+    command: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>, // a trailing "/" marks a directory of generated files
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct PkgFallback {
+    git: Option<String>,
+    rev: Option<String>,
+    url: Option<String>, // tarball
+    build: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Android {
+    ndk: Option<String>,
+    api: Option<u32>,
+    abis: Option<Vec<String>>,
+    pkg_config_libdir: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Embedded {
+    prefix: String,
+    cpu: Option<String>,
+    flags: Option<String>,
+    linker_script: Option<String>,
+    specs: Option<Vec<String>>,
+    outputs: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Qemu {
+    binary: String,
+    sysroot: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Matrix {
+    compilers: Option<Vec<String>>,
+    standards: Option<Vec<String>>,
+    optimize: Option<Vec<String>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
+struct Pgo {
+    train: Option<String>, // run against the instrumented binary ($target); without it, train by hand between phases
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Bolt {
+    record: String,
+    lbr: Option<bool>,
+    flags: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Memory {
+    job_mb: Option<u64>, // estimate for a compile job with no weight and no recorded peak
+    min_free_mb: Option<u64>, // RAM to keep free; jobs wait while less would remain
+    weights: Option<BTreeMap<String, u64>>, // source path glob or file name -> MB
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Cache {
+    enabled: Option<bool>, // default: true; HBUILD_CACHE=0 also turns it off
+    dir: Option<String>, // default: ~/.hbuild/cache/objects
+    max_size_mb: Option<u64>, // least recently used entries are removed beyond this; default: 5120
+    remote: Option<String>, // shared HTTP or S3-compatible cache, e.g. https://cache.hackeros.dev
+    read_only: Option<bool>, // only download from the remote; HBUILD_CACHE_READ_ONLY=1/0 overrides
+    token_env: Option<String>, // variable holding a bearer token for the remote
+    s3_region: Option<String>, // sign requests for S3 with AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Cuda {
+    nvcc: Option<String>, // default: nvcc on PATH, in $CUDA_PATH/bin or in /usr/local/cuda/bin
+    arch: Option<Vec<String>>, // GPU architectures, e.g. ["sm_80", "sm_90"]; default: nvcc's
+    flags: Option<String>, // for nvcc only, e.g. "--expt-relaxed-constexpr -lineinfo"
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Nim {
+    main: Option<String>, // main module; default: <name>.nim or main.nim, at the root or in src
+    flags: Option<String>, // passed to `nim c`, e.g. "--threads:on --mm:orc"
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Profile {
+    optimize: Option<String>,
+    cflags: Option<String>,
+    defines: Option<Vec<String>>,
+    strip: Option<bool>,
+    lto: Option<String>, // overrides [build] lto
+}
+
+/// A cross toolchain selected with `--target <name>`; the name is its target triple unless `triple` is set.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Toolchain {
+    triple: Option<String>,
+    prefix: Option<String>, // e.g. "aarch64-linux-gnu-"; default "<triple>-"
+    compiler: Option<String>, // default <prefix>gcc or <prefix>g++
+    ar: Option<String>, // default <prefix>ar
+    sysroot: Option<String>,
+    cflags: Option<String>,
+    ldflags: Option<String>,
+    pkg_config_path: Option<Vec<String>>, // the target's .pc directories; default <sysroot>/usr/lib/pkgconfig and friends
+    runner: Option<String>, // runs target binaries on this host, e.g. "qemu-aarch64 -L /usr/aarch64-linux-gnu"
+    zig: Option<bool>, // compile C and C++ with `zig cc -target <triple>` instead of a gcc toolchain
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Test {
+    sources: Vec<String>,
+    libs: Option<Vec<String>>,
+    framework: Option<String>, // "none" (one program per source), "gtest", "catch2" or "doctest"
+    exclude: Option<Vec<String>>, // project sources left out of test binaries; default main.*
+}
+
+/// `[bench]`: benchmark programs built like the tests, in release mode; see `bench::run`.
+#[derive(Debug, Deserialize, Serialize)]
+struct Bench {
+    sources: Vec<String>,
+    libs: Option<Vec<String>>,
+    framework: Option<String>, // "none" (one timed program per source) or "benchmark" (Google Benchmark)
+    args: Option<String>, // passed to every benchmark program, e.g. "--benchmark_filter=BM_parse"
+    iterations: Option<u32>, // timed runs, or Google Benchmark repetitions; default 5
+    exclude: Option<Vec<String>>, // project sources left out; default main.*
+}
+
+/// Shell commands run around `hbuild build` and `hbuild install`; see `hooks::run`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Hooks {
+    pre_build: Option<Vec<String>>,
+    post_build: Option<Vec<String>>, // only after a successful build
+    pre_install: Option<Vec<String>>,
+    post_install: Option<Vec<String>>,
+}
+
+/// Fingerprint of each object's compile flags and input contents, from `state::fingerprint`.
+#[derive(Debug, Default, Serialize, Deserialize)]
 struct BuildState {
     hashes: HashMap<PathBuf, String>,
 }
@@ -75,6 +471,83 @@ struct HBuildConfig {
     specs: Specs,
     runtime: Option<Runtime>,
     build: Option<Build>,
+    resources: Option<Resources>,
+    gettext: Option<Gettext>,
+    qt: Option<Qt>,
+    glib: Option<Glib>,
+    protobuf: Option<Protobuf>,
+    swig: Option<Swig>,
+    shaders: Option<Shaders>,
+    #[serde(alias = "codegen")]
+    rules: Option<BTreeMap<String, Rule>>,
+    matrix: Option<Matrix>,
+    pgo: Option<Pgo>,
+    bolt: Option<Bolt>,
+    pkg_fallbacks: Option<BTreeMap<String, PkgFallback>>,
+    android: Option<Android>,
+    embedded: Option<Embedded>,
+    qemu: Option<Qemu>,
+    memory: Option<Memory>,
+    order: Option<BTreeMap<String, Vec<String>>>, // language -> languages built before it
+    test: Option<Test>,
+    bench: Option<Bench>,
+    profile: Option<BTreeMap<String, Profile>>,
+    toolchain: Option<BTreeMap<String, Toolchain>>,
+    hooks: Option<Hooks>,
+    env: Option<BTreeMap<String, String>>, // exported to everything the build runs; see env::export
+    public_headers: Option<PublicHeaders>,
+    cache: Option<Cache>,
+    cuda: Option<Cuda>,
+    nim: Option<Nim>,
+}
+
+/// Per-invocation overrides of the configured build, e.g. one cell of `hbuild matrix`.
+#[derive(Debug, Clone, Default)]
+struct BuildOptions {
+    build_dir: Option<PathBuf>,
+    compiler: Option<String>,
+    standard: Option<String>,
+    optimize: Option<String>,
+    jobs: Option<usize>,
+    extra_flags: Option<String>,
+    cross: Option<cross::Cross>,
+    why: Option<PathBuf>, // explain why this file is dirty instead of building
+    graph: Option<String>, // print the include graph in this format instead of building
+    lint: Option<String>, // run this linter over the C/C++ sources instead of building
+    strip: bool,
+    no_cache: bool, // build without the artifact cache, whose entries lack e.g. coverage notes
+    lto: Option<String>, // from the profile; overrides [build] lto
+    profile: Option<String>,
+}
+
+impl BuildOptions {
+    fn build_dir(&self, path: &Path) -> PathBuf {
+        self.build_dir.clone().unwrap_or_else(|| path.join("build"))
+    }
+
+    /// Where the linked target goes: the project root by default, inside the build dir when it is overridden
+    /// so isolated builds don't clobber each other.
+    fn target_dir(&self, path: &Path) -> PathBuf {
+        self.build_dir.clone().unwrap_or_else(|| path.to_path_buf())
+    }
+}
+
+/// Outputs of a code generation step that feed into the C/C++ compile and link.
+#[derive(Debug, Default)]
+struct Generated {
+    sources: Vec<PathBuf>,
+    objects: Vec<PathBuf>,
+    include_dirs: Vec<PathBuf>,
+    ldflags: Vec<OsString>,
+}
+
+impl Generated {
+    fn extend(&mut self, other: Generated) {
+        self.sources.extend(other.sources);
+        self.objects.extend(other.objects);
+        self.include_dirs.extend(other.include_dirs);
+        self.ldflags.extend(other.ldflags);
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -82,9 +555,7 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let children_clone = children.clone();
     ctrlc::set_handler(move || {
         let guards = children_clone.lock().unwrap();
-        for &pid in guards.iter() {
-            let _ = Command::new("kill").arg("-9").arg(pid.to_string()).status();
-        }
+        cancel::cancel(&guards);
         std::process::exit(1);
     })?;
 
@@ -96,9 +567,94 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             return Ok(());
         }
     };
-    let folder: String = match parser.next()? {
-        Some(Value(val)) => val.string()?,
-        _ => {
+    let mut folder: Option<String> = None;
+    let mut flag_sets: Vec<String> = vec![];
+    let mut file: Option<String> = None;
+    let mut bench: Option<String> = None;
+    let mut runs: usize = 5;
+    let mut diff = false;
+    let mut duplicates = false;
+    let mut strip = false;
+    let mut service = false;
+    let mut interactive = false;
+    let mut invert: Option<String> = None;
+    let mut target_triple: Option<String> = None;
+    let mut remote: Option<String> = None;
+    let mut container: Option<String> = None;
+    let mut config: Option<String> = None;
+    let mut command: Vec<String> = vec![];
+    let mut jobs: Option<usize> = None;
+    let mut profile: Option<String> = None;
+    let mut sanitize: Option<String> = None;
+    let mut phase: Option<String> = None;
+    let mut exec_after = false;
+    let mut prefix: Option<String> = None;
+    let mut destdir: Option<String> = None;
+    let mut message_format = "human".to_string();
+    let mut verbosity: i8 = 0;
+    let mut graph_format = "dot".to_string();
+    let mut check = false;
+    let mut linter: Option<String> = None;
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Value(val) if folder.is_none() => folder = Some(val.string()?),
+            Value(val) if subcommand == "why" && file.is_none() => file = Some(val.string()?),
+            Value(val) if subcommand == "update" => command.push(val.string()?),
+            Value(val) if subcommand == "pgo" && phase.is_none() => phase = Some(val.string()?),
+            Value(val) if subcommand == "exec" || subcommand == "run" => {
+                // The command or program arguments are passed through untouched
+                command.push(val.string()?);
+                for arg in parser.raw_args()? {
+                    command.push(arg.string()?);
+                }
+            }
+            Long("flags") => {
+                // Flag sets start with '-', so take raw arguments up to the next long option
+                let mut raw = parser.raw_args()?;
+                while let Some(set) = raw.next_if(|a| !a.to_string_lossy().starts_with("--")) {
+                    flag_sets.push(set.string()?);
+                }
+            }
+            Long("bench") => bench = Some(parser.value()?.string()?),
+            Long("runs") => runs = parser.value()?.parse()?,
+            Long("diff") => diff = true,
+            Long("duplicates") => duplicates = true,
+            Long("strip") => strip = true,
+            Long("service") => service = true,
+            Long("offline") => vendor::set_offline(),
+            Long("auto-install-deps") => syspkg::set_auto_install(),
+            Long("distribute") => distribute::enable(),
+            Long("interactive") => interactive = true,
+            Long("check") => check = true,
+            Long("invert") => invert = Some(parser.value()?.string()?),
+            Long("target-triple") | Long("target") => target_triple = Some(parser.value()?.string()?),
+            Long("remote") => remote = Some(parser.value()?.string()?),
+            Long("container") => container = Some(parser.value()?.string()?),
+            Long("config") => config = Some(parser.value()?.string()?),
+            Short('j') | Long("jobs") => jobs = Some(parser.value()?.parse()?),
+            Long("profile") => profile = Some(parser.value()?.string()?),
+            Long("release") => profile = Some("release".to_string()),
+            Long("sanitize") => sanitize = Some(parser.value()?.string()?),
+            Long("exec") => exec_after = true,
+            Long("prefix") => prefix = Some(parser.value()?.string()?),
+            Long("destdir") => destdir = Some(parser.value()?.string()?),
+            Long("message-format") => message_format = parser.value()?.string()?,
+            Long("format") => graph_format = parser.value()?.string()?,
+            Long("linter") => linter = Some(parser.value()?.string()?),
+            Short('v') | Long("verbose") => verbosity = verbosity.max(0) + 1,
+            Short('q') | Long("quiet") => verbosity = -1,
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+    match message_format.as_str() {
+        "human" => {}
+        "json" => messages::enable_json(),
+        other => return Err(format!("Unknown --message-format '{}' (expected human or json)", other).into()),
+    }
+    verbosity::set(verbosity);
+    let folder = match folder {
+        Some(folder) => folder,
+        None => {
             eprintln!("{}", "Missing folder argument".red().bold());
             print_help();
             return Ok(());
@@ -109,15 +665,84 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         eprintln!("{}", format!("Folder '{}' does not exist", folder).red().bold());
         return Ok(());
     }
+    if let Some(jobs) = jobs {
+        if jobs == 0 {
+            return Err("--jobs must be at least 1".into());
+        }
+        // Dependency, matrix and fallback builds all read the limit from here
+        std::env::set_var("HBUILD_JOBS", jobs.to_string());
+    }
+    job_count()?;
+    vendor::use_project(&project_path);
+    let config = config.or_else(|| std::env::var("HBUILD_CONFIG").ok().filter(|c| !c.is_empty()));
+    if let Some(selection) = &config {
+        select_config(&project_path, selection)?;
+    }
+    // [env] applies to everything the command runs; make_with exports each dependency's own on top
+    let _env = match find_config_file(&project_path) {
+        Some((config_path, format)) if subcommand != "setup" && subcommand != "fmt" => Some(env::export(&parse_config(&config_path, &format)?)),
+        _ => None,
+    };
     match subcommand.as_str() {
-        "setup" => setup(&project_path)?,
-        "make" => make(&project_path, &children)?,
+        "setup" => setup::run(&project_path, interactive)?,
+        "make" if remote.is_some() || container.is_some() => {
+            let mut make_args: Vec<String> = target_triple.iter().flat_map(|t| ["--target-triple".to_string(), t.clone()]).collect();
+            if let Some((config_path, _)) = config.as_ref().and(find_config_file(&project_path)) {
+                let relative = config_path.strip_prefix(project_path.canonicalize()?).map_err(|_| "--config must point inside the project for --remote and --container builds")?;
+                make_args.extend(["--config".to_string(), relative.display().to_string()]);
+            }
+            if let Some(jobs) = jobs {
+                make_args.extend(["--jobs".to_string(), jobs.to_string()]);
+            }
+            if let Some(profile) = &profile {
+                make_args.extend(["--profile".to_string(), profile.clone()]);
+            }
+            if let Some(sanitize) = &sanitize {
+                make_args.extend(["--sanitize".to_string(), sanitize.clone()]);
+            }
+            match verbosity {
+                -1 => make_args.push("-q".to_string()),
+                0 => {}
+                n => make_args.push(format!("-{}", "v".repeat(n as usize))),
+            }
+            match (&remote, &container) {
+                (Some(_), Some(_)) => return Err("--remote and --container cannot be combined".into()),
+                (Some(host), None) => remote::make(&project_path, host, &make_args)?,
+                (None, Some(image)) => container::make(&project_path, image, &make_args)?,
+                (None, None) => unreachable!(),
+            }
+        }
+        "make" => make_with(&project_path, &children, &target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?)?,
         "clean" => clean(&project_path)?,
         "remake" => {
             clean(&project_path)?;
-            make(&project_path, &children)?;
+            make_with(&project_path, &children, &target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?)?;
         }
-        "install" => install(&project_path)?,
+        "install" => install::run(&project_path, &install::Layout::new(prefix, destdir)?, &target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?, strip, service)?,
+        "package" => package::package(&project_path, &children)?,
+        "uninstall" => install::uninstall(&project_path, &install::Layout::new(prefix, destdir)?)?,
+        "matrix" => matrix::run(&project_path, &children)?,
+        "android" => android::run(&project_path, &children)?,
+        "bolt" => bolt::record(&project_path, &children)?,
+        "size" => size::run(&project_path, diff)?,
+        "pgo" => pgo::run(&project_path, &children, target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?.cross.as_ref(), phase.as_deref())?,
+        "compare" => compare::run(&project_path, &children, &flag_sets, bench.as_deref(), runs, target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?.cross.as_ref())?,
+        "coverage" => coverage::run(&project_path, &children, &target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?)?,
+        "bench" => bench::run(&project_path, &children, &target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?)?,
+        "test" => test::run(&project_path, &children, &target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?)?,
+        "run" => run::run(&project_path, &children, &target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?, &command)?,
+        "watch" => watch::run(&project_path, &children, &target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?, exec_after)?,
+        "update" => gitdep::update(&project_path, &command)?,
+        "vendor" => vendor::run(&project_path)?,
+        "exec" => exec::run(&project_path, &target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?, &command)?,
+        "pot" => pot(&project_path)?,
+        "check" => validate::run(&project_path)?,
+        "fmt" => fmt::run(&project_path, config.is_some(), check)?,
+        "doctor" => doctor::run(&project_path, &target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?)?,
+        "tree" => tree::run(&project_path, duplicates, invert.as_deref())?,
+        "lint" => lint::run(&project_path, &children, &target_options(&project_path, target_triple.as_deref(), profile.as_deref(), sanitize.as_deref())?, linter.as_deref())?,
+        "graph" => graph::run(&project_path, &children, &graph_format)?,
+        "why" => why::run(&project_path, &children, file.as_deref().ok_or("why needs a file: hbuild why <folder> <file>")?)?,
         _ => {
             eprintln!("{}", "Unknown subcommand".red().bold());
             print_help();
@@ -128,24 +753,114 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
 fn print_help() {
     println!("{}", "hbuild - Modern build tool for HackerOS (Linux only)".green().bold());
-    println!("Usage: hbuild <subcommand> <folder>");
+    println!("Usage: hbuild <subcommand> <folder> [--config <name|path>] [-j <jobs>]");
     println!("Subcommands:");
-    println!(" setup - Initialize project configuration");
+    println!(" setup - Initialize project configuration (--interactive to answer prompts)");
     println!(" make - Build the project");
+    println!("   --target <name>           Cross-compile with [toolchain.<name>] or a built-in preset (e.g. riscv64gc-linux-gnu); also --target-triple");
+    println!("   --remote user@host        Sync the project and build over SSH");
+    println!("   --container <image>       Build inside a podman/docker container");
+    println!("   --profile <name>          Apply [profile.<name>] and build into build/<name> (--release for release)");
+    println!("   --sanitize <list>         Build with sanitizers, e.g. address,undefined, into their own build directory");
+    println!("   --distribute              Also compile C/C++ on the workers in ~/.config/hbuild/workers.toml over SSH, linking locally");
     println!(" clean - Clean build artifacts");
     println!(" remake - Clean and rebuild");
     println!(" install - Install built artifacts to system paths");
+    println!("   --prefix <dir>            Install under dir instead of /usr/local; also PREFIX");
+    println!("   --destdir <dir>           Stage the installation under dir, e.g. for packaging; also DESTDIR");
+    println!("   --profile <name>          Install the build of [profile.<name>], with the .debug files a strip = true profile split off");
+    println!("   --strip                   Strip installed binaries, with their debug info in .debug files under /usr/lib/debug");
+    println!("   --service                 Also install a systemd unit for the executable from [runtime], reloading systemd when not staged");
+    println!(" uninstall - Remove the files recorded by install (same --prefix and --destdir)");
+    println!(" package - Build and pack the installed files as a tarball, plus .deb/.rpm when dpkg-deb/rpmbuild exist");
+    println!(" android - Build the C/C++ target for each [android] ABI with the NDK");
+    println!(" bench - Build the [bench] programs in release mode, run them and compare with the previous results in build/bench/");
+    println!(" bolt - Record a perf profile of the [bolt] command and optimize the executable with llvm-bolt");
+    println!(" check - Validate the config without building, reporting each problem's line and likely misspellings");
+    println!(" compare - Build with each --flags set and compare sizes (and --bench <cmd> timings)");
+    println!(" coverage - Build and run the [test] binaries with --coverage, then print lines covered per file and write build/coverage/lcov.info (and html/ with genhtml)");
+    println!(" doctor - Check that the tools and pkg_dependencies the build needs are installed and print versions and fixes (--target for a cross toolchain)");
+    println!(" exec - Run a command with the build's toolchain, flags and library paths (hbuild exec <folder> -- <command>)");
+    println!(" fmt - Rewrite the project's config files in canonical form, keeping comments where the format's parser does (--check to only list unformatted ones, failing if any)");
+    println!(" graph - Print the source and header include graph as Graphviz DOT (--format json for JSON)");
+    println!(" lint - Run clang-tidy, with the project's .clang-tidy, or cppcheck (--linter cppcheck) over the C/C++ sources with their build flags; fails on errors");
+    println!(" matrix - Build every [matrix] combination and print a pass/fail grid");
+    println!(" pgo - Build instrumented, run the [pgo] training command, rebuild with the profile (hbuild pgo <folder> [generate|use] for one phase)");
+    println!(" pot - Extract translatable strings into po/ and update catalogs");
+    println!(" run - Build and run the executable with [runtime] priority and auto-restart (hbuild run <folder> -- <args>)");
+    println!(" size - Analyze sections, symbols and objects of the target (--diff against the previous build)");
+    println!(" test - Build the [test] sources with the project's code, run them and summarize pass/fail");
+    println!(" tree - Show the dependency tree (--duplicates, --invert <dep>)");
+    println!(" update - Move git dependencies (all, or those named after the folder) to their latest commit and relock them in hbuild.lock");
+    println!(" vendor - Copy the git dependencies, and theirs, at their locked commits into vendor/, which builds then use");
+    println!(" watch - Rebuild on every change to the project's files (--exec to restart the executable after each build)");
+    println!(" why - Explain why a source, object or the target would be rebuilt (hbuild why <folder> <file>)");
+    println!("Options:");
+    println!(" --config <name|path> - Use hbuild.<name>.config (or another format's named config) or the given file; also HBUILD_CONFIG");
+    println!(" -j, --jobs <n> - Run at most n compile jobs at once (default: number of CPUs); also HBUILD_JOBS");
+    println!(" -v, -vv - Print compiler, linker and archiver command lines; with -vv also dependency scanning");
+    println!(" -q, --quiet - Hide progress; print only errors, warnings, reports and the final status");
+    println!(" --offline - Never clone, fetch or download; git dependencies must be vendored or already cached");
+    println!(" --auto-install-deps - Install missing pkg_dependencies with the system package manager (apt, dnf, pacman or xbps) instead of printing the command");
+    println!(" --message-format <human|json> - With json, print compile, diagnostic, link and install events as JSON lines on stdout and progress on stderr");
+}
+
+const CONFIG_FILES: &[(&str, &str)] = &[
+    ("hbuild.config", "hk"),
+    ("hbuilt.config", "toml"),
+    ("hbuily.config", "yaml"),
+    ("hbuilj.config", "json"),
+    ("hbuilh.config", "hcl"),
+];
+
+/// The config chosen with `--config` or `HBUILD_CONFIG`, and the project folder it applies to.
+/// Dependencies and other projects keep the default probing.
+static SELECTED_CONFIG: OnceLock<(PathBuf, PathBuf, String)> = OnceLock::new();
+
+/// Format of an explicitly given config file: from its `hbuil?` stem like the default names, else its extension.
+fn config_format(file: &Path) -> String {
+    let name = file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    if let Some((_, format)) = CONFIG_FILES.iter().find(|(f, _)| name.starts_with(&f[..6])) {
+        return format.to_string();
+    }
+    match file.extension().and_then(|e| e.to_str()) {
+        Some("toml") => "toml",
+        Some("yaml" | "yml") => "yaml",
+        Some("json") => "json",
+        Some("hcl") => "hcl",
+        _ => "hk",
+    }.to_string()
+}
+
+/// Resolves `selection` for the project at `path`: a bare name picks `hbuild.<name>.config` (or the
+/// toml/yaml/json/hcl equivalent), anything else is a path to a config file.
+fn select_config(path: &Path, selection: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let is_name = !selection.contains(['/', '.']);
+    let found = if is_name {
+        CONFIG_FILES.iter()
+        .map(|(filename, format)| (path.join(filename.replace(".config", &format!(".{}.config", selection))), format.to_string()))
+        .find(|(config_path, _)| config_path.exists())
+        .ok_or(format!("No config named '{}' in {}", selection, path.display()))?
+    } else {
+        let file = PathBuf::from(selection);
+        if !file.is_file() {
+            return Err(format!("Config file {} does not exist", selection).into());
+        }
+        let format = config_format(&file);
+        (file, format)
+    };
+    let (config_path, format) = found;
+    let _ = SELECTED_CONFIG.set((path.canonicalize()?, config_path.canonicalize()?, format));
+    Ok(())
 }
 
 fn find_config_file(path: &Path) -> Option<(PathBuf, String)> {
-    let possible_files = vec![
-        ("hbuild.config", "hk"),
-        ("hbuilt.config", "toml"),
-        ("hbuily.config", "yaml"),
-        ("hbuilj.config", "json"),
-        ("hbuilh.config", "hcl"),
-    ];
-    for (filename, format) in possible_files {
+    if let Some((project, config_path, format)) = SELECTED_CONFIG.get() {
+        if path.canonicalize().is_ok_and(|p| &p == project) {
+            return Some((config_path.clone(), format.clone()));
+        }
+    }
+    for (filename, format) in CONFIG_FILES {
         let config_path = path.join(filename);
         if config_path.exists() {
             return Some((config_path, format.to_string()));
@@ -154,21 +869,32 @@ fn find_config_file(path: &Path) -> Option<(PathBuf, String)> {
     None
 }
 
+/// Parses a config, expands its `${...}` interpolations (see `env::expand`) and validates it, printing
+/// any problems with their line in the file (once per run) and failing on errors.
 fn parse_config(config_path: &Path, format: &str) -> Result<HBuildConfig, Box<dyn std::error::Error + Send + Sync>> {
     let content = fs::read_to_string(config_path)?;
-    let config = match format {
-        "hk" => {
-            let mut hk = parse_hk(&content)?;
-            resolve_interpolations(&mut hk)?;
-            from_hk(hk)?
-        }
-        "toml" => toml::from_str::<HBuildConfig>(&content)?,
-        "yaml" => serde_yaml::from_str::<HBuildConfig>(&content)?,
-        "json" => serde_json::from_str::<HBuildConfig>(&content)?,
-        "hcl" => hcl::from_str::<HBuildConfig>(&content)?,
+    let in_file = |e: &dyn std::fmt::Display| format!("{}: {}", config_path.display(), e);
+    if format == "hk" {
+        let mut hk = parse_hk(&content).map_err(|e| in_file(&e))?;
+        env::interpolate_hk(&mut hk).map_err(|e| in_file(&e))?;
+        validate::report(config_path, &validate::check(&validate::hk_value(&hk), &content, true))?;
+        return from_hk(hk).map_err(|e| in_file(&e).into());
+    }
+    let mut value = parse_value(&content, format).map_err(|e| in_file(&e))?;
+    env::interpolate(&mut value).map_err(|e| in_file(&e))?;
+    validate::report(config_path, &validate::check(&value, &content, false))?;
+    Ok(serde_json::from_value::<HBuildConfig>(value).map_err(|e| in_file(&e))?)
+}
+
+/// The config in a format other than hk as a plain value, before interpolation and validation.
+fn parse_value(content: &str, format: &str) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(match format {
+        "toml" => toml::from_str(content)?,
+        "yaml" => serde_yaml::from_str(content)?,
+        "json" => serde_json::from_str(content)?,
+        "hcl" => hcl::from_str(content)?,
         _ => return Err("Unknown format".into()),
-    };
-    Ok(config)
+    })
 }
 
 fn from_hk(hk: HkConfig) -> Result<HBuildConfig, Box<dyn std::error::Error + Send + Sync>> {
@@ -188,6 +914,9 @@ fn from_hk(hk: HkConfig) -> Result<HBuildConfig, Box<dyn std::error::Error + Sen
     fn get_opt_bool(map: &IndexMap<String, HkValue>, key: &str) -> Option<bool> {
         map.get(key).and_then(|v| v.as_bool().ok())
     }
+    fn get_opt_u32(map: &IndexMap<String, HkValue>, key: &str) -> Option<u32> {
+        map.get(key).and_then(|v| v.as_number().ok()).map(|n| n as u32)
+    }
     fn get_vec_string(map: &IndexMap<String, HkValue>, key: &str) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
         if let Some(HkValue::Array(a)) = map.get(key) {
             a.iter().map(|v| v.as_string()).collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
@@ -212,18 +941,38 @@ fn from_hk(hk: HkConfig) -> Result<HBuildConfig, Box<dyn std::error::Error + Sen
     };
     let specs_map = get_map(&hk, "specs")?;
     let mut languages: Vec<String> = Vec::new();
-    let mut dependencies: HashMap<String, String> = HashMap::new();
+    let mut dependencies: HashMap<String, Dependency> = HashMap::new();
     for (k, v) in &specs_map {
         if k == "dependencies" {
             if let HkValue::Map(sub) = v {
                 for (sk, sv) in sub {
                     if let Ok(ss) = sv.as_string() {
-                        dependencies.insert(sk.clone(), ss);
+                        dependencies.insert(sk.clone(), Dependency::Short(ss));
                     }
                 }
             }
         } else if let HkValue::String(_) = v {
-            languages.push(k.clone());
+            // hk keys cannot contain '+'
+            languages.push(if k == "cpp" { "c++".to_string() } else { k.clone() });
+        }
+    }
+    // hk can't nest tables in [specs], so dependency tables get their own section
+    if let Ok(deps_map) = get_map(&hk, "dependencies") {
+        for (name, v) in &deps_map {
+            if let HkValue::Map(dep_map) = v {
+                if let Some(dep_path) = get_opt_string(dep_map, "path") {
+                    dependencies.insert(name.clone(), Dependency::Path { path: dep_path });
+                    continue;
+                }
+                dependencies.insert(name.clone(), Dependency::Git {
+                    git: get_string(dep_map, "git")?,
+                    branch: get_opt_string(dep_map, "branch"),
+                    tag: get_opt_string(dep_map, "tag"),
+                    rev: get_opt_string(dep_map, "rev"),
+                    build_command: get_opt_string(dep_map, "build_command"),
+                    outputs: get_opt_vec_string(dep_map, "outputs"),
+                });
+            }
         }
     }
     let specs = Specs {
@@ -234,10 +983,45 @@ fn from_hk(hk: HkConfig) -> Result<HBuildConfig, Box<dyn std::error::Error + Sen
         Some(Runtime {
             priority: get_opt_string(&run_map, "priority"),
              auto_restart: get_opt_bool(&run_map, "auto-restart"),
+             args: get_opt_vec_string(&run_map, "args"),
+             user: get_opt_string(&run_map, "user"),
+             restart: get_opt_string(&run_map, "restart"),
+             environment: run_map.get("environment").and_then(|v| if let HkValue::Map(m) = v {
+                 Some(m.iter().filter_map(|(k, v)| Some((k.clone(), v.as_string().ok()?))).collect())
+             } else {
+                 None
+             }),
         })
     } else {
         None
     };
+    // hk has neither path keys nor arrays of tables, so per-file flags and source sets are named entries of their own sections
+    let mut file_flags: Option<BTreeMap<String, FileFlags>> = None;
+    if let Ok(flags_map) = get_map(&hk, "file_flags") {
+        for (name, v) in &flags_map {
+            if let HkValue::Map(entry_map) = v {
+                file_flags.get_or_insert_with(BTreeMap::new).insert(name.clone(), FileFlags::Table {
+                    flags: get_string(entry_map, "flags")?,
+                    replace: get_opt_bool(entry_map, "replace"),
+                    files: Some(get_vec_string(entry_map, "files")?),
+                });
+            }
+        }
+    }
+    let mut source_set: Option<Vec<SourceSet>> = None;
+    if let Ok(sets_map) = get_map(&hk, "source_set") {
+        for (name, v) in &sets_map {
+            if let HkValue::Map(set_map) = v {
+                source_set.get_or_insert_with(Vec::new).push(SourceSet {
+                    name: name.clone(),
+                    sources: get_vec_string(set_map, "sources")?,
+                    cflags: get_opt_string(set_map, "cflags"),
+                    defines: get_opt_vec_string(set_map, "defines"),
+                    include_dirs: get_opt_vec_string(set_map, "include_dirs"),
+                });
+            }
+        }
+    }
     let build = if let Ok(build_map) = get_map(&hk, "build") {
         Some(Build {
             target: get_string(&build_map, "target")?,
@@ -253,6 +1037,335 @@ fn from_hk(hk: HkConfig) -> Result<HBuildConfig, Box<dyn std::error::Error + Sen
              pkg_dependencies: get_opt_vec_string(&build_map, "pkg_dependencies"),
              build_type: get_string(&build_map, "build_type")?,
              native: get_opt_bool(&build_map, "native"),
+             link_map: get_opt_bool(&build_map, "link_map"),
+             prune_system_headers: get_opt_bool(&build_map, "prune_system_headers"),
+             system_header_prefixes: get_opt_vec_string(&build_map, "system_header_prefixes"),
+             visibility: get_opt_string(&build_map, "visibility"),
+             exported_symbols: get_opt_vec_string(&build_map, "exported_symbols"),
+             version_script: get_opt_string(&build_map, "version_script"),
+             static_variant: get_opt_bool(&build_map, "static_variant"),
+             sandbox: get_opt_bool(&build_map, "sandbox"),
+             pin_toolchain: get_opt_string(&build_map, "pin_toolchain"),
+             compiler_launcher: get_opt_string(&build_map, "compiler_launcher"),
+             sanitizers: get_opt_vec_string(&build_map, "sanitizers"),
+             lto: get_opt_string(&build_map, "lto"),
+             strip_tool: get_opt_string(&build_map, "strip_tool"),
+             fortran_compiler: get_opt_string(&build_map, "fortran_compiler"),
+             cc: get_opt_string(&build_map, "cc"),
+             cxx: get_opt_string(&build_map, "cxx"),
+             c_standard: get_opt_string(&build_map, "c_standard"),
+             cxx_standard: get_opt_string(&build_map, "cxx_standard"),
+             file_flags,
+             source_set,
+             public_include_dirs: get_opt_vec_string(&build_map, "public_include_dirs"),
+             public_defines: get_opt_vec_string(&build_map, "public_defines"),
+             public_libs: get_opt_vec_string(&build_map, "public_libs"),
+        })
+    } else {
+        None
+    };
+    let resources = if let Ok(res_map) = get_map(&hk, "resources") {
+        Some(Resources {
+            files: get_vec_string(&res_map, "files")?,
+             mode: get_opt_string(&res_map, "mode"),
+             prefix: get_opt_string(&res_map, "prefix"),
+        })
+    } else {
+        None
+    };
+    let gettext = if let Ok(gt_map) = get_map(&hk, "gettext") {
+        Some(Gettext {
+            domain: get_opt_string(&gt_map, "domain"),
+             po_dir: get_opt_string(&gt_map, "po_dir"),
+             keywords: get_opt_vec_string(&gt_map, "keywords"),
+             sources: get_opt_vec_string(&gt_map, "sources"),
+        })
+    } else {
+        None
+    };
+    let qt = if let Ok(qt_map) = get_map(&hk, "qt") {
+        Some(Qt {
+            version: get_opt_string(&qt_map, "version"),
+             headers: get_opt_vec_string(&qt_map, "headers"),
+             ui: get_opt_vec_string(&qt_map, "ui"),
+             qrc: get_opt_vec_string(&qt_map, "qrc"),
+             moc: get_opt_string(&qt_map, "moc"),
+             uic: get_opt_string(&qt_map, "uic"),
+             rcc: get_opt_string(&qt_map, "rcc"),
+        })
+    } else {
+        None
+    };
+    let glib = if let Ok(glib_map) = get_map(&hk, "glib") {
+        Some(Glib {
+            resources: get_opt_vec_string(&glib_map, "resources"),
+             schemas: get_opt_vec_string(&glib_map, "schemas"),
+             dbus: get_opt_vec_string(&glib_map, "dbus"),
+             dbus_interface_prefix: get_opt_string(&glib_map, "dbus_interface_prefix"),
+             dbus_namespace: get_opt_string(&glib_map, "dbus_namespace"),
+        })
+    } else {
+        None
+    };
+    let protobuf = if let Ok(pb_map) = get_map(&hk, "protobuf") {
+        Some(Protobuf {
+            files: get_vec_string(&pb_map, "files")?,
+             import_dirs: get_opt_vec_string(&pb_map, "import_dirs"),
+             languages: get_opt_vec_string(&pb_map, "languages"),
+             grpc: get_opt_bool(&pb_map, "grpc"),
+             protoc: get_opt_string(&pb_map, "protoc"),
+             go_out: get_opt_string(&pb_map, "go_out"),
+        })
+    } else {
+        None
+    };
+    let swig = if let Ok(swig_map) = get_map(&hk, "swig") {
+        Some(Swig {
+            interfaces: get_vec_string(&swig_map, "interfaces")?,
+             languages: get_vec_string(&swig_map, "languages")?,
+             cplusplus: get_opt_bool(&swig_map, "cplusplus"),
+             swig: get_opt_string(&swig_map, "swig"),
+        })
+    } else {
+        None
+    };
+    let shaders = if let Ok(sh_map) = get_map(&hk, "shaders") {
+        Some(Shaders {
+            files: get_vec_string(&sh_map, "files")?,
+             compiler: get_opt_string(&sh_map, "compiler"),
+             flags: get_opt_string(&sh_map, "flags"),
+             include_dirs: get_opt_vec_string(&sh_map, "include_dirs"),
+             embed: get_opt_bool(&sh_map, "embed"),
+             install_dir: get_opt_string(&sh_map, "install_dir"),
+        })
+    } else {
+        None
+    };
+    // [codegen] is another name for [rules]
+    let mut rules: Option<BTreeMap<String, Rule>> = None;
+    for section in ["rules", "codegen"] {
+        if let Ok(rules_map) = get_map(&hk, section) {
+            let rules = rules.get_or_insert_with(BTreeMap::new);
+            for (name, v) in &rules_map {
+                if let HkValue::Map(rule_map) = v {
+                    let rule = Rule {
+                        command: get_string(rule_map, "command")?,
+                        inputs: get_vec_string(rule_map, "inputs")?,
+                        outputs: get_vec_string(rule_map, "outputs")?,
+                    };
+                    if rules.insert(name.clone(), rule).is_some() {
+                        return Err(format!("'{}' is defined in both [rules] and [codegen]", name).into());
+                    }
+                }
+            }
+        }
+    }
+    let matrix = if let Ok(matrix_map) = get_map(&hk, "matrix") {
+        Some(Matrix {
+            compilers: get_opt_vec_string(&matrix_map, "compilers"),
+             standards: get_opt_vec_string(&matrix_map, "standards"),
+             optimize: get_opt_vec_string(&matrix_map, "optimize"),
+        })
+    } else {
+        None
+    };
+    let pgo = if let Ok(pgo_map) = get_map(&hk, "pgo") {
+        Some(Pgo {
+            train: get_opt_string(&pgo_map, "train"),
+        })
+    } else {
+        None
+    };
+    let bolt = if let Ok(bolt_map) = get_map(&hk, "bolt") {
+        Some(Bolt {
+            record: get_string(&bolt_map, "record")?,
+             lbr: get_opt_bool(&bolt_map, "lbr"),
+             flags: get_opt_string(&bolt_map, "flags"),
+        })
+    } else {
+        None
+    };
+    let pkg_fallbacks = if let Ok(fallbacks_map) = get_map(&hk, "pkg_fallbacks") {
+        let mut fallbacks = BTreeMap::new();
+        for (name, v) in &fallbacks_map {
+            if let HkValue::Map(fallback_map) = v {
+                fallbacks.insert(name.clone(), PkgFallback {
+                    git: get_opt_string(fallback_map, "git"),
+                    rev: get_opt_string(fallback_map, "rev"),
+                    url: get_opt_string(fallback_map, "url"),
+                    build: get_opt_string(fallback_map, "build"),
+                });
+            }
+        }
+        Some(fallbacks)
+    } else {
+        None
+    };
+    let android = if let Ok(android_map) = get_map(&hk, "android") {
+        Some(Android {
+            ndk: get_opt_string(&android_map, "ndk"),
+             api: get_opt_u32(&android_map, "api"),
+             abis: get_opt_vec_string(&android_map, "abis"),
+             pkg_config_libdir: get_opt_string(&android_map, "pkg_config_libdir"),
+        })
+    } else {
+        None
+    };
+    let embedded = if let Ok(embedded_map) = get_map(&hk, "embedded") {
+        Some(Embedded {
+            prefix: get_string(&embedded_map, "prefix")?,
+             cpu: get_opt_string(&embedded_map, "cpu"),
+             flags: get_opt_string(&embedded_map, "flags"),
+             linker_script: get_opt_string(&embedded_map, "linker_script"),
+             specs: get_opt_vec_string(&embedded_map, "specs"),
+             outputs: get_opt_vec_string(&embedded_map, "outputs"),
+        })
+    } else {
+        None
+    };
+    let qemu = if let Ok(qemu_map) = get_map(&hk, "qemu") {
+        Some(Qemu {
+            binary: get_string(&qemu_map, "binary")?,
+             sysroot: get_opt_string(&qemu_map, "sysroot"),
+        })
+    } else {
+        None
+    };
+    let memory = if let Ok(memory_map) = get_map(&hk, "memory") {
+        let weights = memory_map.get("weights").and_then(|v| if let HkValue::Map(m) = v {
+            Some(m.iter().filter_map(|(k, v)| Some((k.clone(), v.as_number().ok()? as u64))).collect())
+        } else {
+            None
+        });
+        Some(Memory {
+            job_mb: get_opt_u32(&memory_map, "job_mb").map(u64::from),
+             min_free_mb: get_opt_u32(&memory_map, "min_free_mb").map(u64::from),
+             weights,
+        })
+    } else {
+        None
+    };
+    let order = if let Ok(order_map) = get_map(&hk, "order") {
+        // hk keys cannot contain '+'
+        let language = |l: &str| if l == "cpp" { "c++".to_string() } else { l.to_string() };
+        let mut order = BTreeMap::new();
+        for lang in order_map.keys() {
+            order.insert(language(lang), get_vec_string(&order_map, lang)?.iter().map(|d| language(d)).collect());
+        }
+        Some(order)
+    } else {
+        None
+    };
+    let test = if let Ok(test_map) = get_map(&hk, "test") {
+        Some(Test {
+            sources: get_vec_string(&test_map, "sources")?,
+             libs: get_opt_vec_string(&test_map, "libs"),
+             framework: get_opt_string(&test_map, "framework"),
+             exclude: get_opt_vec_string(&test_map, "exclude"),
+        })
+    } else {
+        None
+    };
+    let bench = if let Ok(bench_map) = get_map(&hk, "bench") {
+        Some(Bench {
+            sources: get_vec_string(&bench_map, "sources")?,
+             libs: get_opt_vec_string(&bench_map, "libs"),
+             framework: get_opt_string(&bench_map, "framework"),
+             args: get_opt_string(&bench_map, "args"),
+             iterations: get_opt_u32(&bench_map, "iterations"),
+             exclude: get_opt_vec_string(&bench_map, "exclude"),
+        })
+    } else {
+        None
+    };
+    let profile = if let Ok(profiles_map) = get_map(&hk, "profile") {
+        let mut profiles = BTreeMap::new();
+        for (name, v) in &profiles_map {
+            if let HkValue::Map(profile_map) = v {
+                profiles.insert(name.clone(), Profile {
+                    optimize: get_opt_string(profile_map, "optimize"),
+                    cflags: get_opt_string(profile_map, "cflags"),
+                    defines: get_opt_vec_string(profile_map, "defines"),
+                    strip: get_opt_bool(profile_map, "strip"),
+                    lto: get_opt_string(profile_map, "lto"),
+                });
+            }
+        }
+        Some(profiles)
+    } else {
+        None
+    };
+    let toolchain = if let Ok(toolchains_map) = get_map(&hk, "toolchain") {
+        let mut toolchains = BTreeMap::new();
+        for (name, v) in &toolchains_map {
+            if let HkValue::Map(toolchain_map) = v {
+                toolchains.insert(name.clone(), Toolchain {
+                    triple: get_opt_string(toolchain_map, "triple"),
+                    prefix: get_opt_string(toolchain_map, "prefix"),
+                    compiler: get_opt_string(toolchain_map, "compiler"),
+                    ar: get_opt_string(toolchain_map, "ar"),
+                    sysroot: get_opt_string(toolchain_map, "sysroot"),
+                    cflags: get_opt_string(toolchain_map, "cflags"),
+                    ldflags: get_opt_string(toolchain_map, "ldflags"),
+                    pkg_config_path: get_opt_vec_string(toolchain_map, "pkg_config_path"),
+                    runner: get_opt_string(toolchain_map, "runner"),
+                    zig: get_opt_bool(toolchain_map, "zig"),
+                });
+            }
+        }
+        Some(toolchains)
+    } else {
+        None
+    };
+    let env = if let Ok(env_map) = get_map(&hk, "env") {
+        Some(env_map.iter().filter_map(|(k, v)| Some((k.clone(), v.as_string().ok()?))).collect())
+    } else {
+        None
+    };
+    let hooks = if let Ok(hooks_map) = get_map(&hk, "hooks") {
+        Some(Hooks {
+            pre_build: get_opt_vec_string(&hooks_map, "pre_build"),
+             post_build: get_opt_vec_string(&hooks_map, "post_build"),
+             pre_install: get_opt_vec_string(&hooks_map, "pre_install"),
+             post_install: get_opt_vec_string(&hooks_map, "post_install"),
+        })
+    } else {
+        None
+    };
+    let public_headers = if let Ok(headers_map) = get_map(&hk, "public_headers") {
+        Some(PublicHeaders {
+            files: get_vec_string(&headers_map, "files")?,
+             subdir: get_opt_string(&headers_map, "subdir"),
+        })
+    } else {
+        None
+    };
+    let cache = if let Ok(cache_map) = get_map(&hk, "cache") {
+        Some(Cache {
+            enabled: get_opt_bool(&cache_map, "enabled"),
+             dir: get_opt_string(&cache_map, "dir"),
+             max_size_mb: get_opt_u32(&cache_map, "max_size_mb").map(u64::from),
+             remote: get_opt_string(&cache_map, "remote"),
+             read_only: get_opt_bool(&cache_map, "read_only"),
+             token_env: get_opt_string(&cache_map, "token_env"),
+             s3_region: get_opt_string(&cache_map, "s3_region"),
+        })
+    } else {
+        None
+    };
+    let cuda = if let Ok(cuda_map) = get_map(&hk, "cuda") {
+        Some(Cuda {
+            nvcc: get_opt_string(&cuda_map, "nvcc"),
+             arch: get_opt_vec_string(&cuda_map, "arch"),
+             flags: get_opt_string(&cuda_map, "flags"),
+        })
+    } else {
+        None
+    };
+    let nim = if let Ok(nim_map) = get_map(&hk, "nim") {
+        Some(Nim {
+            main: get_opt_string(&nim_map, "main"),
+             flags: get_opt_string(&nim_map, "flags"),
         })
     } else {
         None
@@ -263,63 +1376,88 @@ fn from_hk(hk: HkConfig) -> Result<HBuildConfig, Box<dyn std::error::Error + Sen
        specs,
        runtime,
        build,
+       resources,
+       gettext,
+       qt,
+       glib,
+       protobuf,
+       swig,
+       shaders,
+       rules,
+       matrix,
+       pgo,
+       bolt,
+       pkg_fallbacks,
+       android,
+       embedded,
+       qemu,
+       memory,
+       order,
+       test,
+       bench,
+       profile,
+       toolchain,
+       hooks,
+       env,
+       public_headers,
+       cache,
+       cuda,
+       nim,
     })
 }
 
-fn setup(path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    println!("{}", "Setting up project...".blue().bold());
-    let config_path = path.join("hbuild.config");
-    if config_path.exists() {
-        println!("{}", "Config already exists".yellow().bold());
-        return Ok(());
-    }
-    let mut file = File::create(&config_path)?;
-    let example = r#"
-    [build]
-    -> target => "my_app"
-    -> sources => ["src/*.cpp"]
-    -> include_dirs => ["include"]
-    -> compiler => "g++"
-    -> standard => "c++20"
-    -> optimize => "O3"
-    -> build_type => "executable"
-    -> native => true
-    -> pkg_dependencies => ["glib-2.0"]
-    "#;
-    file.write_all(example.as_bytes())?;
-    println!("{}", "Setup complete!".green().bold());
-    Ok(())
+/// Dependencies given as a git URL are cloned into the cache; anything else is a registry version.
+fn is_git_url(spec: &str) -> bool {
+    let scp_like = spec.split_once(':').is_some_and(|(host, _)| host.contains('@') && !host.contains('/'));
+    spec.starts_with("https://") && spec.ends_with(".git") || spec.starts_with("git://") || spec.starts_with("ssh://") || scp_like
 }
 
+/// Fetches the project's dependencies: git ones at the commit locked in `hbuild.lock`, locking the
+/// latest commit of any that aren't yet, path dependencies built in place, and registry versions through
+/// the language's package manager.
 fn install_deps(config: &HBuildConfig, path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let home = home_dir().ok_or("Cannot find home directory")?;
-    let cache = home.join(".hbuild/cache");
-    fs::create_dir_all(&cache)?;
-    for (name, url_or_ver) in &config.specs.dependencies {
-        if url_or_ver.starts_with("https://") && url_or_ver.ends_with(".git") || url_or_ver.starts_with("git://") {
-            let dep_dir = cache.join(name);
-            if !dep_dir.exists() {
-                Repository::clone(url_or_ver, &dep_dir)?;
-            } else {
-                let repo = Repository::open(&dep_dir)?;
-                let mut remote = repo.find_remote("origin")?;
-                let mut fetch_options = FetchOptions::new();
-                remote.fetch(&["master"], Some(&mut fetch_options), None)?;
-            }
-            if find_config_file(&dep_dir).is_some() {
-                make(&dep_dir, &Arc::new(Mutex::new(Vec::new())))?;
-            }
-        } else if config.specs.languages.contains(&"rust".to_string()) {
+    let mut lockfile = lock::read(path)?;
+    lockfile.git.retain(|name, _| config.specs.dependencies.get(name).is_some_and(|dep| dep.git().is_some()));
+    let checkpoint = checkpoint::Checkpoint::open(&path.join("build"), "deps")?;
+    if checkpoint.resuming() {
+        verbosity::progress("Resuming interrupted dependency builds".yellow());
+    }
+    for (name, dep) in &config.specs.dependencies {
+        let step = format!("dep {}", name);
+        if checkpoint.is_done(&step) {
+            continue;
+        }
+        checkpoint.start(&step)?;
+        if let Some(source) = dep.git() {
+            // A vendored copy is used as is; `hbuild vendor` is what moves it
+            let (dep_dir, commit) = match vendor::vendored(name, &source, &lockfile)? {
+                Some(dir) => (dir, lockfile.git.get(name).map(|l| l.commit.clone()).unwrap_or_default()),
+                None => {
+                    let commit = gitdep::sync(name, &source, &mut lockfile, false)?;
+                    (gitdep::cache_dir()?.join(name), commit)
+                }
+            };
+            gitdep::build(name, dep, &dep_dir, &commit)?;
+            // Locked as each finishes, so one an interrupted run built, skipped when resuming, is too
+            lock::write(path, &lockfile)?;
+        } else if let Some(dep_dir) = dep.path(path) {
+            pathdep::build(name, &dep_dir)?;
+        } else if let Some(version) = dep.version().filter(|_| config.specs.languages.contains(&"rust".to_string())) {
             let status = Command::new("cargo")
-            .args(["add", name, "--vers", url_or_ver])
+            .args(["add", name, "--vers", version])
+            .args(vendor::offline().then_some("--offline"))
             .current_dir(path)
             .status()?;
             if !status.success() {
                 eprintln!("{}", format!("Failed to add Rust dependency {}", name).red().bold());
             }
         }
+        checkpoint.finish(&step)?;
     }
-    Ok(())
+    if !lockfile.git.is_empty() || lock::path(path).exists() {
+        lock::write(path, &lockfile)?;
+    }
+    checkpoint.complete()
 }
 
 fn needs_recompile(
@@ -336,15 +1474,13 @@ fn needs_recompile(
         Ok(meta) => meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
         Err(_) => return true,
     };
-    let res = if !obj.exists() || file_mtime > obj_mtime {
-        true
-    } else {
-        false
-    };
+    let res = !obj.exists() || file_mtime > obj_mtime;
     if res {
         cache.insert(file.clone(), true);
         return true;
     }
+    // Mark as visited before recursing; headers list themselves in their own -MM output
+    cache.insert(file.clone(), false);
     if let Some(d) = deps.get(file) {
         for dep in d {
             if needs_recompile(dep, obj, deps, cache, obj_mtime) {
@@ -357,166 +1493,638 @@ fn needs_recompile(
     false
 }
 
-fn get_dependencies(compiler: &str, file: &Path, include_flags: &str) -> Result<HashSet<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
-    let output = Command::new(compiler)
-    .arg("-MM")
-    .arg(file.to_str().unwrap())
-    .args(include_flags.split_whitespace())
-    .output()?;
-    if !output.status.success() {
-        return Err(format!("Failed to get dependencies for {}", file.display()).into());
+/// FNV-1a hash of `bytes`, as hex.
+fn hash_bytes(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3));
+    format!("{:016x}", hash)
+}
+
+/// FNV-1a hash of a file's contents, as hex; None when it cannot be read.
+fn hash_file(path: &Path) -> Option<String> {
+    fs::read(path).ok().map(|bytes| hash_bytes(&bytes))
+}
+
+/// Compile jobs to run at once: `HBUILD_JOBS` (also set by `--jobs`), else one per CPU.
+fn job_count() -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    match std::env::var("HBUILD_JOBS") {
+        Ok(jobs) if !jobs.is_empty() => jobs.parse().ok().filter(|&j| j > 0).ok_or_else(|| format!("HBUILD_JOBS must be a positive number, got '{}'", jobs).into()),
+        _ => Ok(num_cpus::get()),
+    }
+}
+
+fn mtime(path: &Path) -> SystemTime {
+    path.metadata().and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Expands project-relative glob patterns into the matching paths, leaving out `.hbuildignore`d files.
+fn expand_globs(path: &Path, patterns: &[String]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    let ignore = ignore::Ignore::load(path);
+    let mut files = vec![];
+    for pattern in patterns {
+        for entry in glob(path.join(pattern).to_str().ok_or("Invalid path")?)? {
+            let entry = entry?;
+            if !ignore.is_ignored(path, &entry) {
+                files.push(entry);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// True when `output` is missing or older than `input`.
+fn is_stale(input: &Path, output: &Path) -> bool {
+    !output.exists() || mtime(input) > mtime(output)
+}
+
+/// Writes `content` only when it differs from what is on disk, keeping mtimes stable for incremental builds.
+fn write_if_changed(path: &Path, content: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if fs::read_to_string(path).map(|old| old == content).unwrap_or(false) {
+        return Ok(());
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Directories whose headers are treated as immutable and left out of the dependency graph:
+/// `system_header_prefixes` (default `/usr/include`, `/usr/local/include`) plus the compiler's own headers.
+fn system_header_prefixes(compiler: &str, build: &Build) -> Vec<PathBuf> {
+    if !build.prune_system_headers.unwrap_or(true) {
+        return vec![];
+    }
+    let mut prefixes: Vec<PathBuf> = match &build.system_header_prefixes {
+        Some(list) => list.iter().map(PathBuf::from).collect(),
+        None => platform::system_include_dirs(),
+    };
+    // e.g. /usr/lib/gcc/x86_64-linux-gnu/12/include -> covers include and include-fixed
+    if let Ok(output) = Command::new(compiler).arg("-print-file-name=include").output() {
+        let dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        if output.status.success() && dir.is_absolute() {
+            if let Some(parent) = dir.parent() {
+                prefixes.push(parent.to_path_buf());
+            }
+        }
     }
-    let dep_str = String::from_utf8_lossy(&output.stdout);
-    let deps: Vec<&str> = dep_str.split(':').nth(1).unwrap_or("").split_whitespace().collect();
+    prefixes.into_iter().map(|p| p.canonicalize().unwrap_or(p)).collect()
+}
+
+/// The headers `file` includes. With `missing_ok`, headers not generated yet are taken for ones that will be.
+fn get_dependencies(compiler: &str, file: &Path, include_flags: &[OsString], pruned: &[PathBuf], missing_ok: bool) -> Result<HashSet<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    let deps: Vec<PathBuf> = if msvc::is_msvc(compiler) {
+        msvc::dependencies(compiler, file, include_flags)?.into_iter().collect()
+    } else {
+        let mut command = Command::new(compiler);
+        command.arg("-MM").arg(file).args(include_flags);
+        if missing_ok {
+            command.arg("-MG");
+        }
+        if verbosity::level() >= 2 {
+            verbosity::command(&command);
+        }
+        let output = command.output()?;
+        if !output.status.success() {
+            return Err(format!("Failed to get dependencies for {}", file.display()).into());
+        }
+        args::make_prerequisites(&String::from_utf8_lossy(&output.stdout))
+    };
     let mut dep_set = HashSet::new();
-    for d in deps {
-        let dep_path = PathBuf::from(d);
+    for dep_path in deps {
         if dep_path.exists() {
-            dep_set.insert(dep_path.canonicalize()?);
+            let dep_path = dep_path.canonicalize()?;
+            if !pruned.iter().any(|p| dep_path.starts_with(p)) {
+                dep_set.insert(dep_path);
+            }
         }
     }
+    verbosity::debug(&format!("Scanned {}: {} dependencies", file.display(), dep_set.len()));
     Ok(dep_set)
 }
 
-fn compile_c_cpp(config: &HBuildConfig, path: &Path, children: &Arc<Mutex<Vec<u32>>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+fn compile_c_cpp(config: &HBuildConfig, path: &Path, children: &Arc<Mutex<Vec<u32>>>, opts: &BuildOptions) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let build = config.build.as_ref().ok_or("No build section for C/C++")?;
-    let compiler = &build.compiler;
-    let std_flag = format!("-std={}", build.standard);
-    let opt_flag = format!("-{}", build.optimize);
-    let mut cflags = build.cflags.clone().unwrap_or_default();
-    let mut ldflags = build.ldflags.clone().unwrap_or_default();
-    let include_dirs: Vec<PathBuf> = build.include_dirs.iter().map(|d| path.join(d)).collect();
-    let mut include_flags = include_dirs.iter().map(|d| format!("-I{}", d.display())).collect::<Vec<_>>().join(" ");
+    // An [embedded] project is always built with its firmware toolchain
+    let embedded = config.embedded.as_ref().filter(|_| opts.cross.is_none()).map(|e| embedded::toolchain(e, config, path));
+    let cross = opts.cross.as_ref().or(embedded.as_ref());
+    let compiler = opts.compiler.as_ref().or(cross.map(|c| &c.compiler)).unwrap_or(&build.compiler);
+    // Explaining a rebuild decides what a build would do without installing, generating, pinning or writing anything
+    let dry_run = opts.why.is_some();
+    // Matrix, compare and cross builds pick their compiler on purpose, so only the default one is pinned
+    let pinned = match opts.compiler.is_none() && cross.is_none() {
+        true if dry_run => lock::pinned(config, path, compiler),
+        true => lock::check_toolchain(config, path, compiler)?,
+        false => None,
+    };
+    let compiler = pinned.as_ref().unwrap_or(compiler);
+    // C and C++ sources are each compiled by their language's driver and standard
+    let drivers = language::drivers(build, compiler, opts.compiler.is_none() && cross.is_none());
+    let language_of = |src: &Path| language::of(src).unwrap_or(language::of_driver(compiler));
+    let optimize = opts.optimize.as_ref().unwrap_or(&build.optimize);
+    let msvc = msvc::is_msvc(compiler);
+    let std_flag = |language| match language::standard(build, opts.standard.as_ref(), language) {
+        Some(standard) if msvc => msvc::std_flag(&standard),
+        Some(standard) => format!("-std={}", standard),
+        None => String::new(),
+    };
+    let (c_std_flag, cxx_std_flag) = (std_flag(Language::C), std_flag(Language::Cxx));
+    let opt_flag = if msvc { msvc::opt_flag(optimize) } else { format!("-{}", optimize) };
+    // Arguments are kept as lists from here on; flag strings from the config are split like a shell would
+    let (c_std_flags, cxx_std_flags) = (args::split(&c_std_flag), args::split(&cxx_std_flag));
+    let mut cflags = args::split(build.cflags.as_deref().unwrap_or_default());
+    let file_flags = fileflags::Overrides::new(build, path, cflags.len())?;
+    let mut ldflags = args::split(build.ldflags.as_deref().unwrap_or_default());
+    let include_dirs: Vec<PathBuf> = build.include_dirs.iter().chain(build.public_include_dirs.iter().flatten()).map(|d| path.join(d)).collect();
+    let mut include_flags: Vec<OsString> = include_dirs.iter().map(|d| args::with_path("-I", d)).collect();
     let lib_dirs = build.lib_dirs.clone().unwrap_or_default();
-    let lib_dir_flags = lib_dirs.iter().map(|d| format!("-L{}", path.join(d).display())).collect::<Vec<_>>().join(" ");
-    let libs = build.libs.clone().unwrap_or_default();
-    let lib_flags = libs.iter().map(|l| format!("-l{}", l)).collect::<Vec<_>>().join(" ");
-    let pkg_deps = build.pkg_dependencies.clone().unwrap_or_default();
+    let lib_dir_flags: Vec<OsString> = lib_dirs.iter().map(|d| args::with_path("-L", &path.join(d))).collect();
+    let libs: Vec<String> = build.libs.iter().chain(&build.public_libs).flatten().cloned().collect();
+    let lib_flags: Vec<OsString> = libs.iter().map(|l| OsString::from(format!("-l{}", l))).collect();
+    let pkg_deps = pkgdeps::modules(config);
+    if let Some(cross) = cross {
+        cflags.extend(args::split(&cross.cflags));
+        ldflags.extend(args::split(&cross.ldflags));
+    }
+    let pkg_mode = cross.map(|c| c.pkg_config.clone()).unwrap_or_default();
+    let ar = cross.map_or("ar", |c| c.ar.as_str());
 
     // Pkg-config
+    if !dry_run {
+        syspkg::ensure(&pkg_deps, config.pkg_fallbacks.as_ref(), &pkg_mode)?;
+    }
     for pkg in &pkg_deps {
-        if let Ok(lib) = pkg_config::probe_library(pkg) {
-            for path in &lib.include_paths {
-                include_flags.push_str(&format!(" -I{}", path.display()));
-            }
-            for (key, val) in &lib.defines {
-                if let Some(val) = val {
-                    cflags.push_str(&format!(" -D{}={}", key, val));
-                } else {
-                    cflags.push_str(&format!(" -D{}", key));
-                }
-            }
-            for path in &lib.link_paths {
-                ldflags.push_str(&format!(" -L{}", path.display()));
+        let lib = if dry_run { pkgdeps::resolve_built(pkg, &pkg_mode)? } else { pkgdeps::resolve(pkg, config.pkg_fallbacks.as_ref(), &pkg_mode)? };
+        for path in &lib.include_paths {
+            include_flags.push(args::with_path("-I", path));
+        }
+        for (key, val) in &lib.defines {
+            if let Some(val) = val {
+                cflags.push(format!("-D{}={}", key, val).into());
+            } else {
+                cflags.push(format!("-D{}", key).into());
             }
-            for l in &lib.libs {
-                ldflags.push_str(&format!(" -l{}", l));
+        }
+        cflags.extend(lib.other_cflags.iter().map(OsString::from));
+        for path in &lib.link_paths {
+            ldflags.push(args::with_path("-L", path));
+            if pkgdeps::is_fallback_path(path) {
+                ldflags.push(args::with_path("-Wl,-rpath,", path));
             }
-        } else {
-            eprintln!("{}", format!("Pkg-config failed for {}", pkg).yellow());
         }
+        ldflags.extend(lib.libs.iter().map(|l| OsString::from(format!("-l{}", l))));
+        ldflags.extend(lib.other_libs.iter().map(OsString::from));
     }
 
+    // Usage requirements: this project's public defines, and what its path dependencies export and
+    // its staged git dependencies installed, once install_deps has built them
+    cflags.extend(build.public_defines.iter().flatten().map(|d| OsString::from(format!("-D{}", d))));
+    let mut dep_usage = pathdep::usage(config, path)?;
+    dep_usage.extend(staged::usage(config)?);
+    include_flags.extend(dep_usage.include_dirs.iter().map(|dir| args::with_path("-I", dir)));
+    cflags.extend(dep_usage.define_flags());
+
     // Native
-    if build.native.unwrap_or(false) {
-        cflags.push_str(" -march=native");
+    if build.native.unwrap_or(false) && !msvc {
+        cflags.push("-march=native".into());
+    }
+
+    // Symbol visibility
+    if let Some(flag) = visibility::compile_flags(build)?.filter(|_| !msvc) {
+        cflags.push(flag.into());
     }
 
+    // Extra flags for this invocation go last so they override the configured ones
+    if let Some(extra) = &opts.extra_flags {
+        cflags.extend(args::split(extra));
+        ldflags.extend(args::split(extra));
+    }
+
+    // Link-time optimization; static archives then need the compiler's ar wrapper
+    let lto = match opts.lto.as_ref().or(build.lto.as_ref()) {
+        Some(mode) => lto::flags(mode, compiler, msvc)?,
+        None => None,
+    };
+    if let Some(flag) = lto {
+        cflags.push(flag.into());
+        ldflags.push(flag.into());
+    }
+    let archiver = if lto.is_some() && (build.build_type == "static" || build.static_variant.unwrap_or(false)) { lto::archiver(ar, compiler) } else { ar.to_string() };
+
+    // BOLT needs relocations preserved in the executable
+    let bolt = config.bolt.as_ref().filter(|_| build.build_type == "executable" && cfg!(target_os = "linux") && cross.is_none());
+    if bolt.is_some() {
+        ldflags.push(bolt::link_flags().into());
+    }
+
+    // Link map and section garbage collection
+    let build_dir = opts.build_dir(path);
+    let link_map = build.link_map.unwrap_or(false) && build.build_type != "static" && !msvc;
+    let map_path = build_dir.join(format!("{}.map", build.target));
+    if link_map {
+        cflags.extend(linkmap::compile_flags().iter().map(OsString::from));
+        ldflags.extend(linkmap::link_flags(&map_path));
+    }
+    let version_script = match msvc {
+        true => None,
+        false if dry_run => visibility::script_path(build, path, &build_dir),
+        false => visibility::version_script(build, path, &build_dir)?,
+    };
+    if let Some(script) = &version_script {
+        ldflags.push(visibility::link_flag(script));
+    }
+
+    let sandboxed = sandbox::enabled(config);
+    sandbox::check(sandboxed)?;
+    let launcher = launcher::resolve(build, msvc, sandboxed)?;
+    let launcher_dirs = launcher.as_deref().filter(|_| sandboxed).map(launcher::cache_dirs).unwrap_or_default();
+    let mut writable: Vec<&Path> = vec![&build_dir];
+    writable.extend(launcher_dirs.iter().map(PathBuf::as_path));
+
     // Parallelism
-    let num_threads = num_cpus::get();
-    rayon::ThreadPoolBuilder::new().num_threads(num_threads).build_global()?;
+    let num_threads = match opts.jobs {
+        Some(jobs) => jobs,
+        None => job_count()?,
+    };
+    // Workers can't reach the sandbox's network-less build, and MSVC isn't a gcc-style compiler
+    let distributor = if msvc || sandboxed || dry_run { None } else { distribute::Distributor::new(compiler, num_threads)? };
+    let num_threads = distributor.as_ref().map_or(num_threads, |d| d.jobs());
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(num_threads).build()?;
 
-    // Scan sources
-    let mut sources: Vec<PathBuf> = vec![];
-    for pattern in &build.sources {
-        for entry in glob(path.join(pattern).to_str().ok_or("Invalid path")?)? {
-            sources.push(entry?);
+    // Scan sources; those of source sets are compiled with their own flags but linked in the same
+    let mut sources = expand_globs(path, &build.sources)?;
+    for set in build.source_set.iter().flatten() {
+        let set_sources = expand_globs(path, &set.sources)?;
+        if set_sources.is_empty() {
+            return Err(format!("Source set {} matches no sources", set.name).into());
         }
+        sources.extend(set_sources.into_iter().filter(|s| !sources.contains(s)).collect::<Vec<_>>());
     }
 
     // Build directory
-    let build_dir = path.join("build");
-    fs::create_dir_all(&build_dir)?;
+    if !dry_run {
+        fs::create_dir_all(&build_dir)?;
+    }
+
+    // Generated sources; a dry run, which must not run the generators, explains only the project's own
+    let mut generated = Generated::default();
+    if let Some(rules) = &config.rules {
+        generated.extend(rules::generated(rules, path));
+    }
+    let grammars: Vec<PathBuf> = sources.iter().filter(|s| grammar::is_grammar(s)).cloned().collect();
+    sources.retain(|s| !grammar::is_grammar(s));
+    if !dry_run {
+        if !grammars.is_empty() {
+            generated.extend(grammar::generate(&grammars, &build_dir)?);
+        }
+        if let Some(res) = &config.resources {
+            generated.extend(resources::generate(res, compiler, path, &build_dir)?);
+        }
+        if let Some(sh) = config.shaders.as_ref().filter(|sh| sh.embed.unwrap_or(false)) {
+            generated.extend(shaders::embed(sh, path, &build_dir, compiler)?);
+        }
+        if let Some(g) = &config.glib {
+            generated.extend(glib::generate(g, path, &build_dir)?);
+        }
+        if qt::is_enabled(config) {
+            generated.extend(qt::generate(config, path, &build_dir, &include_flags)?);
+        }
+        if let Some(pb) = config.protobuf.as_ref().filter(|pb| protobuf::wants(pb, config, "cpp")) {
+            generated.extend(protobuf::generate(pb, config, "cpp", path, &build_dir)?);
+        }
+    }
+    include_flags.extend(generated.include_dirs.iter().map(|dir| args::with_path("-I", dir)));
+    ldflags.extend(generated.ldflags.iter().cloned());
+    sources.extend(generated.sources.iter().cloned());
+
+    // Fortran sources are compiled with gfortran, after the sources of the modules they use
+    let modules = fortran::Modules::scan(&sources, &include_dirs)?;
+    if msvc && !modules.is_empty() {
+        return Err("Fortran sources need a gcc-style toolchain, not MSVC".into());
+    }
+    if msvc && sources.iter().any(|s| asm::is_asm(s)) {
+        return Err("Assembly sources need a gcc-style toolchain, not MSVC".into());
+    }
+    let fortran_compiler = fortran::compiler(build, compiler);
+    let toolkit = if sources.iter().any(|s| cuda::is_cuda(s)) {
+        if msvc {
+            return Err("CUDA sources need a gcc-style host compiler, not MSVC".into());
+        }
+        Some(cuda::Toolkit::find(config.cuda.as_ref(), &drivers.cxx)?)
+    } else {
+        None
+    };
+    // The compiler driver for each kind of source
+    let program = |src: &Path| -> &str {
+        match &toolkit {
+            Some(toolkit) if cuda::is_cuda(src) => &toolkit.nvcc,
+            _ if fortran::is_fortran(src) => &fortran_compiler,
+            _ if language_of(src) == Language::C => &drivers.c,
+            _ => &drivers.cxx,
+        }
+    };
 
-    // Build dependency graph
-    let mut deps: HashMap<PathBuf, HashSet<PathBuf>> = HashMap::new();
+    // Build dependency graph; when nothing it was scanned from changed, the last build's is reused
+    let pruned = system_header_prefixes(compiler, build);
+    // Ignored headers, e.g. a vendored tree, are not tracked
+    let ignore = ignore::Ignore::load(path);
+    let scan_flags: Vec<OsString> = include_flags.iter().chain(cflags.iter().filter(|f| args::is_define(f))).cloned().collect();
+    let mut scan_key = format!("{:?}\n", pruned);
     for src in &sources {
-        let src_deps = get_dependencies(compiler, src, &include_flags)?;
+        scan_key.push_str(&format!("{} {} {}\n", program(src), src.display(), args::display(&file_flags.scan_flags(path, src, &scan_flags))));
+    }
+    let saved = gitstate::load(path, &build_dir, &scan_key);
+    let rescan = saved.is_none();
+    let (mut deps, saved_contents) = saved.map(|s| (s.deps, s.contents)).unwrap_or_default();
+    for src in sources.iter().filter(|_| rescan) {
+        if fortran::is_fortran(src) {
+            deps.insert(src.clone(), modules.dependencies(src));
+            continue;
+        }
+        if let Some(src_deps) = asm::is_asm(src).then(|| asm::dependencies(src, &include_dirs)).flatten() {
+            deps.insert(src.clone(), src_deps);
+            continue;
+        }
+        let include_flags = file_flags.scan_flags(path, src, &scan_flags);
+        let mut src_deps = get_dependencies(program(src), src, &include_flags, &pruned, dry_run)?;
+        src_deps.retain(|d| !ignore.is_ignored(path, d));
         for dep in &src_deps {
-            if !deps.contains_key(dep) {
-                if dep.extension().map_or(false, |e| e == "h" || e == "hpp") {
-                    deps.insert(dep.clone(), get_dependencies(compiler, dep, &include_flags)?);
-                }
+            if !deps.contains_key(dep) && dep.extension().is_some_and(|e| e == "h" || e == "hpp") {
+                deps.insert(dep.clone(), get_dependencies(program(src), dep, &include_flags, &pruned, dry_run)?);
             }
         }
         deps.insert(src.clone(), src_deps);
     }
+    if let Some(format) = &opts.graph {
+        return graph::print(format, &build.target, path, &sources, &deps);
+    }
+
+    // Objects and targets being written when a build was interrupted may be truncated yet look newer than their inputs
+    let checkpoint = if dry_run { checkpoint::Checkpoint::read(&build_dir, "objects") } else { checkpoint::Checkpoint::open(&build_dir, "objects")? };
+    if checkpoint.resuming() {
+        verbosity::progress("Resuming interrupted build".yellow());
+    }
+
+    // Windows DLLs are position independent without it
+    let pic = (build.build_type == "shared" || config.swig.is_some()) && !msvc && !cfg!(windows);
 
-    // Determine which sources need recompilation
+    // Determine which sources need recompilation: by content and flags once an object has a recorded
+    // fingerprint, by mtime for objects from before the state file existed
+    let state = Mutex::new(BuildState::load(&build_dir));
+    // The artifact cache is keyed by the same with paths made portable across checkouts
+    let root = path.canonicalize()?;
+    let portable = |args: &[OsString]| artifacts::portable(args, &root);
+    let key_with = |display: &dyn Fn(&[OsString]) -> String| {
+        let mut key = format!("{} {} {} {} {} {} {} {}", drivers.c, drivers.cxx, c_std_flag, cxx_std_flag, opt_flag, display(&cflags), display(&include_flags), pic);
+        if !modules.is_empty() {
+            key.push_str(&format!(" {}", fortran_compiler));
+        }
+        if let Some(toolkit) = &toolkit {
+            key.push_str(&format!(" {}", toolkit.key()));
+        }
+        key
+    };
+    let (flags_key, cache_flags_key) = (key_with(&args::display), key_with(&portable));
+    let mut contents = saved_contents;
+    let mut fingerprints: HashMap<PathBuf, String> = HashMap::new();
+    let mut cache_keys: HashMap<PathBuf, String> = HashMap::new();
     let mut to_compile: Vec<PathBuf> = vec![];
     for src in &sources {
         let obj = build_dir.join(src.file_name().unwrap()).with_extension("o");
-        let obj_mtime = if obj.exists() {
-            obj.metadata()?.modified()?
-        } else {
-            SystemTime::UNIX_EPOCH
+        let fingerprint = state::fingerprint(src, &deps, &format!("{}{}", flags_key, file_flags.key(path, src, &args::display)), &mut contents);
+        fingerprints.insert(obj.clone(), fingerprint.clone());
+        cache_keys.insert(obj.clone(), state::portable_fingerprint(src, &deps, &format!("{}{}", cache_flags_key, file_flags.key(path, src, &portable)), &mut contents, &root));
+        if checkpoint.was_cut_off(&format!("obj {}", obj.display())) {
+            to_compile.push(src.clone());
+            continue;
+        }
+        let stale = modules.missing_modules(src, &build_dir) || match state.lock().unwrap().hashes.get(&obj) {
+            Some(stored) => !obj.exists() || *stored != fingerprint,
+            None => needs_recompile(src, &obj, &deps, &mut HashMap::new(), mtime(&obj)),
         };
-        let mut cache: HashMap<PathBuf, bool> = HashMap::new();
-        if needs_recompile(src, &obj, &deps, &mut cache, obj_mtime) {
+        verbosity::debug(&format!("{}: {}", src.display(), if stale { "out of date" } else { "up to date" }));
+        if stale {
             to_compile.push(src.clone());
+        } else {
+            state.lock().unwrap().hashes.insert(obj, fingerprint);
         }
     }
 
-    // Parallel compilation
-    to_compile.par_iter().try_for_each_init(
+    if let Some(file) = &opts.why {
+        let mut extra = generated.objects.clone();
+        extra.extend(version_script.iter().cloned());
+        extra.extend(config.embedded.as_ref().and_then(|e| e.linker_script.as_ref()).map(|s| path.join(s)));
+        let target = target_path(build, path, opts);
+        let state = state.into_inner().unwrap();
+        return why::explain(file, &why::LinkInputs { target: &target, sources: &sources, build_dir: &build_dir, extra, state: &state, fingerprints: &fingerprints }, &deps);
+    }
+
+    // Stale objects some other build already compiled with the same compiler, flags and inputs
+    let mut compilers = vec![compiler.as_str(), drivers.c.as_str(), drivers.cxx.as_str()];
+    if sources.iter().any(|s| fortran::is_fortran(s)) {
+        compilers.push(&fortran_compiler);
+    }
+    if let Some(toolkit) = &toolkit {
+        compilers.push(&toolkit.nvcc);
+    }
+    compilers.dedup();
+    let cache = if opts.no_cache { None } else { artifacts::Cache::open(config, &compilers)? };
+    if let Some(cache) = &cache {
+        let stale = to_compile.len();
+        let mut restored = vec![];
+        to_compile.retain(|src| {
+            let obj = build_dir.join(src.file_name().unwrap()).with_extension("o");
+            if modules.provides_modules(src) || !cache.restore(&cache.object_key(&cache_keys[&obj]), &obj) {
+                return true;
+            }
+            restored.push(obj);
+            false
+        });
+        for obj in restored {
+            checkpoint.finish(&format!("obj {}", obj.display()))?;
+            messages::emit(serde_json::json!({"event": "cache-hit", "object": obj}));
+            state.lock().unwrap().hashes.insert(obj.clone(), fingerprints[&obj].clone());
+        }
+        if to_compile.len() < stale {
+            let count = stale - to_compile.len();
+            verbosity::progress(format!("Restored {} object{} from the cache", count, if count == 1 { "" } else { "s" }).cyan());
+        }
+    }
+
+    let std_flags = |src: &Path| if language_of(src) == Language::C { &c_std_flags } else { &cxx_std_flags };
+    let compile_args = |src: &Path, obj: &Path| {
+        let cflags = file_flags.cflags(path, src, &cflags);
+        let include_flags = file_flags.include_flags(path, src, &include_flags);
+        let mut args = if fortran::is_fortran(src) {
+            fortran::compile_args(&opt_flag, &cflags, &include_flags, &build_dir, src, obj)
+        } else if asm::is_asm(src) {
+            asm::compile_args(&opt_flag, &cflags, &include_flags, &include_dirs, src, obj)
+        } else if let Some(toolkit) = toolkit.as_ref().filter(|_| cuda::is_cuda(src)) {
+            toolkit.compile_args(&cxx_std_flag, &opt_flag, &cflags, &include_flags, src, obj)
+        } else if msvc {
+            msvc::compile_args(std_flags(src), &opt_flag, &cflags, &include_flags, src, obj)
+        } else {
+            let mut args = std_flags(src).clone();
+            args.push(opt_flag.clone().into());
+            args.extend(cflags.iter().cloned());
+            args.extend(include_flags.iter().cloned());
+            args.extend(["-c".into(), src.into(), "-o".into(), obj.into()]);
+            args
+        };
+        if pic {
+            args.push(if cuda::is_cuda(src) { cuda::host_flag("-fPIC") } else { "-fPIC".into() });
+        }
+        args
+    };
+
+    // compile_commands.json for clangd and IDEs, covering every source rather than only the stale ones.
+    // Only the regular build writes it: variant builds (tests, PGO, matrix cells, sanitizers, ...) pass their
+    // own build_dir and would otherwise point the IDE at their flags and objects.
+    if opts.build_dir.is_none() {
+        let directory = path.canonicalize()?;
+        compdb::update(path, sources.iter().map(|src| {
+            let obj = build_dir.join(src.file_name().unwrap()).with_extension("o");
+            compdb::Entry::new(&directory, program(src), &compile_args(src, &obj), src, &obj)
+        }).collect())?;
+    }
+
+    if let Some(linter) = &opts.lint {
+        if msvc {
+            return Err(format!("{} cannot take MSVC compile flags", linter).into());
+        }
+        let units: Vec<(PathBuf, Vec<OsString>)> = sources.iter().filter(|src| language::of(src).is_some() && !src.starts_with(&build_dir)).map(|src| {
+            let obj = build_dir.join(src.file_name().unwrap()).with_extension("o");
+            (src.clone(), lint::flags(&compile_args(src, &obj), src, &obj))
+        }).collect();
+        return lint::check(linter, path, &units, num_threads);
+    }
+
+    // Parallel compilation, throttled by memory; each job's output is printed in one piece when it finishes
+    let finished = AtomicUsize::new(0);
+    let console = Mutex::new(());
+    let history = memory::History::load(&build_dir);
+    let scheduler = memory::Scheduler::new(config.memory.as_ref());
+    let batches = modules.batches(&to_compile)?;
+    let compiled = batches.iter().try_for_each(|batch| pool.install(|| batch.par_iter().try_for_each_init(
         || children.clone(),
                                             |children_arc, src| -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                                                 let obj = build_dir.join(src.file_name().unwrap()).with_extension("o");
-                                                let mut compile_flags = format!("{} {} {} {} -c {} -o {}", std_flag, opt_flag, cflags, include_flags, src.display(), obj.display());
-                                                if build.build_type == "shared" {
-                                                    compile_flags.push_str(" -fPIC");
-                                                }
-                                                // FIXED: Removed 'mut' as child is consumed by wait_with_output
-                                                let child = Command::new(compiler)
-                                                .args(compile_flags.split_whitespace())
-                                                .current_dir(path)
-                                                .stdout(Stdio::piped())
-                                                .stderr(Stdio::piped())
-                                                .spawn()?;
-
-                                                // FIXED: Capture ID before moving child into wait_with_output
-                                                let child_id = child.id();
-                                                {
-                                                    let mut guards = children_arc.lock().unwrap();
-                                                    guards.push(child_id);
-                                                }
+                                                let compile_flags = compile_args(src, &obj);
+                                                checkpoint.start(&format!("obj {}", obj.display()))?;
+                                                messages::emit(serde_json::json!({"event": "compile-start", "file": src, "object": obj}));
+                                                let started = std::time::Instant::now();
+                                                // Only C and C++ are distributed: workers can't see the .mod files of the
+                                                // modules a Fortran source uses, and preprocessed assembly or CUDA would be sent as C
+                                                let fortran = fortran::is_fortran(src);
+                                                let program = program(src);
+                                                let slot = distributor.as_ref().map(|d| d.acquire(distribute::distributable(&compile_flags) && !fortran && !asm::is_asm(src) && !cuda::is_cuda(src)));
+                                                let remote = match &slot {
+                                                    Some(slot) => distribute::compile(slot, program, &compile_flags, src, &obj, path)?,
+                                                    None => None,
+                                                };
+                                                let output = match remote {
+                                                    Some(output) => output,
+                                                    None => {
+                                                    let _reservation = scheduler.acquire(memory::estimate(config.memory.as_ref(), &history, path, src));
+                                                    // FIXED: Removed 'mut' as child is consumed by wait_with_output
+                                                    let mut command = match launcher.as_ref().filter(|_| !fortran) {
+                                                        Some(launcher) => {
+                                                            let mut command = sandbox::command(launcher, sandboxed, path, &writable);
+                                                            command.arg(program);
+                                                            command
+                                                        }
+                                                        None => sandbox::command(program, sandboxed, path, &writable),
+                                                    };
+                                                    command
+                                                    .args(&compile_flags)
+                                                    .current_dir(path)
+                                                    .stdout(Stdio::piped())
+                                                    .stderr(Stdio::piped());
+                                                    platform::own_process_group(&mut command);
+                                                    verbosity::command(&command);
+                                                    let child = command.spawn()?;
+
+                                                    // FIXED: Capture ID before moving child into wait_with_output
+                                                    let child_id = child.id();
+                                                    {
+                                                        let mut guards = children_arc.lock().unwrap();
+                                                        guards.push(child_id);
+                                                    }
+                                                    cancel::writing(child_id, &obj);
 
-                                                let output = child.wait_with_output()?;
+                                                    let (output, peak_mb) = memory::wait_with_peak(child)?;
+                                                    {
+                                                        let mut guards = children_arc.lock().unwrap();
+                                                        // FIXED: Use the captured ID
+                                                        guards.retain(|&p| p != child_id);
+                                                        cancel::finished(child_id);
+                                                    }
+                                                    history.record(src, peak_mb);
+                                                        output
+                                                    }
+                                                };
+                                                let done = finished.fetch_add(1, Ordering::SeqCst) + 1;
+                                                let diagnostics = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+                                                messages::diagnostics(&diagnostics, src);
+                                                messages::emit(serde_json::json!({
+                                                    "event": "compile-finish",
+                                                    "file": src,
+                                                    "object": obj,
+                                                    "success": output.status.success(),
+                                                    "duration_ms": started.elapsed().as_millis() as u64,
+                                                }));
+                                                let _console = console.lock().unwrap();
+                                                let header = format!("[{}/{}] {}", done, to_compile.len(), src.display());
                                                 if !output.status.success() {
-                                                    eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
+                                                    eprintln!("{}", header.red().bold());
+                                                    eprint!("{}", diagnostics.red());
                                                     return Err("Compilation failed".into());
                                                 }
-                                                {
-                                                    let mut guards = children_arc.lock().unwrap();
-                                                    // FIXED: Use the captured ID
-                                                    guards.retain(|&p| p != child_id);
+                                                checkpoint.finish(&format!("obj {}", obj.display()))?;
+                                                if let Some(cache) = &cache {
+                                                    cache.store(&cache.object_key(&cache_keys[&obj]), &obj);
                                                 }
+                                                state.lock().unwrap().hashes.insert(obj.clone(), fingerprints[&obj].clone());
+                                                verbosity::progress(header.cyan());
+                                                // Warnings
+                                                eprint!("{}", diagnostics);
                                                 Ok(())
                                             },
-    )?;
+    )));
+    history.save()?;
+    state.into_inner().unwrap().save(&build_dir)?;
+    compiled?;
 
     // Check if linking is needed
     // FIXED: Moved path extension logic here to avoid re-assigning and ensure timestamps check correct file
-    let mut target_path = path.join(&build.target);
-    if build.build_type == "shared" {
-        target_path = target_path.with_extension("so");
-    } else if build.build_type == "static" {
-        target_path = target_path.with_extension("a");
-    }
+    let target_path = target_path(build, path, opts);
 
-    let mut need_link = !target_path.exists() || !to_compile.is_empty();
+    // The shared build's objects are already PIC, so the static variant reuses them
+    let static_variant = if build.static_variant.unwrap_or(false) {
+        if build.build_type != "shared" {
+            return Err("static_variant requires build_type = \"shared\"".into());
+        }
+        Some(target_path.with_extension(platform::static_extension(msvc)))
+    } else {
+        None
+    };
+
+    // Versioned shared libraries are linked to the real file, which the build dir's links point at.
+    // Android loads libraries by file name from the APK, so they stay unversioned.
+    let android = opts.cross.as_ref().is_some_and(|c| c.triple.contains("android"));
+    let versioned = soname::versioned(&target_path, &config.metadata.version).filter(|_| build.build_type == "shared" && !msvc && !android);
+    let linked = versioned.as_ref().map_or(target_path.clone(), |v| v.file.clone());
+
+    let link_step = format!("link {}", linked.display());
+    let mut link_libs: Vec<OsString> = dep_usage.link_args().iter().chain(&ldflags).chain(&lib_dir_flags).chain(&lib_flags).cloned().collect();
+    // Fortran objects call into its runtime, which the C/C++ compiler driver doesn't link
+    if !modules.is_empty() {
+        link_libs.push("-lgfortran".into());
+    }
+    if let Some(toolkit) = &toolkit {
+        link_libs.extend(toolkit.link_flags());
+    }
+    // The C++ driver links its runtime, which C++ and CUDA objects need
+    let linker = if sources.iter().any(|s| cuda::is_cuda(s) || language::of(s) == Some(Language::Cxx)) { &drivers.cxx } else { &drivers.c };
+    let mut need_link = !linked.exists() || !to_compile.is_empty() || static_variant.as_ref().is_some_and(|a| !a.exists()) || checkpoint.was_cut_off(&link_step);
     if !need_link {
-        let exe_mtime = target_path.metadata()?.modified()?;
+        let exe_mtime = linked.metadata()?.modified()?;
         for src in &sources {
             let obj = build_dir.join(src.file_name().unwrap()).with_extension("o");
             if obj.exists() && obj.metadata()?.modified()? > exe_mtime {
@@ -524,99 +2132,316 @@ fn compile_c_cpp(config: &HBuildConfig, path: &Path, children: &Arc<Mutex<Vec<u3
                 break;
             }
         }
+        need_link = need_link || generated.objects.iter().any(|o| mtime(o) > exe_mtime);
+        need_link = need_link || version_script.as_ref().is_some_and(|s| mtime(s) > exe_mtime);
+        need_link = need_link || dep_usage.changed_since(exe_mtime);
+        let linker_script = config.embedded.as_ref().and_then(|e| e.linker_script.as_ref());
+        need_link = need_link || linker_script.is_some_and(|s| mtime(&path.join(s)) > exe_mtime);
     }
 
     if need_link {
-        let objs: String = sources.iter().map(|s| build_dir.join(s.file_name().unwrap()).with_extension("o").display().to_string()).collect::<Vec<_>>().join(" ");
+        checkpoint.start(&link_step)?;
+        size::save_previous(&target_path, &build_dir)?;
+        let objs: Vec<OsString> = sources.iter().map(|s| build_dir.join(s.file_name().unwrap()).with_extension("o"))
+        .chain(generated.objects.iter().cloned())
+        .map(OsString::from).collect();
 
-        if build.build_type == "static" {
-            // Use ar for static lib
-            let status = Command::new("ar")
-            .args(["rcs", target_path.to_str().unwrap()])
-            .args(objs.split_whitespace())
-            .current_dir(path)
-            .status()?;
-            if !status.success() {
-                return Err("Archiving failed".into());
+        // The link map is a by-product of actually linking, so those builds always link
+        let link_key = cache.as_ref().filter(|_| !link_map).map(|cache| {
+            let command = format!("{} {} {} {} {} {}", build.build_type, linker, archiver, opt_flag, portable(&link_libs), versioned.as_ref().map_or("", |v| v.soname.as_str()));
+            let mut inputs: Vec<PathBuf> = objs.iter().map(PathBuf::from).collect();
+            inputs.extend(version_script.iter().cloned());
+            inputs.extend(dep_usage.libraries.iter().cloned());
+            inputs.extend(config.embedded.as_ref().and_then(|e| e.linker_script.as_ref()).map(|s| path.join(s)));
+            cache.link_key(&linked.file_name().unwrap().to_string_lossy(), &command, &inputs)
+        });
+        let static_key = |key: &str| format!("{}-static", key);
+        let restored = match (&cache, &link_key) {
+            (Some(cache), Some(key)) => cache.restore(key, &linked) && static_variant.as_ref().is_none_or(|s| cache.restore(&static_key(key), s)),
+            _ => false,
+        };
+
+        if restored {
+            verbosity::progress(format!("Restored {} from the cache", linked.display()).cyan());
+        } else if build.build_type == "static" && msvc {
+            msvc::archive(&target_path, &objs, path)?;
+        } else if build.build_type == "static" {
+            archive(&archiver, &target_path, &objs, path)?;
+        } else if msvc {
+            link_target(compiler, msvc::link_args(&opt_flag, &target_path, &objs, &link_libs, build.build_type == "shared"), &target_path, build, path, children)?;
+        } else {
+            // Objects go before the libraries so the linker sees what they need resolved
+            let mut link_args: Vec<OsString> = vec![opt_flag.clone().into(), "-o".into(), linked.clone().into()];
+            link_args.extend(objs.iter().cloned());
+            link_args.extend(link_libs.iter().cloned());
+            link_args.extend(versioned.as_ref().map(|v| v.link_flag()));
+            link_target(linker, link_args, &linked, build, path, children)?;
+            if link_map {
+                linkmap::report(&map_path, &build_dir)?;
             }
-            return Ok(());
         }
-
-        // Shared or Executable
-        // FIXED: target_path is already corrected above, so format uses correct extension
-        let mut link_cmd = format!("{} {} {} {} {} -o {} {}", compiler, opt_flag, ldflags, lib_dir_flags, lib_flags, target_path.display(), objs);
-        if build.build_type == "shared" {
-            link_cmd.push_str(" -shared");
+        if let Some(static_path) = static_variant.as_ref().filter(|_| !restored) {
+            if msvc {
+                msvc::archive(static_path, &objs, path)?;
+            } else {
+                archive(&archiver, static_path, &objs, path)?;
+            }
         }
-
-        // FIXED: Removed 'mut'
-        let child = Command::new(compiler)
-        .args(link_cmd.split_whitespace())
-        .current_dir(path)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
-
-        // FIXED: Capture ID before moving child
-        let child_id = child.id();
-        {
-            let mut guards = children.lock().unwrap();
-            guards.push(child_id);
+        if let (Some(cache), Some(key)) = (cache.as_ref().filter(|_| !restored), &link_key) {
+            cache.store(key, &linked);
+            if let Some(static_path) = &static_variant {
+                cache.store(&static_key(key), static_path);
+            }
         }
+        checkpoint.finish(&link_step)?;
+        messages::emit(serde_json::json!({"event": "link", "target": linked, "kind": build.build_type, "static_variant": static_variant}));
+    }
+    if let Some(v) = &versioned {
+        v.link()?;
+    }
 
-        let output = child.wait_with_output()?;
-        if !output.status.success() {
-            eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
-            return Err("Linking failed".into());
+    // Post-link steps
+    if opts.strip && need_link && !msvc {
+        let tool = profile::strip_tool(build, ar);
+        if build.build_type == "static" {
+            profile::strip(&linked, &tool)?;
+        } else {
+            profile::split_debug(&linked, &profile::debug_file(&linked), &tool)?;
         }
-        {
-            let mut guards = children.lock().unwrap();
-            // FIXED: Use captured ID
-            guards.retain(|&p| p != child_id);
+    } else if need_link {
+        // Install would otherwise pair the new target with debug info split off an older one
+        let _ = fs::remove_file(profile::debug_file(&linked));
+    }
+    if let Some(e) = config.embedded.as_ref().filter(|_| build.build_type == "executable" && opts.cross.is_none()) {
+        if need_link || e.outputs.iter().flatten().any(|o| !target_path.with_extension(o).exists()) {
+            embedded::objcopy(e, &target_path)?;
+        }
+    }
+    if let Some(b) = bolt {
+        bolt::optimize(b, &target_path, &build_dir)?;
+    }
+    if let Some(sw) = &config.swig {
+        if build.build_type == "executable" {
+            return Err("SWIG bindings require a shared or static library target".into());
         }
+        let compiler = if sw.cplusplus.unwrap_or(false) { &drivers.cxx } else { linker };
+        swig::build(sw, path, &build_dir, compiler, &target_path, &include_flags, &link_libs)?;
+    }
+    checkpoint.complete()?;
+    gitstate::record(path, &build_dir, &scan_key, &deps, &contents)?;
+    if to_compile.is_empty() && !need_link {
+        verbosity::status("Up to date");
+    }
+    if let Some(cache) = &cache {
+        cache.upload();
+        cache.trim()?;
     }
     Ok(())
 }
 
+/// Path of the linked target, with the extension implied by `build_type`. Shared libraries on ELF
+/// platforms are `lib<target>.so`, the development link to the versioned file `-l<target>` finds.
+fn target_path(build: &Build, path: &Path, opts: &BuildOptions) -> PathBuf {
+    let target_path = opts.target_dir(path).join(&build.target);
+    let msvc = msvc::is_msvc(opts.compiler.as_ref().unwrap_or(&build.compiler));
+    match build.build_type.as_str() {
+        "shared" if !cfg!(windows) && !platform::is_macos() => {
+            let name = build.target.strip_prefix("lib").unwrap_or(&build.target);
+            opts.target_dir(path).join(format!("lib{}.{}", name, platform::shared_extension()))
+        }
+        "shared" => target_path.with_extension(platform::shared_extension()),
+        "static" => target_path.with_extension(platform::static_extension(msvc)),
+        _ if cfg!(windows) => target_path.with_extension("exe"),
+        _ => target_path,
+    }
+}
+
+fn archive(ar: &str, archive_path: &Path, objs: &[OsString], path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Use ar for static lib
+    let mut command = Command::new(ar);
+    command.arg("rcs").arg(archive_path)
+    .args(objs)
+    .current_dir(path);
+    verbosity::command(&command);
+    let status = command.status()?;
+    if !status.success() {
+        return Err("Archiving failed".into());
+    }
+    Ok(())
+}
+
+fn link_target(compiler: &str, mut link_args: Vec<OsString>, target: &Path, build: &Build, path: &Path, children: &Arc<Mutex<Vec<u32>>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Shared or Executable
+    if build.build_type == "shared" && !msvc::is_msvc(compiler) {
+        link_args.extend(platform::shared_link_flags(target));
+    }
+
+    let mut command = Command::new(compiler);
+    command.args(&link_args)
+    .current_dir(path)
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+    platform::own_process_group(&mut command);
+    verbosity::command(&command);
+    let child = command.spawn()?;
+
+    // FIXED: Capture ID before moving child
+    let child_id = child.id();
+    {
+        let mut guards = children.lock().unwrap();
+        guards.push(child_id);
+    }
+    cancel::writing(child_id, target);
+
+    let output = child.wait_with_output()?;
+    {
+        let mut guards = children.lock().unwrap();
+        // FIXED: Use captured ID
+        guards.retain(|&p| p != child_id);
+        cancel::finished(child_id);
+    }
+    if !output.status.success() {
+        messages::diagnostics(&String::from_utf8_lossy(&output.stderr), target);
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
+        return Err("Linking failed".into());
+    }
+    Ok(())
+}
+
+/// Build options for `--target`, `--profile` and `--sanitize`: the `[toolchain.<name>]` or preset toolchain, building into
+/// `build/<triple>`, with the profile's flags on top and then the sanitizers, from `--sanitize` or else `[build] sanitizers`.
+/// Cross binaries run through the `[qemu]` runner when one is configured.
+fn target_options(path: &Path, triple: Option<&str>, profile: Option<&str>, sanitize: Option<&str>) -> Result<BuildOptions, Box<dyn std::error::Error + Send + Sync>> {
+    let Some((config_path, format)) = find_config_file(path) else {
+        return Ok(BuildOptions::default());
+    };
+    let config = parse_config(&config_path, &format)?;
+    let mut opts = BuildOptions::default();
+    if let Some(triple) = triple {
+        let cplusplus = config.specs.languages.iter().any(|l| l == "c++");
+        let toolchain = config.toolchain.as_ref().and_then(|t| t.get(triple));
+        let mut cross = match toolchain {
+            Some(toolchain) if toolchain.zig.unwrap_or(false) => zig::cross(toolchain.triple.as_deref().unwrap_or(triple), Some(toolchain), cplusplus, path)?,
+            Some(toolchain) => cross::configured(triple, toolchain, cplusplus),
+            // zig cross-compiles C and C++ for any target it knows, so it covers triples without a preset
+            None => match cross::preset(triple, cplusplus) {
+                Ok(cross) => cross,
+                Err(_) if platform::which("zig").is_some() => zig::cross(triple, None, cplusplus, path)?,
+                Err(e) => return Err(e),
+            },
+        };
+        // A toolchain's own runner wins over [qemu]
+        if let Some(qemu) = config.qemu.as_ref().filter(|_| toolchain.is_none_or(|t| t.runner.is_none())) {
+            cross.runner = Some(cross::qemu_runner(qemu));
+        }
+        opts.build_dir = Some(cross::build_root(path, Some(&cross)));
+        opts.cross = Some(cross);
+    }
+    let opts = match profile {
+        Some(name) => profile::apply(&config, name, path, opts)?,
+        None => opts,
+    };
+    let sanitizers: Vec<String> = match sanitize {
+        Some(list) => list.split(',').map(String::from).collect(),
+        None => config.build.as_ref().and_then(|b| b.sanitizers.clone()).unwrap_or_default(),
+    };
+    let compiler = opts.cross.as_ref().map(|c| c.compiler.clone()).or(config.build.as_ref().map(|b| b.compiler.clone())).unwrap_or_default();
+    sanitize::apply(&sanitizers, &compiler, path, opts)
+}
+
 fn make(path: &Path, children: &Arc<Mutex<Vec<u32>>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    make_with(path, children, &BuildOptions::default())
+}
+
+fn make_with(path: &Path, children: &Arc<Mutex<Vec<u32>>>, opts: &BuildOptions) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     if let Some((config_path, format)) = find_config_file(path) {
         let config = parse_config(&config_path, &format)?;
-        println!("{}", format!("Building project: {}", config.metadata.name).blue().bold());
+        let _env = env::export(&config);
+        verbosity::progress(format!("Building project: {}", config.metadata.name).blue().bold());
         install_deps(&config, path)?;
-        println!("{}", "Building...".cyan());
-        for lang in &config.specs.languages {
-            println!("{}", format!("Building for {}...", lang).cyan());
+        let hook_env = hooks::env(&config, path, opts);
+        hooks::run(&config, "pre_build", path, &hook_env)?;
+        verbosity::progress("Building...".cyan());
+        if let Some(rules) = &config.rules {
+            rules::run(rules, path, &opts.build_dir(path), sandbox::enabled(&config))?;
+        }
+        if let Some(sh) = &config.shaders {
+            shaders::compile(sh, path, &opts.build_dir(path))?;
+        }
+        let mut failed: Vec<String> = vec![];
+        for lang in &order::schedule(&config.specs.languages, config.order.as_ref())? {
+            if let Some(dep) = order::failed_dependency(lang, config.order.as_ref(), &failed) {
+                eprintln!("{}", format!("Skipping {}: {} failed to build", lang, dep).yellow());
+                failed.push(lang.clone());
+                continue;
+            }
+            verbosity::progress(format!("Building for {}...", lang).cyan());
+            if let Some(pb) = config.protobuf.as_ref().filter(|pb| (lang == "rust" || lang == "go") && protobuf::wants(pb, &config, lang)) {
+                protobuf::generate(pb, &config, lang, path, &opts.build_dir(path))?;
+            }
             let build_result = match lang.as_str() {
-                "rust" => Command::new("cargo").arg("build").current_dir(path).status(),
+                "rust" => {
+                    let mut cargo = Command::new("cargo");
+                    cargo.arg("build").current_dir(path);
+                    if let Some(pb) = config.protobuf.as_ref().filter(|pb| protobuf::wants(pb, &config, "rust")) {
+                        let out_dir = protobuf::out_dir(pb, "rust", path, &opts.build_dir(path));
+                        cargo.env("HBUILD_PROTO_DIR", out_dir.canonicalize().unwrap_or(out_dir));
+                    }
+                    cargo.status()
+                }
                 "c" | "c++" => {
-                    compile_c_cpp(&config, path, children)?;
-                    Ok(ExitStatusExt::from_raw(0))
+                    compile_c_cpp(&config, path, children, opts)?;
+                    Ok(platform::success())
                 }
                 "odin" => Command::new("odin").arg("build").arg(".").current_dir(path).status(),
+                "zig" => {
+                    zig::build(&config, path, opts)?;
+                    Ok(platform::success())
+                }
+                "d" => {
+                    dlang::build(&config, path, opts)?;
+                    Ok(platform::success())
+                }
+                "nim" => {
+                    nim::build(&config, path, opts)?;
+                    Ok(platform::success())
+                }
                 "python" => {
                     if path.join("requirements.txt").exists() {
                         Command::new("pip").arg("install").arg("-r").arg("requirements.txt").current_dir(path).status()
                     } else {
-                        Ok(ExitStatusExt::from_raw(0))
+                        Ok(platform::success())
                     }
                 }
                 "crystal" => Command::new("crystal").arg("build").arg("main.cr").current_dir(path).status(),
                 "go" => Command::new("go").arg("build").current_dir(path).status(),
-                "vala" => Command::new("valac").args(&["--pkg", "gio-2.0", "main.vala"]).current_dir(path).status(),
+                "vala" => Command::new("valac").args(["--pkg", "gio-2.0", "main.vala"]).current_dir(path).status(),
                 _ => {
-                    println!("{}", format!("Unsupported language: {}", lang).yellow());
-                    Ok(ExitStatusExt::from_raw(0))
+                    verbosity::progress(format!("Unsupported language: {}", lang).yellow());
+                    Ok(platform::success())
                 }
             };
             if let Ok(status) = build_result {
                 if !status.success() {
                     eprintln!("{}", format!("Build failed for {}", lang).red().bold());
+                    failed.push(lang.clone());
                 }
             } else if let Err(e) = build_result {
                 eprintln!("{}", format!("Failed to run build command for {}: {}", lang, e).red().bold());
+                failed.push(lang.clone());
             }
         }
-        println!("{}", "Build complete!".green().bold());
+        if gettext::is_enabled(&config, path) {
+            gettext::compile_catalogs(&config, path, &opts.build_dir(path))?;
+        }
+        if !failed.is_empty() {
+            return Err(format!("Build failed for {}", failed.join(", ")).into());
+        }
+        hooks::run(&config, "post_build", path, &hook_env)?;
+        verbosity::status("Build complete!");
+        if let Some(runner) = opts.cross.as_ref().and_then(|c| c.runner.as_ref()) {
+            verbosity::progress(format!("Run target binaries with: {} <binary>", runner));
+        }
     } else {
         eprintln!("{}", "No config file found".red().bold());
     }
@@ -624,7 +2449,7 @@ fn make(path: &Path, children: &Arc<Mutex<Vec<u32>>>) -> Result<(), Box<dyn std:
 }
 
 fn clean(path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    println!("{}", "Cleaning project...".blue().bold());
+    verbosity::progress("Cleaning project...".blue().bold());
     let build_dir = path.join("build");
     if build_dir.exists() {
         fs::remove_dir_all(&build_dir)?;
@@ -632,47 +2457,14 @@ fn clean(path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     if path.join("Cargo.toml").exists() {
         Command::new("cargo").arg("clean").current_dir(path).status()?;
     }
-    println!("{}", "Clean complete!".green().bold());
+    verbosity::status("Clean complete!");
     Ok(())
 }
 
-fn install(path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+fn pot(path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     if let Some((config_path, format)) = find_config_file(path) {
         let config = parse_config(&config_path, &format)?;
-        let build = config.build.as_ref().ok_or("No build section")?;
-        let mut target_path = path.join(&build.target);
-        if !target_path.exists() {
-            eprintln!("{}", "Target not built".red().bold());
-            return Ok(());
-        }
-        let install_prefix = PathBuf::from("/usr/local");
-        match build.build_type.as_str() {
-            "executable" => {
-                let bin_dir = install_prefix.join("bin");
-                fs::create_dir_all(&bin_dir)?;
-                fs::copy(&target_path, bin_dir.join(&config.metadata.name))?;
-            }
-            "shared" => {
-                let lib_dir = install_prefix.join("lib");
-                fs::create_dir_all(&lib_dir)?;
-                target_path = target_path.with_extension("so");
-                fs::copy(&target_path, lib_dir.join(target_path.file_name().unwrap()))?;
-            }
-            "static" => {
-                let lib_dir = install_prefix.join("lib");
-                fs::create_dir_all(&lib_dir)?;
-                target_path = target_path.with_extension("a");
-                fs::copy(&target_path, lib_dir.join(target_path.file_name().unwrap()))?;
-            }
-            _ => {}
-        }
-        // Config files to /etc/<project>
-        if let Some((config_file, _)) = find_config_file(path) {
-            let etc_dir = PathBuf::from("/etc").join(&config.metadata.name);
-            fs::create_dir_all(&etc_dir)?;
-            fs::copy(config_file, etc_dir.join("config"))?;
-        }
-        println!("{}", "Installation complete!".green().bold());
+        gettext::extract(&config, path)?;
     } else {
         eprintln!("{}", "No config file found".red().bold());
     }
@@ -0,0 +1,44 @@
+use std::path::Path;
+use std::process::Command;
+use crate::{job_count, staged};
+
+/// True when `dir` has a makefile GNU make reads by default.
+pub fn has_makefile(dir: &Path) -> bool {
+    ["GNUmakefile", "makefile", "Makefile"].iter().any(|f| dir.join(f).is_file())
+}
+
+/// Builds git dependency `name` in its checkout `src` at `commit` with `command` through the shell,
+/// else with `make`, then stages `outputs` for the projects using it: libraries into the prefix's
+/// `lib`, the contents of header directories into its `include`. A command installing into
+/// `$prefix` itself needs no outputs.
+pub fn build_dependency(name: &str, src: &Path, commit: &str, command: Option<&str>, outputs: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let prefix = staged::dir(name)?.join("prefix");
+    let installs = command.is_some_and(|c| c.contains("$prefix"));
+    if outputs.is_empty() && !installs {
+        return Err(format!("Dependency {} is built by `{}`; list the libraries and header directories it produces in its outputs", name, command.unwrap_or("make")).into());
+    }
+    let mut build = match command {
+        Some(command) => {
+            let mut build = Command::new("sh");
+            build.arg("-c").arg(command.replace("$prefix", &prefix.display().to_string()));
+            build
+        }
+        None => Command::new("make"),
+    };
+    build.current_dir(src).env("MAKEFLAGS", format!("-j{}", job_count()?));
+    let mut mkdir = Command::new("mkdir");
+    mkdir.arg("-p").arg(prefix.join("lib")).arg(prefix.join("include"));
+    let mut commands = vec![build, mkdir];
+    for output in outputs {
+        let from = src.join(output);
+        let mut copy = Command::new("cp");
+        // Header directories exist before the build; the libraries are what it makes
+        if from.is_dir() {
+            copy.arg("-R").arg(from.join(".")).arg(prefix.join("include"));
+        } else {
+            copy.arg(&from).arg(prefix.join("lib"));
+        }
+        commands.push(copy);
+    }
+    staged::run(name, commit, commands)
+}
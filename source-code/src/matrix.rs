@@ -0,0 +1,118 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use owo_colors::OwoColorize;
+use rayon::prelude::*;
+use crate::{compile_c_cpp, find_config_file, install_deps, job_count, parse_config, rules, sandbox, shaders, verbosity, BuildOptions, HBuildConfig};
+
+struct Cell {
+    compiler: String,
+    standard: String,
+    optimize: String,
+}
+
+impl Cell {
+    fn name(&self) -> String {
+        format!("{}-{}-{}", self.compiler, self.standard, self.optimize)
+    }
+}
+
+/// Every combination of the `[matrix]` axes; an axis that is not set contributes the `[build]` value.
+fn cells(config: &HBuildConfig) -> Result<Vec<Cell>, Box<dyn std::error::Error + Send + Sync>> {
+    let build = config.build.as_ref().ok_or("No build section")?;
+    let matrix = config.matrix.as_ref().ok_or("No [matrix] section in config")?;
+    let compilers = matrix.compilers.clone().unwrap_or_else(|| vec![build.compiler.clone()]);
+    let standards = matrix.standards.clone().unwrap_or_else(|| vec![build.standard.clone()]);
+    let optimize = matrix.optimize.clone().unwrap_or_else(|| vec![build.optimize.clone()]);
+    let mut cells = vec![];
+    for compiler in &compilers {
+        for standard in &standards {
+            for opt in &optimize {
+                cells.push(Cell {
+                    compiler: compiler.clone(),
+                    standard: standard.clone(),
+                    optimize: opt.clone(),
+                });
+            }
+        }
+    }
+    Ok(cells)
+}
+
+fn print_grid(results: &[(Cell, Result<(), String>, Duration)]) {
+    let headers = ["Compiler", "Standard", "Optimize", "Result", "Time"];
+    let width = |i: usize| {
+        results.iter().map(|(c, _, _)| match i {
+            0 => c.compiler.len(),
+            1 => c.standard.len(),
+            _ => c.optimize.len(),
+        }).chain([headers[i].len()]).max().unwrap_or(0)
+    };
+    let (w0, w1, w2) = (width(0), width(1), width(2));
+    println!("{}", format!("{:w0$}  {:w1$}  {:w2$}  {:6}  {}", headers[0], headers[1], headers[2], headers[3], headers[4]).bold());
+    for (cell, result, elapsed) in results {
+        let status = match result {
+            Ok(()) => format!("{:6}", "PASS").green().to_string(),
+            Err(_) => format!("{:6}", "FAIL").red().to_string(),
+        };
+        println!("{:w0$}  {:w1$}  {:w2$}  {}  {:.1}s", cell.compiler, cell.standard, cell.optimize, status, elapsed.as_secs_f64());
+    }
+    for (cell, result, _) in results {
+        if let Err(e) = result {
+            eprintln!("{}", format!("{}: {}", cell.name(), e).red());
+        }
+    }
+}
+
+/// Builds every matrix cell into `build/matrix/<compiler>-<standard>-<optimize>`, several at once when
+/// there are enough cores, then prints a pass/fail grid. Fails if any cell failed.
+pub fn run(path: &Path, children: &Arc<Mutex<Vec<u32>>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let cells = cells(&config)?;
+    verbosity::progress(format!("Building {} matrix combinations for {}", cells.len(), config.metadata.name).blue().bold());
+    install_deps(&config, path)?;
+    if let Some(r) = &config.rules {
+        rules::run(r, path, &path.join("build"), sandbox::enabled(&config))?;
+    }
+
+    // Split the cores between concurrently running cells
+    let cpus = job_count()?;
+    let concurrent = cells.len().clamp(1, (cpus / 2).max(1));
+    let jobs = (cpus / concurrent).max(1);
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(concurrent).build()?;
+    let results: Vec<(Cell, Result<(), String>, Duration)> = pool.install(|| {
+        cells.into_par_iter().map(|cell| {
+            let opts = BuildOptions {
+                build_dir: Some(path.join("build/matrix").join(cell.name())),
+                compiler: Some(cell.compiler.clone()),
+                standard: Some(cell.standard.clone()),
+                optimize: Some(cell.optimize.clone()),
+                jobs: Some(jobs),
+                ..Default::default()
+            };
+            let start = Instant::now();
+            let result = (|| -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+                if let Some(sh) = &config.shaders {
+                    shaders::compile(sh, path, &opts.build_dir(path))?;
+                }
+                compile_c_cpp(&config, path, children, &opts)
+            })();
+            (cell, result.map_err(|e| e.to_string()), start.elapsed())
+        }).collect()
+    });
+
+    print_grid(&results);
+    let failed = results.iter().filter(|(_, r, _)| r.is_err()).count();
+    if failed > 0 {
+        return Err(format!("{} of {} matrix combinations failed", failed, results.len()).into());
+    }
+    verbosity::status("Matrix complete!");
+    Ok(())
+}
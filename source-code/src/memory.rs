@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use glob::Pattern;
+use owo_colors::OwoColorize;
+use crate::{verbosity, Memory};
+
+/// Estimate for a compile job with no configured weight and no recorded peak.
+const DEFAULT_JOB_MB: u64 = 512;
+/// RAM left free by default.
+const DEFAULT_MIN_FREE_MB: u64 = 512;
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(20);
+const RECHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+/// `MemAvailable` from /proc/meminfo in MB; None where it can't be read.
+fn available_mb() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    line.split_whitespace().nth(1)?.parse::<u64>().ok().map(|kb| kb / 1024)
+}
+
+/// Resident memory of `pid` and all its descendants in KB; the compiler driver does its work in children.
+fn tree_rss_kb(pid: u32) -> u64 {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+    let own = status.lines().find(|l| l.starts_with("VmRSS:"))
+    .and_then(|l| l.split_whitespace().nth(1)?.parse::<u64>().ok())
+    .unwrap_or(0);
+    let mut children = vec![];
+    for task in fs::read_dir(format!("/proc/{}/task", pid)).into_iter().flatten().flatten() {
+        let list = fs::read_to_string(task.path().join("children")).unwrap_or_default();
+        children.extend(list.split_whitespace().filter_map(|c| c.parse::<u32>().ok()));
+    }
+    own + children.into_iter().map(tree_rss_kb).sum::<u64>()
+}
+
+/// Like `Child::wait_with_output`, also sampling the peak resident memory of the job in MB.
+pub fn wait_with_peak(mut child: Child) -> io::Result<(Output, u64)> {
+    let drain = |pipe: Option<Box<dyn Read + Send>>| thread::spawn(move || {
+        let mut buf = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    });
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let mut peak = 0;
+    let status = loop {
+        peak = peak.max(tree_rss_kb(child.id()));
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        thread::sleep(SAMPLE_INTERVAL);
+    };
+    let output = Output { status, stdout: stdout.join().unwrap_or_default(), stderr: stderr.join().unwrap_or_default() };
+    Ok((output, peak / 1024))
+}
+
+/// Peak memory of each source's last compile, kept in `<build dir>/memory-usage` as `<MB> <source>` lines.
+pub struct History {
+    path: PathBuf,
+    peaks: Mutex<HashMap<PathBuf, u64>>,
+}
+
+impl History {
+    pub fn load(build_dir: &Path) -> Self {
+        let path = build_dir.join("memory-usage");
+        let peaks = fs::read_to_string(&path).unwrap_or_default().lines()
+        .filter_map(|l| l.split_once(' '))
+        .filter_map(|(mb, src)| Some((PathBuf::from(src), mb.parse().ok()?)))
+        .collect();
+        History { path, peaks: Mutex::new(peaks) }
+    }
+
+    pub fn get(&self, src: &Path) -> Option<u64> {
+        self.peaks.lock().unwrap().get(src).copied()
+    }
+
+    /// Jobs too short to be sampled are not recorded.
+    pub fn record(&self, src: &Path, peak_mb: u64) {
+        if peak_mb > 0 {
+            self.peaks.lock().unwrap().insert(src.to_path_buf(), peak_mb);
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let peaks = self.peaks.lock().unwrap();
+        let mut lines: Vec<String> = peaks.iter().map(|(src, mb)| format!("{} {}\n", mb, src.display())).collect();
+        lines.sort();
+        fs::write(&self.path, lines.concat())?;
+        Ok(())
+    }
+}
+
+/// Expected memory of compiling `src`: a `[memory] weights` entry matching its project-relative path or
+/// file name, else its recorded peak plus a margin, else `job_mb`.
+pub fn estimate(memory: Option<&Memory>, history: &History, path: &Path, src: &Path) -> u64 {
+    let relative = src.strip_prefix(path).unwrap_or(src);
+    let file_name = src.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let weight = memory.and_then(|m| m.weights.as_ref()).and_then(|weights| {
+        weights.iter().find(|(pattern, _)| {
+            Pattern::new(pattern).is_ok_and(|p| p.matches_path(relative) || p.matches(&file_name))
+        }).map(|(_, mb)| *mb)
+    });
+    weight
+    .or_else(|| history.get(src).map(|peak| peak + peak / 10))
+    .unwrap_or_else(|| memory.and_then(|m| m.job_mb).unwrap_or(DEFAULT_JOB_MB))
+}
+
+/// Admits compile jobs while their estimates fit in the RAM that was available when the build started,
+/// minus `min_free_mb`, and holds them back while less than that is actually free. A job is always
+/// admitted when nothing else is running so an oversized estimate can't stall the build.
+pub struct Scheduler {
+    min_free: u64,
+    budget: Option<u64>,
+    running: Mutex<(u64, usize)>,
+    released: Condvar,
+    throttled: AtomicBool,
+}
+
+/// A running job's share of the budget, given back on drop.
+pub struct Reservation<'a> {
+    scheduler: &'a Scheduler,
+    mb: u64,
+}
+
+impl Scheduler {
+    pub fn new(memory: Option<&Memory>) -> Self {
+        let min_free = memory.and_then(|m| m.min_free_mb).unwrap_or(DEFAULT_MIN_FREE_MB);
+        Scheduler {
+            min_free,
+            budget: available_mb().map(|mb| mb.saturating_sub(min_free)),
+            running: Mutex::new((0, 0)),
+            released: Condvar::new(),
+            throttled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn acquire(&self, mb: u64) -> Reservation<'_> {
+        let mut running = self.running.lock().unwrap();
+        loop {
+            let (reserved, jobs) = *running;
+            let fits = self.budget.is_none_or(|budget| reserved + mb <= budget);
+            let free = available_mb().is_none_or(|free| free >= self.min_free);
+            if jobs == 0 || (fits && free) {
+                break;
+            }
+            if !self.throttled.swap(true, Ordering::SeqCst) {
+                verbosity::progress(format!("Throttling compile jobs to keep {} MB of RAM free", self.min_free).yellow());
+            }
+            running = self.released.wait_timeout(running, RECHECK_INTERVAL).unwrap().0;
+        }
+        running.0 += mb;
+        running.1 += 1;
+        Reservation { scheduler: self, mb }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut running = self.scheduler.running.lock().unwrap();
+        running.0 -= self.mb;
+        running.1 -= 1;
+        self.scheduler.released.notify_all();
+    }
+}
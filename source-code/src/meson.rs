@@ -0,0 +1,21 @@
+use std::path::Path;
+use std::process::Command;
+use crate::{args, staged};
+
+/// Builds git dependency `name`, a Meson project checked out in `src` at `commit`, and installs it
+/// into its staging prefix, whose `.pc` files the projects using it then link by. The build tree
+/// is set up afresh, since `meson setup` refuses one configured for an older commit.
+pub fn build_dependency(name: &str, src: &Path, commit: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let dir = staged::dir(name)?;
+    let build_dir = dir.join("build");
+    let mut clean = Command::new("rm");
+    clean.arg("-rf").arg(&build_dir);
+    let mut setup = Command::new("meson");
+    setup.arg("setup").arg(&build_dir).arg(src);
+    setup.arg(args::with_path("--prefix=", &dir.join("prefix"))).args(["--libdir=lib", "--buildtype=release"]);
+    let mut compile = Command::new("meson");
+    compile.arg("compile").arg("-C").arg(&build_dir);
+    let mut install = Command::new("meson");
+    install.arg("install").arg("-C").arg(&build_dir);
+    staged::run(name, commit, vec![clean, setup, compile, install])
+}
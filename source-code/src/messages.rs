@@ -0,0 +1,60 @@
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde_json::{json, Value};
+
+/// Set by `--message-format json`.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Switches to `--message-format json`: events go to stdout, one JSON object per line, and
+/// hbuild's own progress and status lines to stderr instead.
+pub fn enable_json() {
+    JSON.store(true, Ordering::Relaxed);
+}
+
+pub fn json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Writes `event` (an object with an `event` field) when JSON messages are enabled.
+pub fn emit(event: Value) {
+    if json() {
+        let _ = writeln!(std::io::stdout().lock(), "{}", event);
+    }
+}
+
+/// A GCC/Clang diagnostic line, `file:line[:column]: level: message`.
+pub fn parse_diagnostic(line: &str) -> Option<Value> {
+    for level in ["fatal error", "error", "warning", "note"] {
+        let Some((location, message)) = line.split_once(&format!(": {}: ", level)) else {
+            continue;
+        };
+        let mut parts = location.rsplitn(3, ':');
+        let (last, middle, first) = (parts.next()?, parts.next()?, parts.next());
+        let (file, line_no, column) = match (first, middle.parse::<u32>(), last.parse::<u32>()) {
+            (Some(file), Ok(line_no), Ok(column)) => (file, line_no, Some(column)),
+            _ => (middle, last.parse().ok()?, None),
+        };
+        return Some(json!({
+            "event": "diagnostic",
+            "level": if level == "fatal error" { "error" } else { level },
+            "file": file,
+            "line": line_no,
+            "column": column,
+            "message": message,
+        }));
+    }
+    None
+}
+
+/// Emits a `diagnostic` event for each GCC/Clang diagnostic in a tool's output, tagged with the
+/// `source` being compiled or the target being linked.
+pub fn diagnostics(output: &str, source: &Path) {
+    if !json() {
+        return;
+    }
+    for mut event in output.lines().filter_map(parse_diagnostic) {
+        event["source"] = json!(source.display().to_string());
+        emit(event);
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use crate::args;
+
+/// True for cl.exe and clang-cl, which take MSVC-style flags.
+pub fn is_msvc(compiler: &str) -> bool {
+    let stem = Path::new(compiler).file_stem().map(|s| s.to_string_lossy().to_lowercase()).unwrap_or_default();
+    stem == "cl" || stem == "clang-cl"
+}
+
+/// `/O` flag for a GCC-style optimize level.
+pub fn opt_flag(optimize: &str) -> String {
+    match optimize {
+        "O0" | "Og" => "/Od",
+        "O1" | "Os" | "Oz" => "/O1",
+        _ => "/O2",
+    }.to_string()
+}
+
+/// `/std:` flag for a GCC-style standard; empty where MSVC has no switch (C89/C99 are its default).
+pub fn std_flag(standard: &str) -> String {
+    let std = standard.trim_start_matches("gnu").trim_start_matches('c');
+    match (standard.contains("++"), std) {
+        (true, "++14") => "/std:c++14",
+        (true, "++17") => "/std:c++17",
+        (true, "++20") => "/std:c++20",
+        (true, _) => "/std:c++latest",
+        (false, "11") => "/std:c11",
+        (false, "17" | "18") => "/std:c17",
+        _ => "",
+    }.to_string()
+}
+
+/// A GCC-style link flag in cl's `/link` form: `-L` becomes `/LIBPATH:`, `-lfoo` becomes `foo.lib`.
+fn translate_link_flag(flag: &OsString) -> OsString {
+    let Some(f) = flag.to_str() else {
+        return flag.clone();
+    };
+    if let Some(dir) = f.strip_prefix("-L") {
+        format!("/LIBPATH:{}", dir).into()
+    } else if let Some(lib) = f.strip_prefix("-l") {
+        format!("{}.lib", lib).into()
+    } else {
+        flag.clone()
+    }
+}
+
+pub fn compile_args(std_flags: &[OsString], opt_flag: &str, cflags: &[OsString], include_flags: &[OsString], src: &Path, obj: &Path) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["/nologo".into(), "/EHsc".into()];
+    args.extend(std_flags.iter().cloned());
+    args.push(opt_flag.into());
+    args.extend(cflags.iter().cloned());
+    args.extend(include_flags.iter().cloned());
+    args.extend(["/c".into(), src.into(), args::with_path("/Fo", obj)]);
+    args
+}
+
+pub fn link_args(opt_flag: &str, target: &Path, objs: &[OsString], ldflags: &[OsString], shared: bool) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["/nologo".into(), opt_flag.into()];
+    if shared {
+        args.push("/LD".into());
+    }
+    args.push(args::with_path("/Fe", target));
+    args.extend(objs.iter().cloned());
+    args.push("/link".into());
+    args.extend(ldflags.iter().map(translate_link_flag));
+    args
+}
+
+/// Headers `file` includes, from the "Note: including file:" lines of `/showIncludes`.
+pub fn dependencies(compiler: &str, file: &Path, include_flags: &[OsString]) -> Result<HashSet<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    let output = Command::new(compiler)
+    .args(["/nologo", "/showIncludes", "/Zs"])
+    .arg(file)
+    .args(include_flags)
+    .output()?;
+    if !output.status.success() {
+        return Err(format!("Failed to get dependencies for {}", file.display()).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines()
+    .filter_map(|l| l.strip_prefix("Note: including file:"))
+    .map(|p| PathBuf::from(p.trim()))
+    .collect())
+}
+
+/// Creates a static library with lib.exe.
+pub fn archive(target: &Path, objs: &[OsString], path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let status = Command::new("lib")
+    .arg("/nologo")
+    .arg(args::with_path("/OUT:", target))
+    .args(objs)
+    .current_dir(path)
+    .status()?;
+    if !status.success() {
+        return Err("Archiving failed".into());
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use crate::{args, verbosity, BuildOptions, HBuildConfig};
+
+/// The main module: `[nim] main`, else `<name>.nim` or `main.nim` at the root or in `src`, as nimble lays them out.
+fn main_module(config: &HBuildConfig, path: &Path) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(main) = config.nim.as_ref().and_then(|n| n.main.as_ref()) {
+        let main = path.join(main);
+        return if main.exists() { Ok(main) } else { Err(format!("[nim] main {} does not exist", main.display()).into()) };
+    }
+    let name = format!("{}.nim", config.metadata.name);
+    let candidates = [PathBuf::from(&name), Path::new("src").join(&name), PathBuf::from("main.nim"), PathBuf::from("src/main.nim")];
+    candidates.iter().map(|c| path.join(c)).find(|c| c.exists())
+    .ok_or_else(|| format!("No Nim main module: set [nim] main or add {}", name).into())
+}
+
+/// nim's flags for an hbuild optimize level: debug builds keep nim's defaults, the rest are release builds.
+fn optimize_flags(optimize: &str) -> &'static [&'static str] {
+    match optimize {
+        "O0" => &[],
+        "Os" | "Oz" => &["-d:release", "--opt:size"],
+        _ => &["-d:release"],
+    }
+}
+
+/// nim's `--cpu` and `--os` for a target triple, e.g. `aarch64-linux-gnu` is arm64 and linux.
+fn platform(triple: &str) -> (&str, &str) {
+    let arch = triple.split('-').next().unwrap_or(triple);
+    let cpu = match arch {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "i386" | "i586" | "i686" => "i386",
+        a if a.starts_with("riscv64") => "riscv64",
+        a if a.starts_with("riscv32") => "riscv32",
+        a if a.starts_with("arm") => "arm",
+        a => a,
+    };
+    let os = if triple.contains("android") { "android" } else if triple.contains("linux") { "linux" } else { "standalone" };
+    (cpu, os)
+}
+
+/// Compiles the main module with `nim c` into the build directory, as the `[build]` target's name
+/// or the project's. Cross builds set nim's target and use the toolchain's C compiler.
+pub fn build(config: &HBuildConfig, path: &Path, opts: &BuildOptions) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let main = main_module(config, path)?;
+    // The release profile keeps [build]'s level, which a Nim-only project may not have
+    let release = opts.profile.as_deref() == Some("release");
+    let optimize = opts.optimize.as_deref().or(config.build.as_ref().map(|b| b.optimize.as_str())).unwrap_or(if release { "O2" } else { "O0" });
+    let name = config.build.as_ref().map_or(&config.metadata.name, |b| &b.target);
+    let build_dir = opts.build_dir(path);
+    fs::create_dir_all(&build_dir)?;
+
+    let mut command = Command::new("nim");
+    command.arg("c").args(optimize_flags(optimize));
+    if let Some(cross) = &opts.cross {
+        let (cpu, os) = platform(&cross.triple);
+        command.arg(format!("--cpu:{}", cpu)).arg(format!("--os:{}", os));
+        command.arg(format!("--gcc.exe:{}", cross.compiler)).arg(format!("--gcc.linkerexe:{}", cross.compiler));
+    }
+    command.args(args::split(config.nim.as_ref().and_then(|n| n.flags.as_deref()).unwrap_or_default()));
+    command.arg(args::with_path("--nimcache:", &build_dir.join("nimcache")));
+    command.arg(args::with_path("--outdir:", &build_dir));
+    command.arg(format!("--out:{}", name));
+    command.arg(&main).current_dir(path);
+    verbosity::command(&command);
+    if !command.status()?.success() {
+        return Err("nim build failed".into());
+    }
+    Ok(())
+}
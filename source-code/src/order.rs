@@ -0,0 +1,35 @@
+use std::collections::BTreeMap;
+
+/// The order to build `languages` in so each comes after the languages `[order]` says it depends on.
+/// Languages without constraints keep their `specs.languages` order.
+pub fn schedule(languages: &[String], order: Option<&BTreeMap<String, Vec<String>>>) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(order) = order else {
+        return Ok(languages.to_vec());
+    };
+    for (lang, deps) in order {
+        for name in std::iter::once(lang).chain(deps) {
+            if !languages.contains(name) {
+                return Err(format!("[order] refers to '{}', which is not in specs.languages", name).into());
+            }
+        }
+    }
+    let mut scheduled: Vec<String> = vec![];
+    while scheduled.len() < languages.len() {
+        let ready = languages.iter().find(|lang| {
+            !scheduled.contains(lang) && order.get(*lang).into_iter().flatten().all(|dep| scheduled.contains(dep))
+        });
+        match ready {
+            Some(lang) => scheduled.push(lang.clone()),
+            None => {
+                let blocked: Vec<&str> = languages.iter().filter(|l| !scheduled.contains(l)).map(|l| l.as_str()).collect();
+                return Err(format!("[order] has a cycle between {}", blocked.join(", ")).into());
+            }
+        }
+    }
+    Ok(scheduled)
+}
+
+/// The first of `lang`'s dependencies that failed to build, if any.
+pub fn failed_dependency<'a>(lang: &str, order: Option<&'a BTreeMap<String, Vec<String>>>, failed: &[String]) -> Option<&'a String> {
+    order?.get(lang)?.iter().find(|dep| failed.contains(dep))
+}
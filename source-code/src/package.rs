@@ -0,0 +1,176 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use owo_colors::OwoColorize;
+use crate::{find_config_file, install, make_with, parse_config, platform, verbosity, BuildOptions, HBuildConfig};
+
+/// Packages install into the distribution prefix.
+const PREFIX: &str = "/usr";
+
+/// Debian and RPM names for the host architecture.
+fn architectures() -> (&'static str, &'static str) {
+    match std::env::consts::ARCH {
+        "x86_64" => ("amd64", "x86_64"),
+        "x86" => ("i386", "i686"),
+        "aarch64" => ("arm64", "aarch64"),
+        "arm" => ("armhf", "armv7hl"),
+        "riscv64" => ("riscv64", "riscv64"),
+        "powerpc64" => ("ppc64el", "ppc64le"),
+        "s390x" => ("s390x", "s390x"),
+        other => (other, other),
+    }
+}
+
+fn run(cmd: &mut Command) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let out = cmd.output()?;
+    if !out.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&out.stderr).red());
+        return Err(format!("{} failed", program).into());
+    }
+    Ok(())
+}
+
+/// Files below `root`, as absolute paths on the target system, sorted.
+fn files(root: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut found = vec![];
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                pending.push(entry.path());
+            } else {
+                found.push(Path::new("/").join(entry.path().strip_prefix(root)?));
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// `long` as a Debian extended description: indented by one space, blank lines as ` .`.
+fn debian_description(summary: &str, long: &str) -> String {
+    let mut text = summary.to_string();
+    for line in long.lines() {
+        text.push_str(if line.trim().is_empty() { "\n ." } else { "\n " });
+        text.push_str(line.trim());
+    }
+    text
+}
+
+fn deb(config: &HBuildConfig, root: &Path, out_dir: &Path, arch: &str) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    let meta = &config.metadata;
+    let installed = files(root)?;
+    let size_kb: u64 = installed.iter().filter_map(|f| root.join(f.strip_prefix("/").unwrap()).metadata().ok()).map(|m| m.len().div_ceil(1024)).sum();
+    // dpkg-deb packs the control file from inside the tree, so it lives in a copy of the staged root
+    let deb_root = out_dir.join("deb");
+    if deb_root.exists() {
+        fs::remove_dir_all(&deb_root)?;
+    }
+    run(Command::new("cp").arg("-a").arg(root).arg(&deb_root))?;
+    fs::create_dir_all(deb_root.join("DEBIAN"))?;
+    let mut control = format!("Package: {}\nVersion: {}\nArchitecture: {}\nMaintainer: {}\nInstalled-Size: {}\n",
+        meta.name, meta.version, arch, meta.authors.as_deref().unwrap_or("unknown"), size_kb);
+    control.push_str(&format!("Description: {}\n", debian_description(&config.description.summary, &config.description.long)));
+    fs::write(deb_root.join("DEBIAN/control"), control)?;
+    // Local edits to files under /etc survive upgrades
+    let conffiles: Vec<String> = installed.iter().filter(|f| f.starts_with("/etc")).map(|f| format!("{}\n", f.display())).collect();
+    if !conffiles.is_empty() {
+        fs::write(deb_root.join("DEBIAN/conffiles"), conffiles.concat())?;
+    }
+    let deb = out_dir.join(format!("{}_{}_{}.deb", meta.name, meta.version, arch));
+    run(Command::new("dpkg-deb").args(["--build", "--root-owner-group"]).arg(&deb_root).arg(&deb))?;
+    fs::remove_dir_all(&deb_root)?;
+    Ok(deb)
+}
+
+fn rpm(config: &HBuildConfig, root: &Path, out_dir: &Path, arch: &str) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    let meta = &config.metadata;
+    let topdir = out_dir.join("rpmbuild");
+    if topdir.exists() {
+        fs::remove_dir_all(&topdir)?;
+    }
+    fs::create_dir_all(topdir.join("SPECS"))?;
+    // RPM versions cannot contain '-'
+    let version = meta.version.replace('-', "_");
+    let listed: Vec<String> = files(root)?.iter().map(|f| {
+        let config_file = if f.starts_with("/etc") { "%config(noreplace) " } else { "" };
+        format!("{}\"{}\"", config_file, f.display())
+    }).collect();
+    let spec = format!(
+        "Name: {}\nVersion: {}\nRelease: 1\nSummary: {}\nLicense: {}\nBuildArch: {}\n\n%description\n{}\n\n\
+         %install\ncp -a \"{}\"/. %{{buildroot}}/\n\n%files\n{}\n",
+        meta.name, version, config.description.summary, meta.license.as_deref().unwrap_or("Unknown"), arch,
+        config.description.long, root.canonicalize()?.display(), listed.join("\n"),
+    );
+    let spec_path = topdir.join("SPECS").join(format!("{}.spec", meta.name));
+    fs::write(&spec_path, spec)?;
+    run(Command::new("rpmbuild").arg("-bb")
+    .arg("--define").arg(format!("_topdir {}", topdir.canonicalize()?.display()))
+    // The staged files are already built and may be stripped differently than rpm would
+    .arg("--define").arg("__os_install_post %{nil}")
+    .arg("--define").arg("debug_package %{nil}")
+    .arg(&spec_path))?;
+    let built = topdir.join("RPMS").join(arch).join(format!("{}-{}-1.{}.rpm", meta.name, version, arch));
+    let rpm = out_dir.join(built.file_name().unwrap());
+    fs::rename(&built, &rpm)?;
+    fs::remove_dir_all(&topdir)?;
+    Ok(rpm)
+}
+
+/// Builds the project, installs it into a staging tree under `/usr` and packs that tree as a tarball,
+/// plus a .deb and an .rpm when `dpkg-deb` and `rpmbuild` are installed. Packages go to `build/package`.
+pub fn package(path: &Path, children: &Arc<Mutex<Vec<u32>>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    if config.build.is_none() {
+        return Err("package needs a [build] section".into());
+    }
+    make_with(path, children, &BuildOptions::default())?;
+
+    let out_dir = path.join("build").join("package");
+    let root = out_dir.join("root");
+    if root.exists() {
+        fs::remove_dir_all(&root)?;
+    }
+    fs::create_dir_all(&root)?;
+    let layout = install::Layout { prefix: PathBuf::from(PREFIX), destdir: Some(root.clone()) };
+    install::run(path, &layout, &BuildOptions::default(), false, false)?;
+    // The package manager tracks the files, not an hbuild manifest
+    let state_dir = layout.staged(Path::new("/var/lib/hbuild"));
+    if state_dir.exists() {
+        fs::remove_dir_all(&state_dir)?;
+        let _ = fs::remove_dir(layout.staged(Path::new("/var/lib")));
+        let _ = fs::remove_dir(layout.staged(Path::new("/var")));
+    }
+
+    let meta = &config.metadata;
+    let (deb_arch, rpm_arch) = architectures();
+    verbosity::progress(format!("Packaging {} {}", meta.name, meta.version).blue().bold());
+    let tarball = out_dir.join(format!("{}-{}-{}.tar.gz", meta.name, meta.version, std::env::consts::ARCH));
+    run(Command::new("tar").args(["--owner=0", "--group=0", "-czf"]).arg(&tarball).arg("-C").arg(&root).arg("."))?;
+    let mut artifacts = vec![tarball];
+    if platform::which("dpkg-deb").is_some() {
+        artifacts.push(deb(&config, &root, &out_dir, deb_arch)?);
+    } else {
+        verbosity::progress("dpkg-deb not found; skipping .deb".yellow());
+    }
+    if platform::which("rpmbuild").is_some() {
+        artifacts.push(rpm(&config, &root, &out_dir, rpm_arch)?);
+    } else {
+        verbosity::progress("rpmbuild not found; skipping .rpm".yellow());
+    }
+    for artifact in &artifacts {
+        println!("   {}", artifact.display().to_string().green());
+    }
+    verbosity::status("Packaging complete!");
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::{args, find_config_file, make, mtime, parse_config, target_path, BuildOptions, HBuildConfig};
+
+/// Path dependencies being built, outermost first, so a cycle is an error rather than endless recursion.
+static BUILDING: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn load(name: &str, dir: &Path) -> Result<HBuildConfig, Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = find_config_file(dir).ok_or_else(|| format!("Path dependency {} has no hbuild config in {}", name, dir.display()))?;
+    parse_config(&config_path, &format)
+}
+
+/// Builds the project at `dir` that dependency `name` points to, along with its own dependencies.
+pub fn build(name: &str, dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let dir = dir.canonicalize().map_err(|_| format!("Path dependency {} not found at {}", name, dir.display()))?;
+    load(name, &dir)?;
+    {
+        let mut building = BUILDING.lock().unwrap();
+        if let Some(start) = building.iter().position(|d| *d == dir) {
+            let cycle: Vec<String> = building[start..].iter().chain([&dir]).map(|d| d.display().to_string()).collect();
+            return Err(format!("Path dependency cycle: {}", cycle.join(" -> ")).into());
+        }
+        building.push(dir.clone());
+    }
+    let result = make(&dir, &Arc::new(Mutex::new(Vec::new())));
+    BUILDING.lock().unwrap().pop();
+    result
+}
+
+/// What a project's dependencies add to its C and C++ build: the usage requirements of its path
+/// dependencies, i.e. the include directories, defines and libraries they export, and their own
+/// libraries, or what a staged dependency installed.
+#[derive(Default)]
+pub struct Usage {
+    pub include_dirs: Vec<PathBuf>,
+    pub defines: Vec<String>,
+    pub libraries: Vec<PathBuf>, // each after those depending on it
+    pub libs: Vec<String>, // `-l` names, e.g. `public_libs`, linked after the libraries needing them
+    pub link_dirs: Vec<PathBuf>, // searched for `libs` at link and run time
+}
+
+/// `items` without repeats, keeping each one's last place: a library several others need has to
+/// follow all of them.
+fn keep_last<T: PartialEq>(items: &mut Vec<T>) {
+    let mut kept: Vec<T> = vec![];
+    for item in items.drain(..).rev() {
+        if !kept.contains(&item) {
+            kept.push(item);
+        }
+    }
+    items.extend(kept.into_iter().rev());
+}
+
+impl Usage {
+    /// The libraries as link arguments, with an rpath to each shared one's directory so the target
+    /// runs from the build tree.
+    pub fn link_args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = self.libraries.iter().map(OsString::from).collect();
+        for lib in self.libraries.iter().filter(|l| l.extension().is_some_and(|e| e != "a" && e != "lib")) {
+            args.extend(lib.parent().map(|d| args::with_path("-Wl,-rpath,", d)));
+        }
+        for dir in &self.link_dirs {
+            args.extend([args::with_path("-L", dir), args::with_path("-Wl,-rpath,", dir)]);
+        }
+        args.extend(self.libs.iter().map(|l| OsString::from(format!("-l{}", l))));
+        args
+    }
+
+    pub fn extend(&mut self, other: Usage) {
+        self.include_dirs.extend(other.include_dirs);
+        self.defines.extend(other.defines);
+        self.libraries.extend(other.libraries);
+        self.libs.extend(other.libs);
+        self.link_dirs.extend(other.link_dirs);
+    }
+
+    /// The defines as compile flags.
+    pub fn define_flags(&self) -> Vec<OsString> {
+        self.defines.iter().map(|d| OsString::from(format!("-D{}", d))).collect()
+    }
+
+    /// True when a library is newer than `linked`, which then has to be linked again.
+    pub fn changed_since(&self, linked: std::time::SystemTime) -> bool {
+        self.libraries.iter().any(|l| mtime(l) > linked)
+    }
+}
+
+/// The usage requirements of the project's path dependencies, as built by `build`: each one's
+/// `public_include_dirs` (else its `include_dirs`), `public_defines` and `public_libs`, with those
+/// of its own path dependencies, which its headers and static library may need in turn.
+pub fn usage(config: &HBuildConfig, path: &Path) -> Result<Usage, Box<dyn std::error::Error + Send + Sync>> {
+    let mut usage = Usage::default();
+    for (name, dep) in &config.specs.dependencies {
+        let Some(dir) = dep.path(path) else {
+            continue;
+        };
+        // Absolute, for the rpath to hold wherever the target runs from
+        let dir = dir.canonicalize().map_err(|_| format!("Path dependency {} not found at {}", name, dir.display()))?;
+        let dep_config = load(name, &dir)?;
+        let build = dep_config.build.as_ref().ok_or_else(|| format!("Path dependency {} has no [build] library to link", name))?;
+        if build.build_type == "executable" {
+            return Err(format!("Path dependency {} builds an executable, not a library", name).into());
+        }
+        let library = target_path(build, &dir, &BuildOptions::default());
+        if !library.exists() {
+            return Err(format!("Path dependency {} did not build {}", name, library.display()).into());
+        }
+        usage.include_dirs.extend(build.public_include_dirs.as_ref().unwrap_or(&build.include_dirs).iter().map(|d| dir.join(d)));
+        usage.defines.extend(build.public_defines.iter().flatten().cloned());
+        usage.libraries.push(library);
+        usage.libs.extend(build.public_libs.iter().flatten().cloned());
+        usage.extend(self::usage(&dep_config, &dir)?);
+    }
+    keep_last(&mut usage.include_dirs);
+    keep_last(&mut usage.defines);
+    keep_last(&mut usage.libraries);
+    keep_last(&mut usage.libs);
+    keep_last(&mut usage.link_dirs);
+    Ok(usage)
+}
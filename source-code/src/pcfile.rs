@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::Path;
+use crate::install::{Layout, Manifest};
+use crate::{pkgdeps, Build, HBuildConfig};
+
+/// The `-l` flag that finds the installed library `file`: `-lfoo` for `libfoo.so` and `libfoo.a`,
+/// else GNU ld's `-l:<file>` for names `-l` can't spell, like `foo.a`.
+fn link_flag(file: &str) -> String {
+    let short = file.strip_prefix("lib").and_then(|f| f.split_once('.')).map(|(name, _)| name);
+    match short {
+        Some(name) if !name.is_empty() => format!("-l{}", name),
+        _ => format!("-l:{}", file),
+    }
+}
+
+/// `pkg_dependencies` as pkg-config module requirements, with `==` spelled the way `.pc` files do.
+fn requires(build: &Build) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let mut modules = vec![];
+    for entry in build.pkg_dependencies.iter().flatten() {
+        let requirement = pkgdeps::parse(entry)?;
+        modules.push(match requirement.constraint {
+            Some((op, version)) => format!("{} {} {}", requirement.name, if op == "==" { "=" } else { &op }, version),
+            None => requirement.name,
+        });
+    }
+    Ok(modules.join(", "))
+}
+
+/// `<name>.pc` for the library installed under `layout`'s prefix as `file`. A shared library's own
+/// dependencies are private, since programs only link against it; a static one passes them on.
+pub fn generate(config: &HBuildConfig, build: &Build, file: &str, layout: &Layout) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let shared = build.build_type == "shared";
+    let libs: Vec<String> = build.libs.iter().flatten().map(|l| format!("-l{}", l)).collect();
+    let mut pc = format!("prefix={}\nexec_prefix=${{prefix}}\nlibdir=${{exec_prefix}}/lib\nincludedir=${{prefix}}/include\n\n", layout.prefix.display());
+    pc.push_str(&format!("Name: {}\n", config.metadata.name));
+    pc.push_str(&format!("Description: {}\n", config.description.summary));
+    pc.push_str(&format!("Version: {}\n", config.metadata.version));
+    let requires = requires(build)?;
+    if !requires.is_empty() {
+        pc.push_str(&format!("{}: {}\n", if shared { "Requires.private" } else { "Requires" }, requires));
+    }
+    let mut public_libs = format!("-L${{libdir}} {}", link_flag(file));
+    if !shared && !libs.is_empty() {
+        public_libs = format!("{} {}", public_libs, libs.join(" "));
+    }
+    pc.push_str(&format!("Libs: {}\n", public_libs));
+    if shared && !libs.is_empty() {
+        pc.push_str(&format!("Libs.private: {}\n", libs.join(" ")));
+    }
+    pc.push_str("Cflags: -I${includedir}\n");
+    Ok(pc)
+}
+
+/// Writes `<name>.pc` to `build_dir` and installs it to `<libdir>/pkgconfig`, where pkg-config
+/// finds it once the prefix is on `PKG_CONFIG_PATH`.
+pub fn install(config: &HBuildConfig, build: &Build, file: &str, build_dir: &Path, layout: &Layout, manifest: &mut Manifest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let name = format!("{}.pc", config.metadata.name);
+    let generated = build_dir.join(&name);
+    fs::create_dir_all(build_dir)?;
+    fs::write(&generated, generate(config, build, file, layout)?)?;
+    let pc_dir = layout.libdir().join("pkgconfig");
+    manifest.create_dir_all(&pc_dir)?;
+    manifest.copy(&generated, &pc_dir.join(name))
+}
@@ -0,0 +1,185 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use owo_colors::OwoColorize;
+use crate::cross::{self, Cross};
+use crate::{compile_c_cpp, find_config_file, install_deps, parse_config, rules, sandbox, shaders, target_path, verbosity, BuildOptions};
+
+fn is_clang(compiler: &str) -> bool {
+    compiler.contains("clang")
+}
+
+/// Profile flags for one phase.
+fn phase_flags(compiler: &str, generate: bool, profile_dir: &Path) -> String {
+    match (is_clang(compiler), generate) {
+        (_, true) => format!("-fprofile-generate={}", profile_dir.display()),
+        (true, false) => format!("-fprofile-use={}", profile_dir.join("default.profdata").display()),
+        (false, false) => format!("-fprofile-use={} -Wno-missing-profile", profile_dir.display()),
+    }
+}
+
+/// Where GCC keeps the counters for objects in `build_dir`: the absolute object path under the profile dir.
+fn gcda_dir(profile_dir: &Path, build_dir: &Path) -> PathBuf {
+    profile_dir.join(build_dir.strip_prefix("/").unwrap_or(build_dir))
+}
+
+/// Prepares the collected profile for the optimized build. Clang's raw profiles are merged into
+/// `default.profdata`; GCC's `.gcda` files are named after the instrumented objects, so they are
+/// copied to the names the optimized objects will look up.
+fn merge(compiler: &str, profile_dir: &Path, instrumented: &Path, optimized: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let ext = if is_clang(compiler) { "profraw" } else { "gcda" };
+    let search = if is_clang(compiler) { profile_dir.to_path_buf() } else { gcda_dir(profile_dir, instrumented) };
+    let raw: Vec<PathBuf> = fs::read_dir(&search).into_iter().flatten()
+    .filter_map(|e| e.ok().map(|e| e.path()))
+    .filter(|p| p.extension().is_some_and(|e| e == ext))
+    .collect();
+    if raw.is_empty() {
+        return Err(format!("No .{} profile data in {}; run `hbuild pgo <folder> generate` and train the instrumented target first", ext, search.display()).into());
+    }
+    if !is_clang(compiler) {
+        let dest = gcda_dir(profile_dir, optimized);
+        fs::create_dir_all(&dest)?;
+        for gcda in &raw {
+            fs::copy(gcda, dest.join(gcda.file_name().unwrap()))?;
+        }
+        return Ok(());
+    }
+    verbosity::progress("Merging profiles".cyan());
+    let output = Command::new("llvm-profdata")
+    .arg("merge")
+    .arg("-output")
+    .arg(profile_dir.join("default.profdata"))
+    .args(&raw)
+    .output()?;
+    if !output.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
+        return Err("llvm-profdata merge failed".into());
+    }
+    Ok(())
+}
+
+/// Which phases of `hbuild pgo` to run: `generate` builds the instrumented target and runs the training
+/// command, `use` merges the collected profile and builds the optimized target; by default both.
+fn phases(phase: Option<&str>) -> Result<(bool, bool), Box<dyn std::error::Error + Send + Sync>> {
+    match phase {
+        None => Ok((true, true)),
+        Some("generate") => Ok((true, false)),
+        Some("use") => Ok((false, true)),
+        Some(other) => Err(format!("Unknown pgo phase '{}' (expected generate or use)", other).into()),
+    }
+}
+
+/// Profile-guided build, all under `build/pgo`. The generate phase builds an instrumented target in
+/// `instrumented` and runs the `[pgo]` training command against it (`$target` is the instrumented
+/// binary), or leaves that to the user when there is none; counters are collected in `profile`. The use
+/// phase merges them and rebuilds from scratch in `optimized` with the profile.
+pub fn run(path: &Path, children: &Arc<Mutex<Vec<u32>>>, cross: Option<&Cross>, phase: Option<&str>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (generate, optimize) = phases(phase)?;
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    // GCC lays out counters by absolute object path
+    let path = &path.canonicalize()?;
+    let build = config.build.as_ref().ok_or("No build section")?;
+    let train = config.pgo.as_ref().and_then(|p| p.train.as_ref());
+    verbosity::progress(format!("Profile-guided build of {}", config.metadata.name).blue().bold());
+    install_deps(&config, path)?;
+    if let Some(r) = &config.rules {
+        rules::run(r, path, &path.join("build"), sandbox::enabled(&config))?;
+    }
+
+    let pgo_dir = cross::build_root(path, cross).join("pgo");
+    let compiler = cross.map_or(&build.compiler, |c| &c.compiler);
+    let profile_dir = pgo_dir.join("profile");
+    let instrumented = pgo_dir.join("instrumented");
+    let optimized = pgo_dir.join("optimized");
+
+    if generate {
+        // Old counters would mix with this run's, or not match objects that are rebuilt
+        if profile_dir.exists() {
+            fs::remove_dir_all(&profile_dir)?;
+        }
+        fs::create_dir_all(&profile_dir)?;
+        verbosity::progress("Building instrumented target".cyan());
+        let opts = BuildOptions {
+            build_dir: Some(instrumented.clone()),
+            extra_flags: Some(phase_flags(compiler, true, &profile_dir)),
+            cross: cross.cloned(),
+            ..Default::default()
+        };
+        if let Some(sh) = &config.shaders {
+            shaders::compile(sh, path, &opts.build_dir(path))?;
+        }
+        compile_c_cpp(&config, path, children, &opts)?;
+
+        let target = cross::invocation(&target_path(build, path, &opts), cross);
+        match train {
+            Some(train) => {
+                let command = train.replace("$target", &target);
+                verbosity::progress(format!("Training: {}", command).cyan());
+                let status = Command::new("sh").arg("-c").arg(&command).current_dir(path).status()?;
+                if !status.success() {
+                    return Err(format!("Training command failed: {}", command).into());
+                }
+            }
+            None if optimize => return Err("No training command; set [pgo] train or run the phases separately".into()),
+            None => verbosity::progress(format!("Run {} with a representative workload, then `hbuild pgo {} use`", target, path.display()).yellow()),
+        }
+    }
+
+    if optimize {
+        merge(compiler, &profile_dir, &instrumented, &optimized)?;
+        // A new profile changes the code generated for unchanged sources, so rebuild from scratch
+        verbosity::progress("Building optimized target".cyan());
+        if optimized.exists() {
+            fs::remove_dir_all(&optimized)?;
+        }
+        let opts = BuildOptions {
+            build_dir: Some(optimized.clone()),
+            extra_flags: Some(phase_flags(compiler, false, &profile_dir)),
+            cross: cross.cloned(),
+            // The profile isn't part of the cache keys, so cached objects may be from an older one
+            no_cache: true,
+            ..Default::default()
+        };
+        if let Some(sh) = &config.shaders {
+            shaders::compile(sh, path, &opts.build_dir(path))?;
+        }
+        compile_c_cpp(&config, path, children, &opts)?;
+        verbosity::status(&format!("PGO build complete: {}", target_path(build, path, &opts).display()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_flags_per_compiler() {
+        let dir = Path::new("/p/build/pgo/profile");
+        assert_eq!(phase_flags("gcc", true, dir), "-fprofile-generate=/p/build/pgo/profile");
+        assert_eq!(phase_flags("clang", true, dir), "-fprofile-generate=/p/build/pgo/profile");
+        assert_eq!(phase_flags("clang++", false, dir), "-fprofile-use=/p/build/pgo/profile/default.profdata");
+        assert_eq!(phase_flags("g++", false, dir), "-fprofile-use=/p/build/pgo/profile -Wno-missing-profile");
+    }
+
+    #[test]
+    fn gcda_files_follow_the_object_path() {
+        assert_eq!(gcda_dir(Path::new("/prof"), Path::new("/p/build/pgo/instrumented")), Path::new("/prof/p/build/pgo/instrumented"));
+    }
+
+    #[test]
+    fn parses_phases() {
+        assert_eq!(phases(None).unwrap(), (true, true));
+        assert_eq!(phases(Some("generate")).unwrap(), (true, false));
+        assert_eq!(phases(Some("use")).unwrap(), (false, true));
+        assert!(phases(Some("train")).is_err());
+    }
+}
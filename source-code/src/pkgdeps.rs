@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use dirs::home_dir;
+use owo_colors::OwoColorize;
+use crate::{credentials, job_count, protobuf, vendor, verbosity, HBuildConfig, PkgFallback};
+
+/// A `pkg_dependencies` entry: a pkg-config module name with an optional version constraint,
+/// e.g. `glib-2.0 >= 2.70`.
+#[derive(Debug)]
+pub struct Requirement {
+    pub name: String,
+    pub constraint: Option<(String, String)>,
+}
+
+const OPERATORS: &[&str] = &[">=", "<=", "==", "!=", "=", ">", "<"];
+
+/// Parses `name`, `name >= 1.2` or `name>=1.2`.
+pub fn parse(entry: &str) -> Result<Requirement, Box<dyn std::error::Error + Send + Sync>> {
+    let entry = entry.trim();
+    let split = OPERATORS.iter().filter_map(|op| entry.find(op).map(|i| (i, *op))).min_by_key(|(i, op)| (*i, std::cmp::Reverse(op.len())));
+    let Some((i, op)) = split else {
+        return Ok(Requirement { name: entry.to_string(), constraint: None });
+    };
+    let name = entry[..i].trim();
+    let version = entry[i + op.len()..].trim();
+    if name.is_empty() || version.is_empty() || version.contains(char::is_whitespace) {
+        return Err(format!("Invalid pkg dependency '{}' (expected e.g. \"glib-2.0 >= 2.70\")", entry).into());
+    }
+    if op == "!=" {
+        return Err(format!("Unsupported version operator '!=' in pkg dependency '{}'", entry).into());
+    }
+    Ok(Requirement { name: name.to_string(), constraint: Some((op.to_string(), version.to_string())) })
+}
+
+/// Where pkg-config looks for `.pc` files.
+#[derive(Debug, Clone, Default)]
+pub enum PkgConfigMode {
+    /// The host's own search path.
+    #[default]
+    Host,
+    /// No usable `.pc` files exist for the target; any pkg dependency is an error.
+    Disabled,
+    /// Only `libdirs`, with paths rewritten under `sysroot`.
+    Sysroot { libdirs: Vec<PathBuf>, sysroot: Option<PathBuf> },
+}
+
+/// Compile and link flags for one pkg-config module.
+#[derive(Debug, Default)]
+pub struct Library {
+    pub include_paths: Vec<PathBuf>,
+    pub defines: Vec<(String, Option<String>)>,
+    pub other_cflags: Vec<String>,
+    pub link_paths: Vec<PathBuf>,
+    pub libs: Vec<String>,
+    pub other_libs: Vec<String>,
+}
+
+fn pkg_config(pc_dirs: &[PathBuf], mode: &PkgConfigMode) -> Command {
+    let mut cmd = Command::new("pkg-config");
+    if let PkgConfigMode::Sysroot { libdirs, sysroot } = mode {
+        let joined = std::env::join_paths(libdirs).unwrap_or_default();
+        cmd.env("PKG_CONFIG_LIBDIR", joined).env_remove("PKG_CONFIG_PATH");
+        if let Some(sysroot) = sysroot {
+            cmd.env("PKG_CONFIG_SYSROOT_DIR", sysroot);
+        }
+    }
+    for dir in pc_dirs {
+        cmd.arg(format!("--with-path={}", dir.display()));
+    }
+    cmd
+}
+
+fn query(cmd: &mut Command) -> Option<String> {
+    let output = cmd.output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Probes `entry` with pkg-config, also searching `pc_dirs`, and enforces its version constraint. A missing
+/// module or one that is too old or too new is an error naming the requirement and the installed version.
+pub fn probe(entry: &str, pc_dirs: &[PathBuf], mode: &PkgConfigMode) -> Result<Library, Box<dyn std::error::Error + Send + Sync>> {
+    let req = parse(entry)?;
+    if let PkgConfigMode::Disabled = mode {
+        return Err(format!("pkg dependency '{}' cannot be probed for this target; configure a pkg-config libdir for it", req.name).into());
+    }
+    // pkg-config checks the constraint itself when given "name op version" as one argument
+    let module = match &req.constraint {
+        Some((op, version)) => format!("{} {} {}", req.name, if op == "==" { "=" } else { op }, version),
+        None => req.name.clone(),
+    };
+    let cflags = query(pkg_config(pc_dirs, mode).arg("--cflags").arg(&module));
+    let libs = query(pkg_config(pc_dirs, mode).arg("--libs").arg(&module));
+    let (Some(cflags), Some(libs)) = (cflags, libs) else {
+        let installed = query(pkg_config(pc_dirs, mode).arg("--modversion").arg(&req.name));
+        return Err(match (installed, &req.constraint) {
+            (Some(found), Some(_)) => format!("pkg dependency '{}' not satisfied: found {} {}", entry.trim(), req.name, found),
+            (None, _) => format!("pkg dependency '{}' not found (is the -dev package installed?)", req.name),
+            (Some(_), None) => format!("pkg-config failed for {}", req.name),
+        }.into());
+    };
+    let mut lib = Library::default();
+    for flag in cflags.split_whitespace() {
+        if let Some(dir) = flag.strip_prefix("-I") {
+            lib.include_paths.push(PathBuf::from(dir));
+        } else if let Some(define) = flag.strip_prefix("-D") {
+            let (key, val) = match define.split_once('=') {
+                Some((k, v)) => (k.to_string(), Some(v.to_string())),
+                None => (define.to_string(), None),
+            };
+            lib.defines.push((key, val));
+        } else {
+            lib.other_cflags.push(flag.to_string());
+        }
+    }
+    for flag in libs.split_whitespace() {
+        if let Some(dir) = flag.strip_prefix("-L") {
+            lib.link_paths.push(PathBuf::from(dir));
+        } else if let Some(name) = flag.strip_prefix("-l") {
+            lib.libs.push(name.to_string());
+        } else {
+            lib.other_libs.push(flag.to_string());
+        }
+    }
+    Ok(lib)
+}
+
+/// Whether pkg-config finds the module `name` at all, whatever its version.
+pub fn found(name: &str, mode: &PkgConfigMode) -> bool {
+    !matches!(mode, PkgConfigMode::Disabled) && query(pkg_config(&[], mode).arg("--exists").arg(name)).is_some()
+}
+
+/// Installed version of the module `entry` names, enforcing its constraint like `resolve` but
+/// never building a fallback.
+pub fn installed(entry: &str, mode: &PkgConfigMode) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    probe(entry, &[], mode)?;
+    let name = parse(entry)?.name;
+    Ok(query(pkg_config(&[], mode).arg("--modversion").arg(&name)).unwrap_or_default())
+}
+
+/// Root of the per-package fallback builds: `~/.hbuild/cache/pkg/<name>/{src,prefix}`.
+fn fallback_root() -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(home_dir().ok_or("Cannot find home directory")?.join(".hbuild/cache/pkg"))
+}
+
+/// True for library dirs produced by a fallback build, which need an rpath to be found at run time.
+pub fn is_fallback_path(path: &Path) -> bool {
+    fallback_root().is_ok_and(|root| path.starts_with(root))
+}
+
+/// `.pc` directories under a fallback build's install prefix.
+pub fn fallback_pc_dirs(prefix: &Path) -> Vec<PathBuf> {
+    ["lib/pkgconfig", "lib64/pkgconfig", "share/pkgconfig"].iter().map(|d| prefix.join(d)).collect()
+}
+
+/// The install prefix of `name`'s fallback build, if one was built.
+pub fn fallback_prefix(name: &str) -> Option<PathBuf> {
+    let prefix = fallback_root().ok()?.join(name).join("prefix");
+    prefix.join(".hbuild-fallback").exists().then_some(prefix)
+}
+
+/// Build command for a fetched source tree, guessed from its build system unless `build` is set.
+fn build_command(fallback: &PkgFallback, src: &Path) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(build) = &fallback.build {
+        return Ok(build.clone());
+    }
+    let jobs = job_count()?;
+    if src.join("configure").exists() {
+        Ok(format!("./configure --prefix=$prefix && make -j{} && make install", jobs))
+    } else if src.join("meson.build").exists() {
+        Ok("meson setup _build --prefix=$prefix --libdir=lib && meson install -C _build".to_string())
+    } else if src.join("CMakeLists.txt").exists() {
+        Ok(format!("cmake -S . -B _build -DCMAKE_INSTALL_PREFIX=$prefix -DCMAKE_INSTALL_LIBDIR=lib && cmake --build _build -j{} && cmake --install _build", jobs))
+    } else {
+        Err(format!("Cannot tell how to build {}; set `build` in its fallback", src.display()).into())
+    }
+}
+
+fn fetch(fallback: &PkgFallback, src: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if vendor::offline() {
+        return Err(vendor::offline_error(&format!("Fetching the fallback source into {}", src.display())));
+    }
+    match (&fallback.git, &fallback.url) {
+        (Some(git), _) => {
+            let repo = credentials::clone(git, src)?;
+            if let Some(rev) = &fallback.rev {
+                let obj = repo.revparse_single(rev)?;
+                repo.checkout_tree(&obj, None)?;
+                repo.set_head_detached(obj.id())?;
+            }
+        }
+        (None, Some(url)) => {
+            fs::create_dir_all(src)?;
+            let archive = src.with_file_name(url.rsplit('/').next().unwrap_or("source.tar"));
+            let status = Command::new("curl").arg("-fsSL").arg("-o").arg(&archive).arg(url).status()?;
+            if !status.success() {
+                return Err(format!("Download failed: {}", url).into());
+            }
+            let status = Command::new("tar").arg("xf").arg(&archive).arg("-C").arg(src).arg("--strip-components=1").status()?;
+            if !status.success() {
+                return Err(format!("Could not unpack {}", archive.display()).into());
+            }
+        }
+        (None, None) => return Err("A pkg fallback needs `git` or `url`".into()),
+    }
+    Ok(())
+}
+
+/// Fetches and builds `name` from its fallback source into the cache, once per source spec.
+/// Returns the install prefix.
+fn build_fallback(name: &str, fallback: &PkgFallback) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    let dir = fallback_root()?.join(name);
+    let src = dir.join("src");
+    let prefix = dir.join("prefix");
+    let stamp = prefix.join(".hbuild-fallback");
+    let spec = format!("{:?}", fallback);
+    if fs::read_to_string(&stamp).is_ok_and(|old| old == spec) {
+        return Ok(prefix);
+    }
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+
+    verbosity::progress(format!("Fetching {} from source", name).cyan());
+    fetch(fallback, &src)?;
+    let command = build_command(fallback, &src)?.replace("$prefix", &prefix.display().to_string());
+    verbosity::progress(format!("Building {}: {}", name, command).cyan());
+    let output = Command::new("sh").arg("-c").arg(&command).current_dir(&src).output()?;
+    if !output.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&output.stdout).red());
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
+        return Err(format!("Building fallback for {} failed", name).into());
+    }
+    fs::create_dir_all(&prefix)?;
+    fs::write(&stamp, spec)?;
+    Ok(prefix)
+}
+
+/// The project's `pkg_dependencies` and the runtimes its generated code needs, such as protobuf's.
+pub fn modules(config: &HBuildConfig) -> Vec<String> {
+    let mut modules = config.build.as_ref().and_then(|b| b.pkg_dependencies.clone()).unwrap_or_default();
+    for runtime in protobuf::runtime(config) {
+        if !modules.iter().any(|m| parse(m).is_ok_and(|r| r.name == runtime)) {
+            modules.push(runtime);
+        }
+    }
+    modules
+}
+
+/// Like `resolve`, but only probing a fallback built before, never building one.
+pub fn resolve_built(entry: &str, mode: &PkgConfigMode) -> Result<Library, Box<dyn std::error::Error + Send + Sync>> {
+    let err = match probe(entry, &[], mode) {
+        Ok(lib) => return Ok(lib),
+        Err(e) => e,
+    };
+    match fallback_prefix(&parse(entry)?.name).filter(|_| matches!(mode, PkgConfigMode::Host)) {
+        Some(prefix) => probe(entry, &fallback_pc_dirs(&prefix), mode),
+        None => Err(err),
+    }
+}
+
+/// Probes `entry` on the system and, when that fails and `pkg_fallbacks` has an entry for it,
+/// builds the package from source into the cache and probes the installed `.pc` file instead.
+pub fn resolve(entry: &str, fallbacks: Option<&BTreeMap<String, PkgFallback>>, mode: &PkgConfigMode) -> Result<Library, Box<dyn std::error::Error + Send + Sync>> {
+    let err = match probe(entry, &[], mode) {
+        Ok(lib) => return Ok(lib),
+        Err(e) => e,
+    };
+    let req = parse(entry)?;
+    // Fallbacks are built for the host
+    let Some(fallback) = fallbacks.and_then(|f| f.get(&req.name)).filter(|_| matches!(mode, PkgConfigMode::Host)) else {
+        return Err(err);
+    };
+    eprintln!("{}", format!("{}; using source fallback", err).yellow());
+    let prefix = build_fallback(&req.name, fallback)?;
+    probe(entry, &fallback_pc_dirs(&prefix), mode)
+}
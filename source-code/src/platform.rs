@@ -0,0 +1,132 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::time::{Duration, Instant};
+
+/// True when linking with Apple's ld64, which differs from GNU ld and lld in most linker flags.
+pub fn is_macos() -> bool {
+    cfg!(target_os = "macos")
+}
+
+/// File extension of shared libraries on the host.
+pub fn shared_extension() -> &'static str {
+    if cfg!(windows) {
+        "dll"
+    } else if is_macos() {
+        "dylib"
+    } else {
+        "so"
+    }
+}
+
+/// File extension of static libraries: `.lib` for MSVC, `.a` for GCC-style toolchains including MinGW.
+pub fn static_extension(msvc: bool) -> &'static str {
+    if msvc { "lib" } else { "a" }
+}
+
+/// Forcefully stops a child build process; on Windows its whole process tree.
+pub fn kill(pid: u32) {
+    if cfg!(windows) {
+        let _ = Command::new("taskkill").args(["/F", "/T", "/PID", &pid.to_string()]).status();
+    } else {
+        let _ = Command::new("kill").arg("-9").arg(pid.to_string()).status();
+    }
+}
+
+/// Starts a build tool in a process group of its own, so stopping it reaches the compiler passes
+/// it runs (cc1plus, as, ld) as well. Tools in their own group no longer get the terminal's Ctrl-C;
+/// the handler stops them with `terminate`.
+pub fn own_process_group(command: &mut Command) {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+    #[cfg(windows)]
+    let _ = command;
+}
+
+/// Stops child processes: SIGTERM to each one's process group (or the process alone when it
+/// shares hbuild's), then SIGKILL to whatever is still running after `timeout`. On Windows the
+/// process trees are killed right away.
+pub fn terminate(pids: &[u32], timeout: Duration) {
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::{kill, killpg, Signal};
+        use nix::unistd::Pid;
+        let signal = |pid: u32, signal: Option<Signal>| {
+            let pid = Pid::from_raw(pid as i32);
+            killpg(pid, signal).or_else(|_| kill(pid, signal)).is_ok()
+        };
+        for &pid in pids {
+            signal(pid, Some(Signal::SIGTERM));
+        }
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline && pids.iter().any(|&pid| signal(pid, None)) {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        for &pid in pids {
+            signal(pid, Some(Signal::SIGKILL));
+        }
+    }
+    #[cfg(windows)]
+    {
+        let _ = timeout;
+        for &pid in pids {
+            kill(pid);
+        }
+    }
+}
+
+/// Flags turning a link into a shared library. On macOS the install name is `@rpath/<file>` so
+/// consumers find the library through their rpath rather than the build location.
+pub fn shared_link_flags(target: &Path) -> Vec<OsString> {
+    if is_macos() {
+        let mut install_name = OsString::from("-Wl,-install_name,@rpath/");
+        install_name.push(target.file_name().unwrap());
+        vec!["-dynamiclib".into(), install_name]
+    } else {
+        vec!["-shared".into()]
+    }
+}
+
+/// Default system header roots for dependency pruning; on macOS the SDK headers live outside `/usr/include`.
+pub fn system_include_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from("/usr/include"), PathBuf::from("/usr/local/include")];
+    if is_macos() {
+        if let Ok(out) = Command::new("xcrun").arg("--show-sdk-path").output() {
+            if out.status.success() {
+                dirs.push(PathBuf::from(String::from_utf8_lossy(&out.stdout).trim()).join("usr/include"));
+            }
+        }
+    }
+    dirs
+}
+
+/// An `ExitStatus` for build steps that ran in-process or had nothing to do.
+pub fn success() -> ExitStatus {
+    #[cfg(unix)]
+    {
+        std::os::unix::process::ExitStatusExt::from_raw(0)
+    }
+    #[cfg(windows)]
+    {
+        std::os::windows::process::ExitStatusExt::from_raw(0)
+    }
+}
+
+/// True when hbuild runs as root; never on Windows, which has no root user.
+pub fn is_root() -> bool {
+    #[cfg(unix)]
+    {
+        nix::unistd::geteuid().is_root()
+    }
+    #[cfg(windows)]
+    {
+        false
+    }
+}
+
+/// Full path of `tool` on PATH.
+pub fn which(tool: &str) -> Option<PathBuf> {
+    let out = Command::new("which").arg(tool).output().ok()?;
+    let found = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    if out.status.success() && !found.is_empty() { Some(PathBuf::from(found)) } else { None }
+}
@@ -0,0 +1,90 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::{verbosity, Build, BuildOptions, HBuildConfig, Profile};
+
+/// Defaults for the two standard profiles; `[profile.<name>]` settings override them field by field.
+fn builtin(name: &str) -> Option<Profile> {
+    match name {
+        "debug" => Some(Profile { optimize: Some("O0".to_string()), cflags: Some("-g".to_string()), defines: None, strip: None, lto: None }),
+        "release" => Some(Profile { optimize: None, cflags: None, defines: Some(vec!["NDEBUG".to_string()]), strip: None, lto: None }),
+        _ => None,
+    }
+}
+
+/// `opts` with profile `name` applied: its optimize level, its cflags and defines after the configured
+/// flags, and its own build directory inside the current one so profiles don't overwrite each other.
+pub fn apply(config: &HBuildConfig, name: &str, path: &Path, opts: BuildOptions) -> Result<BuildOptions, Box<dyn std::error::Error + Send + Sync>> {
+    let configured = config.profile.as_ref().and_then(|p| p.get(name));
+    let defaults = builtin(name);
+    if configured.is_none() && defaults.is_none() {
+        return Err(format!("Unknown profile '{}'; define it in [profile.{}]", name, name).into());
+    }
+    let defaults = defaults.unwrap_or_default();
+    let optimize = configured.and_then(|p| p.optimize.clone()).or(defaults.optimize);
+    let cflags = configured.and_then(|p| p.cflags.clone()).or(defaults.cflags);
+    let defines = configured.and_then(|p| p.defines.clone()).or(defaults.defines);
+    let strip = configured.and_then(|p| p.strip).or(defaults.strip);
+    let lto = configured.and_then(|p| p.lto.clone()).or(defaults.lto);
+    let mut flags: Vec<String> = opts.extra_flags.iter().cloned().collect();
+    flags.extend(cflags);
+    flags.extend(defines.iter().flatten().map(|d| format!("-D{}", d)));
+    Ok(BuildOptions {
+        build_dir: Some(opts.build_dir(path).join(name)),
+        optimize: optimize.or(opts.optimize.clone()),
+        extra_flags: (!flags.is_empty()).then(|| flags.join(" ")),
+        strip: strip.unwrap_or(false),
+        lto: lto.or(opts.lto.clone()),
+        profile: Some(name.to_string()),
+        ..opts
+    })
+}
+
+/// The `strip` to run: `[build] strip_tool`, else the toolchain's, named after its `ar`.
+pub fn strip_tool(build: &Build, ar: &str) -> String {
+    if let Some(tool) = &build.strip_tool {
+        return tool.clone();
+    }
+    match ar.strip_suffix("ar") {
+        Some(prefix) => format!("{}strip", prefix),
+        None => "strip".to_string(),
+    }
+}
+
+/// Where the debug info split off `binary` goes: `<binary>.debug` next to it.
+pub fn debug_file(binary: &Path) -> PathBuf {
+    binary.with_file_name(format!("{}.debug", binary.file_name().unwrap().to_string_lossy()))
+}
+
+fn run(program: &str, args: &[&OsStr], target: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut command = Command::new(program);
+    command.args(args);
+    verbosity::command(&command);
+    let status = command.status().map_err(|e| format!("Cannot run {}: {}", program, e))?;
+    if !status.success() {
+        return Err(format!("{} failed on {}", program, target.display()).into());
+    }
+    Ok(())
+}
+
+/// Strips debug info from a static library; its symbols are what programs link against.
+pub fn strip(target: &Path, tool: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    verbosity::progress(format!("Stripping {}", target.display()).cyan());
+    run(tool, &["--strip-debug".as_ref(), target.as_os_str()], target)
+}
+
+/// Moves the debug info of an executable or shared library to `debug` with the `objcopy` beside
+/// `tool` and strips it, leaving a `.gnu_debuglink` so debuggers find the split file.
+pub fn split_debug(binary: &Path, debug: &Path, tool: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let objcopy = match tool.strip_suffix("strip") {
+        Some(prefix) => format!("{}objcopy", prefix),
+        None => "objcopy".to_string(),
+    };
+    verbosity::progress(format!("Stripping {} (debug info in {})", binary.display(), debug.display()).cyan());
+    run(&objcopy, &["--only-keep-debug".as_ref(), binary.as_os_str(), debug.as_os_str()], binary)?;
+    run(tool, &["--strip-unneeded".as_ref(), binary.as_os_str()], binary)?;
+    let mut link = OsString::from("--add-gnu-debuglink=");
+    link.push(debug);
+    run(&objcopy, &[&link, binary.as_os_str()], binary)
+}
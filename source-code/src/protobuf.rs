@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::{expand_globs, mtime, platform, verbosity, Generated, HBuildConfig, Protobuf};
+
+/// Protobuf output languages implied by `specs.languages` when `[protobuf] languages` is not set.
+fn languages(pb: &Protobuf, config: &HBuildConfig) -> Vec<String> {
+    if let Some(langs) = &pb.languages {
+        return langs.clone();
+    }
+    config.specs.languages.iter().filter_map(|l| match l.as_str() {
+        "c++" => Some("cpp".to_string()),
+        "rust" => Some("rust".to_string()),
+        "go" => Some("go".to_string()),
+        _ => None,
+    }).collect()
+}
+
+/// True when protobuf code should be generated for `lang` ("cpp", "rust" or "go").
+pub fn wants(pb: &Protobuf, config: &HBuildConfig, lang: &str) -> bool {
+    languages(pb, config).iter().any(|l| l == lang)
+}
+
+/// pkg-config modules the generated C++ links against, resolved with the project's `pkg_dependencies`.
+pub fn runtime(config: &HBuildConfig) -> Vec<String> {
+    match config.protobuf.as_ref().filter(|pb| wants(pb, config, "cpp")) {
+        Some(pb) if pb.grpc.unwrap_or(false) => vec!["protobuf".to_string(), "grpc++".to_string()],
+        Some(_) => vec!["protobuf".to_string()],
+        None => vec![],
+    }
+}
+
+/// Where the code for `lang` is generated: `build/proto/<lang>`, except that Go code goes into the
+/// module, beside its protos unless `go_out` is set, for `go build` to find it.
+pub fn out_dir(pb: &Protobuf, lang: &str, path: &Path, build_dir: &Path) -> PathBuf {
+    match (lang, &pb.go_out) {
+        ("go", Some(dir)) => path.join(dir),
+        ("go", None) => path.to_path_buf(),
+        _ => build_dir.join("proto").join(lang),
+    }
+}
+
+/// protoc output arguments for one language; gRPC stubs are added when `grpc = true`.
+fn output_args(lang: &str, out_dir: &Path, grpc: bool) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let out = out_dir.display();
+    let mut args = vec![];
+    match lang {
+        "cpp" => {
+            args.push(format!("--cpp_out={}", out));
+            if grpc {
+                let plugin = platform::which("grpc_cpp_plugin").ok_or("grpc_cpp_plugin not found on PATH")?;
+                args.push(format!("--plugin=protoc-gen-grpc={}", plugin.display()));
+                args.push(format!("--grpc_out={}", out));
+            }
+        }
+        "rust" => {
+            args.push(format!("--prost_out={}", out));
+            if grpc {
+                args.push(format!("--tonic_out={}", out));
+            }
+        }
+        "go" => {
+            args.push(format!("--go_out={}", out));
+            args.push("--go_opt=paths=source_relative".to_string());
+            if grpc {
+                args.push(format!("--go-grpc_out={}", out));
+                args.push("--go-grpc_opt=paths=source_relative".to_string());
+            }
+        }
+        _ => return Err(format!("Unsupported protobuf language: {}", lang).into()),
+    }
+    Ok(args)
+}
+
+/// Path of a proto relative to the import dir it lives under, which is how protoc names its outputs.
+fn relative_to_imports(proto: &Path, import_dirs: &[PathBuf]) -> PathBuf {
+    import_dirs.iter()
+    .filter_map(|d| proto.strip_prefix(d).ok())
+    .min_by_key(|rel| rel.components().count())
+    .map(Path::to_path_buf)
+    .unwrap_or_else(|| PathBuf::from(proto.file_name().unwrap()))
+}
+
+/// Runs protoc for `lang` into its `out_dir` when any `.proto` or the settings changed since the last
+/// run. Rust code is included from `HBUILD_PROTO_DIR`, which `cargo build` is run with.
+pub fn generate(pb: &Protobuf, config: &HBuildConfig, lang: &str, path: &Path, build_dir: &Path) -> Result<Generated, Box<dyn std::error::Error + Send + Sync>> {
+    let protos = expand_globs(path, &pb.files)?;
+    let out_dir = out_dir(pb, lang, path, build_dir);
+    fs::create_dir_all(&out_dir)?;
+    fs::create_dir_all(build_dir.join("proto"))?;
+    let import_dirs: Vec<PathBuf> = pb.import_dirs.clone().unwrap_or_else(|| vec![".".to_string()])
+    .iter().map(|d| path.join(d)).collect();
+    let grpc = pb.grpc.unwrap_or(false);
+
+    // One stamp per language: protos import each other, so any change regenerates the whole set. It
+    // holds the settings the outputs depend on, so changing those regenerates them too
+    let stamp = build_dir.join("proto").join(format!(".{}.stamp", lang));
+    let settings = format!("grpc={} languages={} protoc={} out={}\n", grpc, languages(pb, config).join(","), pb.protoc.as_deref().unwrap_or("protoc"), out_dir.display());
+    let stamp_mtime = mtime(&stamp);
+    if fs::read_to_string(&stamp).ok().as_deref() != Some(settings.as_str()) || protos.iter().any(|p| mtime(p) > stamp_mtime) {
+        verbosity::progress(format!("protoc ({}) {} files", lang, protos.len()).cyan());
+        let output = Command::new(pb.protoc.as_deref().unwrap_or("protoc"))
+        .args(import_dirs.iter().map(|d| format!("-I{}", d.display())))
+        .args(output_args(lang, &out_dir, grpc)?)
+        .args(&protos)
+        .current_dir(path)
+        .output()?;
+        if !output.status.success() {
+            eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
+            return Err("protoc failed".into());
+        }
+        fs::write(&stamp, &settings)?;
+    }
+
+    let mut generated = Generated::default();
+    if lang == "cpp" {
+        generated.include_dirs.push(out_dir.clone());
+        for proto in &protos {
+            let rel = relative_to_imports(proto, &import_dirs);
+            generated.sources.push(out_dir.join(rel.with_extension("pb.cc")));
+            if grpc {
+                generated.sources.push(out_dir.join(rel.with_extension("grpc.pb.cc")));
+            }
+        }
+    }
+    Ok(generated)
+}
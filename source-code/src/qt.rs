@@ -0,0 +1,141 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::{expand_globs, is_stale, mtime, verbosity, Generated, HBuildConfig};
+
+/// True when the project declares a `[qt]` section or depends on a Qt pkg-config module.
+pub fn is_enabled(config: &HBuildConfig) -> bool {
+    config.qt.is_some()
+    || config.build.as_ref()
+    .and_then(|b| b.pkg_dependencies.as_ref())
+    .is_some_and(|deps| deps.iter().any(|d| d.starts_with("Qt5") || d.starts_with("Qt6")))
+}
+
+fn qt_major(config: &HBuildConfig) -> String {
+    if let Some(v) = config.qt.as_ref().and_then(|q| q.version.clone()) {
+        return v;
+    }
+    let qt6 = config.build.as_ref()
+    .and_then(|b| b.pkg_dependencies.as_ref())
+    .is_some_and(|deps| deps.iter().any(|d| d.starts_with("Qt6")));
+    if qt6 { "6".to_string() } else { "5".to_string() }
+}
+
+/// Locates a Qt tool: explicit config path, then the directory pkg-config reports for QtCore, then PATH.
+fn find_tool(config: &HBuildConfig, name: &str, configured: Option<&String>) -> PathBuf {
+    if let Some(tool) = configured {
+        return PathBuf::from(tool);
+    }
+    let major = qt_major(config);
+    let (module, variable) = if major == "6" {
+        ("Qt6Core", "libexecdir")
+    } else {
+        ("Qt5Core", "host_bins")
+    };
+    if let Ok(output) = Command::new("pkg-config").arg(format!("--variable={}", variable)).arg(module).output() {
+        let dir = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if output.status.success() && !dir.is_empty() && Path::new(&dir).join(name).exists() {
+            return Path::new(&dir).join(name);
+        }
+    }
+    PathBuf::from(name)
+}
+
+/// Headers scanned for `Q_OBJECT` when `[qt] headers` is not set: every include dir and source directory.
+fn default_header_patterns(config: &HBuildConfig) -> Vec<String> {
+    let mut dirs: Vec<String> = vec![];
+    if let Some(build) = &config.build {
+        dirs.extend(build.include_dirs.iter().cloned());
+        for src in &build.sources {
+            let parent = Path::new(src).parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+            if !dirs.contains(&parent) {
+                dirs.push(parent);
+            }
+        }
+    }
+    dirs.iter()
+    .flat_map(|d| ["h", "hpp"].map(|ext| if d.is_empty() { format!("*.{}", ext) } else { format!("{}/*.{}", d, ext) }))
+    .collect()
+}
+
+fn run(tool: &Path, args: &[OsString], input: &Path, output: &Path, path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let out = Command::new(tool)
+    .args(args)
+    .arg(input)
+    .arg("-o")
+    .arg(output)
+    .current_dir(path)
+    .output()?;
+    if !out.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&out.stderr).red());
+        return Err(format!("{} failed for {}", tool.display(), input.display()).into());
+    }
+    Ok(())
+}
+
+/// Files embedded by a `.qrc`, so the generated source is refreshed when any of them changes.
+fn qrc_inputs(rcc: &Path, qrc: &Path) -> Vec<PathBuf> {
+    match Command::new(rcc).arg("--list").arg(qrc).output() {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout).lines().map(PathBuf::from).collect(),
+        _ => vec![],
+    }
+}
+
+/// Runs moc on `Q_OBJECT` headers, uic on `.ui` forms and rcc on `.qrc` files, writing into `build/qt`.
+pub fn generate(config: &HBuildConfig, path: &Path, build_dir: &Path, include_flags: &[OsString]) -> Result<Generated, Box<dyn std::error::Error + Send + Sync>> {
+    let qt = config.qt.as_ref();
+    let out_dir = build_dir.join("qt");
+    fs::create_dir_all(&out_dir)?;
+    let mut generated = Generated::default();
+    generated.include_dirs.push(out_dir.clone());
+
+    // uic first: sources and moc inputs may include the generated ui_*.h headers
+    let uic = find_tool(config, "uic", qt.and_then(|q| q.uic.as_ref()));
+    let ui_patterns = qt.and_then(|q| q.ui.clone()).unwrap_or_else(|| vec!["**/*.ui".to_string()]);
+    for form in expand_globs(path, &ui_patterns)? {
+        if form.starts_with(build_dir) {
+            continue;
+        }
+        let out = out_dir.join(format!("ui_{}.h", form.file_stem().unwrap().to_string_lossy()));
+        if is_stale(&form, &out) {
+            verbosity::progress(format!("uic {}", form.display()).cyan());
+            run(&uic, &[], &form, &out, path)?;
+        }
+    }
+
+    let moc = find_tool(config, "moc", qt.and_then(|q| q.moc.as_ref()));
+    let header_patterns = qt.and_then(|q| q.headers.clone()).unwrap_or_else(|| default_header_patterns(config));
+    let moc_includes: Vec<OsString> = include_flags.iter().filter(|f| f.to_str().is_some_and(|f| f.starts_with("-I") || f.starts_with("-D"))).cloned().collect();
+    for header in expand_globs(path, &header_patterns)? {
+        let content = fs::read_to_string(&header).unwrap_or_default();
+        if !content.contains("Q_OBJECT") && !content.contains("Q_GADGET") {
+            continue;
+        }
+        let out = out_dir.join(format!("moc_{}.cpp", header.file_stem().unwrap().to_string_lossy()));
+        if is_stale(&header, &out) {
+            verbosity::progress(format!("moc {}", header.display()).cyan());
+            run(&moc, &moc_includes, &header, &out, path)?;
+        }
+        generated.sources.push(out);
+    }
+
+    let rcc = find_tool(config, "rcc", qt.and_then(|q| q.rcc.as_ref()));
+    let qrc_patterns = qt.and_then(|q| q.qrc.clone()).unwrap_or_else(|| vec!["**/*.qrc".to_string()]);
+    for qrc in expand_globs(path, &qrc_patterns)? {
+        if qrc.starts_with(build_dir) {
+            continue;
+        }
+        let stem = qrc.file_stem().unwrap().to_string_lossy().to_string();
+        let out = out_dir.join(format!("qrc_{}.cpp", stem));
+        let out_mtime = mtime(&out);
+        let inputs_changed = qrc_inputs(&rcc, &qrc).iter().any(|f| mtime(f) > out_mtime);
+        if is_stale(&qrc, &out) || inputs_changed {
+            verbosity::progress(format!("rcc {}", qrc.display()).cyan());
+            run(&rcc, &["-name".into(), stem.into()], &qrc, &out, path)?;
+        }
+        generated.sources.push(out);
+    }
+    Ok(generated)
+}
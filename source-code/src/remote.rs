@@ -0,0 +1,120 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::{find_config_file, parse_config, platform, soname, target_path, verbosity, BuildOptions, HBuildConfig};
+
+/// Project directory on the remote host, relative to the login directory. It is kept between builds so
+/// transfers and rebuilds there stay incremental.
+fn remote_dir(name: &str) -> String {
+    let clean: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' }).collect();
+    format!(".hbuild/remote/{}", clean)
+}
+
+fn rsync(args: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let status = Command::new("rsync").arg("-az").args(args).status()?;
+    if !status.success() {
+        return Err(format!("rsync {} failed", args.join(" ")).into());
+    }
+    Ok(())
+}
+
+/// What a default build links outside `build/`: the target and, for a versioned shared library,
+/// its real file and soname links, plus the static variant.
+fn outputs(config: &HBuildConfig, path: &Path) -> Vec<PathBuf> {
+    let Some(build) = &config.build else {
+        return vec![];
+    };
+    let target = target_path(build, path, &BuildOptions::default());
+    let mut outputs = vec![];
+    if build.build_type == "shared" {
+        if let Some(versioned) = soname::versioned(&target, &config.metadata.version) {
+            outputs.push(versioned.file.clone());
+            outputs.extend(versioned.links().into_iter().map(|(_, link)| link).filter(|link| *link != target));
+        }
+        if build.static_variant.unwrap_or(false) {
+            outputs.push(target.with_extension(platform::static_extension(false)));
+        }
+    }
+    outputs.push(target);
+    outputs
+}
+
+/// rsync filters fetching `build/` and `outputs` back into the project at `path`, and nothing else.
+fn fetch_filters(outputs: &[PathBuf], path: &Path) -> Vec<String> {
+    let mut filters = vec!["--include=/build/***".to_string()];
+    filters.extend(outputs.iter().filter_map(|o| o.strip_prefix(path).ok()).map(|o| format!("--include=/{}", o.display())));
+    filters.push("--exclude=*".to_string());
+    filters
+}
+
+/// Syncs the project to `host`, runs `hbuild make` there with `make_args`, and copies `build/` and the
+/// linked target back. Compiler diagnostics stream through the ssh session.
+pub fn make(path: &Path, host: &str, make_args: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let dir = remote_dir(&config.metadata.name);
+    verbosity::progress(format!("Building {} on {}", config.metadata.name, host).blue().bold());
+
+    verbosity::progress(format!("Syncing sources to {}:{}", host, dir).cyan());
+    let status = Command::new("ssh").arg(host).arg(format!("mkdir -p '{}'", dir)).status()?;
+    if !status.success() {
+        return Err(format!("Cannot reach {}", host).into());
+    }
+    // The remote build dir is the remote cache: never overwrite or delete it from here
+    rsync(&[
+        "--delete".to_string(),
+        "--exclude=/build/".to_string(),
+        "--exclude=/target/".to_string(),
+        "--exclude=/.git/".to_string(),
+        format!("{}/", path.display()),
+        format!("{}:{}/", host, dir),
+    ])?;
+
+    let mut command = format!("cd '{}' && hbuild make .", dir);
+    for arg in make_args {
+        command.push_str(&format!(" '{}'", arg.replace('\'', r"'\''")));
+    }
+    verbosity::progress(format!("Running: {}", command).cyan());
+    let status = Command::new("ssh").arg(host).arg(&command).status()?;
+    if !status.success() {
+        return Err(format!("Remote build on {} failed", host).into());
+    }
+
+    verbosity::progress("Fetching artifacts".cyan());
+    let mut filters = fetch_filters(&outputs(&config, path), path);
+    filters.push(format!("{}:{}/", host, dir));
+    filters.push(format!("{}/", path.display()));
+    rsync(&filters)?;
+    verbosity::status("Remote build complete!");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fetches_build_and_outputs_only() {
+        let path = Path::new("/work/app");
+        let outputs = [path.join("libapp.so.1.2.0"), path.join("libapp.so.1"), path.join("libapp.so")];
+        assert_eq!(fetch_filters(&outputs, path), [
+            "--include=/build/***",
+            "--include=/libapp.so.1.2.0",
+            "--include=/libapp.so.1",
+            "--include=/libapp.so",
+            "--exclude=*",
+        ]);
+    }
+
+    #[test]
+    fn skips_outputs_outside_the_project() {
+        let path = Path::new("/work/app");
+        assert_eq!(fetch_filters(&[PathBuf::from("/elsewhere/app")], path), ["--include=/build/***", "--exclude=*"]);
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::{expand_globs, is_stale, verbosity, write_if_changed, Generated, Resources};
+
+/// Turns a project-relative asset path into a C identifier, e.g. `assets/icon.png` -> `assets_icon_png`.
+fn mangle(rel: &str) -> String {
+    rel.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+fn generate_c_array(asset: &Path, symbol: &str, out: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let data = fs::read(asset)?;
+    let mut c = String::new();
+    writeln!(c, "/* Generated by hbuild from {} */", asset.display())?;
+    writeln!(c, "#include <stddef.h>")?;
+    // Declared extern first so the definitions keep external linkage when built as C++
+    writeln!(c, "#ifdef __cplusplus\nextern \"C\" {{\n#endif")?;
+    writeln!(c, "extern const unsigned char {0}[];\nextern const size_t {0}_size;", symbol)?;
+    writeln!(c, "const unsigned char {}[] = {{", symbol)?;
+    for chunk in data.chunks(12) {
+        let line: Vec<String> = chunk.iter().map(|b| format!("0x{:02x}", b)).collect();
+        writeln!(c, "    {},", line.join(", "))?;
+    }
+    // Trailing NUL so text assets can be used as C strings; not counted in the size
+    writeln!(c, "    0x00\n}};")?;
+    writeln!(c, "const size_t {}_size = {};", symbol, data.len())?;
+    writeln!(c, "#ifdef __cplusplus\n}}\n#endif")?;
+    fs::write(out, c)?;
+    Ok(())
+}
+
+/// Returns the BFD output format and architecture of objects produced by `compiler`, as objcopy expects them.
+fn probe_object_format(compiler: &str, out_dir: &Path) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
+    let probe = out_dir.join("probe.o");
+    let status = Command::new(compiler)
+    .args(["-x", "c", "-c", "/dev/null", "-o"])
+    .arg(&probe)
+    .status()?;
+    if !status.success() {
+        return Err("Failed to compile object format probe".into());
+    }
+    let output = Command::new("objdump").arg("-f").arg(&probe).output()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let format = text.split("file format").nth(1).and_then(|s| s.split_whitespace().next());
+    let arch = text.split("architecture:").nth(1).and_then(|s| s.split(',').next()).map(|s| s.trim());
+    match (format, arch) {
+        (Some(f), Some(a)) => Ok((f.to_string(), a.to_string())),
+        _ => Err("Could not determine object format for objcopy".into()),
+    }
+}
+
+fn generate_object(path: &Path, rel: &str, symbol: &str, format: &(String, String), out: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mangled = format!("_binary_{}", mangle(rel));
+    let status = Command::new("objcopy")
+    .args(["-I", "binary", "-O", &format.0, "-B", &format.1])
+    .args(["--rename-section", ".data=.rodata,alloc,load,readonly,data,contents"])
+    .arg(format!("--redefine-sym={}_start={}", mangled, symbol))
+    .arg(format!("--redefine-sym={}_end={}_end", mangled, symbol))
+    .arg(format!("--strip-symbol={}_size", mangled))
+    .arg(rel)
+    .arg(out)
+    .current_dir(path)
+    .status()?;
+    if !status.success() {
+        return Err(format!("objcopy failed for {}", rel).into());
+    }
+    Ok(())
+}
+
+/// Converts the assets declared in `[resources]` into C sources or objects under `build/resources`,
+/// regenerating only those whose asset changed, and writes a `resources.h` declaring them.
+pub fn generate(resources: &Resources, compiler: &str, path: &Path, build_dir: &Path) -> Result<Generated, Box<dyn std::error::Error + Send + Sync>> {
+    let mode = resources.mode.as_deref().unwrap_or("c");
+    let prefix = resources.prefix.as_deref().unwrap_or("res_");
+    let assets = expand_globs(path, &resources.files)?;
+    embed(&assets, path, mode, prefix, compiler, &build_dir.join("resources"), "resources.h")
+}
+
+/// Embeds `assets` (named by their path relative to `base`) into `out_dir`, declaring them in `header_name`.
+pub fn embed(assets: &[PathBuf], base: &Path, mode: &str, prefix: &str, compiler: &str, out_dir: &Path, header_name: &str) -> Result<Generated, Box<dyn std::error::Error + Send + Sync>> {
+    fs::create_dir_all(out_dir)?;
+    if mode != "c" && mode != "objcopy" {
+        return Err(format!("Unknown resources mode '{}' (expected \"c\" or \"objcopy\")", mode).into());
+    }
+
+    let mut generated = Generated::default();
+    generated.include_dirs.push(out_dir.to_path_buf());
+    let guard = format!("HBUILD_{}", mangle(header_name).to_uppercase());
+    let mut header = format!("/* Generated by hbuild */\n#ifndef {0}\n#define {0}\n#include <stddef.h>\n#ifdef __cplusplus\nextern \"C\" {{\n#endif\n", guard);
+    let mut object_format = None;
+    for asset in assets {
+        let rel = asset.strip_prefix(base).unwrap_or(asset).to_string_lossy().to_string();
+        let symbol = format!("{}{}", prefix, mangle(&rel));
+        writeln!(header, "extern const unsigned char {}[];", symbol)?;
+        if mode == "c" {
+            writeln!(header, "extern const size_t {}_size;", symbol)?;
+            let out = out_dir.join(format!("{}.c", symbol));
+            if is_stale(asset, &out) {
+                verbosity::progress(format!("Embedding {}", rel).cyan());
+                generate_c_array(asset, &symbol, &out)?;
+            }
+            generated.sources.push(out);
+        } else {
+            writeln!(header, "extern const unsigned char {}_end[];", symbol)?;
+            writeln!(header, "#define {0}_size ((size_t)({0}_end - {0}))", symbol)?;
+            let out = out_dir.join(format!("{}.o", symbol));
+            if is_stale(asset, &out) {
+                verbosity::progress(format!("Embedding {}", rel).cyan());
+                if object_format.is_none() {
+                    object_format = Some(probe_object_format(compiler, out_dir)?);
+                }
+                generate_object(base, &rel, &symbol, object_format.as_ref().unwrap(), &out)?;
+            }
+            generated.objects.push(out);
+        }
+    }
+    header.push_str("#ifdef __cplusplus\n}\n#endif\n#endif\n");
+    write_if_changed(&out_dir.join(header_name), &header)?;
+    Ok(generated)
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use glob::Pattern;
+use owo_colors::OwoColorize;
+use crate::{expand_globs, hash_file, sandbox, verbosity, Generated, Rule};
+
+pub(crate) const SOURCE_EXTENSIONS: &[&str] = &["c", "cc", "cpp", "cxx", "c++"];
+pub(crate) const HEADER_EXTENSIONS: &[&str] = &["h", "hh", "hpp", "hxx"];
+
+/// An output ending in `/` is a directory the step generates files into, for tools like
+/// `protoc --cpp_out` whose output names follow from their inputs.
+fn is_dir_output(out: &str) -> bool {
+    out.ends_with('/')
+}
+
+/// True when one of `rule`'s input patterns names an output of `other`, or a file in one of its output directories.
+fn depends_on(rule: &Rule, other: &Rule) -> bool {
+    rule.inputs.iter().any(|input| {
+        let pattern = Pattern::new(input).ok();
+        other.outputs.iter().any(|out| {
+            out == input || pattern.as_ref().is_some_and(|p| p.matches(out)) || (is_dir_output(out) && Path::new(input).starts_with(out))
+        })
+    })
+}
+
+/// Rule names ordered so producers run before the rules that consume their outputs.
+fn ordered(rules: &BTreeMap<String, Rule>) -> Result<Vec<&String>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut order: Vec<&String> = vec![];
+    let mut visiting: Vec<&String> = vec![];
+    fn visit<'a>(name: &'a String, rules: &'a BTreeMap<String, Rule>, order: &mut Vec<&'a String>, visiting: &mut Vec<&'a String>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if order.contains(&name) {
+            return Ok(());
+        }
+        if visiting.contains(&name) {
+            return Err(format!("Rule cycle involving '{}'", name).into());
+        }
+        visiting.push(name);
+        for (other_name, other) in rules {
+            if other_name != name && depends_on(&rules[name], other) {
+                visit(other_name, rules, order, visiting)?;
+            }
+        }
+        visiting.retain(|n| *n != name);
+        order.push(name);
+        Ok(())
+    }
+    for name in rules.keys() {
+        visit(name, rules, &mut order, &mut visiting)?;
+    }
+    Ok(order)
+}
+
+/// Expands `$in` and `$out` to the space-separated input and output paths.
+fn expand_command(command: &str, inputs: &[PathBuf], outputs: &[PathBuf]) -> String {
+    let join = |paths: &[PathBuf]| paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(" ");
+    command.replace("$in", &join(inputs)).replace("$out", &join(outputs))
+}
+
+/// What a rule's outputs were generated from: its command and the contents of each input.
+fn stamp_text(command: &str, path: &Path, inputs: &[PathBuf]) -> String {
+    let mut text = format!("{}\n", command);
+    for input in inputs {
+        text.push_str(&format!("{} {}\n", hash_file(&path.join(input)).unwrap_or_default(), input.display()));
+    }
+    text
+}
+
+/// Runs every rule whose outputs are missing, or whose command or input contents changed since it
+/// last ran; touching an input without changing it doesn't rerun the rule. Output directories are
+/// emptied first, so files generated from removed inputs don't linger. With `sandboxed`, a rule can
+/// only write to its output directories and the directories of its output files.
+pub fn run(rules: &BTreeMap<String, Rule>, path: &Path, build_dir: &Path, sandboxed: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    sandbox::check(sandboxed)?;
+    let stamp_dir = build_dir.join("rules");
+    fs::create_dir_all(&stamp_dir)?;
+    for name in ordered(rules)? {
+        let rule = &rules[name];
+        let inputs: Vec<PathBuf> = expand_globs(path, &rule.inputs)?
+        .into_iter()
+        .map(|p| p.strip_prefix(path).map(Path::to_path_buf).unwrap_or(p))
+        .collect();
+        let outputs: Vec<PathBuf> = rule.outputs.iter().map(PathBuf::from).collect();
+        let command = expand_command(&rule.command, &inputs, &outputs);
+
+        let stamp = stamp_dir.join(format!("{}.stamp", name));
+        let stamp_text = stamp_text(&command, path, &inputs);
+        let changed = fs::read_to_string(&stamp).map(|old| old != stamp_text).unwrap_or(true);
+        let output_paths: Vec<PathBuf> = outputs.iter().map(|o| path.join(o)).collect();
+        let missing = output_paths.iter().any(|o| !o.exists());
+        if !changed && !missing {
+            continue;
+        }
+
+        verbosity::progress(format!("Running rule {}", name).cyan());
+        let mut out_dirs: Vec<PathBuf> = vec![];
+        for out in &rule.outputs {
+            let out_path = path.join(out);
+            if is_dir_output(out) {
+                if out_path.exists() {
+                    fs::remove_dir_all(&out_path)?;
+                }
+                fs::create_dir_all(&out_path)?;
+                out_dirs.push(out_path);
+            } else if let Some(parent) = out_path.parent() {
+                fs::create_dir_all(parent)?;
+                out_dirs.push(parent.to_path_buf());
+            }
+        }
+        let writable: Vec<&Path> = out_dirs.iter().map(PathBuf::as_path).collect();
+        let output = sandbox::command("sh", sandboxed, path, &writable).arg("-c").arg(&command).current_dir(path).output()?;
+        if !output.status.success() {
+            eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
+            return Err(format!("Rule '{}' failed: {}", name, command).into());
+        }
+        if let Some(missing) = output_paths.iter().find(|o| !o.exists()) {
+            return Err(format!("Rule '{}' did not produce declared output {}", name, missing.display()).into());
+        }
+        fs::write(&stamp, &stamp_text)?;
+    }
+    Ok(())
+}
+
+/// Files below `dir`, recursively.
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let entry_path = entry.path();
+        if entry_path.is_dir() {
+            files.extend(files_in(&entry_path));
+        } else {
+            files.push(entry_path);
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Rule outputs that feed the C/C++ build: sources are compiled, header directories are added to the
+/// include path. An output directory is an include directory itself, and every source in it is compiled.
+pub fn generated(rules: &BTreeMap<String, Rule>, path: &Path) -> Generated {
+    let mut generated = Generated::default();
+    for rule in rules.values() {
+        let mut outputs = vec![];
+        for out in &rule.outputs {
+            if is_dir_output(out) {
+                let dir = path.join(out.trim_end_matches('/'));
+                outputs.extend(files_in(&dir));
+                if !generated.include_dirs.contains(&dir) {
+                    generated.include_dirs.push(dir);
+                }
+            } else {
+                outputs.push(path.join(out));
+            }
+        }
+        for out in outputs {
+            let ext = out.extension().and_then(|e| e.to_str()).unwrap_or_default();
+            if SOURCE_EXTENSIONS.contains(&ext) {
+                generated.sources.push(out);
+            } else if ext == "o" {
+                generated.objects.push(out);
+            } else if HEADER_EXTENSIONS.contains(&ext) {
+                let dir = out.parent().unwrap_or(path).to_path_buf();
+                if !generated.include_dirs.contains(&dir) {
+                    generated.include_dirs.push(dir);
+                }
+            }
+        }
+    }
+    generated
+}
@@ -0,0 +1,64 @@
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use owo_colors::OwoColorize;
+use crate::{find_config_file, make_with, parse_config, target_path, verbosity, BuildOptions};
+
+/// Pause before restarting a crashed program so a crash loop doesn't spin.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Nice value for a `[runtime] priority`: `low`, `normal`, `high` or a number from -20 to 19.
+pub fn niceness(priority: &str) -> Result<i32, Box<dyn std::error::Error + Send + Sync>> {
+    match priority {
+        "low" => Ok(10),
+        "normal" => Ok(0),
+        "high" => Ok(-10),
+        _ => priority.parse::<i32>().ok().filter(|n| (-20..=19).contains(n))
+        .ok_or_else(|| format!("Invalid priority '{}' (expected low, normal, high or -20..19)", priority).into()),
+    }
+}
+
+/// Builds the project if needed and runs its executable with `args`, at the `[runtime]` priority and
+/// restarted after a crash when `auto-restart` is set. Exits with the program's status.
+pub fn run(path: &Path, children: &Arc<Mutex<Vec<u32>>>, opts: &BuildOptions, args: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let build = config.build.as_ref().filter(|b| b.build_type == "executable").ok_or("run needs a [build] section with build_type = \"executable\"")?;
+    let nice = config.runtime.as_ref().and_then(|r| r.priority.as_deref()).map(niceness).transpose()?.unwrap_or(0);
+    let auto_restart = config.runtime.as_ref().and_then(|r| r.auto_restart).unwrap_or(false);
+    make_with(path, children, opts)?;
+    let target = target_path(build, path, opts);
+
+    // nice goes first so a cross runner inherits the priority too
+    let mut argv: Vec<String> = vec![];
+    if nice != 0 {
+        argv.extend(["nice".to_string(), "-n".to_string(), nice.to_string()]);
+    }
+    if let Some(runner) = opts.cross.as_ref().and_then(|c| c.runner.as_ref()) {
+        argv.extend(runner.split_whitespace().map(String::from));
+    }
+    argv.push(target.display().to_string());
+    argv.extend(args.iter().cloned());
+
+    loop {
+        verbosity::progress(format!("Running {}", target.display()).blue().bold());
+        let mut child = Command::new(&argv[0]).args(&argv[1..]).spawn()?;
+        let child_id = child.id();
+        children.lock().unwrap().push(child_id);
+        let status = child.wait()?;
+        children.lock().unwrap().retain(|&p| p != child_id);
+        if status.success() || !auto_restart {
+            std::process::exit(status.code().unwrap_or(1));
+        }
+        eprintln!("{}", format!("{} exited with {}; restarting", target.display(), status).yellow());
+        thread::sleep(RESTART_DELAY);
+    }
+}
@@ -0,0 +1,40 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use crate::HBuildConfig;
+
+/// True when `sandbox = true` in `[build]`.
+pub fn enabled(config: &HBuildConfig) -> bool {
+    config.build.as_ref().is_some_and(|b| b.sandbox.unwrap_or(false))
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// A command running `program`, inside a bubblewrap sandbox when `enabled`: the whole filesystem and the
+/// project are read-only, only `writable` directories (which must exist) can be written, `/tmp` is private
+/// and there is no network. Undeclared outputs and downloads then fail instead of silently working.
+pub fn command(program: &str, enabled: bool, project: &Path, writable: &[&Path]) -> Command {
+    if !enabled {
+        return Command::new(program);
+    }
+    let mut cmd = Command::new("bwrap");
+    cmd.args(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"]);
+    // Bound again so a project under /tmp stays visible over the private /tmp
+    let project = absolute(project);
+    cmd.arg("--ro-bind").arg(&project).arg(&project);
+    for dir in writable {
+        let dir = absolute(dir);
+        cmd.arg("--bind").arg(&dir).arg(&dir);
+    }
+    cmd.args(["--unshare-net", "--die-with-parent", "--", program]);
+    cmd
+}
+
+/// Fails early with a clear message when sandboxing is on but bubblewrap is unavailable.
+pub fn check(enabled: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if enabled && Command::new("bwrap").arg("--version").output().is_err() {
+        return Err("sandbox = true needs bubblewrap (bwrap) installed".into());
+    }
+    Ok(())
+}
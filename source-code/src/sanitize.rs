@@ -0,0 +1,53 @@
+use std::path::Path;
+use owo_colors::OwoColorize;
+use crate::BuildOptions;
+
+/// Sanitizers that can't share one binary: their runtimes take over the same memory or thread hooks.
+const INCOMPATIBLE: &[(&str, &str)] = &[
+    ("address", "thread"),
+    ("address", "memory"),
+    ("thread", "memory"),
+    ("leak", "thread"),
+    ("leak", "memory"),
+];
+
+/// `-fsanitize=` name for a sanitizer or its short name (`asan`, `ubsan`, `tsan`, `msan`, `lsan`).
+fn canonical(name: &str) -> Result<&'static str, Box<dyn std::error::Error + Send + Sync>> {
+    match name.trim() {
+        "address" | "asan" => Ok("address"),
+        "undefined" | "ubsan" => Ok("undefined"),
+        "thread" | "tsan" => Ok("thread"),
+        "memory" | "msan" => Ok("memory"),
+        "leak" | "lsan" => Ok("leak"),
+        other => Err(format!("Unknown sanitizer '{}' (expected address, undefined, thread, memory or leak)", other).into()),
+    }
+}
+
+/// `opts` with `sanitizers` enabled: `-fsanitize=` plus frame pointers and debug info for readable
+/// reports, compiled and linked into a `sanitize-<names>` build directory of their own.
+pub fn apply(sanitizers: &[String], compiler: &str, path: &Path, opts: BuildOptions) -> Result<BuildOptions, Box<dyn std::error::Error + Send + Sync>> {
+    let mut names: Vec<&str> = sanitizers.iter().filter(|s| !s.trim().is_empty()).map(|s| canonical(s)).collect::<Result<_, _>>()?;
+    names.sort();
+    names.dedup();
+    if names.is_empty() {
+        return Ok(opts);
+    }
+    for (a, b) in INCOMPATIBLE {
+        if names.contains(a) && names.contains(b) {
+            eprintln!("{}", format!("Warning: the {} and {} sanitizers cannot be combined; the build or the program will fail", a, b).yellow());
+        }
+    }
+    if names.contains(&"memory") && !compiler.contains("clang") {
+        eprintln!("{}", format!("Warning: the memory sanitizer needs clang, not {}", compiler).yellow());
+    }
+    let mut flags: Vec<String> = opts.extra_flags.iter().cloned().collect();
+    flags.push(format!("-fsanitize={} -fno-omit-frame-pointer -g", names.join(",")));
+    if names.contains(&"memory") {
+        flags.push("-fsanitize-memory-track-origins".to_string());
+    }
+    Ok(BuildOptions {
+        build_dir: Some(opts.build_dir(path).join(format!("sanitize-{}", names.join("-")))),
+        extra_flags: Some(flags.join(" ")),
+        ..opts
+    })
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::install::{Layout, Manifest};
+use crate::{run, verbosity, HBuildConfig};
+
+/// `value` for a unit file: `%` specifiers escaped, and in double quotes when it has whitespace or
+/// quotes.
+fn quote(value: &str) -> String {
+    let escaped = value.replace('%', "%%");
+    if escaped.is_empty() || escaped.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\') {
+        format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        escaped
+    }
+}
+
+/// The systemd unit running the installed executable `exec` with the `[runtime]` arguments, user
+/// and environment. `auto-restart` maps to `Restart=on-failure` unless `restart` names a policy,
+/// and `priority` to `Nice=`.
+pub fn render(config: &HBuildConfig, exec: &Path) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let runtime = config.runtime.as_ref();
+    // ExecStart= also expands `$VAR`, so arguments escape it
+    let mut exec_start = quote(&exec.display().to_string().replace('$', "$$"));
+    for arg in runtime.and_then(|r| r.args.as_ref()).into_iter().flatten() {
+        exec_start.push(' ');
+        exec_start.push_str(&quote(&arg.replace('$', "$$")));
+    }
+    let restart = match runtime.and_then(|r| r.restart.as_deref()) {
+        Some(policy) => policy,
+        None if runtime.and_then(|r| r.auto_restart).unwrap_or(false) => "on-failure",
+        None => "no",
+    };
+
+    let mut unit = format!("[Unit]\nDescription={}\n\n[Service]\nExecStart={}\nRestart={}\n", config.description.summary, exec_start, restart);
+    if let Some(user) = runtime.and_then(|r| r.user.as_ref()) {
+        unit.push_str(&format!("User={}\n", user));
+    }
+    if let Some(priority) = runtime.and_then(|r| r.priority.as_deref()) {
+        unit.push_str(&format!("Nice={}\n", run::niceness(priority)?));
+    }
+    for (name, value) in runtime.and_then(|r| r.environment.as_ref()).into_iter().flatten() {
+        unit.push_str(&format!("Environment={}\n", quote(&format!("{}={}", name, value))));
+    }
+    unit.push_str("\n[Install]\nWantedBy=multi-user.target\n");
+    Ok(unit)
+}
+
+/// Writes `<name>.service` to `build_dir` and installs it to `<prefix>/lib/systemd/system`. Installed
+/// straight onto a running systemd, the unit is loaded with `systemctl daemon-reload`.
+pub fn install(config: &HBuildConfig, exec: &Path, build_dir: &Path, layout: &Layout, manifest: &mut Manifest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let name = format!("{}.service", config.metadata.name);
+    let generated = build_dir.join(&name);
+    fs::create_dir_all(build_dir)?;
+    fs::write(&generated, render(config, exec)?)?;
+    let unit_dir = layout.unitdir();
+    manifest.create_dir_all(&unit_dir)?;
+    manifest.copy(&generated, &unit_dir.join(&name))?;
+
+    if layout.destdir.is_some() || !Path::new("/run/systemd/system").exists() {
+        return Ok(());
+    }
+    verbosity::progress("Reloading systemd".cyan());
+    match Command::new("systemctl").arg("daemon-reload").status() {
+        Ok(status) if status.success() => verbosity::progress(format!("Start it with `systemctl enable --now {}`", name).cyan()),
+        Ok(status) => eprintln!("{}", format!("systemctl daemon-reload failed with {}", status).yellow()),
+        Err(e) => eprintln!("{}", format!("Cannot run systemctl: {}", e).yellow()),
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use owo_colors::OwoColorize;
+use serde_json::{json, Map, Value};
+use crate::{find_config_file, parse_config, CONFIG_FILES};
+
+struct Answers {
+    name: String,
+    version: String,
+    license: String,
+    languages: Vec<String>,
+    compiler: String,
+    build_type: String,
+    format: String,
+}
+
+fn ask(stdin: &mut impl BufRead, question: &str, default: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    print!("{} [{}]: ", question.cyan(), default);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    stdin.read_line(&mut line)?;
+    let answer = line.trim();
+    Ok(if answer.is_empty() { default.to_string() } else { answer.to_string() })
+}
+
+fn ask_choice(stdin: &mut impl BufRead, question: &str, choices: &[&str], default: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    loop {
+        let answer = ask(stdin, &format!("{} ({})", question, choices.join("/")), default)?;
+        if choices.contains(&answer.as_str()) {
+            return Ok(answer);
+        }
+        eprintln!("{}", format!("Please answer one of: {}", choices.join(", ")).yellow());
+    }
+}
+
+fn is_c_family(languages: &[String]) -> bool {
+    languages.iter().any(|l| l == "c" || l == "c++")
+}
+
+fn prompt(default_name: &str) -> Result<Answers, Box<dyn std::error::Error + Send + Sync>> {
+    let mut stdin = io::stdin().lock();
+    let name = ask(&mut stdin, "Project name", default_name)?;
+    let version = ask(&mut stdin, "Version", "0.1.0")?;
+    let license = ask(&mut stdin, "License", "MIT")?;
+    let languages: Vec<String> = ask(&mut stdin, "Languages, comma separated (c, c++, rust, go, python, odin, crystal, vala)", "c++")?
+    .split(',').map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect();
+    let (compiler, build_type) = if is_c_family(&languages) {
+        let default_compiler = if languages.iter().any(|l| l == "c++") { "g++" } else { "gcc" };
+        (ask(&mut stdin, "Compiler", default_compiler)?, ask_choice(&mut stdin, "Build type", &["executable", "shared", "static"], "executable")?)
+    } else {
+        (String::new(), String::new())
+    };
+    let format = ask_choice(&mut stdin, "Config format", &CONFIG_FILES.iter().map(|(_, f)| *f).collect::<Vec<_>>(), "hk")?;
+    Ok(Answers { name, version, license, languages, compiler, build_type, format })
+}
+
+/// The config as a plain value; the `build` section only for C/C++ projects.
+fn config_value(a: &Answers) -> Value {
+    let mut config = Map::new();
+    config.insert("metadata".into(), json!({ "name": a.name, "version": a.version, "license": a.license }));
+    config.insert("description".into(), json!({ "summary": a.name, "long": format!("{} built with hbuild", a.name) }));
+    config.insert("specs".into(), json!({ "languages": a.languages, "dependencies": {} }));
+    if is_c_family(&a.languages) {
+        let cpp = a.languages.iter().any(|l| l == "c++");
+        config.insert("build".into(), json!({
+            "target": a.name,
+            "sources": [if cpp { "src/*.cpp" } else { "src/*.c" }],
+            "include_dirs": ["include"],
+            "compiler": a.compiler,
+            "standard": if cpp { "c++20" } else { "c17" },
+            "optimize": "O2",
+            "build_type": a.build_type,
+        }));
+    }
+    Value::Object(config)
+}
+
+/// hk takes scalars verbatim up to the end of the line; only array items are quoted.
+fn hk_value(value: &Value) -> String {
+    match value {
+        Value::Array(items) => format!("[{}]", items.iter().map(|v| format!("\"{}\"", v.as_str().unwrap_or_default())).collect::<Vec<_>>().join(", ")),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// hk keeps languages as keys of `[specs]` rather than as a list, with C++ spelled `cpp`.
+fn to_hk(config: &Value) -> String {
+    let mut out = String::new();
+    for (section, fields) in config.as_object().into_iter().flatten() {
+        out.push_str(&format!("[{}]\n", section));
+        for (key, value) in fields.as_object().into_iter().flatten() {
+            match (section.as_str(), key.as_str()) {
+                ("specs", "languages") => {
+                    for lang in value.as_array().into_iter().flatten() {
+                        let lang = lang.as_str().unwrap_or_default();
+                        out.push_str(&format!("-> {} => *\n", if lang == "c++" { "cpp" } else { lang }));
+                    }
+                }
+                ("specs", "dependencies") => {}
+                _ => out.push_str(&format!("-> {} => {}\n", key, hk_value(value))),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn render(config: &Value, format: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(match format {
+        "hk" => to_hk(config),
+        "toml" => toml::to_string(config)?,
+        "yaml" => serde_yaml::to_string(config)?,
+        "json" => serde_json::to_string_pretty(config)?,
+        "hcl" => hcl::to_string(config)?,
+        _ => return Err(format!("Unknown format '{}'", format).into()),
+    })
+}
+
+/// Writes a complete config: from answers to prompts with `interactive`, otherwise a C++ executable
+/// named after the folder. The written file is parsed back so a broken config is never left behind.
+pub fn run(path: &Path, interactive: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("{}", "Setting up project...".blue().bold());
+    if let Some((existing, _)) = find_config_file(path) {
+        println!("{}", format!("Config already exists: {}", existing.display()).yellow().bold());
+        return Ok(());
+    }
+    let default_name = path.canonicalize()?.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| "my_app".to_string());
+    let answers = if interactive {
+        prompt(&default_name)?
+    } else {
+        Answers {
+            name: default_name,
+            version: "0.1.0".to_string(),
+            license: "MIT".to_string(),
+            languages: vec!["c++".to_string()],
+            compiler: "g++".to_string(),
+            build_type: "executable".to_string(),
+            format: "hk".to_string(),
+        }
+    };
+    let file_name = CONFIG_FILES.iter().find(|(_, f)| *f == answers.format).map(|(n, _)| *n).ok_or("Unknown format")?;
+    let config_path = path.join(file_name);
+    fs::write(&config_path, render(&config_value(&answers), &answers.format)?)?;
+    if let Err(e) = parse_config(&config_path, &answers.format) {
+        fs::remove_file(&config_path)?;
+        return Err(format!("Generated config does not parse: {}", e).into());
+    }
+    println!("{}", format!("Setup complete! Wrote {}", config_path.display()).green().bold());
+    Ok(())
+}
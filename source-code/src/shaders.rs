@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::{expand_globs, install, mtime, resources, verbosity, Generated, Shaders};
+
+/// Dependencies listed in a make-style depfile (`out.spv: a.vert common.glsl`).
+fn parse_depfile(content: &str) -> Vec<PathBuf> {
+    let joined = content.replace("\\\n", " ");
+    joined.split_once(':')
+    .map(|(_, deps)| deps.split_whitespace().map(PathBuf::from).collect())
+    .unwrap_or_default()
+}
+
+/// Output path for a shader source: `shaders/tri.vert` -> `build/shaders/tri.vert.spv`.
+fn output_for(shader: &Path, out_dir: &Path) -> PathBuf {
+    out_dir.join(format!("{}.spv", shader.file_name().unwrap().to_string_lossy()))
+}
+
+/// SPIR-V outputs for every declared shader, compiled or not.
+pub fn outputs(shaders: &Shaders, path: &Path, build_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    let out_dir = build_dir.join("shaders");
+    Ok(expand_globs(path, &shaders.files)?.iter().map(|s| output_for(s, &out_dir)).collect())
+}
+
+/// Stale when the output or its depfile is missing, or the shader or any file it includes is newer.
+fn needs_compile(shader: &Path, out: &Path, depfile: &Path) -> bool {
+    let out_mtime = mtime(out);
+    if !out.exists() || !depfile.exists() || mtime(shader) > out_mtime {
+        return true;
+    }
+    let deps = parse_depfile(&fs::read_to_string(depfile).unwrap_or_default());
+    deps.iter().any(|d| !d.exists() || mtime(d) > out_mtime)
+}
+
+/// Compiles GLSL/HLSL shaders to SPIR-V in `build/shaders` with glslc or glslangValidator.
+pub fn compile(shaders: &Shaders, path: &Path, build_dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let out_dir = build_dir.join("shaders");
+    fs::create_dir_all(&out_dir)?;
+    let tool = shaders.compiler.as_deref().unwrap_or("glslc");
+    let flags = shaders.flags.clone().unwrap_or_default();
+    let include_flags: Vec<String> = shaders.include_dirs.clone().unwrap_or_default()
+    .iter().map(|d| format!("-I{}", path.join(d).display())).collect();
+    for shader in expand_globs(path, &shaders.files)? {
+        let out = output_for(&shader, &out_dir);
+        let depfile = out.with_extension("spv.d");
+        if !needs_compile(&shader, &out, &depfile) {
+            continue;
+        }
+        verbosity::progress(format!("Compiling shader {}", shader.display()).cyan());
+        let hlsl = shader.extension().is_some_and(|e| e == "hlsl");
+        let mut cmd = Command::new(tool);
+        if tool.ends_with("glslangValidator") {
+            cmd.arg("-V");
+            if hlsl {
+                cmd.arg("-D");
+                // foo.frag.hlsl: the stage is the inner extension
+                if let Some(stage) = shader.file_stem().map(Path::new).and_then(|s| s.extension()) {
+                    cmd.arg("-S").arg(stage);
+                }
+            }
+            cmd.arg("--depfile").arg(&depfile);
+        } else {
+            if hlsl {
+                cmd.args(["-x", "hlsl"]);
+                if let Some(stage) = shader.file_stem().map(Path::new).and_then(|s| s.extension()) {
+                    cmd.arg(format!("-fshader-stage={}", stage.to_string_lossy()));
+                }
+            }
+            cmd.arg("-MD").arg("-MF").arg(&depfile);
+        }
+        let output = cmd.args(&include_flags)
+        .args(flags.split_whitespace())
+        .arg("-o")
+        .arg(&out)
+        .arg(&shader)
+        .current_dir(path)
+        .output()?;
+        if !output.status.success() {
+            eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
+            eprintln!("{}", String::from_utf8_lossy(&output.stdout).red());
+            return Err(format!("Shader compilation failed for {}", shader.display()).into());
+        }
+    }
+    Ok(())
+}
+
+/// Embeds the compiled SPIR-V through the resources mechanism, declared in `shaders.h`.
+pub fn embed(shaders: &Shaders, path: &Path, build_dir: &Path, compiler: &str) -> Result<Generated, Box<dyn std::error::Error + Send + Sync>> {
+    let out_dir = build_dir.join("shaders");
+    resources::embed(&outputs(shaders, path, build_dir)?, &out_dir, "c", "shader_", compiler, &out_dir, "shaders.h")
+}
+
+/// Installs SPIR-V files as data under `<prefix>/share/<name>/shaders` (or `install_dir`).
+pub fn install(shaders: &Shaders, name: &str, path: &Path, build_dir: &Path, prefix: &Path, manifest: &mut install::Manifest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let dest = match &shaders.install_dir {
+        Some(dir) => prefix.join(dir),
+        None => prefix.join("share").join(name).join("shaders"),
+    };
+    manifest.create_dir_all(&dest)?;
+    for spv in outputs(shaders, path, build_dir)? {
+        if !spv.exists() {
+            eprintln!("{}", format!("Shader {} not built", spv.display()).yellow());
+            continue;
+        }
+        manifest.copy(&spv, &dest.join(spv.file_name().unwrap()))?;
+    }
+    Ok(())
+}
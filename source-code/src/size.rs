@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::{find_config_file, linkmap, parse_config, target_path, BuildOptions};
+
+/// Number of symbols listed in the report.
+const TOP_SYMBOLS: usize = 15;
+
+/// Where the artifact replaced by the last link is kept for `hbuild size --diff`.
+pub fn previous_path(target: &Path, build_dir: &Path) -> PathBuf {
+    build_dir.join("prev").join(target.file_name().unwrap())
+}
+
+/// Keeps a copy of the current artifact before it is relinked.
+pub fn save_previous(target: &Path, build_dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if target.exists() {
+        let prev = previous_path(target, build_dir);
+        fs::create_dir_all(prev.parent().unwrap())?;
+        fs::copy(target, prev)?;
+    }
+    Ok(())
+}
+
+fn capture(cmd: &mut Command) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let output = cmd.output()?;
+    if !output.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
+        return Err(format!("{} failed", program).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Section sizes from `size -A`, summed over archive members, without debug info and comments.
+fn sections(artifact: &Path) -> Result<BTreeMap<String, u64>, Box<dyn std::error::Error + Send + Sync>> {
+    let out = capture(Command::new("size").arg("-A").arg("-d").arg(artifact))?;
+    let mut sections = BTreeMap::new();
+    for line in out.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() != 3 || !tokens[0].starts_with('.') || tokens[0] == ".comment" || tokens[0].starts_with(".debug") {
+            continue;
+        }
+        if let Ok(size) = tokens[1].parse::<u64>() {
+            if size > 0 {
+                *sections.entry(tokens[0].to_string()).or_default() += size;
+            }
+        }
+    }
+    Ok(sections)
+}
+
+/// Symbol sizes from `nm -S`, demangled.
+fn symbols(artifact: &Path) -> Result<BTreeMap<String, u64>, Box<dyn std::error::Error + Send + Sync>> {
+    let out = capture(Command::new("nm").args(["-S", "-C", "--radix=d", "--size-sort"]).arg(artifact))?;
+    let mut symbols = BTreeMap::new();
+    for line in out.lines() {
+        // address size type name...
+        let mut tokens = line.splitn(4, ' ');
+        let (Some(_), Some(size), Some(_), Some(name)) = (tokens.next(), tokens.next(), tokens.next(), tokens.next()) else {
+            continue;
+        };
+        if let Ok(size) = size.parse::<u64>() {
+            *symbols.entry(name.to_string()).or_default() += size;
+        }
+    }
+    Ok(symbols)
+}
+
+/// Per-object contribution: exact from the link map when `link_map` is on, otherwise the loaded
+/// size of each object file in the build dir.
+fn objects(build_dir: &Path, map_path: &Path) -> Result<Vec<(String, u64)>, Box<dyn std::error::Error + Send + Sync>> {
+    if map_path.exists() {
+        return linkmap::object_sizes(map_path, build_dir);
+    }
+    let mut objects = vec![];
+    for entry in fs::read_dir(build_dir)? {
+        let obj = entry?.path();
+        if obj.extension().is_some_and(|e| e == "o") {
+            let total: u64 = sections(&obj)?.iter()
+            .filter(|(name, _)| !name.starts_with(".note") && !name.starts_with(".eh_frame"))
+            .map(|(_, size)| size).sum();
+            objects.push((obj.display().to_string(), total));
+        }
+    }
+    objects.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    Ok(objects)
+}
+
+fn sorted_desc(map: &BTreeMap<String, u64>) -> Vec<(&String, &u64)> {
+    let mut entries: Vec<(&String, &u64)> = map.iter().collect();
+    entries.sort_by_key(|(_, size)| std::cmp::Reverse(**size));
+    entries
+}
+
+/// Changed entries between two listings, largest change first.
+fn print_changes(title: &str, old: &BTreeMap<String, u64>, new: &BTreeMap<String, u64>, limit: usize) {
+    let mut changes: Vec<(&String, i64)> = old.keys().chain(new.keys())
+    .collect::<std::collections::BTreeSet<_>>()
+    .into_iter()
+    .map(|name| (name, *new.get(name).unwrap_or(&0) as i64 - *old.get(name).unwrap_or(&0) as i64))
+    .filter(|(_, delta)| *delta != 0)
+    .collect();
+    changes.sort_by_key(|(_, delta)| std::cmp::Reverse(delta.abs()));
+    println!("{}", title.cyan());
+    if changes.is_empty() {
+        println!("   (no changes)");
+    }
+    for (name, delta) in changes.iter().take(limit) {
+        let line = format!("   {:>+9} {}", delta, name);
+        if *delta > 0 {
+            println!("{}", line.red());
+        } else {
+            println!("{}", line.green());
+        }
+    }
+}
+
+/// Reports section sizes, the largest symbols and per-object contributions of the built target.
+/// With `diff`, compares sections and symbols against the artifact replaced by the last link.
+pub fn run(path: &Path, diff: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let build = config.build.as_ref().ok_or("No build section")?;
+    let opts = BuildOptions::default();
+    let build_dir = opts.build_dir(path);
+    let target = target_path(build, path, &opts);
+    if !target.exists() {
+        eprintln!("{}", "Target not built".red().bold());
+        return Ok(());
+    }
+    let file_size = fs::metadata(&target)?.len();
+    let sections = sections(&target)?;
+    let symbols = symbols(&target)?;
+
+    if diff {
+        let prev = previous_path(&target, &build_dir);
+        if !prev.exists() {
+            return Err("No previous build to compare against; rebuild after a change first".into());
+        }
+        let prev_size = fs::metadata(&prev)?.len();
+        println!("{}", format!("Size diff for {}: {} -> {} bytes ({:+})", target.display(), prev_size, file_size, file_size as i64 - prev_size as i64).blue().bold());
+        print_changes("Sections:", &self::sections(&prev)?, &sections, usize::MAX);
+        print_changes("Symbols:", &self::symbols(&prev)?, &symbols, TOP_SYMBOLS);
+        return Ok(());
+    }
+
+    println!("{}", format!("Size of {}: {} bytes", target.display(), file_size).blue().bold());
+    println!("{}", "Sections:".cyan());
+    for (name, size) in sorted_desc(&sections) {
+        println!("   {:>9} {}", size, name);
+    }
+    println!("{}", "Largest symbols:".cyan());
+    for (name, size) in sorted_desc(&symbols).into_iter().take(TOP_SYMBOLS) {
+        println!("   {:>9} {}", size, name);
+    }
+    let map_path = build_dir.join(format!("{}.map", build.target));
+    let objects = objects(&build_dir, &map_path)?;
+    if map_path.exists() {
+        println!("{}", "Per-object contribution (from link map):".cyan());
+    } else {
+        println!("{}", "Per-object size (enable link_map for linked contribution):".cyan());
+    }
+    for (object, size) in objects {
+        println!("   {:>9} {}", size, object);
+    }
+    Ok(())
+}
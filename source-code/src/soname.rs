@@ -0,0 +1,69 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use crate::platform;
+
+/// Real file and soname of a shared library whose development link is `libfoo.so`: for version
+/// 1.2.3, `libfoo.so.1.2.3` and `libfoo.so.1`.
+pub struct Versioned {
+    pub file: PathBuf,
+    pub soname: String,
+}
+
+/// The versioned names of the shared library `target` on ELF platforms. `None` on Windows and
+/// macOS, and when `version` doesn't start with a major number, which leaves the library unversioned.
+pub fn versioned(target: &Path, version: &str) -> Option<Versioned> {
+    if cfg!(windows) || platform::is_macos() {
+        return None;
+    }
+    let major = version.split('.').next().filter(|m| !m.is_empty() && m.chars().all(|c| c.is_ascii_digit()))?;
+    let name = target.file_name()?.to_string_lossy();
+    Some(Versioned {
+        file: target.with_file_name(format!("{}.{}", name, version)),
+        soname: format!("{}.{}", name, major),
+    })
+}
+
+impl Versioned {
+    /// Records the soname in the library, so programs linked against it load `libfoo.so.1`.
+    pub fn link_flag(&self) -> OsString {
+        format!("-Wl,-soname,{}", self.soname).into()
+    }
+
+    /// The soname link to the real file and the development link `target` to the soname, next to
+    /// the real file. Relative, so the build directory can move.
+    pub fn links(&self) -> Vec<(PathBuf, PathBuf)> {
+        let dir = self.file.parent().unwrap_or(Path::new(""));
+        let dev = self.soname.rsplit_once('.').map_or(self.soname.as_str(), |(dev, _)| dev);
+        vec![
+            (PathBuf::from(self.file.file_name().unwrap()), dir.join(&self.soname)),
+            (PathBuf::from(&self.soname), dir.join(dev)),
+        ]
+    }
+
+    /// Creates the links of [`Versioned::links`], replacing stale ones from an older version.
+    pub fn link(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        for (original, link) in self.links() {
+            symlink(&original, &link)?;
+        }
+        Ok(())
+    }
+}
+
+/// Makes `link` a symlink to `original`, replacing whatever is there.
+pub fn symlink(original: &Path, link: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if fs::read_link(link).is_ok_and(|current| current == original) {
+        return Ok(());
+    }
+    match fs::remove_file(link) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("Cannot replace {}: {}", link.display(), e).into()),
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(original, link).map_err(|e| format!("Cannot link {} to {}: {}", link.display(), original.display(), e))?;
+    #[cfg(not(unix))]
+    fs::copy(link.with_file_name(original), link)?;
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::pathdep::Usage;
+use crate::pkgdeps::{self, PkgConfigMode};
+use crate::{args, gitdep, verbosity, HBuildConfig};
+
+/// Marks a complete install in a prefix, holding what it was built from.
+const STAMP: &str = ".hbuild-staged";
+
+/// Where git dependency `name`, when it isn't an hbuild project, is built with its own build system:
+/// `build` for the build tree and `prefix` for the install the projects using it compile against.
+pub fn dir(name: &str) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(gitdep::cache_dir()?.join("staged").join(name))
+}
+
+/// Runs `commands` building and installing dependency `name` at `commit` into its prefix, unless
+/// the prefix already holds an install of the same commit by the same commands, whatever their
+/// environment.
+pub fn run(name: &str, commit: &str, commands: Vec<Command>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let prefix = dir(name)?.join("prefix");
+    let stamp = prefix.join(STAMP);
+    let lines: Vec<String> = commands.iter().map(|c| std::iter::once(c.get_program()).chain(c.get_args()).map(args::quote).collect::<Vec<_>>().join(" ")).collect();
+    let spec = format!("{}\n{}", commit, lines.join("\n"));
+    if fs::read_to_string(&stamp).is_ok_and(|old| old == spec) {
+        return Ok(());
+    }
+    // Files an older version installed mustn't linger
+    if prefix.exists() {
+        fs::remove_dir_all(&prefix)?;
+    }
+    verbosity::progress(format!("Building {}", name).cyan());
+    for mut command in commands {
+        verbosity::command(&command);
+        let output = command.output()?;
+        if !output.status.success() {
+            eprintln!("{}", String::from_utf8_lossy(&output.stdout).red());
+            eprintln!("{}", String::from_utf8_lossy(&output.stderr).red());
+            return Err(format!("Building dependency {} failed", name).into());
+        }
+    }
+    fs::create_dir_all(&prefix)?;
+    fs::write(&stamp, spec)?;
+    Ok(())
+}
+
+/// What the prefix of a staged dependency offers: the modules of the `.pc` files it installed, else
+/// its `include` directory and the libraries in `lib`, static ones over shared ones.
+fn prefix_usage(prefix: &Path) -> Result<Usage, Box<dyn std::error::Error + Send + Sync>> {
+    let mut usage = Usage::default();
+    let pc_dirs = pkgdeps::fallback_pc_dirs(prefix);
+    let modules: Vec<String> = pc_dirs.iter().filter_map(|d| fs::read_dir(d).ok()).flatten().flatten()
+    .filter_map(|e| e.file_name().to_str()?.strip_suffix(".pc").map(String::from)).collect();
+    if !modules.is_empty() {
+        for module in modules {
+            let lib = pkgdeps::probe(&module, &pc_dirs, &PkgConfigMode::Host)?;
+            usage.include_dirs.extend(lib.include_paths);
+            usage.defines.extend(lib.defines.into_iter().map(|(key, val)| match val {
+                Some(val) => format!("{}={}", key, val),
+                None => key,
+            }));
+            usage.link_dirs.extend(lib.link_paths.into_iter().filter(|d| d.starts_with(prefix)));
+            usage.libs.extend(lib.libs);
+        }
+        return Ok(usage);
+    }
+    usage.include_dirs.push(prefix.join("include"));
+    // By name without extension, so a library installed both ways is linked once
+    let mut libraries: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for dir in ["lib", "lib64"].iter().map(|d| prefix.join(d)) {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let file = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(stem) = name.strip_suffix(".a").or_else(|| name.strip_suffix(".so")) else {
+                continue;
+            };
+            if name.ends_with(".a") || !libraries.contains_key(stem) {
+                libraries.insert(stem.to_string(), file);
+            }
+        }
+    }
+    usage.libraries.extend(libraries.into_values());
+    Ok(usage)
+}
+
+/// What the project's staged git dependencies installed, for its C and C++ build.
+pub fn usage(config: &HBuildConfig) -> Result<Usage, Box<dyn std::error::Error + Send + Sync>> {
+    let mut usage = Usage::default();
+    for (name, _) in config.specs.dependencies.iter().filter(|(_, dep)| dep.git().is_some()) {
+        let prefix = dir(name)?.join("prefix");
+        if prefix.join(STAMP).exists() {
+            usage.extend(prefix_usage(&prefix)?);
+        }
+    }
+    Ok(usage)
+}
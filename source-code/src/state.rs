@@ -0,0 +1,124 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use dirs::home_dir;
+use crate::{hash_bytes, hash_file, BuildState};
+
+impl BuildState {
+    fn path(build_dir: &Path) -> PathBuf {
+        build_dir.join(".hbuild-state.json")
+    }
+
+    /// The fingerprints recorded by the last build; empty when there is none or it can't be read.
+    pub fn load(build_dir: &Path) -> Self {
+        fs::read_to_string(Self::path(build_dir)).ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+    }
+
+    pub fn save(&self, build_dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        fs::write(Self::path(build_dir), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// `src` and every header it includes, directly or through other headers.
+fn inputs(src: &Path, deps: &HashMap<PathBuf, HashSet<PathBuf>>) -> BTreeSet<PathBuf> {
+    let mut seen = BTreeSet::new();
+    let mut pending = vec![src.to_path_buf()];
+    while let Some(file) = pending.pop() {
+        if seen.insert(file.clone()) {
+            pending.extend(deps.get(&file).into_iter().flatten().cloned());
+        }
+    }
+    seen
+}
+
+/// Each of the inputs of `src` as `(name, contents hash)`.
+fn hashed_inputs(src: &Path, deps: &HashMap<PathBuf, HashSet<PathBuf>>, contents: &mut HashMap<PathBuf, String>, name: impl Fn(&Path) -> String) -> Vec<(String, String)> {
+    inputs(src, deps).into_iter().map(|file| {
+        let hash = contents.entry(file.clone()).or_insert_with(|| hash_file(&file).unwrap_or_else(|| "missing".to_string()));
+        (name(&file), hash.clone())
+    }).collect()
+}
+
+fn combine(flags: &str, inputs: &[(String, String)]) -> String {
+    let combined: String = inputs.iter().map(|(name, hash)| format!("{} {}\n", name, hash)).collect();
+    format!("{}-{}", hash_bytes(flags.as_bytes()), hash_bytes(combined.as_bytes()))
+}
+
+/// What an object was built from, as `<flags hash>-<contents hash>`: the compile flags, and the contents
+/// of its source and headers. `contents` caches file hashes across sources sharing headers.
+pub fn fingerprint(src: &Path, deps: &HashMap<PathBuf, HashSet<PathBuf>>, flags: &str, contents: &mut HashMap<PathBuf, String>) -> String {
+    combine(flags, &hashed_inputs(src, deps, contents, |file| file.display().to_string()))
+}
+
+/// `file` named the same in every checkout of the project at `root`: relative to it, or to the home
+/// directory, where dependencies are cached, when outside it.
+pub fn portable_path(file: &Path, root: &Path) -> String {
+    let canonical = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+    if let Ok(relative) = canonical.strip_prefix(root) {
+        return relative.display().to_string();
+    }
+    match home_dir().and_then(|home| canonical.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(relative) => format!("~/{}", relative.display()),
+        None => canonical.display().to_string(),
+    }
+}
+
+/// Like `fingerprint`, but naming the source and headers by `portable_path`, so another checkout at
+/// another path or on another machine gets the same one when `flags` are made portable too. This is
+/// what the artifact cache is keyed by.
+pub fn portable_fingerprint(src: &Path, deps: &HashMap<PathBuf, HashSet<PathBuf>>, flags: &str, contents: &mut HashMap<PathBuf, String>, root: &Path) -> String {
+    // The source can be both as given and, included back, canonical; portable names are the same
+    let mut inputs = hashed_inputs(src, deps, contents, |file| portable_path(file, root));
+    inputs.sort();
+    inputs.dedup();
+    combine(flags, &inputs)
+}
+
+/// Why an object recorded as `stored` differs from `current`, or None when it doesn't.
+pub fn difference(stored: &str, current: &str) -> Option<&'static str> {
+    let (stored_flags, stored_contents) = stored.split_once('-').unwrap_or((stored, ""));
+    let (current_flags, current_contents) = current.split_once('-').unwrap_or((current, ""));
+    if stored_flags != current_flags {
+        Some("the compile flags changed")
+    } else if stored_contents != current_contents {
+        Some("the contents of the source or one of its headers changed")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A checkout at `<tmp>/<name>` with `main.c` including `util.h`, and its dependency graph.
+    fn checkout(name: &str, header: &str) -> (PathBuf, HashMap<PathBuf, HashSet<PathBuf>>) {
+        let root = std::env::temp_dir().join(format!("hbuild-state-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("main.c"), "#include \"util.h\"\n").unwrap();
+        fs::write(root.join("util.h"), header).unwrap();
+        let root = root.canonicalize().unwrap();
+        let deps = HashMap::from([(root.join("main.c"), HashSet::from([root.join("util.h")]))]);
+        (root, deps)
+    }
+
+    #[test]
+    fn portable_fingerprints_match_across_checkouts() {
+        let (a, a_deps) = checkout("a", "int x;\n");
+        let (b, b_deps) = checkout("b", "int x;\n");
+        let (c, c_deps) = checkout("c", "int y;\n");
+        let key = |root: &Path, deps, flags| portable_fingerprint(&root.join("main.c"), deps, flags, &mut HashMap::new(), root);
+        assert_eq!(key(&a, &a_deps, "-O2"), key(&b, &b_deps, "-O2"));
+        assert_ne!(key(&a, &a_deps, "-O2"), key(&c, &c_deps, "-O2"));
+        assert_eq!(difference(&key(&a, &a_deps, "-O2"), &key(&c, &c_deps, "-O2")), Some("the contents of the source or one of its headers changed"));
+        assert_eq!(difference(&key(&a, &a_deps, "-O2"), &key(&a, &a_deps, "-O3")), Some("the compile flags changed"));
+        // The local fingerprint names files by their absolute path, so it differs between checkouts
+        assert_ne!(fingerprint(&a.join("main.c"), &a_deps, "-O2", &mut HashMap::new()), fingerprint(&b.join("main.c"), &b_deps, "-O2", &mut HashMap::new()));
+        for root in [a, b, c] {
+            fs::remove_dir_all(root).unwrap();
+        }
+    }
+}
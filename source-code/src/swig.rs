@@ -0,0 +1,153 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use owo_colors::OwoColorize;
+use crate::{expand_globs, install, is_stale, mtime, verbosity, Swig};
+
+/// Module name declared by `%module` (or `%module(options) name`) in a SWIG interface.
+fn module_name(interface: &Path) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let content = fs::read_to_string(interface)?;
+    for line in content.lines() {
+        if let Some(rest) = line.trim().strip_prefix("%module") {
+            let rest = match rest.trim_start().strip_prefix('(') {
+                Some(opts) => opts.split_once(')').map(|(_, name)| name).unwrap_or(""),
+                None => rest,
+            };
+            if let Some(name) = rest.split_whitespace().next() {
+                return Ok(name.to_string());
+            }
+        }
+    }
+    Err(format!("No %module directive in {}", interface.display()).into())
+}
+
+fn capture(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(program).args(args).output().ok()?;
+    if out.status.success() {
+        Some(String::from_utf8_lossy(&out.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+/// Compile flags for the interpreter headers, via python3-config or the first Lua pkg-config module found.
+fn interpreter_cflags(lang: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let flags = match lang {
+        "python" => capture("python3-config", &["--includes"]).or_else(|| capture("pkg-config", &["--cflags", "python3"])),
+        "lua" => ["lua", "lua5.4", "lua5.3", "luajit"].iter().find_map(|m| capture("pkg-config", &["--cflags", m])),
+        _ => return Err(format!("Unsupported SWIG language: {}", lang).into()),
+    };
+    flags.ok_or_else(|| format!("Could not find {} development headers", lang).into())
+}
+
+/// File name of the compiled extension module for `lang`.
+fn extension_file(lang: &str, module: &str) -> String {
+    match lang {
+        "python" => {
+            let suffix = capture("python3-config", &["--extension-suffix"]).unwrap_or_else(|| ".so".to_string());
+            format!("_{}{}", module, suffix)
+        }
+        _ => format!("{}.so", module),
+    }
+}
+
+fn run(cmd: &mut Command, what: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let out = cmd.output()?;
+    if !out.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&out.stderr).red());
+        return Err(format!("{} failed", what).into());
+    }
+    Ok(())
+}
+
+/// Generates, compiles and links the bindings for every interface and language into `build/swig/<lang>`,
+/// linking each extension module against the project's library target.
+pub fn build(swig: &Swig, path: &Path, build_dir: &Path, compiler: &str, target_path: &Path, include_flags: &[OsString], ldflags: &[OsString]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let cplusplus = swig.cplusplus.unwrap_or(false);
+    for interface in expand_globs(path, &swig.interfaces)? {
+        let module = module_name(&interface)?;
+        for lang in &swig.languages {
+            let out_dir = build_dir.join("swig").join(lang);
+            fs::create_dir_all(&out_dir)?;
+            let wrapper = out_dir.join(format!("{}_wrap.{}", module, if cplusplus { "cxx" } else { "c" }));
+            if is_stale(&interface, &wrapper) {
+                verbosity::progress(format!("swig -{} {}", lang, interface.display()).cyan());
+                let mut cmd = Command::new(swig.swig.as_deref().unwrap_or("swig"));
+                cmd.arg(format!("-{}", lang));
+                if cplusplus {
+                    cmd.arg("-c++");
+                }
+                run(cmd.args(include_flags.iter().filter(|f| f.to_str().is_some_and(|f| f.starts_with("-I"))))
+                .arg("-outdir").arg(&out_dir)
+                .arg("-o").arg(&wrapper)
+                .arg(&interface)
+                .current_dir(path), "swig")?;
+            }
+
+            let object = wrapper.with_extension("o");
+            if is_stale(&wrapper, &object) {
+                run(Command::new(compiler)
+                .args(["-fPIC", "-c"])
+                .arg(&wrapper)
+                .args(include_flags)
+                .args(interpreter_cflags(lang)?.split_whitespace())
+                .arg("-o").arg(&object)
+                .current_dir(path), "Compiling SWIG wrapper")?;
+            }
+
+            let extension = out_dir.join(extension_file(lang, &module));
+            if is_stale(&object, &extension) || mtime(target_path) > mtime(&extension) {
+                verbosity::progress(format!("Linking {} binding {}", lang, extension.display()).cyan());
+                run(Command::new(compiler)
+                .arg("-shared")
+                .arg("-o").arg(&extension)
+                .arg(&object)
+                .arg(target_path)
+                .args(ldflags)
+                .current_dir(path), "Linking SWIG module")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Directory extension modules for `lang` are installed into under `prefix`.
+fn install_dir(lang: &str, prefix: &Path) -> PathBuf {
+    match lang {
+        "python" => {
+            let code = format!(
+                "import sysconfig; print(sysconfig.get_path('platlib', vars={{'base': '{0}', 'platbase': '{0}'}}))",
+                prefix.display()
+            );
+            capture("python3", &["-c", &code]).map(PathBuf::from).unwrap_or_else(|| prefix.join("lib/python3/site-packages"))
+        }
+        _ => {
+            let version = capture("lua", &["-e", "print((_VERSION:gsub('Lua ', '')))"]).unwrap_or_else(|| "5.4".to_string());
+            prefix.join("lib/lua").join(version)
+        }
+    }
+}
+
+/// Copies built extension modules (and Python wrapper scripts) into the interpreter's module directory.
+pub fn install(swig: &Swig, path: &Path, build_dir: &Path, prefix: &Path, manifest: &mut install::Manifest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    for interface in expand_globs(path, &swig.interfaces)? {
+        let module = module_name(&interface)?;
+        for lang in &swig.languages {
+            let out_dir = build_dir.join("swig").join(lang);
+            let dest = install_dir(lang, prefix);
+            manifest.create_dir_all(&dest)?;
+            let extension = extension_file(lang, &module);
+            if !out_dir.join(&extension).exists() {
+                eprintln!("{}", format!("{} binding {} not built", lang, module).yellow());
+                continue;
+            }
+            manifest.copy(&out_dir.join(&extension), &dest.join(&extension))?;
+            let script = format!("{}.py", module);
+            if lang == "python" && out_dir.join(&script).exists() {
+                manifest.copy(&out_dir.join(&script), &dest.join(&script))?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use owo_colors::OwoColorize;
+use crate::pkgdeps::{self, PkgConfigMode};
+use crate::{platform, verbosity, PkgFallback};
+
+/// With `--auto-install-deps`, missing pkg dependencies are installed instead of only reported.
+static AUTO_INSTALL: AtomicBool = AtomicBool::new(false);
+
+pub fn set_auto_install() {
+    AUTO_INSTALL.store(true, Ordering::Relaxed);
+}
+
+/// The host's package manager.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Manager {
+    Apt,
+    Dnf,
+    Pacman,
+    Xbps,
+}
+
+/// Packages shipping common `.pc` files, as (module, apt, pacman, xbps). dnf looks modules up itself.
+const PACKAGES: &[(&str, &str, &str, &str)] = &[
+    ("alsa", "libasound2-dev", "alsa-lib", "alsa-lib-devel"),
+    ("dbus-1", "libdbus-1-dev", "dbus", "dbus-devel"),
+    ("fontconfig", "libfontconfig-dev", "fontconfig", "fontconfig-devel"),
+    ("freetype2", "libfreetype-dev", "freetype2", "freetype-devel"),
+    ("gio-2.0", "libglib2.0-dev", "glib2", "glib-devel"),
+    ("glib-2.0", "libglib2.0-dev", "glib2", "glib-devel"),
+    ("gobject-2.0", "libglib2.0-dev", "glib2", "glib-devel"),
+    ("gtk+-3.0", "libgtk-3-dev", "gtk3", "gtk+3-devel"),
+    ("gtk4", "libgtk-4-dev", "gtk4", "gtk4-devel"),
+    ("libcurl", "libcurl4-openssl-dev", "curl", "libcurl-devel"),
+    ("libffi", "libffi-dev", "libffi", "libffi-devel"),
+    ("libpng", "libpng-dev", "libpng", "libpng-devel"),
+    ("libpulse", "libpulse-dev", "libpulse", "pulseaudio-devel"),
+    ("libsystemd", "libsystemd-dev", "systemd-libs", "elogind-devel"),
+    ("libudev", "libudev-dev", "systemd-libs", "eudev-libudev-devel"),
+    ("libxml-2.0", "libxml2-dev", "libxml2", "libxml2-devel"),
+    ("ncurses", "libncurses-dev", "ncurses", "ncurses-devel"),
+    ("openssl", "libssl-dev", "openssl", "openssl-devel"),
+    ("libssl", "libssl-dev", "openssl", "openssl-devel"),
+    ("libcrypto", "libssl-dev", "openssl", "openssl-devel"),
+    ("sdl2", "libsdl2-dev", "sdl2", "SDL2-devel"),
+    ("sqlite3", "libsqlite3-dev", "sqlite", "sqlite-devel"),
+    ("vulkan", "libvulkan-dev", "vulkan-icd-loader", "Vulkan-Headers"),
+    ("wayland-client", "libwayland-dev", "wayland", "wayland-devel"),
+    ("x11", "libx11-dev", "libx11", "libX11-devel"),
+    ("xkbcommon", "libxkbcommon-dev", "libxkbcommon", "libxkbcommon-devel"),
+    ("zlib", "zlib1g-dev", "zlib", "zlib-devel"),
+];
+
+impl Manager {
+    /// The first package manager found on PATH.
+    pub fn detect() -> Option<Manager> {
+        [("apt-get", Manager::Apt), ("dnf", Manager::Dnf), ("pacman", Manager::Pacman), ("xbps-install", Manager::Xbps)]
+        .into_iter()
+        .find(|(program, _)| platform::which(program).is_some())
+        .map(|(_, manager)| manager)
+    }
+
+    /// The package providing the pkg-config module `name`: from the table of common modules, else
+    /// the distro's usual development package naming, e.g. `libfoo-dev` on Debian.
+    pub fn package(self, name: &str) -> String {
+        if self == Manager::Dnf {
+            return format!("pkgconfig({})", name);
+        }
+        if let Some((_, apt, pacman, xbps)) = PACKAGES.iter().find(|(module, ..)| *module == name) {
+            return match self {
+                Manager::Apt => apt,
+                Manager::Pacman => pacman,
+                _ => xbps,
+            }.to_string();
+        }
+        // Modules carry their API version (`foo-1.0`), which package names mostly drop
+        let base = name.rsplit_once('-').filter(|(_, v)| v.chars().all(|c| c.is_ascii_digit() || c == '.')).map_or(name, |(base, _)| base);
+        let base = base.strip_prefix("lib").unwrap_or(base);
+        match self {
+            Manager::Apt => format!("lib{}-dev", base),
+            Manager::Pacman => base.to_string(),
+            _ => format!("{}-devel", base),
+        }
+    }
+
+    /// The command installing `packages`, through sudo unless hbuild runs as root.
+    pub fn install_command(self, packages: &[String]) -> Vec<String> {
+        let mut command: Vec<String> = match self {
+            Manager::Apt => vec!["apt-get", "install", "-y"],
+            Manager::Dnf => vec!["dnf", "install", "-y"],
+            Manager::Pacman => vec!["pacman", "-S", "--needed", "--noconfirm"],
+            Manager::Xbps => vec!["xbps-install", "-y"],
+        }.into_iter().map(String::from).collect();
+        if !platform::is_root() {
+            command.insert(0, "sudo".to_string());
+        }
+        command.extend(packages.iter().cloned());
+        command
+    }
+}
+
+/// `command` as it would be typed, quoting the dnf `pkgconfig(...)` arguments for the shell.
+pub fn display(command: &[String]) -> String {
+    command.iter().map(|a| if a.contains('(') { format!("'{}'", a) } else { a.clone() }).collect::<Vec<_>>().join(" ")
+}
+
+/// Checks the host's pkg dependencies without a source fallback before anything is built. Missing
+/// ones are reported with the host package manager's command installing them, or installed with
+/// `--auto-install-deps`. Version mismatches and unknown package managers are left to the probe.
+pub fn ensure(entries: &[String], fallbacks: Option<&BTreeMap<String, PkgFallback>>, mode: &PkgConfigMode) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !matches!(mode, PkgConfigMode::Host) {
+        return Ok(());
+    }
+    let mut missing = vec![];
+    for entry in entries {
+        let name = pkgdeps::parse(entry)?.name;
+        if !fallbacks.is_some_and(|f| f.contains_key(&name)) && !pkgdeps::found(&name, mode) {
+            missing.push(name);
+        }
+    }
+    let Some(manager) = Manager::detect().filter(|_| !missing.is_empty()) else {
+        return Ok(());
+    };
+    let mut packages: Vec<String> = missing.iter().map(|name| manager.package(name)).collect();
+    packages.sort();
+    packages.dedup();
+    let command = manager.install_command(&packages);
+    let listed = missing.join(", ");
+    if !AUTO_INSTALL.load(Ordering::Relaxed) {
+        let plural = if missing.len() == 1 { "y" } else { "ies" };
+        return Err(format!("pkg dependenc{} {} not found; install with `{}` or rerun with --auto-install-deps", plural, listed, display(&command)).into());
+    }
+    verbosity::progress(format!("Installing {}: {}", listed, display(&command)).cyan());
+    let status = Command::new(&command[0]).args(&command[1..]).status()?;
+    if !status.success() {
+        return Err(format!("`{}` failed", display(&command)).into());
+    }
+    for name in missing.iter().filter(|name| !pkgdeps::found(name, mode)) {
+        eprintln!("{}", format!("{} still has no .pc file after installing {}; install the package providing it or add [pkg_fallbacks.{}]", name, manager.package(name), name).yellow());
+    }
+    Ok(())
+}
@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use glob::Pattern;
+use owo_colors::OwoColorize;
+use crate::{compile_c_cpp, cross, expand_globs, find_config_file, install_deps, parse_config, platform, rules, sandbox, shaders, target_path, verbosity, write_if_changed, BuildOptions, HBuildConfig, PkgFallback};
+
+/// A test framework hbuild builds the `[test]` sources against and reads the results of.
+struct Framework {
+    /// The pkg-config module with the framework and its `main`, if it has one.
+    module: &'static str,
+    /// Where to fetch it from, and how to build it, when pkg-config does not find it.
+    fallback: PkgFallback,
+    /// For a header-only framework, its header: when on the system include path, no module is needed.
+    header: Option<&'static str>,
+    /// A source defining `main`, for frameworks whose library has none.
+    main: Option<&'static str>,
+    /// Arguments making a test binary write a JUnit report to a file.
+    report: fn(&Path) -> String,
+    /// Whether the framework still prints its own results while writing the report.
+    console: bool,
+}
+
+fn framework(name: &str) -> Result<Option<Framework>, Box<dyn std::error::Error + Send + Sync>> {
+    let git = |url: &str, rev: &str| PkgFallback { git: Some(url.to_string()), rev: Some(rev.to_string()), url: None, build: None };
+    match name {
+        "none" => Ok(None),
+        "gtest" => Ok(Some(Framework {
+            module: "gtest_main",
+            fallback: git("https://github.com/google/googletest", "v1.14.0"),
+            header: None,
+            main: None,
+            report: |file| format!("--gtest_output=xml:{}", file.display()),
+            console: true,
+        })),
+        "catch2" => Ok(Some(Framework {
+            module: "catch2-with-main",
+            fallback: git("https://github.com/catchorg/Catch2", "v3.5.4"),
+            header: None,
+            main: None,
+            report: |file| format!("--reporter console --reporter junit::out={}", file.display()),
+            console: true,
+        })),
+        // Header-only and without a .pc file of its own, so its fallback installs one
+        "doctest" => Ok(Some(Framework {
+            module: "doctest",
+            fallback: PkgFallback {
+                git: None,
+                rev: None,
+                url: Some("https://github.com/doctest/doctest/archive/refs/tags/v2.4.11.tar.gz".to_string()),
+                build: Some("mkdir -p $prefix/include/doctest $prefix/lib/pkgconfig && cp doctest/doctest.h $prefix/include/doctest/ && printf 'Name: doctest\\nDescription: C++ testing framework\\nVersion: 2.4.11\\nCflags: -I$prefix/include\\n' > $prefix/lib/pkgconfig/doctest.pc".to_string()),
+            },
+            header: Some("doctest/doctest.h"),
+            main: Some("#define DOCTEST_CONFIG_IMPLEMENT_WITH_MAIN\n#include <doctest/doctest.h>\n"),
+            report: |file| format!("--reporters=junit --out={}", file.display()),
+            console: false,
+        })),
+        _ => Err(format!("Unknown test framework '{}' (expected none, gtest, catch2 or doctest)", name).into()),
+    }
+}
+
+/// Project sources linked into test and benchmark binaries: everything but `exclude`, by default the
+/// files named `main.*`.
+pub fn code_under_test(path: &Path, sources: &[String], exclude: Option<&Vec<String>>) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    let excluded = match exclude {
+        Some(patterns) => expand_globs(path, patterns)?,
+        None => vec![],
+    };
+    Ok(expand_globs(path, sources)?.into_iter()
+    .filter(|src| !excluded.contains(src))
+    .filter(|src| exclude.is_some() || src.file_stem().is_none_or(|s| s != "main"))
+    .collect())
+}
+
+/// Dependencies, rules and shaders the project's code needs before programs using it are built.
+pub fn prepare(config: &HBuildConfig, path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    install_deps(config, path)?;
+    if let Some(r) = &config.rules {
+        rules::run(r, path, &path.join("build"), sandbox::enabled(config))?;
+    }
+    if let Some(sh) = &config.shaders {
+        shaders::compile(sh, path, &path.join("build"))?;
+    }
+    Ok(())
+}
+
+/// An executable built from the project's code and sources of its own, like a test binary.
+pub struct Program {
+    pub name: String,
+    pub sources: Vec<PathBuf>,
+    pub libs: Vec<String>,
+    pub ldflags: String,
+    pub pkg_dependencies: Vec<String>,
+    /// Fallbacks for `pkg_dependencies`, used where the project's `[pkg_fallbacks]` has none.
+    pub pkg_fallbacks: BTreeMap<String, PkgFallback>,
+}
+
+/// Builds `program` with its own copy of the config at `config_path`, as an executable into the
+/// build directory of `opts`, and returns its path.
+pub fn build_program(path: &Path, children: &Arc<Mutex<Vec<u32>>>, config_path: &Path, format: &str, program: &Program, opts: &BuildOptions) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    let mut config = parse_config(config_path, format)?;
+    let build = config.build.as_mut().ok_or("No build section")?;
+    build.target = program.name.clone();
+    build.build_type = "executable".to_string();
+    build.sources = program.sources.iter().map(|src| Pattern::escape(&src.display().to_string())).collect();
+    build.libs.get_or_insert_with(Vec::new).extend(program.libs.iter().cloned());
+    build.ldflags = Some(format!("{} {}", build.ldflags.as_deref().unwrap_or(""), program.ldflags));
+    build.pkg_dependencies.get_or_insert_with(Vec::new).extend(program.pkg_dependencies.iter().cloned());
+    build.static_variant = None;
+    build.version_script = None;
+    build.exported_symbols = None;
+    let fallbacks = config.pkg_fallbacks.get_or_insert_with(BTreeMap::new);
+    for (name, fallback) in &program.pkg_fallbacks {
+        fallbacks.entry(name.clone()).or_insert_with(|| fallback.clone());
+    }
+    config.swig = None;
+    config.bolt = None;
+    compile_c_cpp(&config, path, children, opts)?;
+    Ok(target_path(config.build.as_ref().unwrap(), path, opts))
+}
+
+/// How a test case ended.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Pass,
+    /// With the framework's message, if it gave one.
+    Fail(String),
+    Skip,
+}
+
+/// The value of attribute `name` in an XML start tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let end = tag[start..].find('"')? + start;
+    Some(unescape(&tag[start..end]))
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&#10;", "\n").replace("&amp;", "&")
+}
+
+/// The message of a `<failure>` or `<error>` element: its text, where frameworks put the location
+/// and values, else its `message` attribute.
+fn failure_message(element: &str) -> String {
+    let tag_end = element.find('>').unwrap_or(element.len());
+    let tag = &element[..tag_end];
+    let text = if tag.ends_with('/') {
+        ""
+    } else {
+        let body = &element[(tag_end + 1).min(element.len())..];
+        &body[..body.find("</").unwrap_or(body.len())]
+    };
+    let text = unescape(text.trim_matches('\n').trim_start_matches("<![CDATA[").trim_end_matches("]]>"));
+    let indent = text.lines().filter(|l| !l.trim().is_empty()).map(|l| l.len() - l.trim_start().len()).min().unwrap_or(0);
+    let text: Vec<&str> = text.lines().map(|l| l.get(indent..).unwrap_or("").trim_end()).collect();
+    let text = text.join("\n").trim().to_string();
+    if text.is_empty() { attribute(tag, "message").unwrap_or_default() } else { text }
+}
+
+/// The test cases in a JUnit XML report, named `class.name` when `qualified`. All three frameworks
+/// write one; they differ only in the attributes telling a skipped case.
+fn junit_cases(xml: &str, qualified: bool) -> Vec<(String, Outcome)> {
+    let mut cases = vec![];
+    let mut rest = xml;
+    while let Some(start) = rest.find("<testcase ") {
+        rest = &rest[start..];
+        let tag_end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..tag_end];
+        let body = if tag.ends_with('/') { "" } else { &rest[tag_end..rest.find("</testcase>").unwrap_or(rest.len())] };
+        let name = attribute(tag, "name").unwrap_or_default();
+        let name = match attribute(tag, "classname").filter(|_| qualified) {
+            Some(class) => format!("{}.{}", class, name),
+            None => name,
+        };
+        let skipped = body.contains("<skipped") || attribute(tag, "status").is_some_and(|s| s == "notrun") || attribute(tag, "result").is_some_and(|r| r == "skipped" || r == "suppressed");
+        let outcome = match body.find("<failure").or(body.find("<error")) {
+            Some(at) => Outcome::Fail(failure_message(&body[at..])),
+            None if skipped => Outcome::Skip,
+            None => Outcome::Pass,
+        };
+        cases.push((name, outcome));
+        rest = &rest[tag_end..];
+    }
+    cases
+}
+
+/// Builds the `[test]` sources together with the project's code and runs them. With `framework = "none"`
+/// every test source is its own program, passing when it exits successfully. With `gtest`, `catch2` or
+/// `doctest` all of them form one binary using the framework, found with pkg-config or else fetched
+/// and built once into the pkg fallback cache, and with its `main` unless a test source defines one;
+/// each of its test cases is reported from the JUnit report the binary writes. Each binary is built in
+/// `test/<name>` of the build directory, e.g. `build/test/<name>`.
+pub fn run(path: &Path, children: &Arc<Mutex<Vec<u32>>>, opts: &BuildOptions) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let build = config.build.as_ref().ok_or("No build section")?;
+    let test = config.test.as_ref().ok_or("No [test] section in config")?;
+    let framework = framework(test.framework.as_deref().unwrap_or("none"))?;
+    let tests = expand_globs(path, &test.sources)?;
+    if tests.is_empty() {
+        return Err("No test sources match [test] sources".into());
+    }
+    let code = code_under_test(path, &build.sources, test.exclude.as_ref())?;
+    verbosity::progress(format!("Testing {}", config.metadata.name).blue().bold());
+    prepare(&config, path)?;
+
+    let binaries: Vec<(String, Vec<&PathBuf>)> = if framework.is_none() {
+        tests.iter().map(|t| (t.file_stem().unwrap().to_string_lossy().to_string(), vec![t])).collect()
+    } else {
+        vec![(format!("{}-tests", build.target), tests.iter().collect())]
+    };
+    let mut results = vec![];
+    for (name, test_sources) in &binaries {
+        let build_dir = opts.build_dir(path).join("test").join(name);
+        let mut program = Program {
+            name: name.clone(),
+            sources: code.iter().chain(test_sources.iter().copied()).cloned().collect(),
+            libs: test.libs.iter().flatten().cloned().collect(),
+            ldflags: String::new(),
+            pkg_dependencies: vec![],
+            pkg_fallbacks: BTreeMap::new(),
+        };
+        if let Some(framework) = &framework {
+            let on_system = framework.header.is_some_and(|h| platform::system_include_dirs().iter().any(|d| d.join(h).exists()));
+            if !on_system {
+                program.pkg_dependencies.push(framework.module.to_string());
+                program.pkg_fallbacks.insert(framework.module.to_string(), framework.fallback.clone());
+            }
+            // e.g. a source defining DOCTEST_CONFIG_IMPLEMENT_WITH_MAIN, or DOCTEST_CONFIG_IMPLEMENT with its own main
+            let own_main = test_sources.iter().any(|t| fs::read_to_string(t).is_ok_and(|s| s.contains("_CONFIG_IMPLEMENT")));
+            if let Some(main) = framework.main.filter(|_| !own_main) {
+                let file = build_dir.join("test_main.cpp");
+                fs::create_dir_all(&build_dir)?;
+                write_if_changed(&file, main)?;
+                program.sources.push(file);
+            }
+        }
+        let test_opts = BuildOptions {
+            build_dir: Some(build_dir.clone()),
+            ..opts.clone()
+        };
+        verbosity::progress(format!("Building test {}", name).cyan());
+        let binary = build_program(path, children, &config_path, &format, &program, &test_opts)?;
+        verbosity::progress(format!("Running {}", name).cyan());
+        let report = build_dir.join("report.xml");
+        let _ = fs::remove_file(&report);
+        let mut command = cross::invocation(&binary, opts.cross.as_ref());
+        if let Some(framework) = &framework {
+            command = format!("{} {}", command, (framework.report)(&report));
+        }
+        let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(path)
+        .status()?;
+        let cases = fs::read_to_string(&report).map(|xml| junit_cases(&xml, test.framework.as_deref() == Some("gtest"))).unwrap_or_default();
+        // Without a failed case to blame, e.g. after a crash, the binary itself failed
+        let blamed = cases.iter().any(|(_, outcome)| matches!(outcome, Outcome::Fail(_)));
+        if cases.is_empty() || (!status.success() && !blamed) {
+            let outcome = if status.success() { Outcome::Pass } else { Outcome::Fail(String::new()) };
+            results.push((name.clone(), outcome));
+        }
+        results.extend(cases);
+    }
+
+    let quiet_framework = framework.as_ref().is_some_and(|f| !f.console);
+    println!("{}", "Test results:".blue().bold());
+    for (name, outcome) in &results {
+        match outcome {
+            Outcome::Pass => println!("   {} {}", "PASS".green(), name),
+            Outcome::Skip => println!("   {} {}", "SKIP".yellow(), name),
+            Outcome::Fail(message) => {
+                println!("   {} {}", "FAIL".red().bold(), name);
+                // Frameworks printing nothing while writing the report leave explaining failures to us
+                if quiet_framework {
+                    for line in message.lines() {
+                        println!("        {}", line);
+                    }
+                }
+            }
+        }
+    }
+    let count = |pick: fn(&Outcome) -> bool| results.iter().filter(|(_, o)| pick(o)).count();
+    let failed = count(|o| matches!(o, Outcome::Fail(_)));
+    let skipped = count(|o| matches!(o, Outcome::Skip));
+    let mut summary = format!("{} passed, {} failed", results.len() - failed - skipped, failed);
+    if skipped > 0 {
+        summary.push_str(&format!(", {} skipped", skipped));
+    }
+    if failed > 0 {
+        eprintln!("{}", summary.red().bold());
+        return Err(format!("{} of {} tests failed", failed, results.len() - skipped).into());
+    }
+    println!("{}", summary.green().bold());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_junit_cases() {
+        let xml = r#"<testsuite name="Math">
+  <testcase name="adds" classname="Math" status="run"/>
+  <testcase name="divides" classname="Math">
+    <failure message="divides failed">
+      math.cpp:12
+      Expected: 2
+        Actual: 3
+    </failure>
+  </testcase>
+  <testcase name="later" classname="Math" status="notrun"/>
+  <testcase name="large" classname="Math"><skipped/></testcase>
+</testsuite>"#;
+        assert_eq!(junit_cases(xml, true), [
+            ("Math.adds".to_string(), Outcome::Pass),
+            ("Math.divides".to_string(), Outcome::Fail("math.cpp:12\nExpected: 2\n  Actual: 3".to_string())),
+            ("Math.later".to_string(), Outcome::Skip),
+            ("Math.large".to_string(), Outcome::Skip),
+        ]);
+        assert_eq!(junit_cases(xml, false)[0].0, "adds");
+        assert!(junit_cases("<testsuite/>", true).is_empty());
+    }
+
+    #[test]
+    fn reads_failure_messages() {
+        assert_eq!(failure_message(r#"<failure message="a &lt; b"/>"#), "a < b");
+        assert_eq!(failure_message(r#"<failure message="ignored"><![CDATA[x == y]]></failure>"#), "x == y");
+        assert_eq!(failure_message("<error>\n  boom &amp; bust\n</error>"), "boom & bust");
+        assert_eq!(failure_message("<failure></failure>"), "");
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::process::Command;
+use git2::Repository;
+use owo_colors::OwoColorize;
+use crate::{find_config_file, gitdep, mtime, parse_config, pkgdeps, target_path, vendor, BuildOptions, Dependency, HBuildConfig};
+
+struct Node {
+    name: String,
+    kind: &'static str,
+    version: String,
+    location: Option<String>,
+    status: Option<String>,
+    children: Vec<Node>,
+}
+
+fn pkg_config(args: &[&str]) -> Option<String> {
+    // Modules built from a source fallback are only visible through their prefix
+    let extra: Vec<String> = args.last().and_then(|name| pkgdeps::fallback_prefix(name))
+    .map(|prefix| pkgdeps::fallback_pc_dirs(&prefix).iter().map(|d| format!("--with-path={}", d.display())).collect())
+    .unwrap_or_default();
+    let out = Command::new("pkg-config").args(&extra).args(args).output().ok()?;
+    out.status.success().then(|| String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// A pkg-config module and, through `--print-requires`, the modules it requires.
+fn pkg_node(name: &str, seen: &mut Vec<String>) -> Node {
+    let version = pkg_config(&["--modversion", name]);
+    let fallback = pkgdeps::fallback_prefix(name);
+    let mut children = vec![];
+    if version.is_some() && !seen.iter().any(|s| s == name) {
+        seen.push(name.to_string());
+        for line in pkg_config(&["--print-requires", name]).unwrap_or_default().lines() {
+            if let Some(req) = line.split_whitespace().next() {
+                children.push(pkg_node(req, seen));
+            }
+        }
+        seen.pop();
+    }
+    Node {
+        name: name.to_string(),
+        kind: if fallback.is_some() { "pkg-config, source fallback" } else { "pkg-config" },
+        version: version.unwrap_or_else(|| "not found".to_string()),
+        location: pkg_config(&["--variable=pcfiledir", name]).or_else(|| fallback.map(|f| f.display().to_string())),
+        status: None,
+        children,
+    }
+}
+
+/// Whether a git dependency's hbuild target is newer than its checked out commit.
+fn build_status(config: &HBuildConfig, dir: &Path, commit_time: i64) -> Option<String> {
+    let build = config.build.as_ref()?;
+    let target = target_path(build, dir, &BuildOptions::default());
+    if !target.exists() {
+        return Some("not built".to_string());
+    }
+    let built = mtime(&target).duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    Some(if built >= commit_time { "built" } else { "stale, rebuilt on next make" }.to_string())
+}
+
+fn project_children(config: &HBuildConfig, depth: usize) -> Result<Vec<Node>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut nodes = vec![];
+    let deps: BTreeMap<&String, &Dependency> = config.specs.dependencies.iter().collect();
+    for (name, dep) in deps {
+        let Some(source) = dep.git() else {
+            let version = dep.version().unwrap_or_default().to_string();
+            nodes.push(Node { name: name.clone(), kind: "registry", version, location: None, status: None, children: vec![] });
+            continue;
+        };
+        let dir = gitdep::dep_dir(name)?;
+        let mut node = Node { name: name.clone(), kind: "git", version: "not fetched".to_string(), location: Some(source.to_string()), status: None, children: vec![] };
+        // A vendored copy isn't a repository; it is as new as the `hbuild vendor` that wrote it
+        let checkout = match vendor::commit(name) {
+            Some(commit) => Some((commit, mtime(&dir).duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0))),
+            None => match Repository::open(&dir) {
+                Ok(repo) => {
+                    let commit = repo.head()?.peel_to_commit()?;
+                    Some((commit.id().to_string(), commit.time().seconds()))
+                }
+                Err(_) => None,
+            },
+        };
+        if let Some((commit, time)) = checkout {
+            node.version = commit[..10].to_string();
+            node.location = Some(dir.display().to_string());
+            if let Some((config_path, format)) = find_config_file(&dir) {
+                let dep_config = parse_config(&config_path, &format)?;
+                node.status = build_status(&dep_config, &dir, time);
+                // Dependency cycles between git repos would otherwise recurse forever
+                if depth < 16 {
+                    node.children = project_children(&dep_config, depth + 1)?;
+                }
+            }
+        }
+        nodes.push(node);
+    }
+    let mut seen = vec![];
+    for entry in config.build.as_ref().and_then(|b| b.pkg_dependencies.as_ref()).into_iter().flatten() {
+        let req = pkgdeps::parse(entry)?;
+        let mut node = pkg_node(&req.name, &mut seen);
+        if let Some((op, version)) = &req.constraint {
+            node.version = format!("{} (requires {} {})", node.version, op, version);
+        }
+        nodes.push(node);
+    }
+    Ok(nodes)
+}
+
+fn label(node: &Node) -> String {
+    let mut label = format!("{} {} [{}]", node.name.bold(), node.version, node.kind);
+    if let Some(status) = &node.status {
+        label.push_str(&format!(" {}", status.cyan()));
+    }
+    if let Some(location) = &node.location {
+        label.push_str(&format!(" {}", location.dimmed()));
+    }
+    label
+}
+
+fn print_tree(nodes: &[Node], prefix: &str) {
+    for (i, node) in nodes.iter().enumerate() {
+        let last = i + 1 == nodes.len();
+        println!("{}{}{}", prefix, if last { "└── " } else { "├── " }, label(node));
+        print_tree(&node.children, &format!("{}{}", prefix, if last { "    " } else { "│   " }));
+    }
+}
+
+/// Every (name, version) in the tree with how often it occurs.
+fn collect<'a>(nodes: &'a [Node], out: &mut BTreeMap<&'a str, Vec<&'a str>>) {
+    for node in nodes {
+        out.entry(&node.name).or_default().push(&node.version);
+        collect(&node.children, out);
+    }
+}
+
+/// Paths from the root to every occurrence of `dep`, innermost first.
+fn paths_to<'a>(nodes: &'a [Node], dep: &str, stack: &mut Vec<&'a Node>, out: &mut Vec<Vec<&'a Node>>) {
+    for node in nodes {
+        stack.push(node);
+        if node.name == dep {
+            out.push(stack.iter().rev().copied().collect());
+        }
+        paths_to(&node.children, dep, stack, out);
+        stack.pop();
+    }
+}
+
+/// Prints the dependency tree: git dependencies with their checked out commit, cache directory and build
+/// state, registry dependencies, and pkg-config modules with their requirements. `duplicates` lists
+/// dependencies reached more than once; `invert` shows what pulls in one dependency.
+pub fn run(path: &Path, duplicates: bool, invert: Option<&str>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let nodes = project_children(&config, 0)?;
+    let root = format!("{} {}", config.metadata.name, config.metadata.version);
+
+    if duplicates {
+        let mut all = BTreeMap::new();
+        collect(&nodes, &mut all);
+        let dups: Vec<_> = all.iter().filter(|(_, versions)| versions.len() > 1).collect();
+        if dups.is_empty() {
+            println!("{}", "No duplicate dependencies".green());
+        }
+        for (name, versions) in dups {
+            let distinct: BTreeSet<&&str> = versions.iter().collect();
+            println!("{} x{}: {}", name.bold(), versions.len(), distinct.into_iter().copied().collect::<Vec<_>>().join(", "));
+        }
+        return Ok(());
+    }
+    if let Some(dep) = invert {
+        let mut paths = vec![];
+        paths_to(&nodes, dep, &mut vec![], &mut paths);
+        if paths.is_empty() {
+            return Err(format!("{} is not a dependency of {}", dep, config.metadata.name).into());
+        }
+        for chain in paths {
+            println!("{}", label(chain[0]));
+            for (i, node) in chain.iter().skip(1).enumerate() {
+                println!("{}└── {}", "    ".repeat(i), label(node));
+            }
+            println!("{}└── {}", "    ".repeat(chain.len() - 1), root.bold());
+        }
+        return Ok(());
+    }
+    println!("{}", root.bold());
+    print_tree(&nodes, "");
+    Ok(())
+}
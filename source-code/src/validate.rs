@@ -0,0 +1,527 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use hk_parser::{HkConfig, HkValue};
+use owo_colors::OwoColorize;
+use serde_json::{Map, Value};
+use crate::{find_config_file, parse_config, verbosity};
+
+/// What a config value has to be.
+#[derive(Clone, Copy)]
+enum Kind {
+    Str,
+    Bool,
+    Num,
+    List,
+    Map,
+    OneOf(&'static [&'static str]),
+    /// An array of tables, e.g. `[[build.source_set]]`.
+    Tables(&'static [Field]),
+}
+
+struct Field {
+    name: &'static str,
+    kind: Kind,
+    required: bool,
+}
+
+const fn req(name: &'static str, kind: Kind) -> Field {
+    Field { name, kind, required: true }
+}
+
+const fn opt(name: &'static str, kind: Kind) -> Field {
+    Field { name, kind, required: false }
+}
+
+enum Shape {
+    Fields(&'static [Field]),
+    /// A table per name, e.g. `[rules.<name>]`.
+    Named(&'static [Field]),
+    /// Any key, each holding the same kind, e.g. `[order]`.
+    Free(Kind),
+}
+
+/// A source set's keys; hk names it by its entry instead.
+const SOURCE_SET: &[Field] = &[req("sources", Kind::List), opt("cflags", Kind::Str), opt("defines", Kind::List), opt("include_dirs", Kind::List)];
+
+const LTO: Kind = Kind::OneOf(&["off", "full", "thin"]);
+
+/// A dependency given as a table: a git URL, at most one ref to check out, and how to build it when
+/// hbuild can't tell.
+const GIT_DEPENDENCY: &[Field] = &[
+    req("git", Kind::Str),
+    opt("branch", Kind::Str),
+    opt("tag", Kind::Str),
+    opt("rev", Kind::Str),
+    opt("build_command", Kind::Str),
+    opt("outputs", Kind::List),
+];
+
+/// A dependency on a local hbuild project, by its path.
+const PATH_DEPENDENCY: &[Field] = &[req("path", Kind::Str)];
+
+/// Every section hbuild reads, with the keys `from_hk` and the serde structs accept. `[specs]` is
+/// checked separately since hk spells it differently from the other formats.
+const SECTIONS: &[(&str, bool, Shape)] = &[
+    ("metadata", true, Shape::Fields(&[req("name", Kind::Str), req("version", Kind::Str), opt("authors", Kind::Str), opt("license", Kind::Str)])),
+    ("description", true, Shape::Fields(&[req("summary", Kind::Str), req("long", Kind::Str)])),
+    ("specs", true, Shape::Free(Kind::Str)),
+    ("runtime", false, Shape::Fields(&[
+        opt("priority", Kind::Str),
+        opt("auto-restart", Kind::Bool),
+        opt("args", Kind::List),
+        opt("user", Kind::Str),
+        opt("restart", Kind::OneOf(&["no", "always", "on-success", "on-failure", "on-abnormal", "on-abort", "on-watchdog"])),
+        opt("environment", Kind::Map),
+    ])),
+    ("build", false, Shape::Fields(&[
+        req("target", Kind::Str),
+        req("sources", Kind::List),
+        req("include_dirs", Kind::List),
+        req("compiler", Kind::Str),
+        req("standard", Kind::Str),
+        req("optimize", Kind::Str),
+        opt("cflags", Kind::Str),
+        opt("ldflags", Kind::Str),
+        opt("lib_dirs", Kind::List),
+        opt("libs", Kind::List),
+        opt("pkg_dependencies", Kind::List),
+        req("build_type", Kind::OneOf(&["executable", "shared", "static"])),
+        opt("native", Kind::Bool),
+        opt("link_map", Kind::Bool),
+        opt("prune_system_headers", Kind::Bool),
+        opt("system_header_prefixes", Kind::List),
+        opt("visibility", Kind::OneOf(&["default", "hidden", "protected", "internal"])),
+        opt("exported_symbols", Kind::List),
+        opt("version_script", Kind::Str),
+        opt("static_variant", Kind::Bool),
+        opt("sandbox", Kind::Bool),
+        opt("pin_toolchain", Kind::OneOf(&["warn", "fail", "select"])),
+        opt("compiler_launcher", Kind::Str),
+        opt("sanitizers", Kind::List),
+        opt("lto", LTO),
+        opt("strip_tool", Kind::Str),
+        opt("fortran_compiler", Kind::Str),
+        opt("cc", Kind::Str),
+        opt("cxx", Kind::Str),
+        opt("c_standard", Kind::Str),
+        opt("cxx_standard", Kind::Str),
+        opt("file_flags", Kind::Map),
+        opt("public_include_dirs", Kind::List),
+        opt("public_defines", Kind::List),
+        opt("public_libs", Kind::List),
+        opt("source_set", Kind::Tables(&[req("name", Kind::Str), req("sources", Kind::List), opt("cflags", Kind::Str), opt("defines", Kind::List), opt("include_dirs", Kind::List)])),
+    ])),
+    ("resources", false, Shape::Fields(&[req("files", Kind::List), opt("mode", Kind::OneOf(&["c", "objcopy"])), opt("prefix", Kind::Str)])),
+    ("gettext", false, Shape::Fields(&[opt("domain", Kind::Str), opt("po_dir", Kind::Str), opt("keywords", Kind::List), opt("sources", Kind::List)])),
+    ("qt", false, Shape::Fields(&[
+        opt("version", Kind::OneOf(&["5", "6"])),
+        opt("headers", Kind::List),
+        opt("ui", Kind::List),
+        opt("qrc", Kind::List),
+        opt("moc", Kind::Str),
+        opt("uic", Kind::Str),
+        opt("rcc", Kind::Str),
+    ])),
+    ("glib", false, Shape::Fields(&[
+        opt("resources", Kind::List),
+        opt("schemas", Kind::List),
+        opt("dbus", Kind::List),
+        opt("dbus_interface_prefix", Kind::Str),
+        opt("dbus_namespace", Kind::Str),
+    ])),
+    ("protobuf", false, Shape::Fields(&[
+        req("files", Kind::List),
+        opt("import_dirs", Kind::List),
+        opt("languages", Kind::List),
+        opt("grpc", Kind::Bool),
+        opt("protoc", Kind::Str),
+        opt("go_out", Kind::Str),
+    ])),
+    ("swig", false, Shape::Fields(&[req("interfaces", Kind::List), req("languages", Kind::List), opt("cplusplus", Kind::Bool), opt("swig", Kind::Str)])),
+    ("shaders", false, Shape::Fields(&[
+        req("files", Kind::List),
+        opt("compiler", Kind::Str),
+        opt("flags", Kind::Str),
+        opt("include_dirs", Kind::List),
+        opt("embed", Kind::Bool),
+        opt("install_dir", Kind::Str),
+    ])),
+    ("rules", false, Shape::Named(&[req("command", Kind::Str), req("inputs", Kind::List), req("outputs", Kind::List)])),
+    ("codegen", false, Shape::Named(&[req("command", Kind::Str), req("inputs", Kind::List), req("outputs", Kind::List)])),
+    ("matrix", false, Shape::Fields(&[opt("compilers", Kind::List), opt("standards", Kind::List), opt("optimize", Kind::List)])),
+    ("pgo", false, Shape::Fields(&[opt("train", Kind::Str)])),
+    ("bolt", false, Shape::Fields(&[req("record", Kind::Str), opt("lbr", Kind::Bool), opt("flags", Kind::Str)])),
+    ("pkg_fallbacks", false, Shape::Named(&[opt("git", Kind::Str), opt("rev", Kind::Str), opt("url", Kind::Str), opt("build", Kind::Str)])),
+    ("android", false, Shape::Fields(&[opt("ndk", Kind::Str), opt("api", Kind::Num), opt("abis", Kind::List), opt("pkg_config_libdir", Kind::Str)])),
+    ("embedded", false, Shape::Fields(&[
+        req("prefix", Kind::Str),
+        opt("cpu", Kind::Str),
+        opt("flags", Kind::Str),
+        opt("linker_script", Kind::Str),
+        opt("specs", Kind::List),
+        opt("outputs", Kind::List),
+    ])),
+    ("qemu", false, Shape::Fields(&[req("binary", Kind::Str), opt("sysroot", Kind::Str)])),
+    ("memory", false, Shape::Fields(&[opt("job_mb", Kind::Num), opt("min_free_mb", Kind::Num), opt("weights", Kind::Map)])),
+    ("order", false, Shape::Free(Kind::List)),
+    ("test", false, Shape::Fields(&[
+        req("sources", Kind::List),
+        opt("libs", Kind::List),
+        opt("framework", Kind::OneOf(&["none", "gtest", "catch2", "doctest"])),
+        opt("exclude", Kind::List),
+    ])),
+    ("bench", false, Shape::Fields(&[
+        req("sources", Kind::List),
+        opt("libs", Kind::List),
+        opt("framework", Kind::OneOf(&["none", "benchmark"])),
+        opt("args", Kind::Str),
+        opt("iterations", Kind::Num),
+        opt("exclude", Kind::List),
+    ])),
+    ("profile", false, Shape::Named(&[opt("optimize", Kind::Str), opt("cflags", Kind::Str), opt("defines", Kind::List), opt("strip", Kind::Bool), opt("lto", LTO)])),
+    ("toolchain", false, Shape::Named(&[
+        opt("triple", Kind::Str),
+        opt("prefix", Kind::Str),
+        opt("compiler", Kind::Str),
+        opt("ar", Kind::Str),
+        opt("sysroot", Kind::Str),
+        opt("cflags", Kind::Str),
+        opt("ldflags", Kind::Str),
+        opt("pkg_config_path", Kind::List),
+        opt("runner", Kind::Str),
+        opt("zig", Kind::Bool),
+    ])),
+    ("hooks", false, Shape::Fields(&[opt("pre_build", Kind::List), opt("post_build", Kind::List), opt("pre_install", Kind::List), opt("post_install", Kind::List)])),
+    ("env", false, Shape::Free(Kind::Str)),
+    // hk's spelling of dependency tables, which its `[specs]` can't nest
+    ("dependencies", false, Shape::Named(GIT_DEPENDENCY)),
+    // hk's spelling of [build.file_flags], whose path keys it can't write
+    ("file_flags", false, Shape::Named(&[req("files", Kind::List), req("flags", Kind::Str), opt("replace", Kind::Bool)])),
+    // hk's spelling of [[build.source_set]], which it has no arrays of tables for
+    ("source_set", false, Shape::Named(SOURCE_SET)),
+    ("public_headers", false, Shape::Fields(&[req("files", Kind::List), opt("subdir", Kind::Str)])),
+    ("cache", false, Shape::Fields(&[
+        opt("enabled", Kind::Bool),
+        opt("dir", Kind::Str),
+        opt("max_size_mb", Kind::Num),
+        opt("remote", Kind::Str),
+        opt("read_only", Kind::Bool),
+        opt("token_env", Kind::Str),
+        opt("s3_region", Kind::Str),
+    ])),
+    ("cuda", false, Shape::Fields(&[
+        opt("nvcc", Kind::Str),
+        opt("arch", Kind::List),
+        opt("flags", Kind::Str),
+    ])),
+    ("nim", false, Shape::Fields(&[
+        opt("main", Kind::Str),
+        opt("flags", Kind::Str),
+    ])),
+];
+
+/// Languages `make` builds, as hk `[specs]` keys (which cannot contain '+') and as `languages` entries.
+const HK_LANGUAGES: &[&str] = &["c", "cpp", "rust", "go", "python", "odin", "crystal", "vala", "zig", "d", "nim"];
+const LANGUAGES: &[&str] = &["c", "c++", "rust", "go", "python", "odin", "crystal", "vala", "zig", "d", "nim"];
+
+/// A problem found in a config file. Errors stop the build; warnings are keys hbuild ignores.
+pub struct Issue {
+    pub error: bool,
+    /// The section, e.g. `build` or `profile.release`, and the key if the issue is about one.
+    table: Vec<String>,
+    key: Option<String>,
+    line: Option<usize>,
+    message: String,
+}
+
+/// Edit distance, for did-you-mean suggestions.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (prev + usize::from(ca != *cb)).min(row[j] + 1).min(row[j + 1] + 1);
+            prev = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+/// The one of `candidates` closest to `word`, if any is close enough to be a misspelling.
+fn closest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    candidates.into_iter().map(|c| (distance(word, c), c)).filter(|(d, c)| *d <= (c.len() / 3).max(2)).min().map(|(_, c)| c)
+}
+
+/// `; did you mean 'x'?` for the closest of `candidates` to `word`.
+fn suggest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+    closest(word, candidates).map(|c| format!("; did you mean '{}'?", c)).unwrap_or_default()
+}
+
+/// hk values as JSON so every format is checked the same way.
+fn hk_to_json(value: &HkValue) -> Value {
+    match value {
+        HkValue::String(s) => Value::String(s.clone()),
+        HkValue::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => Value::from(*n as i64),
+        HkValue::Number(n) => serde_json::Number::from_f64(*n).map_or(Value::Null, Value::Number),
+        HkValue::Bool(b) => Value::Bool(*b),
+        HkValue::Array(a) => Value::Array(a.iter().map(hk_to_json).collect()),
+        HkValue::Map(m) => Value::Object(m.iter().map(|(k, v)| (k.clone(), hk_to_json(v))).collect()),
+    }
+}
+
+pub fn hk_value(hk: &HkConfig) -> Value {
+    Value::Object(hk.iter().map(|(k, v)| (k.clone(), hk_to_json(v))).collect())
+}
+
+struct Checker {
+    /// hk turns numbers and booleans into strings where a string is expected.
+    lenient: bool,
+    issues: Vec<Issue>,
+}
+
+impl Checker {
+    fn issue(&mut self, error: bool, table: &[&str], key: Option<&str>, message: String) {
+        let table = table.iter().map(|s| s.to_string()).collect();
+        self.issues.push(Issue { error, table, key: key.map(String::from), line: None, message });
+    }
+
+    fn error(&mut self, table: &[&str], key: Option<&str>, message: String) {
+        self.issue(true, table, key, message);
+    }
+
+    fn warning(&mut self, table: &[&str], key: Option<&str>, message: String) {
+        self.issue(false, table, key, message);
+    }
+
+    fn value(&mut self, table: &[&str], key: &str, kind: Kind, value: &Value) {
+        let scalar = |v: &Value| match v {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(_) | Value::Bool(_) if self.lenient => Some(v.to_string()),
+            _ => None,
+        };
+        let expected = match kind {
+            Kind::Str if scalar(value).is_none() => "a string",
+            Kind::Bool if !value.is_boolean() => "true or false",
+            Kind::Num if !value.is_u64() => "a whole number",
+            Kind::List if !value.as_array().is_some_and(|a| a.iter().all(|v| scalar(v).is_some())) => "a list of strings like [\"a\", \"b\"]",
+            Kind::Map if !value.is_object() => "a table of keys",
+            Kind::OneOf(choices) => match scalar(value) {
+                Some(s) if choices.contains(&s.as_str()) => return,
+                Some(s) => {
+                    let message = format!("invalid {} {}; expected one of {}{}", key, value, choices.join(", "), suggest(&s, choices.iter().copied()));
+                    return self.error(table, Some(key), message);
+                }
+                None => "a string",
+            },
+            Kind::Tables(fields) => match value.as_array().filter(|a| a.iter().all(Value::is_object)) {
+                Some(tables) => {
+                    let path: Vec<&str> = table.iter().copied().chain([key]).collect();
+                    for entry in tables.iter().filter_map(Value::as_object) {
+                        self.fields(&path, fields, entry);
+                    }
+                    return;
+                }
+                None => "a list of tables",
+            },
+            _ => return,
+        };
+        self.error(table, Some(key), format!("invalid {} {}; expected {}", key, value, expected));
+    }
+
+    fn fields(&mut self, table_path: &[&str], fields: &[Field], table: &Map<String, Value>) {
+        for field in fields {
+            match table.get(field.name) {
+                Some(value) => self.value(table_path, field.name, field.kind, value),
+                None if field.required => {
+                    let unknown = table.keys().map(String::as_str).filter(|k| !fields.iter().any(|f| f.name == *k));
+                    let typo = closest(field.name, unknown).map(|k| format!(" (is '{}' a typo?)", k)).unwrap_or_default();
+                    self.error(table_path, None, format!("missing key '{}'{}", field.name, typo));
+                }
+                None => {}
+            }
+        }
+        for key in table.keys().filter(|k| !fields.iter().any(|f| f.name == k.as_str())) {
+            let close = suggest(key, fields.iter().map(|f| f.name));
+            self.warning(table_path, Some(key), format!("unknown key '{}'{}", key, close));
+        }
+    }
+
+    /// A table of [`PATH_DEPENDENCY`] fields, or of [`GIT_DEPENDENCY`] ones naming one ref at most.
+    fn dependency_table(&mut self, table_path: &[&str], table: &Map<String, Value>) {
+        if table.contains_key("path") {
+            return self.fields(table_path, PATH_DEPENDENCY, table);
+        }
+        self.fields(table_path, GIT_DEPENDENCY, table);
+        let refs: Vec<&str> = ["branch", "tag", "rev"].into_iter().filter(|r| table.contains_key(*r)).collect();
+        if refs.len() > 1 {
+            self.error(table_path, Some(refs[1]), format!("give only one of branch, tag and rev (found {})", refs.join(" and ")));
+        }
+    }
+
+    fn dependencies(&mut self, value: &Value) {
+        let Some(dependencies) = value.as_object() else {
+            return self.value(&["specs"], "dependencies", Kind::Map, value);
+        };
+        for (name, spec) in dependencies {
+            match spec {
+                Value::String(_) => {}
+                Value::Number(_) if self.lenient => {}
+                Value::Object(table) => self.dependency_table(&["specs", "dependencies", name], table),
+                _ => self.error(&["specs", "dependencies"], Some(name), format!("invalid {} {}; expected a version, a git URL or a table like {{ git = \"...\", tag = \"v1.2\" }} or {{ path = \"../lib\" }}", name, spec)),
+            }
+        }
+    }
+
+    fn specs(&mut self, specs: &Map<String, Value>) {
+        if self.lenient {
+            for (key, value) in specs {
+                if key == "dependencies" {
+                    self.dependencies(value);
+                } else if !HK_LANGUAGES.contains(&key.as_str()) {
+                    self.warning(&["specs"], Some(key), format!("unknown language '{}'{}", key, suggest(key, HK_LANGUAGES.iter().copied())));
+                }
+            }
+            return;
+        }
+        self.fields(&["specs"], &[req("languages", Kind::List), req("dependencies", Kind::Map)], specs);
+        if let Some(dependencies) = specs.get("dependencies") {
+            self.dependencies(dependencies);
+        }
+        for language in specs.get("languages").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+            if !LANGUAGES.contains(&language) {
+                self.warning(&["specs"], Some("languages"), format!("unknown language '{}'{}", language, suggest(language, LANGUAGES.iter().copied())));
+            }
+        }
+    }
+
+    fn config(&mut self, config: &Value) {
+        let Some(config) = config.as_object() else {
+            return self.error(&[], None, "expected sections at the top level".to_string());
+        };
+        for (name, required, shape) in SECTIONS {
+            let Some(section) = config.get(*name) else {
+                if *required {
+                    self.error(&[], None, format!("missing section [{}]", name));
+                }
+                continue;
+            };
+            let Some(table) = section.as_object() else {
+                self.error(&[name], None, "must be a section of keys".to_string());
+                continue;
+            };
+            match shape {
+                _ if *name == "specs" => self.specs(table),
+                _ if *name == "dependencies" && !self.lenient => {
+                    self.warning(&[name], None, "ignored; dependency tables go in [specs.dependencies]".to_string());
+                }
+                _ if *name == "file_flags" && !self.lenient => {
+                    self.warning(&[name], None, "ignored; per-file flags go in [build.file_flags]".to_string());
+                }
+                _ if *name == "source_set" && !self.lenient => {
+                    self.warning(&[name], None, "ignored; source sets go in [[build.source_set]]".to_string());
+                }
+                _ if *name == "dependencies" => {
+                    for (entry, value) in table {
+                        match value.as_object() {
+                            Some(entry_table) => self.dependency_table(&[name, entry.as_str()], entry_table),
+                            None => self.error(&[name], Some(entry), "must be a table of keys".to_string()),
+                        }
+                    }
+                }
+                Shape::Fields(fields) => self.fields(&[name], fields, table),
+                Shape::Named(fields) => {
+                    for (entry, value) in table {
+                        match value.as_object() {
+                            Some(entry_table) => self.fields(&[name, entry.as_str()], fields, entry_table),
+                            None => self.error(&[name], Some(entry), "must be a table of keys".to_string()),
+                        }
+                    }
+                }
+                Shape::Free(kind) => {
+                    for (key, value) in table {
+                        self.value(&[name], key, *kind, value);
+                    }
+                }
+            }
+        }
+        for name in config.keys().filter(|k| !SECTIONS.iter().any(|(s, _, _)| s == k)) {
+            let close = suggest(name, SECTIONS.iter().map(|(s, _, _)| *s));
+            self.warning(&[name.as_str()], None, format!("unknown section{}", close));
+        }
+    }
+}
+
+/// Line (1-based) of the section, table and key `path` names, found by scanning the file: each name
+/// is looked for below the previous one, at the start of a line after hk arrows, quotes and
+/// brackets, or as a dotted or quoted table name.
+fn locate(content: &str, path: &[String]) -> Option<usize> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut from = 0;
+    let mut found = None;
+    for name in path {
+        let starts_with_name = |line: &str| {
+            let line = line.trim_start().trim_start_matches(['-', '>', '[', '"', ' ']);
+            line.strip_prefix(name.as_str()).is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '-'))
+        };
+        let matches = |line: &str| starts_with_name(line) || line.contains(&format!("\"{}\"", name)) || line.contains(&format!(".{}]", name));
+        let index = (from..lines.len()).find(|&i| matches(lines[i]))?;
+        found = Some(index + 1);
+        // A table name can be on the section's own line, as in `[profile.release]`
+        from = index;
+    }
+    found
+}
+
+/// Checks a config (parsed into JSON values, hk with `lenient` scalars) against the sections and
+/// keys hbuild knows, with line numbers looked up in `content`.
+pub fn check(config: &Value, content: &str, lenient: bool) -> Vec<Issue> {
+    let mut checker = Checker { lenient, issues: vec![] };
+    checker.config(config);
+    for issue in &mut checker.issues {
+        issue.line = locate(content, &issue.table.iter().chain(&issue.key).cloned().collect::<Vec<_>>());
+    }
+    checker.issues.sort_by_key(|i| i.line);
+    checker.issues
+}
+
+/// Configs whose issues were already printed; `parse_config` runs several times per command.
+static REPORTED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Prints `issues` as `file:line: level: [section] message` and fails if any is an error.
+pub fn report(config_path: &Path, issues: &[Issue]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let errors = issues.iter().filter(|i| i.error).count();
+    let mut reported = REPORTED.lock().unwrap();
+    if !reported.iter().any(|p| p == config_path) {
+        reported.push(config_path.to_path_buf());
+        for issue in issues {
+            let location = match issue.line {
+                Some(line) => format!("{}:{}", config_path.display(), line),
+                None => config_path.display().to_string(),
+            };
+            let section = if issue.table.is_empty() { String::new() } else { format!("[{}] ", issue.table.join(".")) };
+            if issue.error {
+                eprintln!("{}", format!("{}: error: {}{}", location, section, issue.message).red());
+            } else {
+                eprintln!("{}", format!("{}: warning: {}{}", location, section, issue.message).yellow());
+            }
+        }
+    }
+    if errors > 0 {
+        return Err(format!("{} has {} error{}", config_path.display(), errors, if errors == 1 { "" } else { "s" }).into());
+    }
+    Ok(())
+}
+
+/// `hbuild check`: parses and validates the config without building.
+pub fn run(path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some((config_path, format)) = find_config_file(path) else {
+        eprintln!("{}", "No config file found".red().bold());
+        return Ok(());
+    };
+    parse_config(&config_path, &format)?;
+    verbosity::status(&format!("{} is valid", config_path.display()));
+    Ok(())
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use git2::{ObjectType, Oid, Repository, TreeWalkMode, TreeWalkResult};
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+use crate::{find_config_file, gitdep, lock, parse_config, verbosity};
+
+/// With `--offline`, nothing is cloned, fetched or downloaded.
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// `vendor/` of the project hbuild was run on, which its dependencies' builds share.
+static VENDOR: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_offline() {
+    OFFLINE.store(true, Ordering::Relaxed);
+}
+
+pub fn offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// The error for `what` needing the network under `--offline`.
+pub fn offline_error(what: &str) -> Box<dyn std::error::Error + Send + Sync> {
+    format!("{} needs the network, which --offline rules out; run `hbuild vendor` while online", what).into()
+}
+
+/// Resolves git dependencies from `<project>/vendor` for the rest of the run.
+pub fn use_project(project: &Path) {
+    let _ = VENDOR.set(project.join("vendor"));
+}
+
+/// `vendor/vendor.toml`: the source and commit each vendored dependency was copied at.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    git: BTreeMap<String, lock::GitLock>,
+}
+
+fn read_manifest(vendor: &Path) -> Result<Manifest, Box<dyn std::error::Error + Send + Sync>> {
+    match fs::read_to_string(vendor.join("vendor.toml")) {
+        Ok(text) => Ok(toml::from_str(&text)?),
+        Err(_) => Ok(Manifest::default()),
+    }
+}
+
+/// `vendor/<name>` when `name` was vendored into the project.
+pub fn dir(name: &str) -> Option<PathBuf> {
+    let vendor = VENDOR.get()?;
+    let listed = read_manifest(vendor).ok()?.git.contains_key(name);
+    Some(vendor.join(name)).filter(|d| listed && d.is_dir())
+}
+
+/// The commit `name` was vendored at.
+pub fn commit(name: &str) -> Option<String> {
+    dir(name)?;
+    Some(read_manifest(VENDOR.get()?).ok()?.git.remove(name)?.commit)
+}
+
+/// The vendored copy of git dependency `name`, warning when it was copied from another source or
+/// at another commit than `lockfile` pins.
+pub fn vendored(name: &str, source: &gitdep::Source, lockfile: &lock::Lockfile) -> Result<Option<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(dir) = dir(name) else {
+        return Ok(None);
+    };
+    let entry = &read_manifest(VENDOR.get().unwrap())?.git[name];
+    let locked = lockfile.git.get(name).map(|l| l.commit.as_str());
+    if entry.url != source.url || entry.reference != source.reference.lock_key() || locked.is_some_and(|c| c != entry.commit) {
+        eprintln!("{}", format!("vendor/{} holds {} at {}, not what the config and hbuild.lock ask for; run `hbuild vendor`", name, entry.url, lock::short(&entry.commit)).yellow());
+    }
+    Ok(Some(dir))
+}
+
+/// Writes the files of `commit` in `repo` to `dest`, keeping executable bits and symlinks.
+fn export(repo: &Repository, commit: &str, dest: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let tree = repo.find_commit(Oid::from_str(commit)?)?.tree()?;
+    let mut result: Result<(), Box<dyn std::error::Error + Send + Sync>> = Ok(());
+    tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        let path = dest.join(dir).join(entry.name().unwrap_or_default());
+        let written = (|| -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            match entry.kind() {
+                Some(ObjectType::Tree) => fs::create_dir_all(&path)?,
+                Some(ObjectType::Blob) => {
+                    let blob = repo.find_blob(entry.id())?;
+                    if entry.filemode() == 0o120000 {
+                        #[cfg(unix)]
+                        std::os::unix::fs::symlink(String::from_utf8_lossy(blob.content()).as_ref(), &path)?;
+                    } else {
+                        fs::write(&path, blob.content())?;
+                        #[cfg(unix)]
+                        if entry.filemode() == 0o100755 {
+                            use std::os::unix::fs::PermissionsExt;
+                            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+                        }
+                    }
+                }
+                _ => eprintln!("{}", format!("Skipping submodule {}", path.display()).yellow()),
+            }
+            Ok(())
+        })();
+        match written {
+            Ok(()) => TreeWalkResult::Ok,
+            Err(e) => {
+                result = Err(e);
+                TreeWalkResult::Abort
+            }
+        }
+    })?;
+    result
+}
+
+/// Copies the vendored `name` anew at the commit `entry` locks, from its cache clone, as `hbuild
+/// vendor` would, so builds use what was just locked. Nothing when `name` isn't vendored.
+pub fn refresh(name: &str, entry: &lock::GitLock) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(dest) = dir(name) else {
+        return Ok(());
+    };
+    let vendor = VENDOR.get().unwrap();
+    let mut manifest = read_manifest(vendor)?;
+    fs::remove_dir_all(&dest)?;
+    fs::create_dir_all(&dest)?;
+    export(&Repository::open(gitdep::cache_dir()?.join(name))?, &entry.commit, &dest)?;
+    manifest.git.insert(name.to_string(), entry.clone());
+    fs::write(vendor.join("vendor.toml"), toml::to_string(&manifest)?)?;
+    verbosity::progress(format!("Re-vendored {}", dest.display()).cyan());
+    Ok(())
+}
+
+/// Copies every git dependency, and theirs, at its locked commit into `vendor/<name>`, so builds
+/// use those copies and `--offline` builds need no network. Locks dependencies that weren't yet.
+pub fn run(path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let vendor = path.join("vendor");
+    let previous = read_manifest(&vendor)?;
+    let mut lockfile = lock::read(path)?;
+    let mut manifest = Manifest::default();
+    verbosity::progress("Vendoring git dependencies".blue().bold());
+
+    // Dependencies of dependencies are pinned by their own hbuild.lock
+    let mut queue: Vec<(String, gitdep::Source, Option<PathBuf>)> = config.specs.dependencies.iter()
+    .filter_map(|(name, dep)| Some((name.clone(), dep.git()?, None)))
+    .collect();
+    while let Some((name, source, parent)) = queue.pop() {
+        if let Some(existing) = manifest.git.get(&name) {
+            if existing.url != source.url {
+                return Err(format!("Two dependencies are named {}: {} and {}", name, existing.url, source.url).into());
+            }
+            continue;
+        }
+        let commit = match &parent {
+            None => gitdep::sync(&name, &source, &mut lockfile, false)?,
+            Some(parent) => gitdep::sync(&name, &source, &mut lock::read(parent)?, false)?,
+        };
+        let dest = vendor.join(&name);
+        if dest.exists() {
+            fs::remove_dir_all(&dest)?;
+        }
+        fs::create_dir_all(&dest)?;
+        export(&Repository::open(gitdep::cache_dir()?.join(&name))?, &commit, &dest)?;
+        verbosity::progress(format!("   {} {} at {}", name.cyan(), source, lock::short(&commit)));
+        if let Some((dep_config_path, dep_format)) = find_config_file(&dest) {
+            let dep_config = parse_config(&dep_config_path, &dep_format)?;
+            queue.extend(dep_config.specs.dependencies.iter().filter_map(|(n, d)| Some((n.clone(), d.git()?, Some(dest.clone())))));
+        }
+        manifest.git.insert(name, lock::GitLock { url: source.url.clone(), reference: source.reference.lock_key(), commit });
+    }
+
+    // Only what an earlier run vendored is removed; anything else in vendor/ isn't ours
+    for name in previous.git.keys().filter(|n| !manifest.git.contains_key(*n)) {
+        let stale = vendor.join(name);
+        if stale.exists() {
+            fs::remove_dir_all(&stale)?;
+            verbosity::progress(format!("Removed {}", stale.display()).cyan());
+        }
+    }
+    fs::create_dir_all(&vendor)?;
+    fs::write(vendor.join("vendor.toml"), toml::to_string(&manifest)?)?;
+    lock::write(path, &lockfile)?;
+    verbosity::status(&format!("Vendored {} dependenc{} into {}", manifest.git.len(), if manifest.git.len() == 1 { "y" } else { "ies" }, vendor.display()));
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use std::fmt::Display;
+use std::process::Command;
+use std::sync::atomic::{AtomicI8, Ordering};
+use owo_colors::OwoColorize;
+use crate::{args, messages};
+
+/// -1 with `-q`, 0 by default, 1 with `-v`, 2 with `-vv`.
+static LEVEL: AtomicI8 = AtomicI8::new(0);
+
+pub fn set(level: i8) {
+    LEVEL.store(level, Ordering::Relaxed);
+}
+
+pub fn level() -> i8 {
+    LEVEL.load(Ordering::Relaxed)
+}
+
+/// Prints one of hbuild's own lines: to stdout, or to stderr when stdout carries JSON events.
+fn print(line: impl Display) {
+    if messages::json() {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Prints the outcome of a command, which `-q` keeps.
+pub fn status(message: &str) {
+    print(message.green().bold());
+}
+
+/// Prints what hbuild is doing, which `-q` drops. Reports, such as those of `size` or `why`, and
+/// the output of the programs it runs are printed as they are.
+pub fn progress(line: impl Display) {
+    if level() >= 0 {
+        print(line);
+    }
+}
+
+/// Prints `command` as it would be typed in a shell, with `-v`.
+pub fn command(command: &Command) {
+    if level() < 1 {
+        return;
+    }
+    let mut line = args::quote(command.get_program());
+    for arg in command.get_args() {
+        line.push(' ');
+        line.push_str(&args::quote(arg));
+    }
+    print(line.dimmed());
+}
+
+/// Prints dependency scanning and other bookkeeping, with `-vv`.
+pub fn debug(message: &str) {
+    if level() >= 2 {
+        print(message.dimmed());
+    }
+}
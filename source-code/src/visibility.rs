@@ -0,0 +1,76 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::{args, platform, write_if_changed, Build};
+
+/// `-fvisibility=<visibility>` when `visibility` is set.
+pub fn compile_flags(build: &Build) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    match build.visibility.as_deref() {
+        None => Ok(None),
+        Some(v @ ("default" | "hidden" | "protected" | "internal")) => Ok(Some(format!("-fvisibility={}", v))),
+        Some(v) => Err(format!("Unknown visibility '{}' (expected default, hidden, protected or internal)", v).into()),
+    }
+}
+
+/// Anonymous version node exporting `symbols` (glob patterns allowed) and hiding everything else.
+/// ld64 takes a plain list of mangled (underscore-prefixed) names instead.
+fn exports_script(symbols: &[String]) -> String {
+    if platform::is_macos() {
+        return symbols.iter().map(|sym| format!("_{}\n", sym)).collect();
+    }
+    let mut script = String::from("{\n  global:\n");
+    for sym in symbols {
+        script.push_str(&format!("    {};\n", sym));
+    }
+    script.push_str("  local:\n    *;\n};\n");
+    script
+}
+
+/// Linker flag applying the export list from [`version_script`].
+pub fn link_flag(script: &Path) -> OsString {
+    if platform::is_macos() {
+        args::with_path("-Wl,-exported_symbols_list,", script)
+    } else {
+        args::with_path("-Wl,--version-script=", script)
+    }
+}
+
+/// The version script to link a shared library with: `version_script` as given, or one generated in the
+/// build dir from `exported_symbols`. Exported symbols must not be hidden at compile time, so with
+/// `visibility = "hidden"` they still need `__attribute__((visibility("default")))` in the source.
+pub fn version_script(build: &Build, path: &Path, build_dir: &Path) -> Result<Option<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    if build.build_type != "shared" {
+        if build.version_script.is_some() || build.exported_symbols.is_some() {
+            return Err("version_script and exported_symbols only apply to shared libraries".into());
+        }
+        return Ok(None);
+    }
+    match (&build.version_script, &build.exported_symbols) {
+        (Some(_), Some(_)) => Err("Use either version_script or exported_symbols, not both".into()),
+        (Some(_), None) if platform::is_macos() => Err("Version scripts are not supported by the macOS linker; use exported_symbols".into()),
+        (Some(script), None) => {
+            let script = path.join(script);
+            if !script.exists() {
+                return Err(format!("Version script {} not found", script.display()).into());
+            }
+            Ok(Some(script))
+        }
+        (None, Some(symbols)) => {
+            fs::create_dir_all(build_dir)?;
+            let script = build_dir.join(format!("{}.exports.map", build.target));
+            write_if_changed(&script, &exports_script(symbols))?;
+            Ok(Some(script))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/// The script `version_script` uses, without checking or writing it.
+pub fn script_path(build: &Build, path: &Path, build_dir: &Path) -> Option<PathBuf> {
+    match (&build.version_script, &build.exported_symbols) {
+        _ if build.build_type != "shared" => None,
+        (Some(script), _) => Some(path.join(script)),
+        (None, Some(_)) => Some(build_dir.join(format!("{}.exports.map", build.target))),
+        (None, None) => None,
+    }
+}
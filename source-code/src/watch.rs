@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+use std::time::SystemTime;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use nix::errno::Errno;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use owo_colors::OwoColorize;
+use crate::{find_config_file, ignore, make_with, parse_config, platform, rules, target_path, verbosity, BuildOptions};
+
+/// Quiet period after the last change before rebuilding, so a save touching several files builds once.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Build output and tool state never trigger a rebuild.
+const SKIPPED_DIRS: &[&str] = &["build", "target", ".git", ".hbuild"];
+
+/// Inputs of the other languages and generators besides C/C++ sources and headers.
+const OTHER_INPUTS: &[&str] = &["rs", "go", "proto", "i", "ui", "qrc", "po", "glsl", "hlsl", "vert", "frag", "comp", "geom", "tesc", "tese"];
+
+/// True for the files a build reads: sources, headers, generator inputs and config files.
+/// Anything else, such as files the running program writes, doesn't trigger a rebuild.
+fn is_input(file: &Path) -> bool {
+    let name = file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    if name.starts_with("hbuil") && name.ends_with(".config") {
+        return true;
+    }
+    file.extension().and_then(|e| e.to_str()).is_some_and(|ext| {
+        rules::SOURCE_EXTENSIONS.contains(&ext) || rules::HEADER_EXTENSIONS.contains(&ext) || OTHER_INPUTS.contains(&ext)
+    })
+}
+
+/// Watches the project through inotify on Linux, and elsewhere by comparing modification times.
+struct Watcher {
+    root: PathBuf,
+    /// A `--config` file with a name of its own
+    config: Option<PathBuf>,
+    ignore: ignore::Ignore,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    inotify: Inotify,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    mtimes: HashMap<PathBuf, SystemTime>,
+}
+
+impl Watcher {
+    fn skipped(&self, file: &Path) -> bool {
+        let name = file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let top_level = file.parent() == Some(self.root.as_path());
+        (top_level && SKIPPED_DIRS.contains(&name.as_str()))
+        // Editor swap and backup files
+        || name.starts_with(".#") || name.ends_with(".swp") || name.ends_with('~')
+        || self.ignore.is_ignored(&self.root, file)
+    }
+
+    fn relevant(&self, file: &Path) -> bool {
+        is_input(file) || self.config.as_deref() == Some(file)
+    }
+
+    /// Blocks until a relevant file changes and no further changes arrive for [`DEBOUNCE`].
+    fn wait(&mut self) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut changed = vec![];
+        loop {
+            changed.extend(self.poll()?);
+            if !changed.is_empty() {
+                break;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        loop {
+            thread::sleep(DEBOUNCE);
+            let more = self.poll()?;
+            if more.is_empty() {
+                break;
+            }
+            changed.extend(more);
+        }
+        changed.sort();
+        changed.dedup();
+        Ok(changed)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Watcher {
+    fn new(root: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut watcher = Watcher {
+            root: root.to_path_buf(),
+            config: find_config_file(root).and_then(|(c, _)| c.canonicalize().ok()),
+            ignore: ignore::Ignore::load(root),
+            inotify: Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK)?,
+            dirs: HashMap::new(),
+        };
+        watcher.add_tree(root)?;
+        Ok(watcher)
+    }
+
+    /// Watches `dir` and every directory below it that isn't skipped or ignored.
+    fn add_tree(&mut self, dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let flags = AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_CREATE | AddWatchFlags::IN_DELETE
+        | AddWatchFlags::IN_MOVED_FROM | AddWatchFlags::IN_MOVED_TO;
+        let wd = self.inotify.add_watch(dir, flags)?;
+        self.dirs.insert(wd, dir.to_path_buf());
+        for entry in fs::read_dir(dir)?.flatten() {
+            let sub = entry.path();
+            if sub.is_dir() && !self.skipped(&sub) {
+                self.add_tree(&sub)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The relevant files changed since the last call; new directories are watched as they appear.
+    fn poll(&mut self) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+        let events = match self.inotify.read_events() {
+            Ok(events) => events,
+            Err(Errno::EAGAIN) => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut changed = vec![];
+        for event in events {
+            let (Some(dir), Some(name)) = (self.dirs.get(&event.wd), event.name) else {
+                continue;
+            };
+            let file = dir.join(name);
+            if self.skipped(&file) {
+                continue;
+            }
+            if event.mask.contains(AddWatchFlags::IN_ISDIR) {
+                if event.mask.intersects(AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO) && file.is_dir() {
+                    self.add_tree(&file)?;
+                }
+                continue;
+            }
+            if self.relevant(&file) {
+                changed.push(file);
+            }
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+impl Watcher {
+    fn new(root: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut watcher = Watcher {
+            root: root.to_path_buf(),
+            config: find_config_file(root).and_then(|(c, _)| c.canonicalize().ok()),
+            ignore: ignore::Ignore::load(root),
+            mtimes: HashMap::new(),
+        };
+        watcher.mtimes = watcher.scan();
+        Ok(watcher)
+    }
+
+    /// The modification time of every relevant file under the root that isn't skipped or ignored.
+    fn scan(&self) -> HashMap<PathBuf, SystemTime> {
+        let mut mtimes = HashMap::new();
+        let mut pending = vec![self.root.clone()];
+        while let Some(dir) = pending.pop() {
+            for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+                let file = entry.path();
+                if self.skipped(&file) {
+                    continue;
+                }
+                match entry.metadata() {
+                    Ok(meta) if meta.is_dir() => pending.push(file),
+                    Ok(meta) if self.relevant(&file) => {
+                        mtimes.insert(file, meta.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        mtimes
+    }
+
+    /// The relevant files created, modified or removed since the last call.
+    fn poll(&mut self) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+        let mtimes = self.scan();
+        let mut changed: Vec<PathBuf> = mtimes.iter().filter(|(file, mtime)| self.mtimes.get(*file) != Some(*mtime)).map(|(file, _)| file.clone()).collect();
+        changed.extend(self.mtimes.keys().filter(|file| !mtimes.contains_key(*file)).cloned());
+        self.mtimes = mtimes;
+        Ok(changed)
+    }
+}
+
+fn stop(program: &mut Option<Child>, children: &Arc<Mutex<Vec<u32>>>) {
+    if let Some(mut child) = program.take() {
+        platform::kill(child.id());
+        let _ = child.wait();
+        children.lock().unwrap().retain(|&p| p != child.id());
+    }
+}
+
+/// Rebuilds whenever a source, header or the config changes, skipping build output and `.hbuildignore`d paths.
+/// With `exec`, the executable is restarted after every successful build.
+pub fn run(path: &Path, children: &Arc<Mutex<Vec<u32>>>, opts: &BuildOptions, exec: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if find_config_file(path).is_none() {
+        eprintln!("{}", "No config file found".red().bold());
+        return Ok(());
+    }
+    let root = path.canonicalize()?;
+    let mut watcher = Watcher::new(&root)?;
+    let mut program: Option<Child> = None;
+    loop {
+        match make_with(path, children, opts) {
+            Ok(()) if exec => {
+                let (config_path, format) = find_config_file(path).ok_or("No config file found")?;
+                let config = parse_config(&config_path, &format)?;
+                match config.build.as_ref().filter(|b| b.build_type == "executable") {
+                    Some(build) => {
+                        let target = target_path(build, path, opts);
+                        verbosity::progress(format!("Running {}", target.display()).blue().bold());
+                        let mut argv: Vec<String> = opts.cross.as_ref().and_then(|c| c.runner.as_ref())
+                        .map(|r| r.split_whitespace().map(String::from).collect()).unwrap_or_default();
+                        argv.push(target.display().to_string());
+                        let child = Command::new(&argv[0]).args(&argv[1..]).spawn()?;
+                        children.lock().unwrap().push(child.id());
+                        program = Some(child);
+                    }
+                    None => eprintln!("{}", "--exec needs build_type = \"executable\"".yellow()),
+                }
+            }
+            Ok(()) => {}
+            Err(e) => eprintln!("{}", format!("Build failed: {}", e).red().bold()),
+        }
+        verbosity::progress(format!("Watching {} for changes (Ctrl-C to stop)", root.display()).cyan());
+        let changed = watcher.wait()?;
+        let first = changed[0].strip_prefix(&root).unwrap_or(&changed[0]).display().to_string();
+        let others = if changed.len() > 1 { format!(" and {} more", changed.len() - 1) } else { String::new() };
+        verbosity::progress(format!("Changed: {}{}", first, others).blue().bold());
+        stop(&mut program, children);
+    }
+}
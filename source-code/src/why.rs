@@ -0,0 +1,122 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use owo_colors::OwoColorize;
+use crate::{compile_c_cpp, find_config_file, mtime, parse_config, state, BuildOptions, BuildState};
+
+/// What the incremental logic compares for the target.
+pub struct LinkInputs<'a> {
+    pub target: &'a Path,
+    pub sources: &'a [PathBuf],
+    pub build_dir: &'a Path,
+    /// Generated objects, version and linker scripts: anything else newer than the target relinks it.
+    pub extra: Vec<PathBuf>,
+    /// Fingerprints recorded by the last build and the current ones, by object.
+    pub state: &'a BuildState,
+    pub fingerprints: &'a HashMap<PathBuf, String>,
+}
+
+fn same(a: &Path, b: &Path) -> bool {
+    a == b || a.canonicalize().ok().is_some_and(|a| b.canonicalize().ok().is_some_and(|b| a == b))
+}
+
+fn object(build_dir: &Path, src: &Path) -> PathBuf {
+    build_dir.join(src.file_name().unwrap()).with_extension("o")
+}
+
+/// The include chain from `file` to the first file that changed after `obj_mtime`, mirroring `needs_recompile`.
+fn chain(file: &Path, deps: &HashMap<PathBuf, HashSet<PathBuf>>, obj_mtime: std::time::SystemTime, visited: &mut HashSet<PathBuf>) -> Option<Vec<PathBuf>> {
+    if !file.exists() || mtime(file) > obj_mtime {
+        return Some(vec![file.to_path_buf()]);
+    }
+    if !visited.insert(file.to_path_buf()) {
+        return None;
+    }
+    for dep in deps.get(file).into_iter().flatten() {
+        if let Some(mut rest) = chain(dep, deps, obj_mtime, visited) {
+            rest.insert(0, file.to_path_buf());
+            return Some(rest);
+        }
+    }
+    None
+}
+
+/// Why `src` would be recompiled, or None when its object is up to date.
+fn source_reason(src: &Path, inputs: &LinkInputs, deps: &HashMap<PathBuf, HashSet<PathBuf>>) -> Option<String> {
+    let obj = object(inputs.build_dir, src);
+    if !obj.exists() {
+        return Some(format!("object {} does not exist", obj.display()));
+    }
+    if let (Some(stored), Some(current)) = (inputs.state.hashes.get(&obj), inputs.fingerprints.get(&obj)) {
+        return state::difference(stored, current).map(|why| format!("{} since {} was built", why, obj.display()));
+    }
+    let chain = chain(src, deps, mtime(&obj), &mut HashSet::new())?;
+    let changed = chain.last().unwrap();
+    let path = chain.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(" -> ");
+    if !changed.exists() {
+        Some(format!("{}: {} no longer exists", path, changed.display()))
+    } else if chain.len() == 1 {
+        Some(format!("{} changed after {} was built", src.display(), obj.display()))
+    } else {
+        Some(format!("{}: {} changed after {} was built", path, changed.display(), obj.display()))
+    }
+}
+
+/// Prints why `file` (a source, object or the target) is considered dirty.
+pub fn explain(file: &Path, inputs: &LinkInputs, deps: &HashMap<PathBuf, HashSet<PathBuf>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("{}", format!("Why is {} dirty?", file.display()).blue().bold());
+    let source = inputs.sources.iter().find(|s| same(s, file) || same(&object(inputs.build_dir, s), file));
+    if let Some(src) = source {
+        match source_reason(src, inputs, deps) {
+            Some(reason) => println!("  {}", reason.yellow()),
+            None => println!("  {}", "Up to date".green()),
+        }
+        return Ok(());
+    }
+    if !same(inputs.target, file) {
+        return Err(format!("{} is not a source, object or target of this build", file.display()).into());
+    }
+    if !inputs.target.exists() {
+        println!("  {}", format!("{} does not exist", inputs.target.display()).yellow());
+        return Ok(());
+    }
+    let target_mtime = mtime(inputs.target);
+    let mut reasons = vec![];
+    for src in inputs.sources {
+        if let Some(reason) = source_reason(src, inputs, deps) {
+            reasons.push(format!("{} is recompiled: {}", src.display(), reason));
+        } else if mtime(&object(inputs.build_dir, src)) > target_mtime {
+            reasons.push(format!("{} is newer than the target", object(inputs.build_dir, src).display()));
+        }
+    }
+    for extra in &inputs.extra {
+        if mtime(extra) > target_mtime {
+            reasons.push(format!("{} is newer than the target", extra.display()));
+        }
+    }
+    if reasons.is_empty() {
+        println!("  {}", "Up to date".green());
+    }
+    for reason in reasons {
+        println!("  {}", reason.yellow());
+    }
+    Ok(())
+}
+
+/// Runs the incremental analysis of `hbuild make` without compiling, installing packages, running
+/// generators or writing anything, and explains `file`.
+pub fn run(path: &Path, children: &Arc<Mutex<Vec<u32>>>, file: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (config_path, format) = match find_config_file(path) {
+        Some(found) => found,
+        None => {
+            eprintln!("{}", "No config file found".red().bold());
+            return Ok(());
+        }
+    };
+    let config = parse_config(&config_path, &format)?;
+    let opts = BuildOptions {
+        why: Some(path.join(file)),
+        ..Default::default()
+    };
+    compile_c_cpp(&config, path, children, &opts)
+}
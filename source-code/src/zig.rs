@@ -0,0 +1,129 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use crate::cross::Cross;
+use crate::pkgdeps::PkgConfigMode;
+use crate::{args, expand_globs, pkgdeps, target_path, verbosity, BuildOptions, HBuildConfig, Toolchain};
+
+/// Zig's `-O` mode for an hbuild optimize level; zig's own mode names pass through.
+fn mode(optimize: &str) -> &str {
+    match optimize {
+        "Debug" | "ReleaseSafe" | "ReleaseFast" | "ReleaseSmall" => optimize,
+        "O1" | "O2" | "O3" | "Ofast" => "ReleaseFast",
+        "Os" | "Oz" => "ReleaseSmall",
+        _ => "Debug",
+    }
+}
+
+fn run(mut command: Command, path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    command.current_dir(path);
+    verbosity::command(&command);
+    if !command.status()?.success() {
+        return Err("zig build failed".into());
+    }
+    Ok(())
+}
+
+/// Builds the zig part of the project: with `zig build` when it has a `build.zig`, else the `[build]`
+/// target from its `.zig` root plus any C sources, compiled in by zig. `--target` and the optimize
+/// level are passed on either way.
+pub fn build(config: &HBuildConfig, path: &Path, opts: &BuildOptions) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let triple = opts.cross.as_ref().map(|c| c.triple.as_str());
+    let optimize = opts.optimize.as_deref().or(config.build.as_ref().map(|b| b.optimize.as_str()));
+    if path.join("build.zig").exists() {
+        let mut command = Command::new("zig");
+        command.arg("build");
+        if let Some(optimize) = optimize {
+            command.arg(format!("-Doptimize={}", mode(optimize)));
+        }
+        // Each target installs into its own build root, like the other languages' outputs
+        if let Some(triple) = triple {
+            command.arg(format!("-Dtarget={}", triple)).arg("--prefix").arg(opts.build_dir(path).join("zig-out"));
+        }
+        return run(command, path);
+    }
+
+    let build = config.build.as_ref().ok_or("zig needs a build.zig or a [build] section")?;
+    let sources = expand_globs(path, &build.sources)?;
+    let (zig, others): (Vec<PathBuf>, Vec<PathBuf>) = sources.into_iter().partition(|s| s.extension().is_some_and(|e| e == "zig"));
+    let root = zig.iter().find(|s| s.file_name().is_some_and(|n| n == "main.zig" || n == "root.zig")).or(zig.first())
+    .ok_or("No .zig source in [build] sources")?;
+    let build_dir = opts.build_dir(path);
+    fs::create_dir_all(&build_dir)?;
+
+    let mut command = Command::new("zig");
+    command.arg(match build.build_type.as_str() {
+        "executable" => "build-exe",
+        _ => "build-lib",
+    });
+    if build.build_type == "shared" {
+        command.arg("-dynamic");
+    }
+    command.arg(root);
+    // Only the root is compiled directly; other .zig files are reached through its imports
+    if !others.is_empty() {
+        command.arg("-cflags").args(args::split(build.cflags.as_deref().unwrap_or_default())).arg("--").args(&others);
+    }
+    command.args(["-O", mode(optimize.unwrap_or("O0"))]);
+    if let Some(triple) = triple {
+        command.args(["-target", triple]);
+    }
+    let mut link: Vec<OsString> = build.include_dirs.iter().map(|d| args::with_path("-I", &path.join(d))).collect();
+    link.extend(build.lib_dirs.iter().flatten().map(|d| args::with_path("-L", &path.join(d))));
+    link.extend(build.libs.iter().flatten().map(|l| OsString::from(format!("-l{}", l))));
+    let mode = opts.cross.as_ref().map_or(PkgConfigMode::Host, |c| c.pkg_config.clone());
+    for pkg in build.pkg_dependencies.iter().flatten() {
+        let lib = pkgdeps::resolve(pkg, config.pkg_fallbacks.as_ref(), &mode)?;
+        link.extend(lib.include_paths.iter().map(|d| args::with_path("-I", d)));
+        link.extend(lib.link_paths.iter().map(|d| args::with_path("-L", d)));
+        link.extend(lib.libs.iter().map(|l| OsString::from(format!("-l{}", l))));
+    }
+    // C sources and C libraries need libc, which zig only links when asked
+    if !others.is_empty() || !link.is_empty() {
+        link.push("-lc".into());
+    }
+    command.args(&link);
+    command.arg("--cache-dir").arg(build_dir.join("zig-cache"));
+    command.arg(args::with_path("-femit-bin=", &target_path(build, path, opts)));
+    run(command, path)
+}
+
+/// Scripts running `zig cc`, `zig c++` and `zig ar` for `triple`, written to `dir`, so zig is the C
+/// and C++ cross compiler for targets without a gcc toolchain.
+fn wrappers(triple: &str, dir: &Path) -> Result<(PathBuf, PathBuf, PathBuf), Box<dyn std::error::Error + Send + Sync>> {
+    fs::create_dir_all(dir)?;
+    let dir = dir.canonicalize()?;
+    let write = |name: &str, command: &str| -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+        let script = dir.join(name);
+        fs::write(&script, format!("#!/bin/sh\nexec {} \"$@\"\n", command))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(script)
+    };
+    let quoted = args::quote(triple.as_ref());
+    Ok((
+        write("cc", &format!("zig cc -target {}", quoted))?,
+        write("c++", &format!("zig c++ -target {}", quoted))?,
+        write("ar", "zig ar")?,
+    ))
+}
+
+/// The toolchain compiling C and C++ for `triple` with zig, with the flags and runner of its
+/// `[toolchain]` section if it has one. Zig ships the target's libc headers, but not its other
+/// libraries, so pkg dependencies can't be probed.
+pub fn cross(triple: &str, toolchain: Option<&Toolchain>, cplusplus: bool, path: &Path) -> Result<Cross, Box<dyn std::error::Error + Send + Sync>> {
+    let (cc, cxx, ar) = wrappers(triple, &path.join("build").join(triple).join("zig"))?;
+    Ok(Cross {
+        triple: triple.to_string(),
+        compiler: (if cplusplus { cxx } else { cc }).display().to_string(),
+        ar: ar.display().to_string(),
+        cflags: toolchain.and_then(|t| t.cflags.clone()).unwrap_or_default(),
+        ldflags: toolchain.and_then(|t| t.ldflags.clone()).unwrap_or_default(),
+        pkg_config: PkgConfigMode::Disabled,
+        runner: toolchain.and_then(|t| t.runner.clone()),
+    })
+}